@@ -0,0 +1,286 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use self::addressmanager::{
+    AddressManager, ASSET_MANAGER, ERC20, ERC721, EXCHANGE_MANAGER, LEASING_MANAGER,
+    LENDING_MANAGER,
+};
+use ink_lang as ink;
+
+#[ink::contract]
+pub mod addressmanager {
+    use ink_prelude::{string::String, vec::Vec};
+    use ink_storage::{
+        collections::HashMap as StorageHashMap,
+        traits::{SpreadLayout, StorageLayout},
+    };
+    use scale::{Decode, Encode};
+
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, SpreadLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    struct Ownable {
+        owner: AccountId,
+    }
+
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        AddressNotFound,
+    }
+
+    /// Registry of protocol contract addresses.
+    /// Managers look up their dependencies here so that
+    /// a contract can be replaced by updating a single entry.
+    #[ink(storage)]
+    pub struct AddressManager {
+        owner: Ownable,
+        addresses: StorageHashMap<String, AccountId>,
+        contracts: StorageHashMap<Vec<u8>, (AccountId, u32)>,
+    }
+
+    #[ink(event)]
+    pub struct AddressChanged {
+        key: String,
+        #[ink(topic)]
+        old_value: Option<AccountId>,
+        #[ink(topic)]
+        new_value: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ContractRegistered {
+        name: Vec<u8>,
+        #[ink(topic)]
+        address: AccountId,
+        version: u32,
+    }
+
+    #[ink(event)]
+    pub struct ContractDeregistered {
+        name: Vec<u8>,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+    }
+
+    pub const ERC20: &str = "ERC20";
+    pub const ERC721: &str = "ERC721";
+    pub const ASSET_MANAGER: &str = "ASSET_MANAGER";
+    pub const LENDING_MANAGER: &str = "LENDING_MANAGER";
+    pub const LEASING_MANAGER: &str = "LEASING_MANAGER";
+    pub const EXCHANGE_MANAGER: &str = "EXCHANGE_MANAGER";
+
+    pub const VERSION: (u32, u32, u32) = (1, 0, 0);
+    // Fails to compile unless the minor and patch versions are below 1000
+    const _: [(); 0 - !(VERSION.1 < 1000 && VERSION.2 < 1000) as usize] = [];
+
+    impl AddressManager {
+        #[ink(constructor)]
+        pub fn new() -> Self {
+            let owner = Self::env().caller();
+            Self {
+                owner: Ownable { owner },
+                addresses: Default::default(),
+                contracts: Default::default(),
+            }
+        }
+
+        /// Returns the (major, minor, patch) version of the contract
+        #[ink(message)]
+        pub fn get_contract_version(&self) -> (u32, u32, u32) {
+            VERSION
+        }
+
+        /// Returns the name of the contract
+        #[ink(message)]
+        pub fn get_contract_name(&self) -> String {
+            String::from("AddressManager")
+        }
+
+        /// Checks if caller is owner of AddressManager contract
+        #[ink(message)]
+        pub fn is_owner(&self) -> bool {
+            return self.env().caller() == self.owner.owner;
+        }
+
+        /// Gets owner address of AddressManager contract
+        #[ink(message)]
+        pub fn get_owner(&self) -> AccountId {
+            self.owner.owner
+        }
+
+        /// Transfers ownership from current owner to new_owner address
+        /// Can only be called by the current owner
+        #[ink(message)]
+        pub fn transfer_ownership(&mut self, new_owner: AccountId) -> bool {
+            let caller = self.env().caller();
+            assert!(self.only_owner(caller));
+            self.owner.owner = new_owner;
+            self.env().emit_event(OwnershipTransferred {
+                from: caller,
+                to: new_owner,
+            });
+            true
+        }
+
+        fn only_owner(&self, caller: AccountId) -> bool {
+            caller == self.owner.owner
+        }
+
+        /// Allows owner to register the address of a contract under the given key
+        #[ink(message)]
+        pub fn set_address(&mut self, key: String, addr: AccountId) {
+            assert!(self.only_owner(self.env().caller()));
+            let old_value = self.addresses.insert(key.clone(), addr);
+            self.env().emit_event(AddressChanged {
+                key,
+                old_value,
+                new_value: addr,
+            });
+        }
+
+        /// Returns the address registered under the given key
+        #[ink(message)]
+        pub fn get_address(&self, key: String) -> Option<AccountId> {
+            self.addresses.get(&key).cloned()
+        }
+
+        /// Returns the address registered under the given key
+        /// or an error if nothing is registered
+        #[ink(message)]
+        pub fn require_address(&self, key: String) -> Result<AccountId, Error> {
+            self.get_address(key).ok_or(Error::AddressNotFound)
+        }
+
+        /// Allows owner to register a versioned contract under the given name
+        #[ink(message)]
+        pub fn register_contract(&mut self, name: Vec<u8>, address: AccountId, version: u32) {
+            assert!(self.only_owner(self.env().caller()));
+            self.contracts.insert(name.clone(), (address, version));
+            self.env().emit_event(ContractRegistered {
+                name,
+                address,
+                version,
+            });
+        }
+
+        /// Allows owner to remove the contract registered under the given name
+        #[ink(message)]
+        pub fn deregister_contract(&mut self, name: Vec<u8>) -> Result<(), Error> {
+            assert!(self.only_owner(self.env().caller()));
+            if self.contracts.take(&name).is_none() {
+                return Err(Error::AddressNotFound);
+            }
+            self.env().emit_event(ContractDeregistered { name });
+            Ok(())
+        }
+
+        /// Returns the address and version of the contract registered under the given name
+        #[ink(message)]
+        pub fn get_contract(&self, name: Vec<u8>) -> Option<(AccountId, u32)> {
+            self.contracts.get(&name).cloned()
+        }
+
+        /// Returns the address of the contract registered under the given name
+        #[ink(message)]
+        pub fn get_contract_address(&self, name: Vec<u8>) -> Option<AccountId> {
+            self.get_contract(name).map(|(address, _)| address)
+        }
+
+        /// Returns name, address and version of every registered contract
+        #[ink(message)]
+        pub fn list_registered_contracts(&self) -> Vec<(Vec<u8>, AccountId, u32)> {
+            self.contracts
+                .iter()
+                .map(|(name, (address, version))| (name.clone(), *address, *version))
+                .collect()
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
+    /// The below code is technically just normal Rust code.
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_lang as ink;
+
+        #[ink::test]
+        fn new_works() {
+            let addressmanager = AddressManager::new();
+            assert_eq!(addressmanager.is_owner(), true);
+            assert_eq!(addressmanager.get_address(String::from(ERC20)), None);
+            assert_eq!(
+                addressmanager.require_address(String::from(ERC20)),
+                Err(Error::AddressNotFound)
+            );
+        }
+
+        #[ink::test]
+        fn contract_version_works() {
+            let addressmanager = AddressManager::new();
+            assert_eq!(addressmanager.get_contract_version(), VERSION);
+            assert_eq!(addressmanager.get_contract_version(), (1, 0, 0));
+            assert_eq!(
+                addressmanager.get_contract_name(),
+                String::from("AddressManager")
+            );
+        }
+
+        #[ink::test]
+        fn set_address_works() {
+            let mut addressmanager = AddressManager::new();
+            let erc20 = AccountId::from([0x01; 32]);
+            let new_erc20 = AccountId::from([0x02; 32]);
+
+            addressmanager.set_address(String::from(ERC20), erc20);
+            assert_eq!(addressmanager.get_address(String::from(ERC20)), Some(erc20));
+            assert_eq!(
+                addressmanager.require_address(String::from(ERC20)),
+                Ok(erc20)
+            );
+            assert_eq!(addressmanager.get_address(String::from(ERC721)), None);
+
+            addressmanager.set_address(String::from(ERC20), new_erc20);
+            assert_eq!(
+                addressmanager.require_address(String::from(ERC20)),
+                Ok(new_erc20)
+            );
+        }
+
+        #[ink::test]
+        fn register_contract_works() {
+            let mut addressmanager = AddressManager::new();
+            let erc20 = AccountId::from([0x01; 32]);
+            let new_erc20 = AccountId::from([0x02; 32]);
+            let name = ERC20.as_bytes().to_vec();
+
+            assert_eq!(addressmanager.get_contract(name.clone()), None);
+            addressmanager.register_contract(name.clone(), erc20, 1);
+            assert_eq!(addressmanager.get_contract(name.clone()), Some((erc20, 1)));
+            assert_eq!(
+                addressmanager.get_contract_address(name.clone()),
+                Some(erc20)
+            );
+
+            addressmanager.register_contract(name.clone(), new_erc20, 2);
+            assert_eq!(
+                addressmanager.list_registered_contracts(),
+                vec![(name.clone(), new_erc20, 2)]
+            );
+
+            assert_eq!(addressmanager.deregister_contract(name.clone()), Ok(()));
+            assert_eq!(addressmanager.get_contract_address(name.clone()), None);
+            assert_eq!(addressmanager.list_registered_contracts(), vec![]);
+            assert_eq!(
+                addressmanager.deregister_contract(name),
+                Err(Error::AddressNotFound)
+            );
+        }
+    }
+}
@@ -4,10 +4,11 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod assetmanager {
+    use addressmanager::{AddressManager as AddressRegistry, ERC20, ERC721};
     use erc20::Erc20;
     use erc721::Erc721;
-    use ink_env::call::FromAccountId;
-    use ink_prelude::vec::Vec;
+    use ink_env::call::{build_call, utils::ReturnType, ExecutionInput, FromAccountId, Selector};
+    use ink_prelude::{string::String, vec::Vec};
     use ink_storage::{
         collections::HashMap as StorageHashMap,
         traits::{PackedLayout, SpreadLayout, StorageLayout},
@@ -28,6 +29,7 @@ mod assetmanager {
         erc721_address: AccountId,
         erc20_owner: AccountId,
         erc721_owner: AccountId,
+        emergency_address: AccountId,
     }
 
     #[derive(Encode, Decode, Debug, Default, Copy, Clone, SpreadLayout)]
@@ -36,6 +38,32 @@ mod assetmanager {
         interest_rate: u64,
         transfer_rate: u128,
         enabled: bool,
+        timelock_delay: u64,
+        interest_rate_model: u8,
+        flash_fee_bps: u16,
+        max_loan_duration: u64,
+        admin_threshold: u8,
+        interest_checkpoint_threshold: Balance,
+        max_borrow_per_borrower: Balance,
+        utilization_multiplier: u64,
+        max_borrows_per_block: u32,
+        minimum_deposit_amount: Balance,
+    }
+
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum InterestModel {
+        Linear,
+        Compound,
+    }
+
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum LoanStatus {
+        Active,
+        Repaid,
+        Liquidated,
+        Defaulted,
     }
 
     pub type LoanId = u64;
@@ -48,6 +76,27 @@ mod assetmanager {
         ERC721TransferFailed,
         ERC20TransferFailed,
         InsufficientBalance,
+        ContractImmutable,
+        Reentrant,
+        FlashLoanNotRepaid,
+        NotEnabled,
+        LoanAlreadyRepaid,
+        LoanNotActive,
+        StorageError,
+        LoanNotExpired,
+        NoSuchExtension,
+        CollectionBorrowLimitExceeded,
+        NotAuthorized,
+        ERC721LockFailed,
+        BorrowerBorrowLimitExceeded,
+        ArithmeticOverflow,
+        InvalidTimestamp,
+        LoanAlreadyExists,
+        RateLimitExceeded,
+        AmountBelowMinimum,
+        NoPendingChange,
+        TimelockNotExpired,
+        Timelocked,
     }
 
     #[derive(Clone, Default, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
@@ -58,6 +107,8 @@ mod assetmanager {
         loans: Vec<TokenId>,
     }
 
+    /// Loans stored by releases without expiry can't be decoded,
+    /// contracts deployed with those releases have to be redeployed
     #[derive(Clone, Default, Copy, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
     pub struct Loan {
@@ -66,8 +117,111 @@ mod assetmanager {
         transfer_rate: u128,
         interest_rate: u64,
         date_borrowed: u64,
-        date_repaid: Option<u64>,
-        is_repaid: bool,
+        closed_at: Option<u64>,
+        status: u8,
+        expiry: u64,
+        last_checkpoint: u64,
+        checkpoint_interest: Balance,
+        borrower_address: AccountId,
+    }
+
+    impl Loan {
+        fn is_active(&self) -> bool {
+            self.status == LoanStatus::Active as u8
+        }
+    }
+
+    /// Change admins approve together
+    #[derive(
+        Clone,
+        Default,
+        Copy,
+        Encode,
+        Decode,
+        Debug,
+        PartialEq,
+        Eq,
+        PartialOrd,
+        Ord,
+        SpreadLayout,
+        PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct AdminAction {
+        pub parameter: u8,
+        pub value: u128,
+    }
+
+    impl AdminAction {
+        pub fn set(parameter: u8, value: u128) -> Self {
+            AdminAction { parameter, value }
+        }
+    }
+
+    /// Interest of a loan when it was last checkpointed
+    #[derive(
+        Clone, Default, Copy, Encode, Decode, Debug, PartialEq, Eq, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct InterestCheckpoint {
+        last_checkpoint: u64,
+        checkpoint_interest: Balance,
+    }
+
+    #[derive(Clone, Default, Copy, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct PendingExtension {
+        borrower: AccountId,
+        token_id: TokenId,
+        additional_days: u64,
+        requested_at: u64,
+    }
+
+    /// Borrowing parameters for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct AdminConfig {
+        interest_rate: u64,
+        transfer_rate: Balance,
+        enabled: bool,
+    }
+
+    /// Summary of a borrower's position for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct BorrowerStats {
+        principal_balance: Balance,
+        total_balance: Balance,
+        accrued_interest: Balance,
+        active_loan_count: u32,
+        total_loan_count: u32,
+        health_factor: u128,
+    }
+
+    /// State of the whole protocol for off-chain analytics in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct ProtocolSnapshot {
+        total_loans: u64,
+        total_active_loans: u64,
+        total_principal_outstanding: Balance,
+        total_interest_accrued: Balance,
+        erc20_reserve_balance: Balance,
+        utilization_rate: u64,
+        current_interest_rate: u64,
+        transfer_rate: Balance,
+        enabled: bool,
+        total_borrowers: u32,
+    }
+
+    /// Outstanding loan and the amount needed to repay it
+    #[derive(Encode, Decode, Debug, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct PendingRepayment {
+        borrower: AccountId,
+        token_id: TokenId,
+        amount: Balance,
+        expiry: u64,
     }
 
     /// Defines the storage of your contract.
@@ -83,6 +237,18 @@ mod assetmanager {
         total_loans: u64,
         erc20: Lazy<Erc20>,
         erc721: Lazy<Erc721>,
+        pending_changes: StorageHashMap<u8, (u128, u64)>,
+        total_principal_outstanding: Balance,
+        locked: bool,
+        pending_extensions: StorageHashMap<(AccountId, TokenId), PendingExtension>,
+        loan_index: StorageHashMap<LoanId, (AccountId, TokenId)>,
+        pending_repayment_count: u64,
+        collection_borrow_limits: StorageHashMap<AccountId, Balance>,
+        collection_borrowed: StorageHashMap<AccountId, Balance>,
+        allowed_repayers: StorageHashMap<(AccountId, TokenId), AccountId>,
+        admins: StorageHashMap<AccountId, bool>,
+        pending_admin_actions: StorageHashMap<AdminAction, Vec<AccountId>>,
+        borrows_this_block: StorageHashMap<u32, u32>,
     }
 
     #[ink(event)]
@@ -135,6 +301,180 @@ mod assetmanager {
         to: AccountId,
     }
 
+    #[ink(event)]
+    pub struct OwnershipRenounced {
+        #[ink(topic)]
+        former_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ChangeProposed {
+        #[ink(topic)]
+        parameter: u8,
+        value: u128,
+        executable_at: u64,
+    }
+
+    #[ink(event)]
+    pub struct AdminAdded {
+        #[ink(topic)]
+        admin: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct AdminRemoved {
+        #[ink(topic)]
+        admin: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct AdminThresholdChanged {
+        old: u8,
+        new: u8,
+    }
+
+    #[ink(event)]
+    pub struct AdminActionApproved {
+        #[ink(topic)]
+        parameter: u8,
+        #[ink(topic)]
+        admin: AccountId,
+        value: u128,
+        approvals: u8,
+    }
+
+    #[ink(event)]
+    pub struct ChangeExecuted {
+        #[ink(topic)]
+        parameter: u8,
+        value: u128,
+    }
+
+    #[ink(event)]
+    pub struct ChangeCancelled {
+        #[ink(topic)]
+        parameter: u8,
+    }
+
+    #[ink(event)]
+    pub struct MaxLoanDurationChanged {
+        old: u64,
+        new: u64,
+    }
+
+    #[ink(event)]
+    pub struct MinimumDepositChanged {
+        old: Balance,
+        new: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Liquidated {
+        #[ink(topic)]
+        borrower: AccountId,
+        #[ink(topic)]
+        token_id: u32,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ExtensionRequested {
+        #[ink(topic)]
+        borrower: AccountId,
+        #[ink(topic)]
+        token_id: u32,
+        additional_days: u64,
+    }
+
+    #[ink(event)]
+    pub struct ExtensionApproved {
+        #[ink(topic)]
+        borrower: AccountId,
+        #[ink(topic)]
+        token_id: u32,
+        expiry: u64,
+    }
+
+    #[ink(event)]
+    pub struct ExtensionRejected {
+        #[ink(topic)]
+        borrower: AccountId,
+        #[ink(topic)]
+        token_id: u32,
+    }
+
+    #[ink(event)]
+    pub struct InterestCheckpointed {
+        #[ink(topic)]
+        borrower: AccountId,
+        #[ink(topic)]
+        token_id: u32,
+        accrued_interest: Balance,
+        timestamp: u64,
+    }
+
+    #[ink(event)]
+    pub struct RepayerAuthorized {
+        #[ink(topic)]
+        borrower: AccountId,
+        #[ink(topic)]
+        token_id: u32,
+        repayer: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct FlashLoan {
+        #[ink(topic)]
+        receiver: AccountId,
+        amount: Balance,
+        fee: Balance,
+    }
+
+    #[ink(event)]
+    pub struct LoanMigrated {
+        #[ink(topic)]
+        old_borrower: AccountId,
+        #[ink(topic)]
+        new_borrower: AccountId,
+        token_id: TokenId,
+    }
+
+    #[ink(event)]
+    pub struct EmergencyWithdrawal {
+        #[ink(topic)]
+        borrower: AccountId,
+        #[ink(topic)]
+        token_id: TokenId,
+        emergency_address: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ReserveTopUp {
+        #[ink(topic)]
+        by: AccountId,
+        amount: Balance,
+    }
+
+    /// Selector of `on_flash_loan(amount: Balance, fee: Balance, data: Vec<u8>) -> bool`
+    /// which flash loan receivers have to implement.
+    /// Equal to the selector ink! derives for an inherent message named `on_flash_loan`
+    pub const FLASH_LOAN_RECEIVER_SELECTOR: [u8; 4] = [0x08, 0x48, 0x1e, 0x7f];
+
+    pub const INTEREST_RATE_PARAMETER: u8 = 0;
+    pub const TRANSFER_RATE_PARAMETER: u8 = 1;
+    pub const ENABLED_PARAMETER: u8 = 2;
+    pub const TIMELOCK_DELAY_PARAMETER: u8 = 3;
+    pub const VARIABLE_INTEREST_RATE_PARAMETER: u8 = 4;
+    pub const LIQUIDATION_THRESHOLD: u128 = 10_500;
+    pub const DEFAULT_MAX_LOAN_DURATION: u64 = 30 * 86_400 * 1_000;
+    pub const MAX_SWEEP_BORROWERS: usize = 50;
+    pub const MILLISECONDS_IN_DAY: u64 = 86_400_000;
+    pub const SECONDS_IN_DAY: u64 = 86_400;
+
+    pub const VERSION: (u32, u32, u32) = (1, 0, 0);
+    // Fails to compile unless the minor and patch versions are below 1000
+    const _: [(); 0 - !(VERSION.1 < 1000 && VERSION.2 < 1000) as usize] = [];
+
     impl AssetManager {
         /// Constructors can delegate to other constructors.
         #[ink(constructor)]
@@ -149,28 +489,90 @@ mod assetmanager {
 
             let erc20 = Erc20::from_account_id(erc20_address);
             let erc721 = Erc721::from_account_id(erc721_address);
-            let instance = Self {
+            let mut instance = Self {
                 owner: Ownable { owner },
                 administration: Administration {
                     interest_rate,
                     transfer_rate,
                     enabled,
+                    timelock_delay: 0,
+                    interest_rate_model: InterestModel::Compound as u8,
+                    flash_fee_bps: 0,
+                    max_loan_duration: DEFAULT_MAX_LOAN_DURATION,
+                    admin_threshold: 1,
+                    interest_checkpoint_threshold: 0,
+                    max_borrow_per_borrower: Balance::MAX,
+                    utilization_multiplier: 0,
+                    max_borrows_per_block: u32::MAX,
+                    minimum_deposit_amount: 0,
                 },
                 address_manager: AddressManager {
                     erc20_address: erc20_address,
                     erc721_address: erc721_address,
                     erc20_owner: owner,
                     erc721_owner: owner,
+                    emergency_address: owner,
                 },
                 borrowers: Default::default(),
                 loans: Default::default(),
                 total_loans: 0,
                 erc20: Lazy::new(erc20),
                 erc721: Lazy::new(erc721),
+                pending_changes: Default::default(),
+                total_principal_outstanding: 0,
+                locked: false,
+                pending_extensions: Default::default(),
+                loan_index: Default::default(),
+                pending_repayment_count: 0,
+                collection_borrow_limits: Default::default(),
+                collection_borrowed: Default::default(),
+                allowed_repayers: Default::default(),
+                admins: Default::default(),
+                pending_admin_actions: Default::default(),
+                borrows_this_block: Default::default(),
             };
+            instance.admins.insert(owner, true);
             instance
         }
 
+        /// Looks up the erc20 and erc721 addresses through the address manager once
+        /// Collateral stays in the erc721 contract it was deposited to,
+        /// so later changes to the registry don't affect this contract
+        #[ink(constructor)]
+        pub fn new_with_address_manager(
+            address_manager: AccountId,
+            interest_rate: u64,
+            transfer_rate: Balance,
+            enabled: bool,
+        ) -> Self {
+            let address_manager = AddressRegistry::from_account_id(address_manager);
+            let erc20_address = address_manager
+                .require_address(String::from(ERC20))
+                .expect("ERC20 address not registered");
+            let erc721_address = address_manager
+                .require_address(String::from(ERC721))
+                .expect("ERC721 address not registered");
+            Self::new(
+                erc20_address,
+                erc721_address,
+                interest_rate,
+                transfer_rate,
+                enabled,
+            )
+        }
+
+        /// Returns the (major, minor, patch) version of the contract
+        #[ink(message)]
+        pub fn get_contract_version(&self) -> (u32, u32, u32) {
+            VERSION
+        }
+
+        /// Returns the name of the contract
+        #[ink(message)]
+        pub fn get_contract_name(&self) -> String {
+            String::from("AssetManager")
+        }
+
         /// Checks if caller is owner of AssetManager contract
         #[ink(message)]
         pub fn is_owner(&self) -> bool {
@@ -184,11 +586,20 @@ mod assetmanager {
         }
 
         /// Transfers ownership from current owner to new_owner address
+        /// The admin seat of the current owner moves to new_owner along with it.
         /// Can only be called by the current owner
         #[ink(message)]
         pub fn transfer_ownership(&mut self, new_owner: AccountId) -> bool {
             let caller = self.env().caller();
             assert!(self.only_owner(caller));
+            if self.admins.take(&caller).is_some() {
+                self.discard_admin_approvals(caller);
+                self.admins.insert(new_owner, true);
+                assert!(
+                    self.admins.len() >= self.administration.admin_threshold as u32,
+                    "Not enough admins left for threshold"
+                );
+            }
             self.owner.owner = new_owner;
             self.env().emit_event(OwnershipTransferred {
                 from: caller,
@@ -197,15 +608,113 @@ mod assetmanager {
             true
         }
 
+        /// Permanently removes the owner making all parameters immutable
+        /// Can only be called by the current owner
+        #[ink(message)]
+        pub fn renounce_ownership(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_owner(caller));
+            self.owner.owner = AccountId::from([0x0; 32]);
+            self.env().emit_event(OwnershipRenounced {
+                former_owner: caller,
+            });
+            Ok(())
+        }
+
         fn only_owner(&self, caller: AccountId) -> bool {
             caller == self.owner.owner
         }
 
+        fn only_admin(&self, caller: AccountId) -> bool {
+            self.admins.contains_key(&caller)
+        }
+
+        /// Records approval of admin for action
+        /// Approvals are counted per action, so admins approving different values don't add up
+        /// Returns true once enough admins approved and the action should be applied
+        fn approve_admin_action(&mut self, action: AdminAction, admin: AccountId) -> bool {
+            let threshold = self.administration.admin_threshold;
+            if threshold <= 1 {
+                return true;
+            }
+
+            let approvals = self
+                .pending_admin_actions
+                .entry(action)
+                .or_insert_with(Vec::new);
+            if !approvals.contains(&admin) {
+                approvals.push(admin);
+            }
+            let approvals = approvals.len() as u8;
+            self.env().emit_event(AdminActionApproved {
+                parameter: action.parameter,
+                admin,
+                value: action.value,
+                approvals,
+            });
+
+            if approvals < threshold {
+                return false;
+            }
+            // Approvals of other values of the same change can't be applied later on
+            self.clear_admin_actions(action.parameter);
+            true
+        }
+
+        /// Drops the approvals of every value of parameter
+        fn clear_admin_actions(&mut self, parameter: u8) {
+            let pending: Vec<AdminAction> = self
+                .pending_admin_actions
+                .keys()
+                .filter(|pending| pending.parameter == parameter)
+                .copied()
+                .collect();
+            for action in pending {
+                self.pending_admin_actions.take(&action);
+            }
+        }
+
+        /// Drops the approvals admin gave on pending actions
+        fn discard_admin_approvals(&mut self, admin: AccountId) {
+            for (_action, approvals) in self.pending_admin_actions.iter_mut() {
+                approvals.retain(|approver| *approver != admin);
+            }
+        }
+
+        fn ensure_mutable(&self) -> Result<(), Error> {
+            if self.owner.owner == AccountId::from([0x0; 32]) {
+                return Err(Error::ContractImmutable);
+            }
+            Ok(())
+        }
+
+        /// Rates can only be set directly while there is no timelock delay
+        fn ensure_not_timelocked(&self) -> Result<(), Error> {
+            if self.administration.timelock_delay != 0 {
+                return Err(Error::Timelocked);
+            }
+            Ok(())
+        }
+
+        /// Guards against re-entrant calls from the token contracts
+        /// Re-entrant calls panic so that the outer call reverts as a whole.
+        /// The guard is released whatever body returns
+        fn with_reentrancy_guard<T>(&mut self, body: impl FnOnce(&mut Self) -> T) -> T {
+            assert!(!self.locked, "Reentrant call");
+            self.locked = true;
+            let result = body(self);
+            self.locked = false;
+            result
+        }
+
         /// Sets owner address of erc20 contract
         #[ink(message)]
-        pub fn set_erc20_owner(&mut self, erc20_owner: AccountId) {
+        pub fn set_erc20_owner(&mut self, erc20_owner: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
             self.address_manager.erc20_owner = erc20_owner;
+            Ok(())
         }
 
         /// Returns owner address of erc20 contract
@@ -216,9 +725,11 @@ mod assetmanager {
 
         /// Sets owner address of erc721 contract
         #[ink(message)]
-        pub fn set_erc721_owner(&mut self, erc721_owner: AccountId) {
+        pub fn set_erc721_owner(&mut self, erc721_owner: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
             self.address_manager.erc721_owner = erc721_owner;
+            Ok(())
         }
 
         /// Returns owner address of erc721 contract
@@ -227,46 +738,87 @@ mod assetmanager {
             self.address_manager.erc721_owner
         }
 
+        /// Sets address emergency_withdraw_nft sends collateral to
+        #[ink(message)]
+        pub fn set_emergency_address(&mut self, emergency_address: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.address_manager.emergency_address = emergency_address;
+            Ok(())
+        }
+
+        /// Returns address emergency_withdraw_nft sends collateral to
+        #[ink(message)]
+        pub fn get_emergency_address(&self) -> AccountId {
+            self.address_manager.emergency_address
+        }
+
         /// Allows borrowing on behalf of another account
         /// erc20_owner should have granted approval to assetmanager contract to make transfer on their behalf and have sufficient balance
         /// Caller should have granted approval to erc721 token before executing this function
         #[ink(message)]
         pub fn deposit(&mut self, token_id: u32, on_behalf_of: AccountId) -> Result<(), Error> {
-            assert_eq!(self.is_enabled(), true, "Borrowing is not enabled");
+            self.with_reentrancy_guard(|manager| manager.try_deposit(token_id, on_behalf_of))
+        }
+
+        fn try_deposit(&mut self, token_id: u32, on_behalf_of: AccountId) -> Result<(), Error> {
+            if !self.is_enabled() {
+                return Err(Error::NotEnabled);
+            }
+            self.check_block_borrow_limit()?;
             let current_time = self.get_current_time();
             let caller = self.env().caller();
 
-            let interest_rate = self.get_interest_rate();
             let transfer_rate = self.get_transfer_rate();
+            let erc20_amount = Balance::from(transfer_rate);
+            self.check_minimum_deposit(erc20_amount)?;
+
+            let interest_rate = self.get_interest_rate();
             let AddressManager {
                 erc20_owner,
                 erc721_owner,
                 ..
             } = self.address_manager;
 
-            let erc20_amount = Balance::from(transfer_rate);
-
             // Contract does not have enough erc20 balance for loan
             if self.erc20.balance_of(erc20_owner) < erc20_amount {
                 return Err(Error::InsufficientBalance);
             }
 
-            // Handles borrowing
-            let db_transfer =
-                self.handle_borrow(caller, token_id, interest_rate, transfer_rate, current_time);
-            assert_eq!(db_transfer.is_ok(), true, "Error storing transaction");
+            let erc721_address = self.address_manager.erc721_address;
+            self.check_collection_borrow_limit(erc721_address, erc20_amount)?;
+            self.check_borrower_borrow_limit(caller, erc20_amount)?;
+
+            let contract_address = self.env().account_id();
+            Self::check_allowance_sufficient(
+                &self.erc20,
+                erc20_owner,
+                contract_address,
+                erc20_amount,
+            )?;
 
             let erc721_transfer = self.erc721.transfer_from(caller, erc721_owner, token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
+            if erc721_transfer.is_err() {
+                return Err(Error::ERC721TransferFailed);
+            }
+
+            // The collateral has moved: any failure from here on must revert the whole call
+            // Collateral can't be moved until the loan expires
+            let expiry = current_time + self.administration.max_loan_duration;
+            assert!(
+                self.erc721.lock_token(token_id, expiry).is_ok(),
+                "Collateral lock failed"
             );
 
             let erc20_transfer = self
                 .erc20
                 .transfer_from(erc20_owner, on_behalf_of, erc20_amount);
-            assert_eq!(erc20_transfer.is_ok(), true, "ERC20 Token transfer failed");
+            assert!(erc20_transfer.is_ok(), "Loan transfer failed");
+
+            // Handles borrowing
+            let db_transfer =
+                self.handle_borrow(caller, token_id, interest_rate, transfer_rate, current_time);
+            assert!(db_transfer.is_ok(), "Loan bookkeeping failed");
 
             // self.env().emit_event(Borrowed {
             //     borrower: on_behalf_of,
@@ -283,6 +835,10 @@ mod assetmanager {
         // Caller should have granted approval to erc20 before executing this function
         #[ink(message)]
         pub fn withdraw(&mut self, token_id: u32, on_behalf_of: AccountId) -> Result<(), Error> {
+            self.with_reentrancy_guard(|manager| manager.try_withdraw(token_id, on_behalf_of))
+        }
+
+        fn try_withdraw(&mut self, token_id: u32, on_behalf_of: AccountId) -> Result<(), Error> {
             let current_time = self.get_current_time();
             let caller = self.env().caller();
 
@@ -293,23 +849,35 @@ mod assetmanager {
                 ..
             } = self.address_manager;
 
-            let total_balance = self.get_total_balance_of_loan(on_behalf_of, token_id);
-            let db_transfer = self.handle_repayment(on_behalf_of, token_id, current_time);
-            assert_eq!(db_transfer.is_ok(), true, "Error storing transaction");
+            if !self.is_authorized_repayer(on_behalf_of, token_id, caller) {
+                return Err(Error::NotAuthorized);
+            }
+
+            self.validate_repayment(on_behalf_of, token_id)?;
 
+            let total_balance = self.get_total_balance_of_loan(on_behalf_of, token_id);
             let erc20_amount = total_balance;
 
             let erc20_transfer = self.erc20.transfer_from(caller, erc20_owner, erc20_amount);
-            assert_eq!(erc20_transfer.is_ok(), true, "ERC20 Token transfer failed");
+            if erc20_transfer.is_err() {
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            // The repayment has moved: any failure from here on must revert the whole call
+            // Lock taken on deposit lapses on its own once the loan expired
+            assert!(
+                !self.erc721.is_token_locked(token_id)
+                    || self.erc721.unlock_token(token_id).is_ok(),
+                "Collateral unlock failed"
+            );
 
             let erc721_transfer = self
                 .erc721
                 .transfer_from(erc721_owner, on_behalf_of, token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            assert!(erc721_transfer.is_ok(), "Collateral transfer failed");
+
+            let db_transfer = self.handle_repayment(on_behalf_of, token_id, current_time);
+            assert!(db_transfer.is_ok(), "Loan bookkeeping failed");
 
             // self.env().emit_event(Repaid {
             //     borrower: on_behalf_of,
@@ -320,21 +888,315 @@ mod assetmanager {
             Ok(())
         }
 
-        /// Returns principal amount borrowed by the address
+        /// Liquidates an expired loan
+        /// The collateral stays with erc721_owner and the debt is written off
         #[ink(message)]
-        pub fn get_principal_balance_of_borrower(&self, owner: AccountId) -> Balance {
-            let borrower_opt = self.borrowers.get(&owner);
-            if borrower_opt.is_some() {
-                return borrower_opt.unwrap().balance;
+        pub fn liquidate(&mut self, borrower: AccountId, token_id: TokenId) -> Result<(), Error> {
+            if !self.is_loan_expired(borrower, token_id)? {
+                return Err(Error::LoanNotExpired);
             }
-            0
+            self.handle_liquidation(borrower, token_id, self.get_current_time())
         }
 
-        /// Returns total amount borrowed including interest by the address
+        /// Allows owner to recover the collateral of a loan whose borrower is unreachable
+        /// Moves the nft from erc721_owner to emergency_address and marks the loan as liquidated
         #[ink(message)]
-        pub fn get_total_balance_of_borrower(&self, owner: AccountId) -> Balance {
-            let balance = self.get_principal_balance_of_borrower(owner);
-            let debt = self.get_total_debt_of_borrower(owner);
+        pub fn emergency_withdraw_nft(
+            &mut self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.with_reentrancy_guard(|manager| {
+                manager.try_emergency_withdraw_nft(borrower, token_id)
+            })
+        }
+
+        fn try_emergency_withdraw_nft(
+            &mut self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<(), Error> {
+            let current_time = self.get_current_time();
+            let AddressManager {
+                erc721_owner,
+                emergency_address,
+                ..
+            } = self.address_manager;
+
+            if self.validate_repayment(borrower, token_id).is_err() {
+                return Err(Error::NoSuchLoan);
+            }
+
+            if self.erc721.is_token_locked(token_id) && self.erc721.unlock_token(token_id).is_err()
+            {
+                return Err(Error::ERC721LockFailed);
+            }
+
+            // An unlocked token must not be left behind if the transfer fails
+            let erc721_transfer =
+                self.erc721
+                    .transfer_from(erc721_owner, emergency_address, token_id);
+            assert!(erc721_transfer.is_ok(), "Collateral transfer failed");
+
+            assert!(
+                self.handle_emergency_withdrawal(borrower, token_id, current_time)
+                    .is_ok(),
+                "Loan bookkeeping failed"
+            );
+            Ok(())
+        }
+
+        /// Liquidates all expired loans of the given borrowers
+        /// Returns the number of loans liquidated
+        #[ink(message)]
+        pub fn sweep_expired_loans(&mut self, borrowers: Vec<AccountId>) -> u32 {
+            assert!(
+                borrowers.len() <= MAX_SWEEP_BORROWERS,
+                "Too many borrowers to sweep"
+            );
+            let current_time = self.get_current_time();
+            let mut liquidated = 0;
+            for borrower in borrowers {
+                let token_ids = match self.borrowers.get(&borrower) {
+                    Some(details) => details.loans.to_vec(),
+                    None => continue,
+                };
+                for token_id in token_ids {
+                    if self.is_loan_expired(borrower, token_id) != Ok(true) {
+                        continue;
+                    }
+                    if self
+                        .handle_liquidation(borrower, token_id, current_time)
+                        .is_ok()
+                    {
+                        liquidated += 1;
+                    }
+                }
+            }
+            liquidated
+        }
+
+        /// Allows borrower to ask for more time to repay the loan against token_id
+        /// Replaces any earlier request for the same loan
+        #[ink(message)]
+        pub fn request_loan_extension(
+            &mut self,
+            token_id: TokenId,
+            additional_days: u64,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.validate_repayment(caller, token_id)?;
+            self.pending_extensions.insert(
+                (caller, token_id),
+                PendingExtension {
+                    borrower: caller,
+                    token_id,
+                    additional_days,
+                    requested_at: self.get_current_time(),
+                },
+            );
+            self.env().emit_event(ExtensionRequested {
+                borrower: caller,
+                token_id,
+                additional_days,
+            });
+            Ok(())
+        }
+
+        /// Allows owner to approve a pending extension pushing back the loan expiry
+        /// and the lock of its collateral
+        #[ink(message)]
+        pub fn approve_extension(
+            &mut self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            let expiry = self.extend_loan(borrower, token_id)?;
+
+            // The loan has been extended: any failure from here on must revert the whole call
+            // Collateral stays locked until the new expiry
+            assert!(
+                !self.erc721.is_token_locked(token_id)
+                    || self.erc721.unlock_token(token_id).is_ok(),
+                "Collateral unlock failed"
+            );
+            assert!(
+                self.erc721.lock_token(token_id, expiry).is_ok(),
+                "Collateral lock failed"
+            );
+            self.env().emit_event(ExtensionApproved {
+                borrower,
+                token_id,
+                expiry,
+            });
+            Ok(())
+        }
+
+        /// Allows owner to reject a pending extension
+        /// The loan is left untouched
+        #[ink(message)]
+        pub fn reject_extension(
+            &mut self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.pending_extensions
+                .take(&(borrower, token_id))
+                .ok_or(Error::NoSuchExtension)?;
+            self.env()
+                .emit_event(ExtensionRejected { borrower, token_id });
+            Ok(())
+        }
+
+        /// Returns the pending extension request for the loan of borrower against token_id
+        #[ink(message)]
+        pub fn get_pending_extension(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Option<PendingExtension> {
+            self.pending_extensions.get(&(borrower, token_id)).cloned()
+        }
+
+        /// Returns whether the loan of borrower against token_id is past its expiry
+        /// Repaid loans never expire
+        #[ink(message)]
+        pub fn is_loan_expired(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<bool, Error> {
+            let loan = self
+                .loans
+                .get(&(borrower, token_id))
+                .ok_or(Error::NoSuchLoan)?;
+            Ok(loan.is_active() && self.get_current_time() > loan.expiry)
+        }
+
+        /// Allows owner to move the active loan against token_id to a new account of a borrower
+        /// who lost access
+        /// Repayers authorized by the old account are not carried over
+        #[ink(message)]
+        pub fn migrate_loan(
+            &mut self,
+            old_borrower: AccountId,
+            new_borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            let current_time = self.get_current_time();
+
+            let mut loan = *self
+                .loans
+                .get(&(old_borrower, token_id))
+                .ok_or(Error::NoSuchLoan)?;
+            if !loan.is_active() {
+                return Err(Error::LoanNotActive);
+            }
+            if self.loans.contains_key(&(new_borrower, token_id)) {
+                return Err(Error::LoanAlreadyExists);
+            }
+            let amount = loan.amount;
+
+            // Fail before changing anything if the accounting is inconsistent
+            let mut old_record = self
+                .borrowers
+                .get(&old_borrower)
+                .cloned()
+                .ok_or(Error::NoSuchLoan)?;
+            old_record.balance = old_record
+                .balance
+                .checked_sub(amount)
+                .ok_or(Error::InsufficientBalance)?;
+            old_record.loans.retain(|id| *id != token_id);
+            old_record.last_updated_at = current_time;
+            let mut new_record = self
+                .borrowers
+                .get(&new_borrower)
+                .cloned()
+                .unwrap_or_default();
+            new_record.balance = new_record
+                .balance
+                .checked_add(amount)
+                .ok_or(Error::ArithmeticOverflow)?;
+            new_record.loans.push(token_id);
+            new_record.last_updated_at = current_time;
+
+            loan.borrower_address = new_borrower;
+            self.loans.take(&(old_borrower, token_id));
+            self.loans.insert((new_borrower, token_id), loan);
+            self.loan_index.insert(loan.id, (new_borrower, token_id));
+            if old_record.loans.is_empty() {
+                self.borrowers.take(&old_borrower);
+            } else {
+                self.borrowers.insert(old_borrower, old_record);
+            }
+            self.borrowers.insert(new_borrower, new_record);
+
+            if let Some(extension) = self.pending_extensions.take(&(old_borrower, token_id)) {
+                self.pending_extensions
+                    .insert((new_borrower, token_id), extension);
+            }
+            self.allowed_repayers.take(&(old_borrower, token_id));
+
+            self.env().emit_event(LoanMigrated {
+                old_borrower,
+                new_borrower,
+                token_id,
+            });
+            Ok(())
+        }
+
+        /// Allows borrower to let repayer repay the loan against token_id on their behalf
+        #[ink(message)]
+        pub fn authorize_repayer(
+            &mut self,
+            token_id: TokenId,
+            repayer: AccountId,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.validate_repayment(caller, token_id)?;
+            self.allowed_repayers.insert((caller, token_id), repayer);
+            self.env().emit_event(RepayerAuthorized {
+                borrower: caller,
+                token_id,
+                repayer,
+            });
+            Ok(())
+        }
+
+        /// Returns the account allowed to repay the loan of borrower against token_id
+        #[ink(message)]
+        pub fn get_authorized_repayer(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Option<AccountId> {
+            self.allowed_repayers.get(&(borrower, token_id)).copied()
+        }
+
+        /// Returns principal amount borrowed by the address
+        #[ink(message)]
+        pub fn get_principal_balance_of_borrower(&self, owner: AccountId) -> Balance {
+            let borrower_opt = self.borrowers.get(&owner);
+            if borrower_opt.is_some() {
+                return borrower_opt.unwrap().balance;
+            }
+            0
+        }
+
+        /// Returns total amount borrowed including interest by the address
+        #[ink(message)]
+        pub fn get_total_balance_of_borrower(&self, owner: AccountId) -> Balance {
+            let balance = self.get_principal_balance_of_borrower(owner);
+            let debt = self.get_total_debt_of_borrower(owner);
             balance + debt
         }
 
@@ -354,13 +1216,98 @@ mod assetmanager {
             interest
         }
 
+        /// Returns collateral value over outstanding debt of the address scaled by 10_000
+        /// Collateral is valued at the current transfer rate per active loan
+        #[ink(message)]
+        pub fn get_health_factor(&self, borrower: AccountId) -> u128 {
+            let total_outstanding_debt = self.get_total_balance_of_borrower(borrower);
+            if total_outstanding_debt == 0 {
+                return u128::MAX;
+            }
+
+            let active_loans = self.count_active_loans(borrower) as u128;
+            let total_collateral_value = active_loans * self.administration.transfer_rate;
+            total_collateral_value * 10_000 / total_outstanding_debt
+        }
+
+        /// Checks if the health factor of the address is below the liquidation threshold
+        #[ink(message)]
+        pub fn is_liquidatable(&self, borrower: AccountId) -> bool {
+            self.get_health_factor(borrower) < LIQUIDATION_THRESHOLD
+        }
+
+        /// Returns balances, loan counts and health factor of borrower
+        #[ink(message)]
+        pub fn get_borrower_stats(&self, borrower: AccountId) -> BorrowerStats {
+            let principal_balance = self.get_principal_balance_of_borrower(borrower);
+            let total_balance = self.get_total_balance_of_borrower(borrower);
+            let total_loan_count = match self.borrowers.get(&borrower) {
+                Some(details) => details.loans.len() as u32,
+                None => 0,
+            };
+            BorrowerStats {
+                principal_balance,
+                total_balance,
+                accrued_interest: total_balance - principal_balance,
+                active_loan_count: self.count_active_loans(borrower),
+                total_loan_count,
+                health_factor: self.get_health_factor(borrower),
+            }
+        }
+
+        /// Returns loans with ids from start to start + count that are not repaid
+        /// along with their total balance
+        #[ink(message)]
+        pub fn get_pending_repayments(&self, start: u64, count: u64) -> Vec<PendingRepayment> {
+            let mut repayments: Vec<PendingRepayment> = Vec::new();
+            for loan_id in start..start.saturating_add(count) {
+                let (borrower, token_id) = match self.loan_index.get(&loan_id) {
+                    Some(key) => *key,
+                    None => continue,
+                };
+                let loan = match self.loans.get(&(borrower, token_id)) {
+                    Some(loan) => loan,
+                    None => continue,
+                };
+                // Skip ids whose token has since been borrowed against again
+                if loan.id != loan_id || !loan.is_active() {
+                    continue;
+                }
+                repayments.push(PendingRepayment {
+                    borrower,
+                    token_id,
+                    amount: self.get_total_balance_of_loan(borrower, token_id),
+                    expiry: loan.expiry,
+                });
+            }
+            repayments
+        }
+
+        /// Returns number of loans that are not repaid
+        #[ink(message)]
+        pub fn get_pending_repayment_count(&self) -> u64 {
+            self.pending_repayment_count
+        }
+
+        /// Returns principal amount of all loans that are not repaid
+        #[ink(message)]
+        pub fn get_total_principal_outstanding(&self) -> Balance {
+            self.total_principal_outstanding
+        }
+
+        /// Returns share of the erc20 supply lent out in basis points
+        #[ink(message)]
+        pub fn get_utilization_rate(&self) -> u64 {
+            self.calculate_utilization_rate(self.erc20.total_supply())
+        }
+
         /// Returns principal amount borrowed against by address against token_id
         #[ink(message)]
         pub fn get_principal_balance_of_loan(&self, owner: AccountId, token_id: u32) -> Balance {
             let loan_opt = self.loans.get(&(owner, token_id));
             if loan_opt.is_some() {
                 let loan = loan_opt.unwrap();
-                if !loan.is_repaid {
+                if loan.is_active() {
                     return loan.amount;
                 }
             }
@@ -384,7 +1331,7 @@ mod assetmanager {
                 return 0;
             }
             let loan = loan_opt.unwrap();
-            if loan.is_repaid {
+            if !loan.is_active() {
                 return 0;
             }
             let ct: u64 = self.env().block_timestamp(); // Gets timstamp in milliseconds
@@ -394,318 +1341,3480 @@ mod assetmanager {
             interest
         }
 
-        /// Allows owner to set interest rate
-        /// Only affects future borrowing
+        /// Returns principal plus interest the loan of borrower against token_id
+        /// will take to repay at future_timestamp, in milliseconds
+        #[ink(message)]
+        pub fn calculate_payoff_at(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+            future_timestamp: u64,
+        ) -> Result<Balance, Error> {
+            let loan = match self.loans.get(&(borrower, token_id)) {
+                None => return Err(Error::NoSuchLoan),
+                Some(loan) if loan.status == LoanStatus::Repaid as u8 => {
+                    return Err(Error::LoanAlreadyRepaid)
+                }
+                Some(loan) if !loan.is_active() => return Err(Error::LoanNotActive),
+                Some(loan) => loan,
+            };
+            if future_timestamp < loan.date_borrowed {
+                return Err(Error::InvalidTimestamp);
+            }
+            let interest = self.calculate_interest(
+                loan.amount,
+                loan.interest_rate,
+                future_timestamp,
+                loan.date_borrowed,
+            );
+            Ok(loan.amount + interest)
+        }
+
+        /// Returns calculate_payoff_at for each of the given timestamps
+        #[ink(message)]
+        pub fn calculate_payoff_schedule(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+            intervals: Vec<u64>,
+        ) -> Result<Vec<Balance>, Error> {
+            intervals
+                .into_iter()
+                .map(|timestamp| self.calculate_payoff_at(borrower, token_id, timestamp))
+                .collect()
+        }
+
+        /// Returns interest the loan of borrower against token_id will have accrued
+        /// days from now. Without an active loan, previews a new loan taken out now
+        /// at the current transfer and interest rates
+        #[ink(message)]
+        pub fn get_interest_preview(
+            &self,
+            token_id: TokenId,
+            borrower: AccountId,
+            days: u64,
+        ) -> Balance {
+            if days == 0 {
+                return self.get_total_debt_of_loan(borrower, token_id);
+            }
+            let current_time = self.get_current_time();
+            // Saturate so that far off previews can't wrap around to the past
+            let preview_time =
+                current_time.saturating_add(days.saturating_mul(MILLISECONDS_IN_DAY));
+
+            match self.loans.get(&(borrower, token_id)) {
+                Some(loan) if loan.is_active() => self.calculate_interest(
+                    loan.amount,
+                    loan.interest_rate,
+                    preview_time,
+                    loan.date_borrowed,
+                ),
+                _ => self.calculate_interest(
+                    self.administration.transfer_rate,
+                    self.get_current_borrow_rate(),
+                    preview_time,
+                    current_time,
+                ),
+            }
+        }
+
+        /// Checkpoints interest accrued by the loan of borrower against token_id
+        /// The checkpoint only moves, emitting InterestCheckpointed, once interest grew
+        /// by more than interest_checkpoint_threshold since the last checkpoint
+        /// Returns interest accrued so far
+        #[ink(message)]
+        pub fn checkpoint_interest(
+            &mut self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<Balance, Error> {
+            let loan = self
+                .loans
+                .get(&(borrower, token_id))
+                .ok_or(Error::NoSuchLoan)?;
+            if !loan.is_active() {
+                return Err(Error::LoanNotActive);
+            }
+            let accrued_interest = self.get_total_debt_of_loan(borrower, token_id);
+            let current_time = self.get_current_time();
+            self.record_interest_checkpoint(borrower, token_id, accrued_interest, current_time);
+            Ok(accrued_interest)
+        }
+
+        /// Returns the last interest checkpoint of the loan of borrower against token_id
+        #[ink(message)]
+        pub fn get_interest_checkpoint(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Option<InterestCheckpoint> {
+            self.loans
+                .get(&(borrower, token_id))
+                .filter(|loan| loan.checkpoint_interest > 0)
+                .map(|loan| InterestCheckpoint {
+                    last_checkpoint: loan.last_checkpoint,
+                    checkpoint_interest: loan.checkpoint_interest,
+                })
+        }
+
+        /// Allows owner to set how much interest has to accrue between checkpoints
         #[ink(message)]
-        pub fn set_interest_rate(&mut self, _interest_rate: u64) {
+        pub fn set_interest_checkpoint_threshold(
+            &mut self,
+            threshold: Balance,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
+            self.administration.interest_checkpoint_threshold = threshold;
+            Ok(())
+        }
+
+        /// Returns how much interest has to accrue between checkpoints
+        #[ink(message)]
+        pub fn get_interest_checkpoint_threshold(&self) -> Balance {
+            self.administration.interest_checkpoint_threshold
+        }
+
+        /// Allows admins to set interest rate
+        /// Only affects future borrowing
+        /// Takes effect once admin_threshold admins set the same rate
+        #[ink(message)]
+        pub fn set_interest_rate(&mut self, _interest_rate: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            self.ensure_not_timelocked()?;
+            let action = AdminAction::set(INTEREST_RATE_PARAMETER, _interest_rate as u128);
+            if !self.approve_admin_action(action, caller) {
+                return Ok(());
+            }
             self.env().emit_event(InterestRateChanged {
                 old_value: self.administration.interest_rate,
                 new_value: _interest_rate,
             });
             self.administration.interest_rate = _interest_rate;
+            Ok(())
         }
 
         /// Returns current yearly interest rate
+        /// Includes the utilization premium when a variable rate is set
         #[ink(message)]
         pub fn get_interest_rate(&self) -> u64 {
-            self.administration.interest_rate
+            self.get_current_borrow_rate()
         }
 
-        /// Allows owner to set transfer rate
-        /// Only affects future borrowing
+        /// Allows admins to make the interest rate grow with utilization
+        /// base_rate replaces the stored interest rate
+        /// utilization_multiplier is the rate added at 100% utilization, 0 keeps the rate fixed.
+        /// Takes effect once admin_threshold admins set the same rates
         #[ink(message)]
-        pub fn set_transfer_rate(&mut self, _transfer_rate: Balance) {
-            assert!(self.only_owner(self.env().caller()));
-            self.env().emit_event(TransferRateChanged {
-                old_value: self.administration.transfer_rate,
-                new_value: _transfer_rate,
-            });
-            self.administration.transfer_rate = _transfer_rate;
+        pub fn set_variable_interest_rate(
+            &mut self,
+            base_rate: u64,
+            utilization_multiplier: u64,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            self.ensure_not_timelocked()?;
+            let value = Self::pack_variable_rate(base_rate, utilization_multiplier);
+            let action = AdminAction::set(VARIABLE_INTEREST_RATE_PARAMETER, value);
+            if !self.approve_admin_action(action, caller) {
+                return Ok(());
+            }
+            self.apply_variable_interest_rate(base_rate, utilization_multiplier);
+            Ok(())
         }
 
-        /// Returns current transfer rate
+        /// Allows admins to propose a new variable interest rate
+        /// It can be executed once the timelock delay has passed.
+        /// Proposed once admin_threshold admins propose the same rates
         #[ink(message)]
-        pub fn get_transfer_rate(&self) -> Balance {
-            self.administration.transfer_rate
+        pub fn propose_variable_interest_rate_change(
+            &mut self,
+            base_rate: u64,
+            utilization_multiplier: u64,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            let value = Self::pack_variable_rate(base_rate, utilization_multiplier);
+            let action = AdminAction::set(VARIABLE_INTEREST_RATE_PARAMETER, value);
+            if !self.approve_admin_action(action, caller) {
+                return Ok(());
+            }
+            self.propose_change(VARIABLE_INTEREST_RATE_PARAMETER, value);
+            Ok(())
         }
 
-        /// Allows owner to enable borrowing
+        /// Allows admins to apply the proposed variable interest rate after the timelock delay
+        /// Takes effect once admin_threshold admins execute it
         #[ink(message)]
-        pub fn enable(&mut self) {
-            assert!(self.only_owner(self.env().caller()));
-            self.administration.enabled = true;
-            self.env().emit_event(Enabled {});
+        pub fn execute_variable_interest_rate_change(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            if let Some(value) = self.execute_change(VARIABLE_INTEREST_RATE_PARAMETER, caller)? {
+                let (base_rate, utilization_multiplier) = Self::unpack_variable_rate(value);
+                self.apply_variable_interest_rate(base_rate, utilization_multiplier);
+            }
+            Ok(())
         }
 
-        /// Allows owner to disable borrowing
+        /// Allows any admin to discard the proposed variable interest rate
         #[ink(message)]
-        pub fn disable(&mut self) {
-            assert!(self.only_owner(self.env().caller()));
-            self.administration.enabled = false;
-            self.env().emit_event(Disbaled {});
+        pub fn cancel_variable_interest_rate_change(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_admin(self.env().caller()));
+            self.cancel_change(VARIABLE_INTEREST_RATE_PARAMETER)
         }
 
-        /// Checks if borrowing is enabled
-        #[ink(message)]
-        pub fn is_enabled(&self) -> bool {
-            self.administration.enabled
+        fn apply_variable_interest_rate(&mut self, base_rate: u64, utilization_multiplier: u64) {
+            self.env().emit_event(InterestRateChanged {
+                old_value: self.administration.interest_rate,
+                new_value: base_rate,
+            });
+            self.administration.interest_rate = base_rate;
+            self.administration.utilization_multiplier = utilization_multiplier;
         }
 
-        fn handle_borrow(
-            &mut self,
-            borrower_address: AccountId,
-            token_id: TokenId,
+        /// Packs both rates into the single value admin actions and pending changes hold
+        fn pack_variable_rate(base_rate: u64, utilization_multiplier: u64) -> u128 {
+            (base_rate as u128) << 64 | utilization_multiplier as u128
+        }
+
+        fn unpack_variable_rate(value: u128) -> (u64, u64) {
+            ((value >> 64) as u64, value as u64)
+        }
+
+        /// Returns the rate added to the base rate at 100% utilization
+        #[ink(message)]
+        pub fn get_utilization_multiplier(&self) -> u64 {
+            self.administration.utilization_multiplier
+        }
+
+        /// Returns the yearly interest rate new loans are charged right now
+        #[ink(message)]
+        pub fn get_current_borrow_rate(&self) -> u64 {
+            if self.administration.utilization_multiplier == 0 {
+                return self.administration.interest_rate;
+            }
+            self.calculate_borrow_rate(self.get_utilization_rate())
+        }
+
+        /// Allows owner to choose between linear and compound interest
+        /// Affects interest of existing loans too
+        #[ink(message)]
+        pub fn set_interest_rate_model(&mut self, model: u8) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            assert!(
+                model == InterestModel::Linear as u8 || model == InterestModel::Compound as u8,
+                "Unknown interest rate model"
+            );
+            self.administration.interest_rate_model = model;
+            Ok(())
+        }
+
+        /// Returns current interest rate model
+        #[ink(message)]
+        pub fn get_interest_rate_model(&self) -> u8 {
+            self.administration.interest_rate_model
+        }
+
+        /// Allows owner to set the flash loan fee in basis points
+        #[ink(message)]
+        pub fn set_flash_fee_bps(&mut self, flash_fee_bps: u16) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            assert!(flash_fee_bps <= 10_000, "Flash fee cannot exceed 100%");
+            self.administration.flash_fee_bps = flash_fee_bps;
+            Ok(())
+        }
+
+        /// Returns current flash loan fee in basis points
+        #[ink(message)]
+        pub fn get_flash_fee_bps(&self) -> u16 {
+            self.administration.flash_fee_bps
+        }
+
+        /// Moves amount of erc20 from caller to erc20_owner so that new loans can be funded
+        /// Only erc20_owner can top up, moving tokens it holds elsewhere
+        /// Caller should have granted approval to assetmanager contract for amount
+        #[ink(message)]
+        pub fn top_up_erc20_reserve(&mut self, amount: Balance) -> Result<(), Error> {
+            self.with_reentrancy_guard(|manager| manager.try_top_up_erc20_reserve(amount))
+        }
+
+        fn try_top_up_erc20_reserve(&mut self, amount: Balance) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+            let erc20_owner = self.address_manager.erc20_owner;
+            if caller != erc20_owner {
+                return Err(Error::NotAuthorized);
+            }
+
+            Self::check_allowance_sufficient(&self.erc20, caller, contract_address, amount)?;
+
+            if self
+                .erc20
+                .transfer_from(caller, erc20_owner, amount)
+                .is_err()
+            {
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            self.env().emit_event(ReserveTopUp { by: caller, amount });
+            Ok(())
+        }
+
+        /// Returns erc20 balance of erc20_owner available to fund new loans
+        #[ink(message)]
+        pub fn get_reserve_balance(&self) -> Balance {
+            self.erc20.balance_of(self.address_manager.erc20_owner)
+        }
+
+        /// Lends amount of erc20 held by the contract to receiver for the duration of the call
+        /// receiver must implement `on_flash_loan` and pay back amount plus fee before returning
+        /// The fee is forwarded to erc20_owner
+        /// Panics if the loan is not repaid so that the transfer to receiver is reverted
+        #[ink(message)]
+        pub fn flash_loan(
+            &mut self,
+            amount: Balance,
+            receiver: AccountId,
+            data: Vec<u8>,
+        ) -> Result<(), Error> {
+            self.with_reentrancy_guard(|manager| manager.try_flash_loan(amount, receiver, data))
+        }
+
+        fn try_flash_loan(
+            &mut self,
+            amount: Balance,
+            receiver: AccountId,
+            data: Vec<u8>,
+        ) -> Result<(), Error> {
+            let this = self.env().account_id();
+            let fee = self.calculate_flash_fee(amount);
+            let balance_before = self.erc20.balance_of(this);
+            if balance_before < amount {
+                return Err(Error::InsufficientBalance);
+            }
+
+            if self.erc20.transfer(receiver, amount).is_err() {
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            // Everything past the transfer to receiver must revert on failure
+            let callback = build_call::<ink_env::DefaultEnvironment>()
+                .callee(receiver)
+                .gas_limit(0)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(FLASH_LOAN_RECEIVER_SELECTOR))
+                        .push_arg(amount)
+                        .push_arg(fee)
+                        .push_arg(data),
+                )
+                .returns::<ReturnType<bool>>()
+                .fire();
+            let balance_after = self.erc20.balance_of(this);
+            Self::ensure_flash_loan_repaid(
+                callback == Ok(true),
+                balance_before,
+                balance_after,
+                fee,
+            );
+
+            if fee > 0 {
+                let erc20_owner = self.address_manager.erc20_owner;
+                assert!(
+                    self.erc20.transfer(erc20_owner, fee).is_ok(),
+                    "Flash fee transfer failed"
+                );
+            }
+
+            self.env().emit_event(FlashLoan {
+                receiver,
+                amount,
+                fee,
+            });
+            Ok(())
+        }
+
+        /// Allows owner to set the maximum duration of loans in milliseconds
+        /// Only affects future borrowing
+        #[ink(message)]
+        pub fn set_max_loan_duration(&mut self, duration: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.env().emit_event(MaxLoanDurationChanged {
+                old: self.administration.max_loan_duration,
+                new: duration,
+            });
+            self.administration.max_loan_duration = duration;
+            Ok(())
+        }
+
+        /// Returns maximum duration of loans in milliseconds
+        #[ink(message)]
+        pub fn get_max_loan_duration(&self) -> u64 {
+            self.administration.max_loan_duration
+        }
+
+        /// Allows owner to reject deposits lending less than min
+        #[ink(message)]
+        pub fn set_minimum_deposit_amount(&mut self, min: Balance) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.env().emit_event(MinimumDepositChanged {
+                old: self.administration.minimum_deposit_amount,
+                new: min,
+            });
+            self.administration.minimum_deposit_amount = min;
+            Ok(())
+        }
+
+        /// Returns the smallest amount a deposit may lend
+        #[ink(message)]
+        pub fn get_minimum_deposit_amount(&self) -> Balance {
+            self.administration.minimum_deposit_amount
+        }
+
+        /// Allows owner to cap the total principal borrowed against tokens of nft_address
+        #[ink(message)]
+        pub fn set_collection_borrow_limit(
+            &mut self,
+            nft_address: AccountId,
+            limit: Balance,
+        ) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.collection_borrow_limits.insert(nft_address, limit);
+            Ok(())
+        }
+
+        /// Allows owner to cap the total principal a single borrower may owe
+        #[ink(message)]
+        pub fn set_max_borrow_per_borrower(&mut self, limit: Balance) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.max_borrow_per_borrower = limit;
+            Ok(())
+        }
+
+        /// Returns the principal a single borrower may owe, Balance::MAX if unlimited
+        #[ink(message)]
+        pub fn get_max_borrow_per_borrower(&self) -> Balance {
+            self.administration.max_borrow_per_borrower
+        }
+
+        /// Allows owner to cap the number of loans taken out within a single block
+        #[ink(message)]
+        pub fn set_max_borrows_per_block(&mut self, limit: u32) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.max_borrows_per_block = limit;
+            Ok(())
+        }
+
+        /// Returns the number of loans that may be taken out within a single block
+        #[ink(message)]
+        pub fn get_max_borrows_per_block(&self) -> u32 {
+            self.administration.max_borrows_per_block
+        }
+
+        /// Returns the number of loans taken out in block
+        #[ink(message)]
+        pub fn get_borrows_in_block(&self, block: u32) -> u32 {
+            *self.borrows_this_block.get(&block).unwrap_or(&0)
+        }
+
+        /// Returns how much more principal borrower may take out
+        /// Without a borrower limit the erc20 reserve is the practical limit
+        #[ink(message)]
+        pub fn get_borrow_capacity(&self, borrower: AccountId) -> Balance {
+            let limit = self.administration.max_borrow_per_borrower;
+            if limit == Balance::MAX {
+                return self.erc20.balance_of(self.address_manager.erc20_owner);
+            }
+            limit.saturating_sub(self.get_principal_balance_of_borrower(borrower))
+        }
+
+        /// Checks if borrower can take out a loan at the current transfer rate
+        #[ink(message)]
+        pub fn can_borrow(&self, borrower: AccountId) -> bool {
+            self.is_enabled()
+                && self.get_borrow_capacity(borrower) > 0
+                && self.erc20.balance_of(self.address_manager.erc20_owner)
+                    >= self.administration.transfer_rate
+        }
+
+        /// Returns principal borrowed against tokens of nft_address and its limit
+        /// Collections without a limit report Balance::MAX
+        #[ink(message)]
+        pub fn get_collection_utilization(&self, nft_address: AccountId) -> (Balance, Balance) {
+            let borrowed = self
+                .collection_borrowed
+                .get(&nft_address)
+                .copied()
+                .unwrap_or(0);
+            let limit = self
+                .collection_borrow_limits
+                .get(&nft_address)
+                .copied()
+                .unwrap_or(Balance::MAX);
+            (borrowed, limit)
+        }
+
+        /// Allows admins to set transfer rate
+        /// Only affects future borrowing
+        /// Takes effect once admin_threshold admins set the same rate
+        #[ink(message)]
+        pub fn set_transfer_rate(&mut self, _transfer_rate: Balance) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            self.ensure_not_timelocked()?;
+            let action = AdminAction::set(TRANSFER_RATE_PARAMETER, _transfer_rate);
+            if !self.approve_admin_action(action, caller) {
+                return Ok(());
+            }
+            self.env().emit_event(TransferRateChanged {
+                old_value: self.administration.transfer_rate,
+                new_value: _transfer_rate,
+            });
+            self.administration.transfer_rate = _transfer_rate;
+            Ok(())
+        }
+
+        /// Returns current transfer rate
+        #[ink(message)]
+        pub fn get_transfer_rate(&self) -> Balance {
+            self.administration.transfer_rate
+        }
+
+        /// Allows admins to set the delay in milliseconds before proposed changes can be executed
+        /// Direct rate changes are only allowed while the delay is zero.
+        /// Increases apply at once, decreases are proposed behind the current delay.
+        /// Takes effect once admin_threshold admins set the same delay
+        #[ink(message)]
+        pub fn set_timelock_delay(&mut self, timelock_delay: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            let action = AdminAction::set(TIMELOCK_DELAY_PARAMETER, timelock_delay as u128);
+            if !self.approve_admin_action(action, caller) {
+                return Ok(());
+            }
+            if timelock_delay < self.administration.timelock_delay {
+                self.propose_change(TIMELOCK_DELAY_PARAMETER, timelock_delay as u128);
+                return Ok(());
+            }
+            self.pending_changes.take(&TIMELOCK_DELAY_PARAMETER);
+            self.administration.timelock_delay = timelock_delay;
+            Ok(())
+        }
+
+        /// Allows admins to apply a proposed timelock delay decrease after the current delay
+        /// Takes effect once admin_threshold admins execute it
+        #[ink(message)]
+        pub fn execute_timelock_delay_change(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            if let Some(value) = self.execute_change(TIMELOCK_DELAY_PARAMETER, caller)? {
+                self.administration.timelock_delay = value as u64;
+            }
+            Ok(())
+        }
+
+        /// Returns current timelock delay in milliseconds
+        #[ink(message)]
+        pub fn get_timelock_delay(&self) -> u64 {
+            self.administration.timelock_delay
+        }
+
+        /// Returns the proposed value and earliest execution time of a pending change
+        #[ink(message)]
+        pub fn get_pending_change(&self, parameter: u8) -> Option<(u128, u64)> {
+            self.pending_changes.get(&parameter).cloned()
+        }
+
+        /// Allows admins to propose a new interest rate
+        /// It can be executed once the timelock delay has passed.
+        /// Proposed once admin_threshold admins propose the same rate
+        #[ink(message)]
+        pub fn propose_interest_rate_change(&mut self, new_rate: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            let action = AdminAction::set(INTEREST_RATE_PARAMETER, new_rate as u128);
+            if !self.approve_admin_action(action, caller) {
+                return Ok(());
+            }
+            self.propose_change(INTEREST_RATE_PARAMETER, new_rate as u128);
+            Ok(())
+        }
+
+        /// Allows admins to apply the proposed interest rate after the timelock delay
+        /// Takes effect once admin_threshold admins execute it
+        #[ink(message)]
+        pub fn execute_interest_rate_change(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            if let Some(new_rate) = self.execute_change(INTEREST_RATE_PARAMETER, caller)? {
+                let new_rate = new_rate as u64;
+                self.env().emit_event(InterestRateChanged {
+                    old_value: self.administration.interest_rate,
+                    new_value: new_rate,
+                });
+                self.administration.interest_rate = new_rate;
+            }
+            Ok(())
+        }
+
+        /// Allows any admin to discard the proposed interest rate
+        #[ink(message)]
+        pub fn cancel_interest_rate_change(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_admin(self.env().caller()));
+            self.cancel_change(INTEREST_RATE_PARAMETER)
+        }
+
+        /// Allows admins to propose a new transfer rate
+        /// It can be executed once the timelock delay has passed.
+        /// Proposed once admin_threshold admins propose the same rate
+        #[ink(message)]
+        pub fn propose_transfer_rate_change(&mut self, new_rate: Balance) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            let action = AdminAction::set(TRANSFER_RATE_PARAMETER, new_rate);
+            if !self.approve_admin_action(action, caller) {
+                return Ok(());
+            }
+            self.propose_change(TRANSFER_RATE_PARAMETER, new_rate);
+            Ok(())
+        }
+
+        /// Allows admins to apply the proposed transfer rate after the timelock delay
+        /// Takes effect once admin_threshold admins execute it
+        #[ink(message)]
+        pub fn execute_transfer_rate_change(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            if let Some(new_rate) = self.execute_change(TRANSFER_RATE_PARAMETER, caller)? {
+                let new_rate = new_rate;
+                self.env().emit_event(TransferRateChanged {
+                    old_value: self.administration.transfer_rate,
+                    new_value: new_rate,
+                });
+                self.administration.transfer_rate = new_rate;
+            }
+            Ok(())
+        }
+
+        /// Allows any admin to discard the proposed transfer rate
+        #[ink(message)]
+        pub fn cancel_transfer_rate_change(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_admin(self.env().caller()));
+            self.cancel_change(TRANSFER_RATE_PARAMETER)
+        }
+
+        /// Allows admins to enable borrowing
+        /// Takes effect once admin_threshold admins call it
+        #[ink(message)]
+        pub fn enable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            if !self.approve_admin_action(AdminAction::set(ENABLED_PARAMETER, 1), caller) {
+                return Ok(());
+            }
+            self.administration.enabled = true;
+            self.env().emit_event(Enabled {});
+            Ok(())
+        }
+
+        /// Allows admins to disable borrowing
+        /// Takes effect once admin_threshold admins call it
+        #[ink(message)]
+        pub fn disable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_admin(caller));
+            if !self.approve_admin_action(AdminAction::set(ENABLED_PARAMETER, 0), caller) {
+                return Ok(());
+            }
+            self.administration.enabled = false;
+            self.env().emit_event(Disbaled {});
+            Ok(())
+        }
+
+        /// Checks if borrowing is enabled
+        #[ink(message)]
+        pub fn is_enabled(&self) -> bool {
+            self.administration.enabled
+        }
+
+        /// Returns interest rate, transfer rate and whether borrowing is enabled
+        #[ink(message)]
+        pub fn get_all_config(&self) -> AdminConfig {
+            AdminConfig {
+                interest_rate: self.administration.interest_rate,
+                transfer_rate: self.administration.transfer_rate,
+                enabled: self.administration.enabled,
+            }
+        }
+
+        /// Returns loan counts, balances, rates and configuration of the protocol
+        #[ink(message)]
+        pub fn get_protocol_snapshot(&self) -> ProtocolSnapshot {
+            self.build_protocol_snapshot(self.erc20.total_supply(), self.get_reserve_balance())
+        }
+
+        /// Allows owner to add an admin
+        #[ink(message)]
+        pub fn add_admin(&mut self, addr: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.admins.insert(addr, true);
+            self.env().emit_event(AdminAdded { admin: addr });
+            Ok(())
+        }
+
+        /// Allows owner to remove an admin
+        /// Approvals of the admin on pending actions are discarded
+        #[ink(message)]
+        pub fn remove_admin(&mut self, addr: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            assert!(self.admins.contains_key(&addr), "Not an admin");
+            assert!(
+                self.admins.len() > self.administration.admin_threshold as u32,
+                "Not enough admins left for threshold"
+            );
+            self.admins.take(&addr);
+            self.discard_admin_approvals(addr);
+            self.env().emit_event(AdminRemoved { admin: addr });
+            Ok(())
+        }
+
+        /// Allows owner to set the number of admin approvals admin actions need
+        #[ink(message)]
+        pub fn set_admin_threshold(&mut self, n: u8) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            assert!(n > 0, "Threshold must be positive");
+            assert!(
+                n as u32 <= self.admins.len(),
+                "Threshold exceeds number of admins"
+            );
+            self.env().emit_event(AdminThresholdChanged {
+                old: self.administration.admin_threshold,
+                new: n,
+            });
+            self.administration.admin_threshold = n;
+            Ok(())
+        }
+
+        /// Returns number of admin approvals admin actions need
+        #[ink(message)]
+        pub fn get_admin_threshold(&self) -> u8 {
+            self.administration.admin_threshold
+        }
+
+        /// Checks if addr is an admin
+        #[ink(message)]
+        pub fn is_admin(&self, addr: AccountId) -> bool {
+            self.admins.contains_key(&addr)
+        }
+
+        /// Returns the admins who approved action so far
+        #[ink(message)]
+        pub fn get_pending_admin_action(&self, action: AdminAction) -> Option<Vec<AccountId>> {
+            self.pending_admin_actions.get(&action).cloned()
+        }
+
+        fn handle_borrow(
+            &mut self,
+            borrower_address: AccountId,
+            token_id: TokenId,
             interest_rate: u64,
             transfer_rate: Balance,
             time: u64,
         ) -> Result<(), Error> {
+            let mut balance = Balance::from(transfer_rate);
+            self.check_block_borrow_limit()?;
+            self.check_borrower_borrow_limit(borrower_address, balance)?;
+            let erc721_address = self.address_manager.erc721_address;
+            let collection_borrowed =
+                self.check_collection_borrow_limit(erc721_address, balance)?;
+
             let borrower_opt = self.borrowers.get(&borrower_address);
             // assert_eq!(borrower_opt.is_some(), false, "Has already borrowed");
+            let total_principal_outstanding = self
+                .total_principal_outstanding
+                .checked_add(balance)
+                .ok_or(Error::ArithmeticOverflow)?;
+            let expiry = time
+                .checked_add(self.administration.max_loan_duration)
+                .ok_or(Error::ArithmeticOverflow)?;
+            if let Some(borrower) = borrower_opt {
+                balance = balance
+                    .checked_add(borrower.balance)
+                    .ok_or(Error::ArithmeticOverflow)?;
+            }
 
-            let mut balance = Balance::from(transfer_rate);
-
+            self.collection_borrowed
+                .insert(erc721_address, collection_borrowed);
             self.total_loans += 1;
+            self.total_principal_outstanding = total_principal_outstanding;
             let loan = Loan {
                 id: self.total_loans,
-                amount: balance,
+                amount: transfer_rate,
                 interest_rate: interest_rate,
                 transfer_rate: transfer_rate,
                 date_borrowed: time,
-                date_repaid: None,
-                is_repaid: false,
+                closed_at: None,
+                status: LoanStatus::Active as u8,
+                expiry,
+                last_checkpoint: 0,
+                checkpoint_interest: 0,
+                borrower_address,
             };
 
-            self.loans.insert((borrower_address, token_id), loan);
+            self.loans.insert((borrower_address, token_id), loan);
+            self.loan_index
+                .insert(self.total_loans, (borrower_address, token_id));
+            self.pending_repayment_count += 1;
+
+            let mut loans: Vec<TokenId> = Vec::new();
+            if let Some(borrower) = self.borrowers.get(&borrower_address) {
+                loans = borrower.loans.to_vec();
+            }
+            loans.push(token_id);
+
+            self.borrowers.insert(
+                borrower_address,
+                Borrower {
+                    balance: balance,
+                    last_updated_at: time,
+                    loans: loans,
+                },
+            );
+            *self
+                .borrows_this_block
+                .entry(self.env().block_number())
+                .or_insert(0) += 1;
+
+            Ok(())
+        }
+
+        fn is_authorized_repayer(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+            repayer: AccountId,
+        ) -> bool {
+            repayer == borrower
+                || self.only_owner(repayer)
+                || self.get_authorized_repayer(borrower, token_id) == Some(repayer)
+        }
+
+        /// Moves the expiry of the loan by the pending extension and returns the new expiry
+        fn extend_loan(&mut self, borrower: AccountId, token_id: TokenId) -> Result<u64, Error> {
+            let extension = *self
+                .pending_extensions
+                .get(&(borrower, token_id))
+                .ok_or(Error::NoSuchExtension)?;
+            self.validate_repayment(borrower, token_id)?;
+
+            let loan = self.loans.get_mut(&(borrower, token_id)).unwrap();
+            let expiry = extension
+                .additional_days
+                .checked_mul(MILLISECONDS_IN_DAY)
+                .and_then(|extension| loan.expiry.checked_add(extension))
+                .ok_or(Error::ArithmeticOverflow)?;
+            loan.expiry = expiry;
+            self.pending_extensions.take(&(borrower, token_id));
+            Ok(expiry)
+        }
+
+        fn validate_repayment(
+            &self,
+            borrower_address: AccountId,
+            token_id: TokenId,
+        ) -> Result<(), Error> {
+            if self.borrowers.get(&borrower_address).is_none() {
+                return Err(Error::NoSuchLoan);
+            }
+            match self.loans.get(&(borrower_address, token_id)) {
+                None => Err(Error::NoSuchLoan),
+                Some(loan) if loan.status == LoanStatus::Repaid as u8 => {
+                    Err(Error::LoanAlreadyRepaid)
+                }
+                Some(loan) if !loan.is_active() => Err(Error::LoanNotActive),
+                Some(_) => Ok(()),
+            }
+        }
+
+        fn handle_repayment(
+            &mut self,
+            borrower_address: AccountId,
+            token_id: TokenId,
+            time: u64,
+        ) -> Result<(), Error> {
+            self.close_loan(borrower_address, token_id, time, LoanStatus::Repaid)
+        }
+
+        /// Closes an active loan with the given status and releases its principal
+        fn close_loan(
+            &mut self,
+            borrower_address: AccountId,
+            token_id: TokenId,
+            time: u64,
+            status: LoanStatus,
+        ) -> Result<(), Error> {
+            self.validate_repayment(borrower_address, token_id)?;
+            let amount = self
+                .loans
+                .get(&(borrower_address, token_id))
+                .unwrap()
+                .amount;
+            let erc721_address = self.address_manager.erc721_address;
+
+            // Fail before changing anything if the accounting is inconsistent
+            let borrower_balance = self
+                .borrowers
+                .get(&borrower_address)
+                .unwrap()
+                .balance
+                .checked_sub(amount)
+                .ok_or(Error::InsufficientBalance)?;
+            let total_principal_outstanding = self
+                .total_principal_outstanding
+                .checked_sub(amount)
+                .ok_or(Error::InsufficientBalance)?;
+            let pending_repayment_count = self
+                .pending_repayment_count
+                .checked_sub(1)
+                .ok_or(Error::ArithmeticOverflow)?;
+            let collection_borrowed = match self.collection_borrowed.get(&erc721_address) {
+                Some(borrowed) => Some(
+                    borrowed
+                        .checked_sub(amount)
+                        .ok_or(Error::InsufficientBalance)?,
+                ),
+                None => None,
+            };
+
+            let loan = self.loans.get_mut(&(borrower_address, token_id)).unwrap();
+            loan.status = status as u8;
+            loan.closed_at = Some(time);
+            self.allowed_repayers.take(&(borrower_address, token_id));
+
+            let borrower = self.borrowers.get_mut(&borrower_address).unwrap();
+            borrower.balance = borrower_balance;
+            borrower.last_updated_at = time;
+            self.total_principal_outstanding = total_principal_outstanding;
+            self.pending_repayment_count = pending_repayment_count;
+            if let Some(collection_borrowed) = collection_borrowed {
+                self.collection_borrowed
+                    .insert(erc721_address, collection_borrowed);
+            }
+
+            Ok(())
+        }
+
+        fn handle_liquidation(
+            &mut self,
+            borrower_address: AccountId,
+            token_id: TokenId,
+            time: u64,
+        ) -> Result<(), Error> {
+            let amount = self.get_principal_balance_of_loan(borrower_address, token_id);
+            self.close_loan(borrower_address, token_id, time, LoanStatus::Liquidated)?;
+            self.env().emit_event(Liquidated {
+                borrower: borrower_address,
+                token_id,
+                amount,
+            });
+            Ok(())
+        }
+
+        fn handle_emergency_withdrawal(
+            &mut self,
+            borrower_address: AccountId,
+            token_id: TokenId,
+            time: u64,
+        ) -> Result<(), Error> {
+            self.close_loan(borrower_address, token_id, time, LoanStatus::Liquidated)?;
+            self.env().emit_event(EmergencyWithdrawal {
+                borrower: borrower_address,
+                token_id,
+                emergency_address: self.address_manager.emergency_address,
+            });
+            Ok(())
+        }
+
+        /// Returns number of whole days since the loan of borrower against token_id was taken
+        #[ink(message)]
+        pub fn calculate_days_since_borrowed(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<u64, Error> {
+            Ok(self.get_loan_age_in_seconds(borrower, token_id)? / SECONDS_IN_DAY)
+        }
+
+        /// Returns number of seconds since the loan of borrower against token_id was taken
+        #[ink(message)]
+        pub fn get_loan_age_in_seconds(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<u64, Error> {
+            let loan = self
+                .loans
+                .get(&(borrower, token_id))
+                .ok_or(Error::NoSuchLoan)?;
+            if !loan.is_active() {
+                return Err(Error::LoanAlreadyRepaid);
+            }
+            Ok(Self::seconds_between(
+                loan.date_borrowed,
+                self.get_current_time(),
+            ))
+        }
+
+        #[ink(message)]
+        pub fn get_debt_details(
+            &self,
+            borrower: AccountId,
+            token_id: TokenId,
+        ) -> Result<Loan, Error> {
+            let loan = self.loans.get(&(borrower, token_id));
+            if !loan.is_some() {
+                return Err(Error::NoSuchLoan);
+            }
+
+            Ok(*loan.clone().unwrap())
+        }
+
+        /// Moves the checkpoint and returns true if accrued_interest exceeds
+        /// the checkpointed interest by more than the threshold
+        fn record_interest_checkpoint(
+            &mut self,
+            borrower: AccountId,
+            token_id: TokenId,
+            accrued_interest: Balance,
+            time: u64,
+        ) -> bool {
+            let threshold = self.administration.interest_checkpoint_threshold;
+            let loan = match self.loans.get_mut(&(borrower, token_id)) {
+                Some(loan) => loan,
+                None => return false,
+            };
+            if accrued_interest.saturating_sub(loan.checkpoint_interest) <= threshold {
+                return false;
+            }
+
+            loan.last_checkpoint = time;
+            loan.checkpoint_interest = accrued_interest;
+            self.env().emit_event(InterestCheckpointed {
+                borrower,
+                token_id,
+                accrued_interest,
+                timestamp: time,
+            });
+            true
+        }
+
+        /// Checks that spender may transfer amount on behalf of owner
+        /// so that transfers fail with a clear error before anything else happens
+        fn check_allowance_sufficient(
+            erc20: &Erc20,
+            owner: AccountId,
+            spender: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            if erc20.allowance(owner, spender) < amount {
+                return Err(Error::InsufficientBalance);
+            }
+            Ok(())
+        }
+
+        fn calculate_interest(
+            &self,
+            amount: u128,
+            interest_rate: u64,
+            current_timestamp: u64,
+            date_borrowed: u64,
+        ) -> Balance {
+            let difference_in_secs: u128 =
+                ((current_timestamp - date_borrowed) as u128 / 1000_u128).into(); // Total time elapsed in seconds
+            let secs_in_day: u128 = 24 * 60 * 60;
+            let difference_in_days: u128 = difference_in_secs / secs_in_day;
+            let mut days_since_borrowed = difference_in_days;
+            if difference_in_secs.saturating_sub(difference_in_days * days_since_borrowed) > 0 {
+                days_since_borrowed = days_since_borrowed + 1;
+            }
+
+            if self.administration.interest_rate_model == InterestModel::Linear as u8 {
+                return amount * interest_rate as u128 * days_since_borrowed / (365 * 100);
+            }
+
+            let mut s = 0;
+            let mut n = 1;
+            let mut b = 1;
+            let q: u128 = 365 * 100 / interest_rate as u128;
+
+            // let mut p = 8_u32;
+            // if p < days_since_borrowed as u32 {
+            //     p = days_since_borrowed as u32;
+            // }
+            for x in 0..8 {
+                s = s + amount * n / b / (q.pow(x));
+                if days_since_borrowed < x.into() {
+                    break;
+                }
+                n = n * (days_since_borrowed - x as u128);
+                b = b * (x as u128 + 1);
+            }
+            s - amount
+        }
+
+        /// Returns amount borrowed against nft_address after borrowing amount
+        /// or an error if that exceeds the collection limit
+        fn check_collection_borrow_limit(
+            &self,
+            nft_address: AccountId,
+            amount: Balance,
+        ) -> Result<Balance, Error> {
+            let borrowed = self
+                .collection_borrowed
+                .get(&nft_address)
+                .copied()
+                .unwrap_or(0)
+                + amount;
+            match self.collection_borrow_limits.get(&nft_address) {
+                Some(limit) if borrowed > *limit => Err(Error::CollectionBorrowLimitExceeded),
+                _ => Ok(borrowed),
+            }
+        }
+
+        fn check_borrower_borrow_limit(
+            &self,
+            borrower: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            let borrowed = self.get_principal_balance_of_borrower(borrower);
+            if borrowed.saturating_add(amount) > self.administration.max_borrow_per_borrower {
+                return Err(Error::BorrowerBorrowLimitExceeded);
+            }
+            Ok(())
+        }
+
+        fn check_minimum_deposit(&self, amount: Balance) -> Result<(), Error> {
+            if amount < self.administration.minimum_deposit_amount {
+                return Err(Error::AmountBelowMinimum);
+            }
+            Ok(())
+        }
+
+        fn check_block_borrow_limit(&self) -> Result<(), Error> {
+            let borrows = self.get_borrows_in_block(self.env().block_number());
+            if borrows >= self.administration.max_borrows_per_block {
+                return Err(Error::RateLimitExceeded);
+            }
+            Ok(())
+        }
+
+        fn count_active_loans(&self, borrower: AccountId) -> u32 {
+            match self.borrowers.get(&borrower) {
+                Some(details) => details
+                    .loans
+                    .iter()
+                    .filter(|token_id| {
+                        self.loans
+                            .get(&(borrower, **token_id))
+                            .map_or(false, |loan| loan.is_active())
+                    })
+                    .count() as u32,
+                None => 0,
+            }
+        }
+
+        /// Panics unless receiver acknowledged the loan and the balance grew back by at least fee
+        fn ensure_flash_loan_repaid(
+            acknowledged: bool,
+            balance_before: Balance,
+            balance_after: Balance,
+            fee: Balance,
+        ) {
+            assert!(
+                acknowledged && balance_after >= balance_before + fee,
+                "Flash loan not repaid"
+            );
+        }
+
+        fn calculate_flash_fee(&self, amount: Balance) -> Balance {
+            amount * self.administration.flash_fee_bps as Balance / 10_000
+        }
+
+        fn build_protocol_snapshot(
+            &self,
+            total_supply: Balance,
+            reserve_balance: Balance,
+        ) -> ProtocolSnapshot {
+            let utilization_rate = self.calculate_utilization_rate(total_supply);
+            let total_interest_accrued = self
+                .loans
+                .iter()
+                .filter(|(_, loan)| loan.is_active())
+                .map(|((borrower, token_id), _)| self.get_total_debt_of_loan(*borrower, *token_id))
+                .sum();
+            ProtocolSnapshot {
+                total_loans: self.total_loans,
+                total_active_loans: self.pending_repayment_count,
+                total_principal_outstanding: self.total_principal_outstanding,
+                total_interest_accrued,
+                erc20_reserve_balance: reserve_balance,
+                utilization_rate,
+                current_interest_rate: self.calculate_borrow_rate(utilization_rate),
+                transfer_rate: self.administration.transfer_rate,
+                enabled: self.administration.enabled,
+                total_borrowers: self.borrowers.len(),
+            }
+        }
+
+        fn calculate_borrow_rate(&self, utilization_rate: u64) -> u64 {
+            let premium = self.administration.utilization_multiplier as u128
+                * utilization_rate as u128
+                / 10_000;
+            self.administration
+                .interest_rate
+                .saturating_add(premium as u64)
+        }
+
+        fn calculate_utilization_rate(&self, total_supply: Balance) -> u64 {
+            if total_supply == 0 {
+                return 0;
+            }
+            (self.total_principal_outstanding * 10_000 / total_supply) as u64
+        }
+
+        fn get_current_time(&self) -> u64 {
+            self.env().block_timestamp()
+        }
+
+        fn seconds_between(start: u64, end: u64) -> u64 {
+            end.saturating_sub(start) / 1000
+        }
+
+        fn propose_change(&mut self, parameter: u8, value: u128) {
+            let executable_at = self.get_current_time() + self.administration.timelock_delay;
+            self.pending_changes
+                .insert(parameter, (value, executable_at));
+            self.env().emit_event(ChangeProposed {
+                parameter,
+                value,
+                executable_at,
+            });
+        }
+
+        /// Records approval of admin for executing the pending change of parameter
+        /// Returns the value once enough admins approved and the change should be applied
+        fn execute_change(
+            &mut self,
+            parameter: u8,
+            admin: AccountId,
+        ) -> Result<Option<u128>, Error> {
+            let (value, executable_at) = *self
+                .pending_changes
+                .get(&parameter)
+                .ok_or(Error::NoPendingChange)?;
+            if self.get_current_time() < executable_at {
+                return Err(Error::TimelockNotExpired);
+            }
+            if !self.approve_admin_action(AdminAction::set(parameter, value), admin) {
+                return Ok(None);
+            }
+            self.pending_changes.take(&parameter);
+            self.env().emit_event(ChangeExecuted { parameter, value });
+            Ok(Some(value))
+        }
+
+        fn cancel_change(&mut self, parameter: u8) -> Result<(), Error> {
+            self.pending_changes
+                .take(&parameter)
+                .ok_or(Error::NoPendingChange)?;
+            self.clear_admin_actions(parameter);
+            self.env().emit_event(ChangeCancelled { parameter });
+            Ok(())
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
+    /// The below code is technically just normal Rust code.
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_lang as ink;
+
+        type Event = <AssetManager as ::ink_lang::BaseEvent>::Type;
+
+        /// We test if the constructor does its job.
+        fn instantiate_erc20_contract() -> AccountId {
+            let erc20 = Erc20::new(1000000);
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            callee
+        }
+        fn set_caller(caller: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                caller,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+        }
+
+        fn instantiate_erc721_contract() -> AccountId {
+            let erc20 = Erc721::new();
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            callee
+        }
+        #[ink::test]
+        fn new_works() {
+            let assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.get_interest_rate(), 10);
+            assert_eq!(assetmanager.get_transfer_rate(), 1000);
+            assert_eq!(
+                assetmanager.get_all_config(),
+                AdminConfig {
+                    interest_rate: 10,
+                    transfer_rate: 1000,
+                    enabled: true,
+                }
+            );
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Reentrant call")]
+        fn reentrancy_guard_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let owner = AccountId::from([0x01; 32]);
+            // Simulate a call made while another guarded call is in progress
+            assetmanager.locked = true;
+            let _ = assetmanager.deposit(1, owner);
+        }
+
+        #[ink::test]
+        fn non_erc20_owner_top_up_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            assert_eq!(
+                assetmanager.set_erc20_owner(AccountId::from([0x02; 32])),
+                Ok(())
+            );
+
+            assert_eq!(
+                assetmanager.top_up_erc20_reserve(1000),
+                Err(Error::NotAuthorized)
+            );
+            assert_eq!(assetmanager.locked, false);
+            assert_eq!(ink_env::test::recorded_events().count(), 0);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Reentrant call")]
+        fn reentrant_top_up_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            assetmanager.locked = true;
+            let _ = assetmanager.top_up_erc20_reserve(1000);
+        }
+
+        #[ink::test]
+        fn reentrancy_guard_released_on_error() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                false,
+            );
+            let owner = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.deposit(1, owner), Err(Error::NotEnabled));
+            assert_eq!(assetmanager.locked, false);
+            assert_eq!(assetmanager.withdraw(1, owner), Err(Error::NoSuchLoan));
+            assert_eq!(assetmanager.locked, false);
+            assert_eq!(
+                assetmanager.emergency_withdraw_nft(owner, 1),
+                Err(Error::NoSuchLoan)
+            );
+            assert_eq!(assetmanager.locked, false);
+
+            // The contract stays usable after a failed call
+            assert_eq!(assetmanager.enable(), Ok(()));
+            assert_eq!(assetmanager.withdraw(1, owner), Err(Error::NoSuchLoan));
+        }
+
+        #[ink::test]
+        fn contract_version_works() {
+            let assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            assert_eq!(assetmanager.get_contract_version(), VERSION);
+            assert_eq!(assetmanager.get_contract_version(), (1, 0, 0));
+            assert_eq!(
+                assetmanager.get_contract_name(),
+                String::from("AssetManager")
+            );
+        }
+
+        #[ink::test]
+        fn enable_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                false,
+            );
+            assert_eq!(assetmanager.is_enabled(), false);
+            assert_eq!(assetmanager.get_interest_rate(), 7);
+            assert_eq!(assetmanager.get_transfer_rate(), 100);
+
+            assert_eq!(assetmanager.enable(), Ok(()));
+            assert_eq!(assetmanager.is_enabled(), true);
+        }
+
+        #[ink::test]
+        fn disable_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.get_interest_rate(), 7);
+            assert_eq!(assetmanager.get_transfer_rate(), 100);
+
+            assert_eq!(assetmanager.disable(), Ok(()));
+            assert_eq!(assetmanager.is_enabled(), false);
+        }
+
+        #[ink::test]
+        fn set_interest_rate_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+
+            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.get_interest_rate(), 7);
+            assert_eq!(assetmanager.get_transfer_rate(), 100);
+
+            assert_eq!(assetmanager.set_interest_rate(8), Ok(()));
+            assert_eq!(assetmanager.get_interest_rate(), 8);
+        }
+
+        #[ink::test]
+        fn admin_approval_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                20,
+                15,
+                true,
+            );
+            let alice = AccountId::from([0x01; 32]);
+            let bob = AccountId::from([0x02; 32]);
+            let charlie = AccountId::from([0x03; 32]);
+            // Owner is the first admin
+            assert_eq!(assetmanager.is_admin(alice), true);
+            assert_eq!(assetmanager.get_admin_threshold(), 1);
+
+            assert_eq!(assetmanager.add_admin(bob), Ok(()));
+            assert_eq!(assetmanager.add_admin(charlie), Ok(()));
+            assert_eq!(assetmanager.set_admin_threshold(2), Ok(()));
+
+            // A single approval leaves the action pending
+            assert_eq!(assetmanager.set_interest_rate(5), Ok(()));
+            assert_eq!(assetmanager.get_interest_rate(), 20);
+            // Approving twice doesn't count twice
+            assert_eq!(assetmanager.set_interest_rate(5), Ok(()));
+            assert_eq!(assetmanager.get_interest_rate(), 20);
+            assert_eq!(
+                assetmanager.get_pending_admin_action(AdminAction::set(INTEREST_RATE_PARAMETER, 5)),
+                Some(vec![alice])
+            );
+
+            // Second admin approves the same rate
+            set_caller(bob);
+            assert_eq!(assetmanager.set_interest_rate(5), Ok(()));
+            assert_eq!(assetmanager.get_interest_rate(), 5);
+            assert_eq!(
+                assetmanager.get_pending_admin_action(AdminAction::set(INTEREST_RATE_PARAMETER, 5)),
+                None
+            );
+
+            // Approvals of different values are counted apart
+            assert_eq!(assetmanager.disable(), Ok(()));
+            ink_env::test::pop_execution_context();
+            set_caller(charlie);
+            assert_eq!(assetmanager.set_transfer_rate(30), Ok(()));
+            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.disable(), Ok(()));
+            assert_eq!(assetmanager.is_enabled(), false);
+            ink_env::test::pop_execution_context();
+
+            set_caller(bob);
+            assert_eq!(assetmanager.set_transfer_rate(40), Ok(()));
+            ink_env::test::pop_execution_context();
+            let transfer_rate_30 = AdminAction::set(TRANSFER_RATE_PARAMETER, 30);
+            let transfer_rate_40 = AdminAction::set(TRANSFER_RATE_PARAMETER, 40);
+            assert_eq!(
+                assetmanager.get_pending_admin_action(transfer_rate_30),
+                Some(vec![charlie])
+            );
+            assert_eq!(
+                assetmanager.get_pending_admin_action(transfer_rate_40),
+                Some(vec![bob])
+            );
+            assert_eq!(assetmanager.get_transfer_rate(), 15);
+
+            // Owner changes the admin set alone
+            let dave = AccountId::from([0x04; 32]);
+            assert_eq!(assetmanager.add_admin(dave), Ok(()));
+            assert_eq!(assetmanager.is_admin(dave), true);
+
+            // Removed admins lose their approvals
+            assert_eq!(assetmanager.remove_admin(bob), Ok(()));
+            assert_eq!(assetmanager.is_admin(bob), false);
+            assert_eq!(
+                assetmanager.get_pending_admin_action(transfer_rate_40),
+                Some(vec![])
+            );
+
+            // Applying a value drops the approvals of the other values
+            assert_eq!(assetmanager.set_transfer_rate(30), Ok(()));
+            assert_eq!(assetmanager.get_transfer_rate(), 30);
+            assert_eq!(
+                assetmanager.get_pending_admin_action(transfer_rate_30),
+                None
+            );
+            assert_eq!(
+                assetmanager.get_pending_admin_action(transfer_rate_40),
+                None
+            );
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_admin_add_admin_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                20,
+                15,
+                true,
+            );
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = assetmanager.add_admin(AccountId::from([0x02; 32]));
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_owner_add_admin_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                20,
+                15,
+                true,
+            );
+            let bob = AccountId::from([0x02; 32]);
+            assert_eq!(assetmanager.add_admin(bob), Ok(()));
+            // Admins can't change the admin set
+            set_caller(bob);
+            let _ = assetmanager.add_admin(AccountId::from([0x03; 32]));
+        }
+
+        #[ink::test]
+        fn transfer_ownership_moves_admin_seat() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                20,
+                15,
+                true,
+            );
+            let alice = AccountId::from([0x01; 32]);
+            let bob = AccountId::from([0x02; 32]);
+            assert_eq!(assetmanager.transfer_ownership(bob), true);
+            assert_eq!(assetmanager.get_owner(), bob);
+            assert_eq!(assetmanager.is_admin(alice), false);
+            assert_eq!(assetmanager.is_admin(bob), true);
+
+            set_caller(bob);
+            assert_eq!(assetmanager.set_interest_rate(5), Ok(()));
+            assert_eq!(assetmanager.get_interest_rate(), 5);
+            ink_env::test::pop_execution_context();
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_admin_set_interest_rate_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                20,
+                15,
+                true,
+            );
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = assetmanager.set_interest_rate(5);
+        }
+
+        #[ink::test]
+        fn payoff_schedule_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1_000_000_000_000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(
+                assetmanager.calculate_payoff_at(borrower, 1, 0),
+                Err(Error::NoSuchLoan)
+            );
+            let borrowed_at = 10 * MILLISECONDS_IN_DAY;
+            for token_id in 1..=3 {
+                assert_eq!(
+                    assetmanager
+                        .handle_borrow(borrower, token_id, 10, 1_000_000_000_000, borrowed_at)
+                        .is_ok(),
+                    true
+                );
+            }
+            assert_eq!(
+                assetmanager.calculate_payoff_at(borrower, 1, borrowed_at - 1),
+                Err(Error::InvalidTimestamp)
+            );
+            assert_eq!(
+                assetmanager.calculate_payoff_at(borrower, 1, borrowed_at),
+                Ok(1_000_000_000_000)
+            );
+            assert_eq!(
+                assetmanager.calculate_payoff_at(
+                    borrower,
+                    1,
+                    borrowed_at + 30 * MILLISECONDS_IN_DAY
+                ),
+                Ok(1_000_000_000_000
+                    + assetmanager.calculate_interest(
+                        1_000_000_000_000,
+                        10,
+                        30 * MILLISECONDS_IN_DAY,
+                        0
+                    ))
+            );
+
+            // Payoff grows the longer the loan runs
+            let schedule = assetmanager
+                .calculate_payoff_schedule(
+                    borrower,
+                    1,
+                    (0..12)
+                        .map(|month| borrowed_at + month * 30 * MILLISECONDS_IN_DAY)
+                        .collect(),
+                )
+                .unwrap();
+            assert_eq!(schedule.len(), 12);
+            assert!(schedule.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(
+                assetmanager.calculate_payoff_schedule(borrower, 1, vec![borrowed_at, 0]),
+                Err(Error::InvalidTimestamp)
+            );
+
+            // Closed loans have nothing left to pay off
+            assert_eq!(assetmanager.handle_repayment(borrower, 2, 0).is_ok(), true);
+            assert_eq!(
+                assetmanager.calculate_payoff_at(borrower, 2, borrowed_at),
+                Err(Error::LoanAlreadyRepaid)
+            );
+            assert_eq!(
+                assetmanager.handle_liquidation(borrower, 3, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.calculate_payoff_at(borrower, 3, borrowed_at),
+                Err(Error::LoanNotActive)
+            );
+        }
+
+        #[ink::test]
+        fn interest_preview_works() {
+            let interest_rate = 10;
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                interest_rate,
+                1_000_000_000_000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assetmanager.loans.insert(
+                (borrower, 1),
+                Loan {
+                    id: 1,
+                    amount: 2_000_000_000_000,
+                    interest_rate: 20,
+                    status: LoanStatus::Active as u8,
+                    ..Default::default()
+                },
+            );
+
+            // Existing loans are previewed with their own amount and rate
+            assert_eq!(
+                assetmanager.get_interest_preview(1, borrower, 30),
+                assetmanager.calculate_interest(2_000_000_000_000, 20, 30 * MILLISECONDS_IN_DAY, 0)
+            );
+            // Prospective loans use the current transfer and interest rates
+            assert_eq!(
+                assetmanager.get_interest_preview(2, borrower, 30),
+                assetmanager.calculate_interest(
+                    1_000_000_000_000,
+                    interest_rate,
+                    30 * MILLISECONDS_IN_DAY,
+                    0
+                )
+            );
+            assert_eq!(
+                assetmanager.get_interest_preview(1, borrower, 30)
+                    > assetmanager.get_interest_preview(2, borrower, 30),
+                true
+            );
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(
+                assetmanager.get_interest_preview(1, borrower, 0),
+                assetmanager.get_total_debt_of_loan(borrower, 1)
+            );
+            assert_eq!(assetmanager.get_interest_preview(2, borrower, 0), 0);
+        }
+
+        #[ink::test]
+        fn interest_preview_far_off_saturates() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1_000_000_000_000,
+                true,
+            );
+            assert_eq!(
+                assetmanager.set_interest_rate_model(InterestModel::Linear as u8),
+                Ok(())
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assetmanager.loans.insert(
+                (borrower, 1),
+                Loan {
+                    id: 1,
+                    amount: 2_000_000_000_000,
+                    interest_rate: 20,
+                    status: LoanStatus::Active as u8,
+                    ..Default::default()
+                },
+            );
+
+            assert_eq!(
+                assetmanager.get_interest_preview(1, borrower, u64::MAX),
+                assetmanager.calculate_interest(2_000_000_000_000, 20, u64::MAX, 0)
+            );
+            assert_eq!(
+                assetmanager.get_interest_preview(1, borrower, u64::MAX / 1000),
+                assetmanager.get_interest_preview(1, borrower, u64::MAX)
+            );
+        }
+
+        #[ink::test]
+        fn interest_checkpoint_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                20,
+                15,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(
+                assetmanager.checkpoint_interest(borrower, 1),
+                Err(Error::NoSuchLoan)
+            );
+
+            assetmanager.loans.insert(
+                (borrower, 1),
+                Loan {
+                    id: 1,
+                    amount: 1000,
+                    interest_rate: 20,
+                    status: LoanStatus::Active as u8,
+                    ..Default::default()
+                },
+            );
+            // No interest accrued yet so nothing is checkpointed
+            assert_eq!(assetmanager.checkpoint_interest(borrower, 1), Ok(0));
+            assert_eq!(assetmanager.get_interest_checkpoint(borrower, 1), None);
+
+            assert_eq!(assetmanager.set_interest_checkpoint_threshold(10), Ok(()));
+            assert_eq!(assetmanager.get_interest_checkpoint_threshold(), 10);
+            // Interest has to exceed the last checkpoint by more than the threshold
+            assert!(!assetmanager.record_interest_checkpoint(borrower, 1, 10, 100));
+            assert!(assetmanager.record_interest_checkpoint(borrower, 1, 11, 200));
+            assert_eq!(
+                assetmanager.get_interest_checkpoint(borrower, 1),
+                Some(InterestCheckpoint {
+                    last_checkpoint: 200,
+                    checkpoint_interest: 11,
+                })
+            );
+            let loan = assetmanager.loans.get(&(borrower, 1)).unwrap();
+            assert_eq!(loan.last_checkpoint, 200);
+            assert_eq!(loan.checkpoint_interest, 11);
+            assert!(!assetmanager.record_interest_checkpoint(borrower, 1, 21, 300));
+            assert!(assetmanager.record_interest_checkpoint(borrower, 1, 22, 400));
+
+            let emitted_events = ink_env::test::recorded_events()
+                .filter_map(
+                    |event| match <Event as scale::Decode>::decode(&mut &event.data[..]) {
+                        Ok(Event::InterestCheckpointed(checkpointed)) => Some(checkpointed),
+                        _ => None,
+                    },
+                )
+                .collect::<Vec<_>>();
+            assert_eq!(emitted_events.len(), 2);
+            assert_eq!(emitted_events[0].accrued_interest, 11);
+            assert_eq!(emitted_events[1].accrued_interest, 22);
+            assert_eq!(emitted_events[1].timestamp, 400);
+
+            assetmanager.loans.get_mut(&(borrower, 1)).unwrap().status = LoanStatus::Repaid as u8;
+            assert_eq!(
+                assetmanager.checkpoint_interest(borrower, 1),
+                Err(Error::LoanNotActive)
+            );
+        }
+
+        #[ink::test]
+        fn set_transfer_rate_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+
+            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.get_interest_rate(), 7);
+            assert_eq!(assetmanager.get_transfer_rate(), 100);
+
+            assert_eq!(assetmanager.set_transfer_rate(110), Ok(()));
+            assert_eq!(assetmanager.get_transfer_rate(), 110);
+        }
+
+        #[ink::test]
+        fn renounce_ownership_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+            assert_eq!(assetmanager.renounce_ownership(), Ok(()));
+            assert_eq!(assetmanager.is_owner(), false);
+            assert_eq!(assetmanager.get_owner(), AccountId::from([0x0; 32]));
+
+            assert_eq!(assetmanager.enable(), Err(Error::ContractImmutable));
+            assert_eq!(assetmanager.disable(), Err(Error::ContractImmutable));
+            assert_eq!(
+                assetmanager.set_interest_rate(8),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(
+                assetmanager.set_transfer_rate(110),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(
+                assetmanager.set_timelock_delay(10),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(
+                assetmanager.propose_interest_rate_change(8),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(
+                assetmanager.set_erc20_owner(AccountId::from([0x01; 32])),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(
+                assetmanager.renounce_ownership(),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.get_interest_rate(), 7);
+            assert_eq!(assetmanager.get_transfer_rate(), 100);
+        }
+
+        #[ink::test]
+        fn timelocked_rate_change_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            assert_eq!(assetmanager.get_timelock_delay(), 10);
+
+            assert_eq!(assetmanager.propose_interest_rate_change(8), Ok(()));
+            assert_eq!(assetmanager.propose_transfer_rate_change(110), Ok(()));
+            assert_eq!(
+                assetmanager.get_pending_change(INTEREST_RATE_PARAMETER),
+                Some((8, 10))
+            );
+            assert_eq!(assetmanager.cancel_transfer_rate_change(), Ok(()));
+            assert_eq!(
+                assetmanager.get_pending_change(TRANSFER_RATE_PARAMETER),
+                None
+            );
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(assetmanager.execute_interest_rate_change(), Ok(()));
+            assert_eq!(assetmanager.get_interest_rate(), 8);
+            assert_eq!(assetmanager.get_transfer_rate(), 100);
+            assert_eq!(
+                assetmanager.get_pending_change(INTEREST_RATE_PARAMETER),
+                None
+            );
+        }
+
+        #[ink::test]
+        fn timelocked_rate_change_needs_admin_approval() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+            let bob = AccountId::from([0x02; 32]);
+            assert_eq!(assetmanager.add_admin(bob), Ok(()));
+            assert_eq!(assetmanager.set_admin_threshold(2), Ok(()));
+
+            // Owner alone can neither set the delay nor propose
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            assert_eq!(assetmanager.get_timelock_delay(), 0);
+            set_caller(bob);
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(assetmanager.get_timelock_delay(), 10);
+
+            assert_eq!(assetmanager.propose_interest_rate_change(8), Ok(()));
+            assert_eq!(
+                assetmanager.get_pending_change(INTEREST_RATE_PARAMETER),
+                None
+            );
+            set_caller(bob);
+            assert_eq!(assetmanager.propose_interest_rate_change(8), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(
+                assetmanager.get_pending_change(INTEREST_RATE_PARAMETER),
+                Some((8, 10))
+            );
+
+            // Nor execute it once the delay has passed
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(assetmanager.execute_interest_rate_change(), Ok(()));
+            assert_eq!(assetmanager.get_interest_rate(), 7);
+            set_caller(bob);
+            assert_eq!(assetmanager.execute_interest_rate_change(), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(assetmanager.get_interest_rate(), 8);
+            assert_eq!(
+                assetmanager.get_pending_change(INTEREST_RATE_PARAMETER),
+                None
+            );
+
+            // A single admin can veto a proposal
+            assert_eq!(assetmanager.propose_transfer_rate_change(110), Ok(()));
+            set_caller(bob);
+            assert_eq!(assetmanager.propose_transfer_rate_change(110), Ok(()));
+            assert_eq!(assetmanager.cancel_transfer_rate_change(), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(
+                assetmanager.get_pending_change(TRANSFER_RATE_PARAMETER),
+                None
+            );
+        }
+
+        #[ink::test]
+        fn timelocked_rate_change_early_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+            assert_eq!(
+                assetmanager.execute_transfer_rate_change(),
+                Err(Error::NoPendingChange)
+            );
+            assert_eq!(
+                assetmanager.cancel_transfer_rate_change(),
+                Err(Error::NoPendingChange)
+            );
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            assert_eq!(assetmanager.propose_transfer_rate_change(110), Ok(()));
+            assert_eq!(
+                assetmanager.execute_transfer_rate_change(),
+                Err(Error::TimelockNotExpired)
+            );
+            assert_eq!(assetmanager.get_transfer_rate(), 100);
+            assert_eq!(
+                assetmanager.get_pending_change(TRANSFER_RATE_PARAMETER),
+                Some((110, 10))
+            );
+        }
+
+        #[ink::test]
+        fn timelock_delay_decrease_is_timelocked() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+
+            // Dropping the delay doesn't bypass it
+            assert_eq!(assetmanager.set_timelock_delay(0), Ok(()));
+            assert_eq!(assetmanager.get_timelock_delay(), 10);
+            assert_eq!(
+                assetmanager.get_pending_change(TIMELOCK_DELAY_PARAMETER),
+                Some((0, 10))
+            );
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(assetmanager.execute_timelock_delay_change(), Ok(()));
+            assert_eq!(assetmanager.get_timelock_delay(), 0);
+            assert_eq!(assetmanager.set_interest_rate(8), Ok(()));
+
+            // Raising the delay discards a pending decrease
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            assert_eq!(assetmanager.set_timelock_delay(5), Ok(()));
+            assert_eq!(assetmanager.set_timelock_delay(20), Ok(()));
+            assert_eq!(assetmanager.get_timelock_delay(), 20);
+            assert_eq!(
+                assetmanager.get_pending_change(TIMELOCK_DELAY_PARAMETER),
+                None
+            );
+        }
+
+        #[ink::test]
+        fn timelock_delay_decrease_early_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                100,
+                true,
+            );
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            assert_eq!(assetmanager.set_timelock_delay(0), Ok(()));
+            assert_eq!(
+                assetmanager.execute_timelock_delay_change(),
+                Err(Error::TimelockNotExpired)
+            );
+            assert_eq!(assetmanager.get_timelock_delay(), 10);
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn borrow_disabled_works() {
+            // Disabled should panic
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                false,
+            );
+            assert_eq!(assetmanager.is_enabled(), false);
+            let owner = AccountId::from([0x01; 32]);
+            assert!(
+                assetmanager.deposit(1, owner).is_err(),
+                "Should not allow deposit in disabled state"
+            );
+
+            assert_eq!(assetmanager.enable(), Ok(()));
+            assert_eq!(assetmanager.is_enabled(), true);
+            assert!(
+                assetmanager.deposit(1, owner).is_err(),
+                "Should not allow deposit when erc721 allowance is not made"
+            );
+        }
+
+        #[ink::test]
+        fn typed_errors_work() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                false,
+            );
+            let owner = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.deposit(1, owner), Err(Error::NotEnabled));
+            assert_eq!(assetmanager.withdraw(1, owner), Err(Error::NoSuchLoan));
+            // Failed calls release the re-entrancy guard
+            assert_eq!(assetmanager.locked, false);
+        }
+
+        #[ink::test]
+        fn borrower_stats_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(
+                assetmanager.get_borrower_stats(borrower),
+                BorrowerStats {
+                    health_factor: u128::MAX,
+                    ..Default::default()
+                }
+            );
+
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 2, 10, 1000, 0).is_ok(),
+                true
+            );
+            let stats = assetmanager.get_borrower_stats(borrower);
+            assert_eq!(stats.principal_balance, 2000);
+            assert_eq!(
+                stats.total_balance,
+                assetmanager.get_total_balance_of_borrower(borrower)
+            );
+            assert_eq!(
+                stats.accrued_interest,
+                assetmanager.get_total_debt_of_borrower(borrower)
+            );
+            assert_eq!(stats.active_loan_count, 2);
+            assert_eq!(stats.total_loan_count, 2);
+            assert_eq!(stats.health_factor, 10_000);
+
+            // Partially through repayment
+            assert_eq!(assetmanager.handle_repayment(borrower, 1, 0).is_ok(), true);
+            let stats = assetmanager.get_borrower_stats(borrower);
+            assert_eq!(stats.principal_balance, 1000);
+            assert_eq!(
+                stats.total_balance,
+                stats.principal_balance + stats.accrued_interest
+            );
+            assert_eq!(stats.active_loan_count, 1);
+            assert_eq!(stats.total_loan_count, 2);
+            assert_eq!(
+                stats.health_factor,
+                assetmanager.get_health_factor(borrower)
+            );
+
+            assert_eq!(assetmanager.handle_repayment(borrower, 2, 0).is_ok(), true);
+            let stats = assetmanager.get_borrower_stats(borrower);
+            assert_eq!(stats.principal_balance, 0);
+            assert_eq!(stats.active_loan_count, 0);
+            assert_eq!(stats.total_loan_count, 2);
+            assert_eq!(stats.health_factor, u128::MAX);
+        }
+
+        #[ink::test]
+        fn minimum_deposit_amount_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.get_minimum_deposit_amount(), 0);
+            assert_eq!(assetmanager.check_minimum_deposit(1), Ok(()));
+
+            assert_eq!(assetmanager.set_minimum_deposit_amount(1001), Ok(()));
+            assert_eq!(assetmanager.get_minimum_deposit_amount(), 1001);
+            assert_eq!(
+                assetmanager.deposit(1, borrower),
+                Err(Error::AmountBelowMinimum)
+            );
+            assert_eq!(assetmanager.locked, false);
+
+            // Deposits lending exactly the minimum are allowed
+            assert_eq!(assetmanager.set_minimum_deposit_amount(1000), Ok(()));
+            assert_eq!(
+                assetmanager.check_minimum_deposit(999),
+                Err(Error::AmountBelowMinimum)
+            );
+            assert_eq!(
+                assetmanager.check_minimum_deposit(assetmanager.get_transfer_rate()),
+                Ok(())
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded =
+                <Event as scale::Decode>::decode(&mut &emitted_events.last().unwrap().data[..])
+                    .expect("encountered invalid contract event data buffer");
+            if let Event::MinimumDepositChanged(MinimumDepositChanged { old, new }) = decoded {
+                assert_eq!((old, new), (1001, 1000));
+            } else {
+                panic!("encountered unexpected event kind: expected a MinimumDepositChanged event")
+            }
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_owner_set_minimum_deposit_amount_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = assetmanager.set_minimum_deposit_amount(1);
+        }
+
+        #[ink::test]
+        fn max_loan_duration_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(
+                assetmanager.get_max_loan_duration(),
+                DEFAULT_MAX_LOAN_DURATION
+            );
+            assert_eq!(
+                assetmanager.is_loan_expired(borrower, 1),
+                Err(Error::NoSuchLoan)
+            );
+
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.get_debt_details(borrower, 1).unwrap().expiry,
+                DEFAULT_MAX_LOAN_DURATION
+            );
+            assert_eq!(assetmanager.is_loan_expired(borrower, 1), Ok(false));
+            assert_eq!(
+                assetmanager.liquidate(borrower, 1),
+                Err(Error::LoanNotExpired)
+            );
+
+            // Loans taken without any allowed duration expire right away
+            assert_eq!(assetmanager.set_max_loan_duration(0), Ok(()));
+            assert_eq!(assetmanager.get_max_loan_duration(), 0);
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 2, 10, 1000, 0).is_ok(),
+                true
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(assetmanager.is_loan_expired(borrower, 1), Ok(false));
+            assert_eq!(assetmanager.is_loan_expired(borrower, 2), Ok(true));
+
+            assert_eq!(assetmanager.liquidate(borrower, 2), Ok(()));
+            let loan = assetmanager.get_debt_details(borrower, 2).unwrap();
+            assert_eq!(loan.status, LoanStatus::Liquidated as u8);
+            assert_eq!(loan.closed_at, Some(5));
+            assert_eq!(assetmanager.is_loan_expired(borrower, 2), Ok(false));
+            assert_eq!(
+                assetmanager.withdraw(2, borrower),
+                Err(Error::LoanNotActive)
+            );
+            assert_eq!(
+                assetmanager.get_principal_balance_of_borrower(borrower),
+                1000
+            );
+            assert_eq!(
+                assetmanager.liquidate(borrower, 2),
+                Err(Error::LoanNotExpired)
+            );
+        }
+
+        #[ink::test]
+        fn emergency_withdrawal_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x02; 32]);
+            let vault = AccountId::from([0x03; 32]);
+            assert_eq!(
+                assetmanager.get_emergency_address(),
+                AccountId::from([0x01; 32])
+            );
+            assert_eq!(assetmanager.set_emergency_address(vault), Ok(()));
+            assert_eq!(assetmanager.get_emergency_address(), vault);
+            assert_eq!(
+                assetmanager.emergency_withdraw_nft(borrower, 1),
+                Err(Error::NoSuchLoan)
+            );
+
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 2, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(assetmanager.handle_repayment(borrower, 2, 0).is_ok(), true);
+            // Repaid loans have no collateral left to recover
+            assert_eq!(
+                assetmanager.emergency_withdraw_nft(borrower, 2),
+                Err(Error::NoSuchLoan)
+            );
+
+            // Mirrors the storage updates of emergency_withdraw_nft after the nft transfer
+            assert_eq!(
+                assetmanager.handle_emergency_withdrawal(borrower, 1, 5),
+                Ok(())
+            );
+            let loan = assetmanager.get_debt_details(borrower, 1).unwrap();
+            assert_eq!(loan.status, LoanStatus::Liquidated as u8);
+            assert_eq!(loan.closed_at, Some(5));
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 0);
+            assert_eq!(
+                assetmanager.emergency_withdraw_nft(borrower, 1),
+                Err(Error::NoSuchLoan)
+            );
+            assert_eq!(assetmanager.locked, false);
+
+            let decoded = <Event as scale::Decode>::decode(
+                &mut &ink_env::test::recorded_events().last().unwrap().data[..],
+            )
+            .expect("encountered invalid contract event data buffer");
+            match decoded {
+                Event::EmergencyWithdrawal(event) => {
+                    assert_eq!(event.borrower, borrower);
+                    assert_eq!(event.token_id, 1);
+                    assert_eq!(event.emergency_address, vault);
+                }
+                _ => panic!("expected an EmergencyWithdrawal event"),
+            }
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_owner_emergency_withdrawal_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = assetmanager.emergency_withdraw_nft(AccountId::from([0x02; 32]), 1);
+        }
+
+        #[ink::test]
+        fn sweep_expired_loans_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let first = AccountId::from([0x01; 32]);
+            let second = AccountId::from([0x02; 32]);
+            let idle = AccountId::from([0x03; 32]);
+
+            assert_eq!(
+                assetmanager.handle_borrow(first, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(assetmanager.set_max_loan_duration(0), Ok(()));
+            assert_eq!(
+                assetmanager.handle_borrow(first, 2, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(first, 3, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(second, 4, 10, 1000, 0).is_ok(),
+                true
+            );
+
+            // Nothing has expired yet
+            assert_eq!(
+                assetmanager.sweep_expired_loans(vec![first, second, idle]),
+                0
+            );
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 4000);
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(
+                assetmanager.sweep_expired_loans(vec![first, second, idle, first]),
+                3
+            );
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 1000);
+            assert_eq!(assetmanager.is_loan_expired(first, 1), Ok(false));
+            assert_eq!(assetmanager.get_principal_balance_of_loan(first, 1), 1000);
+            assert_eq!(assetmanager.get_principal_balance_of_borrower(second), 0);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let liquidations = emitted_events
+                .iter()
+                .filter(|event| {
+                    let decoded = <Event as scale::Decode>::decode(&mut &event.data[..])
+                        .expect("encountered invalid contract event data buffer");
+                    matches!(decoded, Event::Liquidated(_))
+                })
+                .count();
+            assert_eq!(liquidations, 3);
+
+            assert_eq!(assetmanager.sweep_expired_loans(vec![first, second]), 0);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Too many borrowers to sweep")]
+        fn sweep_expired_loans_limit_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrowers = vec![AccountId::from([0x01; 32]); MAX_SWEEP_BORROWERS + 1];
+            assetmanager.sweep_expired_loans(borrowers);
+        }
+
+        #[ink::test]
+        fn loan_extension_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            // The default caller is both owner and borrower
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(
+                assetmanager.request_loan_extension(1, 5),
+                Err(Error::NoSuchLoan)
+            );
+            assert_eq!(
+                assetmanager.approve_extension(borrower, 1),
+                Err(Error::NoSuchExtension)
+            );
+
+            assert_eq!(assetmanager.set_max_loan_duration(0), Ok(()));
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(assetmanager.is_loan_expired(borrower, 1), Ok(true));
+
+            // Rejection leaves the loan untouched
+            assert_eq!(assetmanager.request_loan_extension(1, 5), Ok(()));
+            let extension = assetmanager.get_pending_extension(borrower, 1).unwrap();
+            assert_eq!(extension.borrower, borrower);
+            assert_eq!(extension.additional_days, 5);
+            assert_eq!(extension.requested_at, 5);
+            assert_eq!(assetmanager.reject_extension(borrower, 1), Ok(()));
+            assert_eq!(
+                assetmanager.get_pending_extension(borrower, 1).is_none(),
+                true
+            );
+            assert_eq!(
+                assetmanager.get_debt_details(borrower, 1).unwrap().expiry,
+                0
+            );
+
+            assert_eq!(assetmanager.request_loan_extension(1, 5), Ok(()));
+            // approve_extension also moves the collateral lock, which cannot be done off-chain
+            assert_eq!(
+                assetmanager.extend_loan(borrower, 1),
+                Ok(5 * MILLISECONDS_IN_DAY)
+            );
+            assert_eq!(
+                assetmanager.get_debt_details(borrower, 1).unwrap().expiry,
+                5 * MILLISECONDS_IN_DAY
+            );
+            assert_eq!(assetmanager.is_loan_expired(borrower, 1), Ok(false));
+            assert_eq!(
+                assetmanager.approve_extension(borrower, 1),
+                Err(Error::NoSuchExtension)
+            );
+
+            // Extensions past the representable time are refused and stay pending
+            assert_eq!(assetmanager.request_loan_extension(1, u64::MAX), Ok(()));
+            assert_eq!(
+                assetmanager.approve_extension(borrower, 1),
+                Err(Error::ArithmeticOverflow)
+            );
+            let max_days = (u64::MAX - 5 * MILLISECONDS_IN_DAY) / MILLISECONDS_IN_DAY;
+            assetmanager
+                .pending_extensions
+                .get_mut(&(borrower, 1))
+                .unwrap()
+                .additional_days = max_days + 1;
+            assert_eq!(
+                assetmanager.approve_extension(borrower, 1),
+                Err(Error::ArithmeticOverflow)
+            );
+            assert_eq!(
+                assetmanager.get_debt_details(borrower, 1).unwrap().expiry,
+                5 * MILLISECONDS_IN_DAY
+            );
+            assert_eq!(
+                assetmanager.get_pending_extension(borrower, 1).is_some(),
+                true
+            );
+            assert_eq!(assetmanager.reject_extension(borrower, 1), Ok(()));
+        }
+
+        #[ink::test]
+        fn pending_repayments_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let first = AccountId::from([0x01; 32]);
+            let second = AccountId::from([0x02; 32]);
+            assert_eq!(assetmanager.get_pending_repayment_count(), 0);
+            assert_eq!(assetmanager.get_pending_repayments(0, 10), vec![]);
+
+            assert_eq!(
+                assetmanager.handle_borrow(first, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(second, 2, 10, 2000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(first, 3, 10, 3000, 0).is_ok(),
+                true
+            );
+            assert_eq!(assetmanager.get_pending_repayment_count(), 3);
+
+            let pending = |borrower, token_id, amount| PendingRepayment {
+                borrower,
+                token_id,
+                amount,
+                expiry: DEFAULT_MAX_LOAN_DURATION,
+            };
+            assert_eq!(
+                assetmanager.get_pending_repayments(0, 10),
+                vec![
+                    pending(first, 1, 1000),
+                    pending(second, 2, 2000),
+                    pending(first, 3, 3000)
+                ]
+            );
+            assert_eq!(
+                assetmanager.get_pending_repayments(2, 1),
+                vec![pending(second, 2, 2000)]
+            );
+
+            assert_eq!(assetmanager.handle_repayment(second, 2, 0).is_ok(), true);
+            assert_eq!(assetmanager.get_pending_repayment_count(), 2);
+            assert_eq!(
+                assetmanager.get_pending_repayments(1, 3),
+                vec![pending(first, 1, 1000), pending(first, 3, 3000)]
+            );
+        }
+
+        #[ink::test]
+        fn inconsistent_repayment_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.handle_borrow(borrower, 1, 10, 1000, 0), Ok(()));
+
+            // Borrower balance lower than the loan amount
+            assetmanager.borrowers.get_mut(&borrower).unwrap().balance = 999;
+            assert_eq!(
+                assetmanager.handle_repayment(borrower, 1, 0),
+                Err(Error::InsufficientBalance)
+            );
+            // Nothing is changed by the failed repayment
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 1000);
+            assert_eq!(
+                assetmanager.get_debt_details(borrower, 1).unwrap().status,
+                LoanStatus::Active as u8
+            );
+
+            assetmanager.borrowers.get_mut(&borrower).unwrap().balance = 1000;
+            assetmanager.total_principal_outstanding = 0;
+            assert_eq!(
+                assetmanager.handle_repayment(borrower, 1, 0),
+                Err(Error::InsufficientBalance)
+            );
+
+            assetmanager.total_principal_outstanding = 1000;
+            assert_eq!(assetmanager.handle_repayment(borrower, 1, 0), Ok(()));
+            assert_eq!(assetmanager.get_principal_balance_of_borrower(borrower), 0);
+
+            // Principal outstanding can't overflow
+            assetmanager.total_principal_outstanding = Balance::MAX;
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 2, 10, 1000, 0),
+                Err(Error::ArithmeticOverflow)
+            );
+            assert_eq!(assetmanager.get_debt_details(borrower, 2).is_err(), true);
+        }
+
+        #[ink::test]
+        fn borrow_capacity_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.get_max_borrow_per_borrower(), Balance::MAX);
+
+            assert_eq!(assetmanager.set_max_borrow_per_borrower(2500), Ok(()));
+            assert_eq!(assetmanager.get_borrow_capacity(borrower), 2500);
+            assert_eq!(assetmanager.handle_borrow(borrower, 1, 10, 1000, 0), Ok(()));
+            assert_eq!(assetmanager.handle_borrow(borrower, 2, 10, 1000, 0), Ok(()));
+            assert_eq!(assetmanager.get_borrow_capacity(borrower), 500);
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 3, 10, 1000, 0),
+                Err(Error::BorrowerBorrowLimitExceeded)
+            );
+
+            // Lowering the limit below the principal owed leaves no capacity
+            assert_eq!(assetmanager.set_max_borrow_per_borrower(1500), Ok(()));
+            assert_eq!(assetmanager.get_borrow_capacity(borrower), 0);
+            assert_eq!(assetmanager.can_borrow(borrower), false);
+
+            assert_eq!(assetmanager.set_max_borrow_per_borrower(5000), Ok(()));
+            assert_eq!(assetmanager.disable(), Ok(()));
+            assert_eq!(assetmanager.can_borrow(borrower), false);
+        }
+
+        #[ink::test]
+        fn max_borrows_per_block_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.get_max_borrows_per_block(), u32::MAX);
+            assert_eq!(assetmanager.set_max_borrows_per_block(2), Ok(()));
+            assert_eq!(assetmanager.get_max_borrows_per_block(), 2);
+
+            // The deposit reaching the limit within a block is rejected
+            assert_eq!(assetmanager.handle_borrow(borrower, 1, 10, 1000, 0), Ok(()));
+            assert_eq!(assetmanager.handle_borrow(borrower, 2, 10, 1000, 0), Ok(()));
+            assert_eq!(assetmanager.get_borrows_in_block(0), 2);
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 3, 10, 1000, 0),
+                Err(Error::RateLimitExceeded)
+            );
+            assert_eq!(
+                assetmanager.deposit(3, borrower),
+                Err(Error::RateLimitExceeded)
+            );
+            assert_eq!(assetmanager.get_borrows_in_block(0), 2);
+            assert_eq!(assetmanager.locked, false);
+
+            // The count starts over in the next block
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(assetmanager.handle_borrow(borrower, 3, 10, 1000, 0), Ok(()));
+            assert_eq!(assetmanager.get_borrows_in_block(1), 1);
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_owner_set_max_borrows_per_block_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = assetmanager.set_max_borrows_per_block(1);
+        }
+
+        #[ink::test]
+        fn collection_borrow_limit_works() {
+            let erc721 = instantiate_erc721_contract();
+            let mut assetmanager =
+                AssetManager::new(instantiate_erc20_contract(), erc721, 10, 1000, true);
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(
+                assetmanager.get_collection_utilization(erc721),
+                (0, Balance::MAX)
+            );
+
+            assert_eq!(
+                assetmanager.set_collection_borrow_limit(erc721, 2500),
+                Ok(())
+            );
+            assert_eq!(assetmanager.handle_borrow(borrower, 1, 10, 1000, 0), Ok(()));
+            assert_eq!(assetmanager.handle_borrow(borrower, 2, 10, 1000, 0), Ok(()));
+            assert_eq!(
+                assetmanager.get_collection_utilization(erc721),
+                (2000, 2500)
+            );
+
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 3, 10, 1000, 0),
+                Err(Error::CollectionBorrowLimitExceeded)
+            );
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 2000);
+
+            assert_eq!(assetmanager.handle_repayment(borrower, 1, 0), Ok(()));
+            assert_eq!(
+                assetmanager.get_collection_utilization(erc721),
+                (1000, 2500)
+            );
+            assert_eq!(assetmanager.handle_borrow(borrower, 3, 10, 1000, 0), Ok(()));
+            assert_eq!(
+                assetmanager.get_collection_utilization(erc721),
+                (2000, 2500)
+            );
+        }
+
+        #[ink::test]
+        fn legacy_loan_decodes_with_status() {
+            // Loan as stored by the first release
+            #[derive(Encode)]
+            struct LegacyLoan {
+                id: LoanId,
+                amount: Balance,
+                transfer_rate: u128,
+                interest_rate: u64,
+                date_borrowed: u64,
+                date_repaid: Option<u64>,
+                is_repaid: bool,
+            }
+            let legacy = |date_repaid, is_repaid| LegacyLoan {
+                id: 1,
+                amount: 1000,
+                transfer_rate: 1000,
+                interest_rate: 10,
+                date_borrowed: 0,
+                date_repaid,
+                is_repaid,
+            };
+
+            // Without expiry the loan can't be decoded, see Upgrading in the README
+            assert_eq!(
+                Loan::decode(&mut &legacy(None, false).encode()[..]).is_err(),
+                true
+            );
+
+            // status and closed_at take the places of is_repaid and date_repaid.
+            // A bool encodes as a single 0 or 1 byte, which decodes as Active or Repaid
+            let with_expiry = |loan: LegacyLoan| {
+                let (last_checkpoint, checkpoint_interest): (u64, Balance) = (0, 0);
+                let borrower_address = AccountId::from([0x01; 32]);
+                (
+                    loan,
+                    DEFAULT_MAX_LOAN_DURATION,
+                    last_checkpoint,
+                    checkpoint_interest,
+                    borrower_address,
+                )
+                    .encode()
+            };
+            let active = Loan::decode(&mut &with_expiry(legacy(None, false))[..]).unwrap();
+            assert_eq!(active.status, LoanStatus::Active as u8);
+            assert_eq!(active.closed_at, None);
+            assert_eq!(active.expiry, DEFAULT_MAX_LOAN_DURATION);
+            assert_eq!(active.checkpoint_interest, 0);
+
+            let repaid = Loan::decode(&mut &with_expiry(legacy(Some(7), true))[..]).unwrap();
+            assert_eq!(repaid.status, LoanStatus::Repaid as u8);
+            assert_eq!(repaid.closed_at, Some(7));
+        }
+
+        #[ink::test]
+        fn migrate_loan_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let old_borrower = AccountId::from([0x02; 32]);
+            let new_borrower = AccountId::from([0x03; 32]);
+            let repayer = AccountId::from([0x04; 32]);
+            assert_eq!(
+                assetmanager.migrate_loan(old_borrower, new_borrower, 1),
+                Err(Error::NoSuchLoan)
+            );
+
+            for token_id in 1..=2 {
+                assert_eq!(
+                    assetmanager
+                        .handle_borrow(old_borrower, token_id, 10, 1000, 0)
+                        .is_ok(),
+                    true
+                );
+            }
+            assert_eq!(
+                assetmanager
+                    .handle_borrow(new_borrower, 2, 10, 1000, 0)
+                    .is_ok(),
+                true
+            );
+            assetmanager
+                .allowed_repayers
+                .insert((old_borrower, 1), repayer);
+            assert_eq!(
+                assetmanager.migrate_loan(old_borrower, new_borrower, 2),
+                Err(Error::LoanAlreadyExists)
+            );
+
+            assert_eq!(
+                assetmanager.migrate_loan(old_borrower, new_borrower, 1),
+                Ok(())
+            );
+            assert_eq!(
+                assetmanager.get_debt_details(old_borrower, 1).is_err(),
+                true
+            );
+            let loan = assetmanager.get_debt_details(new_borrower, 1).unwrap();
+            assert_eq!(loan.id, 1);
+            assert_eq!(loan.status, LoanStatus::Active as u8);
+            assert_eq!(loan.borrower_address, new_borrower);
+            assert_eq!(assetmanager.loan_index.get(&1), Some(&(new_borrower, 1)));
+            assert_eq!(
+                assetmanager.get_principal_balance_of_borrower(old_borrower),
+                1000
+            );
+            assert_eq!(
+                assetmanager.get_principal_balance_of_borrower(new_borrower),
+                2000
+            );
+            assert_eq!(
+                assetmanager.borrowers.get(&new_borrower).unwrap().loans,
+                vec![2, 1]
+            );
+            assert_eq!(assetmanager.get_authorized_repayer(new_borrower, 1), None);
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 3000);
+
+            // The old account's record goes once its last loan moved
+            let recovered = AccountId::from([0x05; 32]);
+            assert_eq!(
+                assetmanager.migrate_loan(old_borrower, recovered, 2),
+                Ok(())
+            );
+            assert_eq!(assetmanager.borrowers.get(&old_borrower).is_none(), true);
+            assert_eq!(
+                assetmanager
+                    .get_debt_details(recovered, 2)
+                    .unwrap()
+                    .borrower_address,
+                recovered
+            );
+
+            // Closed loans stay where they are
+            assert_eq!(assetmanager.handle_repayment(recovered, 2, 0).is_ok(), true);
+            assert_eq!(
+                assetmanager.migrate_loan(recovered, old_borrower, 2),
+                Err(Error::LoanNotActive)
+            );
+            assert_eq!(assetmanager.get_principal_balance_of_borrower(recovered), 0);
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_owner_migrate_loan_fails() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = assetmanager.migrate_loan(
+                AccountId::from([0x02; 32]),
+                AccountId::from([0x03; 32]),
+                1,
+            );
+        }
+
+        #[ink::test]
+        fn authorize_repayer_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(accounts.bob, 1, 10, 1000, 0),
+                Ok(())
+            );
+
+            // Borrower and owner can always repay
+            assert_eq!(
+                assetmanager.is_authorized_repayer(accounts.bob, 1, accounts.bob),
+                true
+            );
+            assert_eq!(
+                assetmanager.is_authorized_repayer(accounts.bob, 1, accounts.alice),
+                true
+            );
+            assert_eq!(
+                assetmanager.is_authorized_repayer(accounts.bob, 1, accounts.charlie),
+                false
+            );
+
+            set_caller(accounts.charlie);
+            assert_eq!(
+                assetmanager.withdraw(1, accounts.bob),
+                Err(Error::NotAuthorized)
+            );
+            assert_eq!(
+                assetmanager.authorize_repayer(1, accounts.charlie),
+                Err(Error::NoSuchLoan)
+            );
+            ink_env::test::pop_execution_context();
+
+            set_caller(accounts.bob);
+            assert_eq!(assetmanager.authorize_repayer(1, accounts.charlie), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(
+                assetmanager.get_authorized_repayer(accounts.bob, 1),
+                Some(accounts.charlie)
+            );
+            assert_eq!(
+                assetmanager.is_authorized_repayer(accounts.bob, 1, accounts.charlie),
+                true
+            );
+
+            // Closing the loan clears the authorization
+            assert_eq!(assetmanager.handle_repayment(accounts.bob, 1, 0), Ok(()));
+            assert_eq!(assetmanager.get_authorized_repayer(accounts.bob, 1), None);
+        }
 
-            let mut loans: Vec<TokenId> = Vec::new();
-            if borrower_opt.is_some() {
-                let borrower = self.borrowers.get_mut(&borrower_address).unwrap();
-                balance = balance + borrower.balance;
-                loans = borrower.loans.to_vec();
-            }
-            loans.push(token_id);
+        #[ink::test]
+        fn loan_age_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(
+                assetmanager.calculate_days_since_borrowed(borrower, 1),
+                Err(Error::NoSuchLoan)
+            );
+            assert_eq!(
+                assetmanager.get_loan_age_in_seconds(borrower, 1),
+                Err(Error::NoSuchLoan)
+            );
 
-            self.borrowers.insert(
-                borrower_address,
-                Borrower {
-                    balance: balance,
-                    last_updated_at: time,
-                    loans: loans,
-                },
+            assert_eq!(assetmanager.handle_borrow(borrower, 1, 10, 1000, 0), Ok(()));
+            // The off-chain environment only advances time by 5 ms per block
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(assetmanager.get_loan_age_in_seconds(borrower, 1), Ok(0));
+            assert_eq!(
+                assetmanager.calculate_days_since_borrowed(borrower, 1),
+                Ok(0)
             );
 
-            Ok(())
+            assert_eq!(AssetManager::seconds_between(0, 999), 0);
+            assert_eq!(AssetManager::seconds_between(1000, 61_000), 60);
+            assert_eq!(
+                AssetManager::seconds_between(0, 3 * MILLISECONDS_IN_DAY + 1) / SECONDS_IN_DAY,
+                3
+            );
+
+            assert_eq!(assetmanager.handle_repayment(borrower, 1, 0), Ok(()));
+            assert_eq!(
+                assetmanager.calculate_days_since_borrowed(borrower, 1),
+                Err(Error::LoanAlreadyRepaid)
+            );
         }
 
-        fn handle_repayment(
-            &mut self,
-            borrower_address: AccountId,
-            token_id: TokenId,
-            time: u64,
-        ) -> Result<(), Error> {
-            let borrower_opt = self.borrowers.get_mut(&borrower_address);
-            assert_eq!(borrower_opt.is_some(), true, "Borrower does not exist");
-            let loan_opt = self.loans.get_mut(&(borrower_address, token_id));
-            assert_eq!(loan_opt.is_some(), true, "Loan does not exist");
+        #[ink::test]
+        fn health_factor_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.get_health_factor(borrower), u128::MAX);
+            assert_eq!(assetmanager.is_liquidatable(borrower), false);
 
-            let loan = loan_opt.unwrap();
-            assert_eq!(loan.is_repaid, false, "Loan has already been paid");
+            // Borderline: collateral value equals outstanding debt
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 2, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(assetmanager.get_health_factor(borrower), 10_000);
+            assert_eq!(assetmanager.is_liquidatable(borrower), true);
 
-            loan.is_repaid = true;
-            loan.date_repaid = Some(time);
+            // Healthy: collateral is now valued above the liquidation threshold
+            assert_eq!(assetmanager.set_transfer_rate(1100), Ok(()));
+            assert_eq!(assetmanager.get_health_factor(borrower), 11_000);
+            assert_eq!(assetmanager.is_liquidatable(borrower), false);
 
-            let borrower = borrower_opt.unwrap();
-            borrower.balance = borrower.balance - loan.amount;
-            borrower.last_updated_at = time;
+            // Underwater: collateral is worth half of the outstanding debt
+            assert_eq!(assetmanager.set_transfer_rate(500), Ok(()));
+            assert_eq!(assetmanager.get_health_factor(borrower), 5_000);
+            assert_eq!(assetmanager.is_liquidatable(borrower), true);
 
-            Ok(())
+            // Repaid loans no longer count as collateral or debt
+            assert_eq!(assetmanager.handle_repayment(borrower, 1, 0).is_ok(), true);
+            assert_eq!(assetmanager.get_health_factor(borrower), 5_000);
+            assert_eq!(assetmanager.handle_repayment(borrower, 2, 0).is_ok(), true);
+            assert_eq!(assetmanager.get_health_factor(borrower), u128::MAX);
         }
 
-        #[ink(message)]
-        pub fn get_debt_details(
-            &self,
-            borrower: AccountId,
-            token_id: TokenId,
-        ) -> Result<Loan, Error> {
-            let loan = self.loans.get(&(borrower, token_id));
-            if !loan.is_some() {
-                return Err(Error::NoSuchLoan);
-            }
+        #[ink::test]
+        fn utilization_rate_works() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 0);
+            assert_eq!(assetmanager.calculate_utilization_rate(1_000_000), 0);
 
-            Ok(*loan.clone().unwrap())
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 1, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(
+                assetmanager.handle_borrow(borrower, 2, 10, 1000, 0).is_ok(),
+                true
+            );
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 2000);
+            assert_eq!(assetmanager.calculate_utilization_rate(1_000_000), 20);
+
+            assert_eq!(assetmanager.handle_repayment(borrower, 1, 0).is_ok(), true);
+            assert_eq!(
+                assetmanager.handle_repayment(borrower, 1, 0),
+                Err(Error::LoanAlreadyRepaid)
+            );
+            assert_eq!(
+                assetmanager.handle_repayment(borrower, 3, 0),
+                Err(Error::NoSuchLoan)
+            );
+            assert_eq!(assetmanager.get_total_principal_outstanding(), 1000);
+            assert_eq!(assetmanager.calculate_utilization_rate(1_000_000), 10);
+            assert_eq!(assetmanager.calculate_utilization_rate(0), 0);
         }
 
-        fn calculate_interest(
-            &self,
-            amount: u128,
-            interest_rate: u64,
-            current_timestamp: u64,
-            date_borrowed: u64,
-        ) -> Balance {
-            let difference_in_secs: u128 =
-                ((current_timestamp - date_borrowed) as u128 / 1000_u128).into(); // Total time elapsed in seconds
-            let secs_in_day: u128 = 24 * 60 * 60;
-            let difference_in_days: u128 = difference_in_secs / secs_in_day;
-            let mut days_since_borrowed = difference_in_days;
-            if difference_in_secs - (difference_in_days * days_since_borrowed) > 0 {
-                days_since_borrowed = days_since_borrowed + 1;
-            }
+        #[ink::test]
+        fn variable_interest_rate_needs_admin_approval() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            let bob = AccountId::from([0x02; 32]);
+            assert_eq!(assetmanager.add_admin(bob), Ok(()));
+            assert_eq!(assetmanager.set_admin_threshold(2), Ok(()));
 
-            let mut s = 0;
-            let mut n = 1;
-            let mut b = 1;
-            let q: u128 = 365 * 100 / interest_rate as u128;
+            // A single admin can't change the rate
+            assert_eq!(assetmanager.set_variable_interest_rate(5, 20), Ok(()));
+            assert_eq!(assetmanager.get_all_config().interest_rate, 10);
+            assert_eq!(assetmanager.get_utilization_multiplier(), 0);
+            // Nor can another admin approving different rates
+            set_caller(bob);
+            assert_eq!(assetmanager.set_variable_interest_rate(5, 21), Ok(()));
+            assert_eq!(assetmanager.get_utilization_multiplier(), 0);
 
-            // let mut p = 8_u32;
-            // if p < days_since_borrowed as u32 {
-            //     p = days_since_borrowed as u32;
-            // }
-            for x in 0..8 {
-                s = s + amount * n / b / (q.pow(x));
-                if days_since_borrowed < x.into() {
-                    break;
-                }
-                n = n * (days_since_borrowed - x as u128);
-                b = b * (x as u128 + 1);
-            }
-            s - amount
+            assert_eq!(assetmanager.set_variable_interest_rate(5, 20), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(assetmanager.get_all_config().interest_rate, 5);
+            assert_eq!(assetmanager.get_utilization_multiplier(), 20);
         }
 
-        fn get_current_time(&self) -> u64 {
-            self.env().block_timestamp()
+        #[ink::test]
+        fn variable_interest_rate_change_is_timelocked() {
+            let mut assetmanager = AssetManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                1000,
+                true,
+            );
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            assert_eq!(
+                assetmanager.propose_variable_interest_rate_change(5, 20),
+                Ok(())
+            );
+            assert_eq!(
+                assetmanager.get_pending_change(VARIABLE_INTEREST_RATE_PARAMETER),
+                Some((AssetManager::pack_variable_rate(5, 20), 10))
+            );
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>().unwrap();
+            }
+            assert_eq!(assetmanager.execute_variable_interest_rate_change(), Ok(()));
+            assert_eq!(assetmanager.get_all_config().interest_rate, 5);
+            assert_eq!(assetmanager.get_utilization_multiplier(), 20);
         }
-    }
 
-    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
-    /// module and test functions are marked with a `#[test]` attribute.
-    /// The below code is technically just normal Rust code.
-    #[cfg(test)]
-    mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
-        use ink_lang as ink;
-        /// We test if the constructor does its job.
-        fn instantiate_erc20_contract() -> AccountId {
-            let erc20 = Erc20::new(1000000);
-            let callee =
-                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
-            callee
-        }
-        fn instantiate_erc721_contract() -> AccountId {
-            let erc20 = Erc721::new();
-            let callee =
-                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
-            callee
-        }
         #[ink::test]
-        fn new_works() {
-            let assetmanager = AssetManager::new(
+        fn variable_interest_rate_set_while_timelocked_fails() {
+            let mut assetmanager = AssetManager::new(
                 instantiate_erc20_contract(),
                 instantiate_erc721_contract(),
                 10,
                 1000,
                 true,
             );
-            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.set_timelock_delay(10), Ok(()));
+            assert_eq!(
+                assetmanager.set_variable_interest_rate(5, 20),
+                Err(Error::Timelocked)
+            );
+            assert_eq!(assetmanager.set_interest_rate(5), Err(Error::Timelocked));
+            assert_eq!(assetmanager.set_transfer_rate(5), Err(Error::Timelocked));
             assert_eq!(assetmanager.get_interest_rate(), 10);
             assert_eq!(assetmanager.get_transfer_rate(), 1000);
         }
 
         #[ink::test]
-        fn enable_works() {
+        fn variable_interest_rate_works() {
             let mut assetmanager = AssetManager::new(
                 instantiate_erc20_contract(),
                 instantiate_erc721_contract(),
-                7,
-                100,
-                false,
+                10,
+                1000,
+                true,
             );
-            assert_eq!(assetmanager.is_enabled(), false);
-            assert_eq!(assetmanager.get_interest_rate(), 7);
-            assert_eq!(assetmanager.get_transfer_rate(), 100);
+            let borrower = AccountId::from([0x01; 32]);
+            // Fixed rate by default
+            assert_eq!(assetmanager.get_utilization_multiplier(), 0);
+            assert_eq!(assetmanager.get_current_borrow_rate(), 10);
 
-            assetmanager.enable();
-            assert_eq!(assetmanager.is_enabled(), true);
+            assert_eq!(assetmanager.set_variable_interest_rate(5, 20), Ok(()));
+            assert_eq!(assetmanager.get_all_config().interest_rate, 5);
+            assert_eq!(assetmanager.get_utilization_multiplier(), 20);
+            let supply = 10_000;
+            let rate_at = |assetmanager: &AssetManager| {
+                assetmanager.calculate_borrow_rate(assetmanager.calculate_utilization_rate(supply))
+            };
+            assert_eq!(rate_at(&assetmanager), 5);
+
+            // Each borrow takes 10% of the supply and adds 2 to the rate
+            let mut previous_rate = rate_at(&assetmanager);
+            for token_id in 1..=5 {
+                assert_eq!(
+                    assetmanager
+                        .handle_borrow(borrower, token_id, 5, 1000, 0)
+                        .is_ok(),
+                    true
+                );
+                let rate = rate_at(&assetmanager);
+                assert!(rate > previous_rate);
+                previous_rate = rate;
+            }
+            assert_eq!(assetmanager.calculate_utilization_rate(supply), 5_000);
+            assert_eq!(rate_at(&assetmanager), 15);
+            assert_eq!(assetmanager.calculate_borrow_rate(10_000), 25);
+
+            // Setting the multiplier back to 0 restores a fixed rate
+            assert_eq!(assetmanager.set_variable_interest_rate(7, 0), Ok(()));
+            assert_eq!(assetmanager.get_current_borrow_rate(), 7);
+            assert_eq!(assetmanager.get_interest_rate(), 7);
         }
 
         #[ink::test]
-        fn disable_works() {
+        fn protocol_snapshot_works() {
             let mut assetmanager = AssetManager::new(
                 instantiate_erc20_contract(),
                 instantiate_erc721_contract(),
-                7,
-                100,
+                10,
+                1_000_000_000_000,
                 true,
             );
-            assert_eq!(assetmanager.is_enabled(), true);
-            assert_eq!(assetmanager.get_interest_rate(), 7);
-            assert_eq!(assetmanager.get_transfer_rate(), 100);
+            let first = AccountId::from([0x02; 32]);
+            let second = AccountId::from([0x03; 32]);
+            let supply = 10_000_000_000_000;
+            assert_eq!(
+                assetmanager.build_protocol_snapshot(supply, supply),
+                ProtocolSnapshot {
+                    current_interest_rate: 10,
+                    transfer_rate: 1_000_000_000_000,
+                    enabled: true,
+                    erc20_reserve_balance: supply,
+                    ..Default::default()
+                }
+            );
 
-            assetmanager.disable();
-            assert_eq!(assetmanager.is_enabled(), false);
+            for (borrower, token_id) in [(first, 1), (first, 2), (second, 3)].iter() {
+                assert_eq!(
+                    assetmanager
+                        .handle_borrow(*borrower, *token_id, 10, 1_000_000_000_000, 0)
+                        .is_ok(),
+                    true
+                );
+            }
+            assert_eq!(assetmanager.handle_repayment(first, 2, 0).is_ok(), true);
+            assert_eq!(assetmanager.set_variable_interest_rate(10, 20), Ok(()));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+
+            assert_eq!(
+                assetmanager.build_protocol_snapshot(supply, 8_000_000_000_000),
+                ProtocolSnapshot {
+                    total_loans: 3,
+                    total_active_loans: 2,
+                    total_principal_outstanding: 2_000_000_000_000,
+                    total_interest_accrued: assetmanager.get_total_debt_of_loan(first, 1)
+                        + assetmanager.get_total_debt_of_loan(second, 3),
+                    erc20_reserve_balance: 8_000_000_000_000,
+                    // 20% of the supply is lent out
+                    utilization_rate: 2_000,
+                    current_interest_rate: 14,
+                    transfer_rate: 1_000_000_000_000,
+                    enabled: true,
+                    total_borrowers: 2,
+                }
+            );
         }
 
         #[ink::test]
-        fn set_interest_rate_works() {
+        fn flash_fee_works() {
             let mut assetmanager = AssetManager::new(
                 instantiate_erc20_contract(),
                 instantiate_erc721_contract(),
-                7,
-                100,
+                10,
+                1000,
                 true,
             );
+            assert_eq!(assetmanager.get_flash_fee_bps(), 0);
+            assert_eq!(assetmanager.calculate_flash_fee(1_000_000), 0);
 
-            assert_eq!(assetmanager.is_enabled(), true);
-            assert_eq!(assetmanager.get_interest_rate(), 7);
-            assert_eq!(assetmanager.get_transfer_rate(), 100);
+            assert_eq!(assetmanager.set_flash_fee_bps(9), Ok(()));
+            assert_eq!(assetmanager.get_flash_fee_bps(), 9);
+            assert_eq!(assetmanager.calculate_flash_fee(1_000_000), 900);
+            assert_eq!(assetmanager.calculate_flash_fee(1_000), 0);
+        }
 
-            assetmanager.set_interest_rate(8);
-            assert_eq!(assetmanager.get_interest_rate(), 8);
+        #[ink::test]
+        fn flash_loan_repaid_works() {
+            AssetManager::ensure_flash_loan_repaid(true, 1000, 1009, 9);
+            AssetManager::ensure_flash_loan_repaid(true, 1000, 1100, 9);
         }
 
         #[ink::test]
-        fn set_transfer_rate_works() {
+        #[should_panic(expected = "Flash loan not repaid")]
+        fn flash_loan_not_repaid_reverts() {
+            // The receiver kept the loan: the panic reverts the transfer out of the pool
+            AssetManager::ensure_flash_loan_repaid(true, 1000, 0, 9);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Flash loan not repaid")]
+        fn flash_loan_fee_not_paid_reverts() {
+            AssetManager::ensure_flash_loan_repaid(true, 1000, 1008, 9);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Flash loan not repaid")]
+        fn flash_loan_callback_failure_reverts() {
+            AssetManager::ensure_flash_loan_repaid(false, 1000, 1009, 9);
+        }
+
+        #[ink::test]
+        fn interest_rate_model_works() {
             let mut assetmanager = AssetManager::new(
                 instantiate_erc20_contract(),
                 instantiate_erc721_contract(),
-                7,
-                100,
+                10,
+                1000,
                 true,
             );
+            assert_eq!(
+                assetmanager.get_interest_rate_model(),
+                InterestModel::Compound as u8
+            );
+            let erc20_decimals = 1000_000_000_000;
+            let compound_day =
+                assetmanager.calculate_interest(1 * erc20_decimals, 7, 86401 * 1000, 86400 * 1000);
+            let compound_year = assetmanager.calculate_interest(
+                1 * erc20_decimals,
+                10,
+                86400 * 365 * 1000,
+                86400 * 1000,
+            );
 
-            assert_eq!(assetmanager.is_enabled(), true);
-            assert_eq!(assetmanager.get_interest_rate(), 7);
-            assert_eq!(assetmanager.get_transfer_rate(), 100);
+            assert_eq!(
+                assetmanager.set_interest_rate_model(InterestModel::Linear as u8),
+                Ok(())
+            );
+            assert_eq!(
+                assetmanager.get_interest_rate_model(),
+                InterestModel::Linear as u8
+            );
+            let linear_day =
+                assetmanager.calculate_interest(1 * erc20_decimals, 7, 86401 * 1000, 86400 * 1000);
+            let linear_year = assetmanager.calculate_interest(
+                1 * erc20_decimals,
+                10,
+                86400 * 365 * 1000,
+                86400 * 1000,
+            );
 
-            assetmanager.set_transfer_rate(110);
-            assert_eq!(assetmanager.get_transfer_rate(), 110);
+            // Models barely differ for a single day
+            assert_eq!(linear_day, 191_780_821);
+            assert_eq!(compound_day - linear_day, 10_510);
+            // but diverge over a year
+            assert_eq!(linear_year, 100_000_000_000);
+            assert_eq!(compound_year - linear_year, 5_155_781_613);
         }
 
         #[ink::test]
-        #[should_panic]
-        fn borrow_disabled_works() {
-            // Disabled should panic
+        #[should_panic(expected = "Unknown interest rate model")]
+        fn interest_rate_model_unknown_fails() {
             let mut assetmanager = AssetManager::new(
                 instantiate_erc20_contract(),
                 instantiate_erc721_contract(),
                 10,
                 1000,
-                false,
-            );
-            assert_eq!(assetmanager.is_enabled(), false);
-            let owner = AccountId::from([0x01; 32]);
-            assert!(
-                assetmanager.deposit(1, owner).is_err(),
-                "Should not allow deposit in disabled state"
-            );
-
-            assetmanager.enable();
-            assert_eq!(assetmanager.is_enabled(), true);
-            assert!(
-                assetmanager.deposit(1, owner).is_err(),
-                "Should not allow deposit when erc721 allowance is not made"
+                true,
             );
+            let _ = assetmanager.set_interest_rate_model(2);
         }
 
         #[ink::test]
@@ -18,10 +18,7 @@ use ink_lang as ink;
 
 #[ink::contract]
 pub mod erc20 {
-    use ink_storage::{
-        collections::HashMap as StorageHashMap,
-        lazy::Lazy,
-    };
+    use ink_storage::{collections::HashMap as StorageHashMap, lazy::Lazy};
 
     /// A simple ERC-20 contract.
     #[ink(storage)]
@@ -71,6 +68,9 @@ pub mod erc20 {
     /// The ERC-20 result type.
     pub type Result<T> = core::result::Result<T, Error>;
 
+    /// Number of decimals token amounts are expressed with.
+    pub const DECIMALS: u8 = 12;
+
     impl Erc20 {
         /// Creates a new ERC-20 contract with the specified initial supply.
         #[ink(constructor)]
@@ -97,6 +97,14 @@ pub mod erc20 {
             *self.total_supply
         }
 
+        /// Returns the number of decimals token amounts are expressed with.
+        ///
+        /// One whole token is `1_000_000_000_000` units.
+        #[ink(message)]
+        pub fn decimals(&self) -> u8 {
+            DECIMALS
+        }
+
         /// Returns the account balance for the specified `owner`.
         ///
         /// Returns `0` if the account is non-existent.
@@ -169,7 +177,7 @@ pub mod erc20 {
             let caller = self.env().caller();
             let allowance = self.allowance(from, caller);
             if allowance < value {
-                return Err(Error::InsufficientAllowance)
+                return Err(Error::InsufficientAllowance);
             }
             self.transfer_from_to(from, to, value)?;
             self.allowances.insert((from, caller), allowance - value);
@@ -192,7 +200,7 @@ pub mod erc20 {
         ) -> Result<()> {
             let from_balance = self.balance_of(from);
             if from_balance < value {
-                return Err(Error::InsufficientBalance)
+                return Err(Error::InsufficientBalance);
             }
             self.balances.insert(from, from_balance - value);
             let to_balance = self.balance_of(to);
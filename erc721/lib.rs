@@ -19,6 +19,7 @@ use ink_lang as ink;
 
 #[ink::contract]
 pub mod erc721 {
+    use ink_prelude::{string::String, vec::Vec};
     use ink_storage::collections::{hashmap::Entry, HashMap as StorageHashMap};
     use scale::{Decode, Encode};
 
@@ -36,6 +37,56 @@ pub mod erc721 {
         owned_tokens_count: StorageHashMap<AccountId, u32>,
         /// Mapping from owner to operator approvals.
         operator_approvals: StorageHashMap<(AccountId, AccountId), bool>,
+        /// Mapping from owner to approved operators.
+        owner_operators: StorageHashMap<AccountId, Vec<AccountId>>,
+        /// Mapping from owner to owned tokens.
+        owner_tokens: StorageHashMap<AccountId, Vec<TokenId>>,
+        /// Number of tokens currently in existence.
+        total_supply: u32,
+        /// Maximum number of tokens that can exist at once, if capped.
+        max_supply: Option<u32>,
+        /// Account that instantiated the contract.
+        owner: AccountId,
+        /// Accounts allowed to mint tokens to other accounts.
+        minters: StorageHashMap<AccountId, bool>,
+        /// Mapping from token to its metadata URI.
+        token_uris: StorageHashMap<TokenId, String>,
+        /// Prefix prepended to every token URI.
+        base_uri: String,
+        /// Name of the token collection.
+        name: String,
+        /// Symbol of the token collection.
+        symbol: String,
+        /// Maximum number of tokens that can be minted in a single batch.
+        max_batch_size: u32,
+        /// Whether transfers, mints and burns are halted.
+        paused: bool,
+        /// Mapping from token to its royalty recipient.
+        royalty_recipients: StorageHashMap<TokenId, AccountId>,
+        /// Mapping from token to its royalty in basis points of the sale price.
+        royalty_bps: StorageHashMap<TokenId, u16>,
+        /// Identifier of the collection the contract belongs to, if any.
+        collection_id: Option<u32>,
+        /// All tokens currently in existence in minting order.
+        all_tokens: Vec<TokenId>,
+        /// Mapping from token to the timestamp until which it cannot be transferred.
+        locked_tokens: StorageHashMap<TokenId, u64>,
+        /// Mapping from locked token to the account that locked it.
+        token_lockers: StorageHashMap<TokenId, AccountId>,
+        /// Mapping from owner and operator to the timestamp the operator approval expires at.
+        operator_expiries: StorageHashMap<(AccountId, AccountId), u64>,
+        /// Mapping from token to whether its metadata URI can no longer change.
+        metadata_frozen: StorageHashMap<TokenId, bool>,
+        /// Mapping from snapshot and owner to the number of tokens owned when it was taken.
+        snapshot_balances: StorageHashMap<(u32, AccountId), u32>,
+        /// Mapping from snapshot to the total supply when it was taken.
+        snapshot_supplies: StorageHashMap<u32, u32>,
+        /// Id of the most recent snapshot, 0 if none was taken.
+        latest_snapshot_id: u32,
+        /// Mapping from token to the kind of its transfer restriction.
+        token_restrictions: StorageHashMap<TokenId, u8>,
+        /// Mapping from token to the timestamp its `LockedUntil` restriction lapses at.
+        restriction_expiries: StorageHashMap<TokenId, u64>,
     }
 
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
@@ -49,6 +100,36 @@ pub mod erc721 {
         CannotRemove,
         CannotFetchValue,
         NotAllowed,
+        CapExceeded,
+        BatchSizeExceeded,
+        ContractPaused,
+        TokenLocked,
+        TokenNotLocked,
+        MetadataFrozen,
+    }
+
+    /// Limits on who can transfer a token and when.
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum TransferRestriction {
+        None,
+        /// The token can never be transferred nor have its restriction changed.
+        SoulboundPermanent,
+        /// The token cannot be transferred until the given timestamp.
+        LockedUntil(u64),
+        /// Only the owner can transfer the token, operators and approved accounts cannot.
+        OwnerOnly,
+    }
+
+    impl TransferRestriction {
+        fn kind(&self) -> u8 {
+            match self {
+                TransferRestriction::None => 0,
+                TransferRestriction::SoulboundPermanent => 1,
+                TransferRestriction::LockedUntil(_) => 2,
+                TransferRestriction::OwnerOnly => 3,
+            }
+        }
     }
 
     /// Event emitted when a token transfer occurs.
@@ -60,6 +141,31 @@ pub mod erc721 {
         to: Option<AccountId>,
         #[ink(topic)]
         id: TokenId,
+        data: Option<Vec<u8>>,
+    }
+
+    /// Event emitted when a token transfer with attached data occurs.
+    #[ink(event)]
+    pub struct TransferWithData {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+        #[ink(topic)]
+        id: TokenId,
+        data: Vec<u8>,
+    }
+
+    /// Event emitted when an approved account or operator transfers a token it does not own.
+    #[ink(event)]
+    pub struct OperatorTransfer {
+        #[ink(topic)]
+        operator: AccountId,
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+        id: TokenId,
     }
 
     /// Event emitted when a token approve occurs.
@@ -84,6 +190,88 @@ pub mod erc721 {
         approved: bool,
     }
 
+    /// Event emitted when several tokens are minted at once.
+    #[ink(event)]
+    pub struct BatchMint {
+        #[ink(topic)]
+        minter: AccountId,
+        count: u32,
+        first_id: TokenId,
+        last_id: TokenId,
+    }
+
+    /// Event emitted when tokens are burned in a batch.
+    #[ink(event)]
+    pub struct BatchBurn {
+        #[ink(topic)]
+        burner: AccountId,
+        count: u32,
+        burned_ids: Vec<TokenId>,
+        failed_ids: Vec<TokenId>,
+    }
+
+    /// Event emitted when the metadata URI of a token changes.
+    #[ink(event)]
+    pub struct MetadataUpdate {
+        #[ink(topic)]
+        id: TokenId,
+    }
+
+    /// Event emitted when the metadata URI of a token is frozen for good.
+    #[ink(event)]
+    pub struct MetadataFrozen {
+        #[ink(topic)]
+        id: TokenId,
+    }
+
+    /// Event emitted when a token is locked against transfers.
+    #[ink(event)]
+    pub struct Locked {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        locker: AccountId,
+        until: u64,
+    }
+
+    /// Event emitted when a token is unlocked before its lock expired.
+    #[ink(event)]
+    pub struct Unlocked {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        locker: AccountId,
+    }
+
+    /// Event emitted when the owner changes the transfer restriction of a token.
+    #[ink(event)]
+    pub struct RestrictionUpdated {
+        #[ink(topic)]
+        id: TokenId,
+        restriction: TransferRestriction,
+    }
+
+    /// Event emitted when the contract owner records the balances of all owners.
+    #[ink(event)]
+    pub struct Snapshot {
+        #[ink(topic)]
+        id: u32,
+    }
+
+    /// Event emitted when the contract owner halts transfers.
+    #[ink(event)]
+    pub struct Paused {
+        #[ink(topic)]
+        by: AccountId,
+    }
+
+    /// Event emitted when the contract owner resumes transfers.
+    #[ink(event)]
+    pub struct Unpaused {
+        #[ink(topic)]
+        by: AccountId,
+    }
+
     impl Erc721 {
         /// Creates a new ERC721 token contract.
         #[ink(constructor)]
@@ -93,104 +281,715 @@ pub mod erc721 {
                 token_approvals: Default::default(),
                 owned_tokens_count: Default::default(),
                 operator_approvals: Default::default(),
+                owner_operators: Default::default(),
+                owner_tokens: Default::default(),
+                total_supply: 0,
+                max_supply: None,
+                owner: Self::env().caller(),
+                minters: Default::default(),
+                token_uris: Default::default(),
+                base_uri: Default::default(),
+                name: Default::default(),
+                symbol: Default::default(),
+                max_batch_size: 100,
+                paused: false,
+                royalty_recipients: Default::default(),
+                royalty_bps: Default::default(),
+                collection_id: None,
+                all_tokens: Default::default(),
+                locked_tokens: Default::default(),
+                token_lockers: Default::default(),
+                operator_expiries: Default::default(),
+                metadata_frozen: Default::default(),
+                snapshot_balances: Default::default(),
+                snapshot_supplies: Default::default(),
+                latest_snapshot_id: 0,
+                token_restrictions: Default::default(),
+                restriction_expiries: Default::default(),
+            }
+        }
+
+        /// Creates a new ERC721 token contract with the given collection name and symbol.
+        #[ink(constructor)]
+        pub fn new_with_metadata(name: String, symbol: String) -> Self {
+            Self {
+                name,
+                symbol,
+                ..Self::new()
             }
         }
 
-        /// Returns the balance of the owner.
-        ///
-        /// This represents the amount of unique tokens the owner has.
+        /// Creates a new ERC721 token contract that allows at most `max_supply` tokens.
+        #[ink(constructor)]
+        pub fn new_with_cap(max_supply: u32) -> Self {
+            Self {
+                max_supply: Some(max_supply),
+                ..Self::new()
+            }
+        }
+
+        /// Returns the name of the token collection.
+        #[ink(message)]
+        pub fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        /// Returns the symbol of the token collection.
+        #[ink(message)]
+        pub fn symbol(&self) -> String {
+            self.symbol.clone()
+        }
+
+        /// Returns the number of tokens currently in existence.
+        #[ink(message)]
+        pub fn total_supply(&self) -> u32 {
+            self.total_supply
+        }
+
+        /// Returns the balance of the owner.
+        ///
+        /// This represents the amount of unique tokens the owner has.
+        #[ink(message)]
+        pub fn balance_of(&self, owner: AccountId) -> u32 {
+            self.balance_of_or_zero(&owner)
+        }
+
+        /// Returns all the tokens owned by the owner.
+        #[ink(message)]
+        pub fn get_tokens_of(&self, owner: AccountId) -> Vec<TokenId> {
+            self.owner_tokens.get(&owner).cloned().unwrap_or_default()
+        }
+
+        /// Returns the token at `index` of the tokens owned by the owner.
+        #[ink(message)]
+        pub fn token_of_owner_by_index(
+            &self,
+            owner: AccountId,
+            index: u32,
+        ) -> Result<TokenId, Error> {
+            self.owner_tokens
+                .get(&owner)
+                .and_then(|tokens| tokens.get(index as usize))
+                .cloned()
+                .ok_or(Error::TokenNotFound)
+        }
+
+        /// Returns the owner of the token.
+        #[ink(message)]
+        pub fn owner_of(&self, id: TokenId) -> Option<AccountId> {
+            self.token_owner.get(&id).cloned()
+        }
+
+        /// Returns the approved account ID for this token if any.
+        #[ink(message)]
+        pub fn get_approved(&self, id: TokenId) -> Option<AccountId> {
+            self.token_approvals.get(&id).cloned()
+        }
+
+        /// Returns `true` if the operator is approved by the owner.
+        #[ink(message)]
+        pub fn is_approved_for_all(&self, owner: AccountId, operator: AccountId) -> bool {
+            self.approved_for_all(owner, operator)
+        }
+
+        /// Approves or disapproves the operator for all tokens of the caller.
+        #[ink(message)]
+        pub fn set_approval_for_all(&mut self, to: AccountId, approved: bool) -> Result<(), Error> {
+            self.set_approval_for_all_with_expiry(to, approved, u64::MAX)
+        }
+
+        /// Approves or disapproves the operator for all tokens of the caller
+        /// until the `expiry` timestamp has passed.
+        #[ink(message)]
+        pub fn set_approval_for_all_with_expiry(
+            &mut self,
+            operator: AccountId,
+            approved: bool,
+            expiry: u64,
+        ) -> Result<(), Error> {
+            self.approve_for_all(operator, approved)?;
+            let caller = self.env().caller();
+            self.operator_expiries.insert((caller, operator), expiry);
+            Ok(())
+        }
+
+        /// Returns the timestamp the operator approval of the owner expires at.
+        #[ink(message)]
+        pub fn get_operator_expiry(&self, owner: AccountId, operator: AccountId) -> Option<u64> {
+            self.operator_expiries.get(&(owner, operator)).copied()
+        }
+
+        /// Returns the operators approved for all tokens of the owner.
+        #[ink(message)]
+        pub fn get_operator_list(&self, owner: AccountId) -> Vec<AccountId> {
+            self.owner_operators
+                .get(&owner)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// Disapproves every operator of the caller.
+        #[ink(message)]
+        pub fn revoke_all_operators(&mut self) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let operators = self.owner_operators.take(&caller).unwrap_or_default();
+            for operator in operators {
+                self.operator_approvals.insert((caller, operator), false);
+                self.env().emit_event(ApprovalForAll {
+                    owner: caller,
+                    operator,
+                    approved: false,
+                });
+            }
+            Ok(())
+        }
+
+        /// Approves the account to transfer the specified token on behalf of the caller.
+        #[ink(message)]
+        pub fn approve(&mut self, to: AccountId, id: TokenId) -> Result<(), Error> {
+            self.approve_for(&to, id)?;
+            Ok(())
+        }
+
+        /// Returns the metadata URI of the token prefixed with the base URI.
+        ///
+        /// Returns `None` if no URI has been set for the token.
+        #[ink(message)]
+        pub fn token_uri(&self, id: TokenId) -> Option<String> {
+            let uri = self.token_uris.get(&id)?;
+            let mut full_uri = self.base_uri.clone();
+            full_uri.push_str(uri);
+            Some(full_uri)
+        }
+
+        /// Sets the metadata URI of the token. Only the owner or an approved account can set it.
+        #[ink(message)]
+        pub fn set_token_uri(&mut self, id: TokenId, uri: String) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if !self.approved_or_owner(Some(caller), id) {
+                return Err(Error::NotOwner);
+            };
+            if self.is_metadata_frozen(id) {
+                return Err(Error::MetadataFrozen);
+            };
+            self.token_uris.insert(id, uri);
+            self.env().emit_event(MetadataUpdate { id });
+            Ok(())
+        }
+
+        /// Permanently prevents the metadata URI of the token from changing.
+        /// Only the token owner can freeze it. The base URI is not affected.
+        #[ink(message)]
+        pub fn freeze_metadata(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if self.owner_of(id) != Some(caller) {
+                return Err(Error::NotOwner);
+            };
+            if self.is_metadata_frozen(id) {
+                return Err(Error::MetadataFrozen);
+            };
+            self.metadata_frozen.insert(id, true);
+            self.env().emit_event(MetadataFrozen { id });
+            Ok(())
+        }
+
+        /// Returns true if the metadata URI of the token can no longer change.
+        #[ink(message)]
+        pub fn is_metadata_frozen(&self, id: TokenId) -> bool {
+            *self.metadata_frozen.get(&id).unwrap_or(&false)
+        }
+
+        /// Sets the prefix of all token URIs. Only the contract owner can set it.
+        #[ink(message)]
+        pub fn set_base_uri(&mut self, uri: String) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            };
+            self.base_uri = uri;
+            Ok(())
+        }
+
+        /// Returns the identifier of the collection the contract belongs to.
+        #[ink(message)]
+        pub fn collection_id(&self) -> Option<u32> {
+            self.collection_id
+        }
+
+        /// Sets the identifier of the collection. Only the contract owner can set it.
+        #[ink(message)]
+        pub fn set_collection_id(&mut self, collection_id: Option<u32>) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            };
+            self.collection_id = collection_id;
+            Ok(())
+        }
+
+        /// Returns the number of tokens currently in existence in the collection.
+        #[ink(message)]
+        pub fn get_tokens_count_for_collection(&self) -> u32 {
+            self.all_tokens.len() as u32
+        }
+
+        /// Returns at most `count` existing tokens starting at index `start`, in minting order.
+        #[ink(message)]
+        pub fn get_all_token_ids_paginated(&self, start: u32, count: u32) -> Vec<TokenId> {
+            self.all_tokens
+                .iter()
+                .skip(start as usize)
+                .take(count as usize)
+                .copied()
+                .collect()
+        }
+
+        /// Prevents the token from being transferred until `until`.
+        /// Only the owner or an operator of the owner can lock the token.
+        #[ink(message)]
+        pub fn lock_token(&mut self, id: TokenId, until: u64) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let owner = self.owner_of(id).ok_or(Error::TokenNotFound)?;
+            if caller != owner && !self.approved_for_all(owner, caller) {
+                return Err(Error::NotApproved);
+            };
+            if self.is_token_locked(id) {
+                return Err(Error::TokenLocked);
+            };
+            self.locked_tokens.insert(id, until);
+            self.token_lockers.insert(id, caller);
+            self.env().emit_event(Locked {
+                id,
+                locker: caller,
+                until,
+            });
+            Ok(())
+        }
+
+        /// Lifts the lock of the token before it expires. Only the locker can unlock the token.
+        #[ink(message)]
+        pub fn unlock_token(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.is_token_locked(id) {
+                return Err(Error::TokenNotLocked);
+            };
+            if self.token_lockers.get(&id) != Some(&caller) {
+                return Err(Error::NotAllowed);
+            };
+            self.locked_tokens.take(&id);
+            self.token_lockers.take(&id);
+            self.env().emit_event(Unlocked { id, locker: caller });
+            Ok(())
+        }
+
+        /// Returns true if the token cannot be transferred at the moment.
+        #[ink(message)]
+        pub fn is_token_locked(&self, id: TokenId) -> bool {
+            self.locked_tokens
+                .get(&id)
+                .map_or(false, |until| *until > self.env().block_timestamp())
+        }
+
+        /// Restricts who can transfer the token and when. Only the owner can set the restriction
+        /// and `SoulboundPermanent` tokens keep their restriction forever.
+        #[ink(message)]
+        pub fn set_transfer_restriction(
+            &mut self,
+            id: TokenId,
+            restriction: TransferRestriction,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let owner = self.owner_of(id).ok_or(Error::TokenNotFound)?;
+            if caller != owner {
+                return Err(Error::NotOwner);
+            };
+            if self.get_transfer_restriction(id) == TransferRestriction::SoulboundPermanent {
+                return Err(Error::NotAllowed);
+            };
+            self.restriction_expiries.take(&id);
+            match restriction {
+                TransferRestriction::None => {
+                    self.token_restrictions.take(&id);
+                }
+                TransferRestriction::LockedUntil(until) => {
+                    self.token_restrictions.insert(id, restriction.kind());
+                    self.restriction_expiries.insert(id, until);
+                }
+                _ => {
+                    self.token_restrictions.insert(id, restriction.kind());
+                }
+            }
+            self.env()
+                .emit_event(RestrictionUpdated { id, restriction });
+            Ok(())
+        }
+
+        /// Returns the transfer restriction of the token.
+        #[ink(message)]
+        pub fn get_transfer_restriction(&self, id: TokenId) -> TransferRestriction {
+            match self.token_restrictions.get(&id) {
+                Some(1) => TransferRestriction::SoulboundPermanent,
+                Some(2) => TransferRestriction::LockedUntil(
+                    *self.restriction_expiries.get(&id).unwrap_or(&0),
+                ),
+                Some(3) => TransferRestriction::OwnerOnly,
+                _ => TransferRestriction::None,
+            }
+        }
+
+        /// Returns the royalty recipient and amount owed for selling the token at `sale_price`.
+        #[ink(message)]
+        pub fn royalty_info(&self, token_id: TokenId, sale_price: Balance) -> (AccountId, Balance) {
+            match (
+                self.royalty_recipients.get(&token_id),
+                self.royalty_bps.get(&token_id),
+            ) {
+                (Some(recipient), Some(bps)) => {
+                    (*recipient, sale_price * Balance::from(*bps) / 10_000)
+                }
+                _ => (AccountId::from([0x0; 32]), 0),
+            }
+        }
+
+        /// Sets the royalty of the token in basis points. Only the token owner can set the royalty.
+        #[ink(message)]
+        pub fn set_royalty_info(
+            &mut self,
+            id: TokenId,
+            recipient: AccountId,
+            bps: u16,
+        ) -> Result<(), Error> {
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if self.owner_of(id) != Some(self.env().caller()) {
+                return Err(Error::NotOwner);
+            };
+            if bps > 10_000 {
+                return Err(Error::NotAllowed);
+            };
+            self.royalty_recipients.insert(id, recipient);
+            self.royalty_bps.insert(id, bps);
+            Ok(())
+        }
+
+        /// Removes the royalty of the token. Only the token owner can remove the royalty.
+        #[ink(message)]
+        pub fn delete_royalty_info(&mut self, id: TokenId) -> Result<(), Error> {
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if self.owner_of(id) != Some(self.env().caller()) {
+                return Err(Error::NotOwner);
+            };
+            self.royalty_recipients.take(&id);
+            self.royalty_bps.take(&id);
+            Ok(())
+        }
+
+        /// Transfers the token from the caller to the given destination.
+        #[ink(message)]
+        pub fn transfer(&mut self, destination: AccountId, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.transfer_token_from(&caller, &destination, id, None)?;
+            Ok(())
+        }
+
+        /// Transfers the token from the caller to the given destination, attaching `data` to the emitted events.
+        #[ink(message)]
+        pub fn transfer_with_data(
+            &mut self,
+            destination: AccountId,
+            id: TokenId,
+            data: Vec<u8>,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.transfer_token_from(&caller, &destination, id, Some(data.clone()))?;
+            self.env().emit_event(TransferWithData {
+                from: caller,
+                to: destination,
+                id,
+                data,
+            });
+            Ok(())
+        }
+
+        /// Transfer approved or owned token.
+        #[ink(message)]
+        pub fn transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            id: TokenId,
+        ) -> Result<(), Error> {
+            self.transfer_token_from(&from, &to, id, None)?;
+            Ok(())
+        }
+
+        /// Transfers a token on behalf of its owner, emitting an `OperatorTransfer` event
+        /// after the `Transfer` event. The caller must not own the token.
+        #[ink(message)]
+        pub fn operator_transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            id: TokenId,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if self.owner_of(id) == Some(caller) {
+                return Err(Error::NotAllowed);
+            };
+            self.transfer_token_from(&from, &to, id, None)?;
+            self.env().emit_event(OperatorTransfer {
+                operator: caller,
+                from,
+                to,
+                id,
+            });
+            Ok(())
+        }
+
+        /// Creates a new token.
+        #[ink(message)]
+        pub fn mint(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.mint_token_to(&caller, id)?;
+            Ok(())
+        }
+
+        /// Creates a new token for the given account. Only minters can mint to other accounts.
+        #[ink(message)]
+        pub fn mint_to(&mut self, to: AccountId, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.is_minter(caller) {
+                return Err(Error::NotAllowed);
+            };
+            self.mint_token_to(&to, id)?;
+            Ok(())
+        }
+
+        /// Allows the account to mint tokens to other accounts. Only the contract owner can add minters.
+        #[ink(message)]
+        pub fn add_minter(&mut self, minter: AccountId) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            };
+            self.minters.insert(minter, true);
+            Ok(())
+        }
+
+        /// Disallows the account to mint tokens to other accounts. Only the contract owner can remove minters.
+        #[ink(message)]
+        pub fn remove_minter(&mut self, minter: AccountId) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            };
+            self.minters.take(&minter);
+            Ok(())
+        }
+
+        /// Returns `true` if the account is allowed to mint tokens to other accounts.
+        #[ink(message)]
+        pub fn is_minter(&self, addr: AccountId) -> bool {
+            addr == self.owner || *self.minters.get(&addr).unwrap_or(&false)
+        }
+
+        /// Halts all transfers, mints and burns. Only the contract owner can pause the contract.
         #[ink(message)]
-        pub fn balance_of(&self, owner: AccountId) -> u32 {
-            self.balance_of_or_zero(&owner)
+        pub fn pause_transfers(&mut self) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::NotOwner);
+            };
+            self.paused = true;
+            self.env().emit_event(Paused { by: caller });
+            Ok(())
         }
 
-        /// Returns the owner of the token.
+        /// Resumes transfers, mints and burns. Only the contract owner can unpause the contract.
         #[ink(message)]
-        pub fn owner_of(&self, id: TokenId) -> Option<AccountId> {
-            self.token_owner.get(&id).cloned()
+        pub fn unpause_transfers(&mut self) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::NotOwner);
+            };
+            self.paused = false;
+            self.env().emit_event(Unpaused { by: caller });
+            Ok(())
         }
 
-        /// Returns the approved account ID for this token if any.
+        /// Returns `true` if transfers are halted.
         #[ink(message)]
-        pub fn get_approved(&self, id: TokenId) -> Option<AccountId> {
-            self.token_approvals.get(&id).cloned()
+        pub fn is_paused(&self) -> bool {
+            self.paused
         }
 
-        /// Returns `true` if the operator is approved by the owner.
+        /// Records the current balance of every owner and the total supply,
+        /// e.g. for governance voting. Only the contract owner can take snapshots.
+        ///
+        /// Returns the id of the new snapshot. Ids start at 1.
         #[ink(message)]
-        pub fn is_approved_for_all(&self, owner: AccountId, operator: AccountId) -> bool {
-            self.approved_for_all(owner, operator)
+        pub fn take_snapshot(&mut self) -> Result<u32, Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            };
+            let id = self.latest_snapshot_id + 1;
+            for (owner, count) in self.owned_tokens_count.iter() {
+                if *count > 0 {
+                    self.snapshot_balances.insert((id, *owner), *count);
+                }
+            }
+            self.snapshot_supplies.insert(id, self.total_supply);
+            self.latest_snapshot_id = id;
+            self.env().emit_event(Snapshot { id });
+            Ok(id)
         }
 
-        /// Approves or disapproves the operator for all tokens of the caller.
+        /// Returns the number of tokens the owner had when the snapshot was taken.
         #[ink(message)]
-        pub fn set_approval_for_all(&mut self, to: AccountId, approved: bool) -> Result<(), Error> {
-            self.approve_for_all(to, approved)?;
-            Ok(())
+        pub fn balance_of_at(&self, owner: AccountId, snapshot_id: u32) -> u32 {
+            *self
+                .snapshot_balances
+                .get(&(snapshot_id, owner))
+                .unwrap_or(&0)
         }
 
-        /// Approves the account to transfer the specified token on behalf of the caller.
+        /// Returns the number of tokens in existence when the snapshot was taken.
         #[ink(message)]
-        pub fn approve(&mut self, to: AccountId, id: TokenId) -> Result<(), Error> {
-            self.approve_for(&to, id)?;
-            Ok(())
+        pub fn snapshot_total_supply_at(&self, snapshot_id: u32) -> u32 {
+            *self.snapshot_supplies.get(&snapshot_id).unwrap_or(&0)
         }
 
-        /// Transfers the token from the caller to the given destination.
+        /// Returns the id of the most recent snapshot, 0 if none was taken.
         #[ink(message)]
-        pub fn transfer(&mut self, destination: AccountId, id: TokenId) -> Result<(), Error> {
-            let caller = self.env().caller();
-            self.transfer_token_from(&caller, &destination, id)?;
-            Ok(())
+        pub fn get_latest_snapshot_id(&self) -> u32 {
+            self.latest_snapshot_id
         }
 
-        /// Transfer approved or owned token.
+        /// Creates all the given tokens for the caller.
+        ///
+        /// Nothing is minted if any of the tokens cannot be created.
         #[ink(message)]
-        pub fn transfer_from(
-            &mut self,
-            from: AccountId,
-            to: AccountId,
-            id: TokenId,
-        ) -> Result<(), Error> {
-            self.transfer_token_from(&from, &to, id)?;
+        pub fn batch_mint(&mut self, ids: Vec<TokenId>) -> Result<(), Error> {
+            self.require_not_paused()?;
+            let caller = self.env().caller();
+            let (first_id, last_id) = match (ids.first(), ids.last()) {
+                (Some(first_id), Some(last_id)) => (*first_id, *last_id),
+                _ => return Ok(()),
+            };
+            let count = ids.len() as u32;
+            if ids.len() > self.max_batch_size as usize {
+                return Err(Error::BatchSizeExceeded);
+            }
+            if let Some(max_supply) = self.max_supply {
+                if self.total_supply + count > max_supply {
+                    return Err(Error::CapExceeded);
+                }
+            }
+            if caller == AccountId::from([0x0; 32]) {
+                return Err(Error::NotAllowed);
+            };
+            for (index, id) in ids.iter().enumerate() {
+                if self.exists(*id) || ids[..index].contains(id) {
+                    return Err(Error::TokenExists);
+                }
+            }
+
+            for id in ids {
+                self.add_token_to(&caller, id)?;
+                self.all_tokens.push(id);
+            }
+            self.total_supply += count;
+            self.env().emit_event(BatchMint {
+                minter: caller,
+                count,
+                first_id,
+                last_id,
+            });
             Ok(())
         }
 
-        /// Creates a new token.
+        /// Deletes all the given tokens the caller owns or is approved for.
+        ///
+        /// Each token is burned independently and the outcomes are returned in order.
         #[ink(message)]
-        pub fn mint(&mut self, id: TokenId) -> Result<(), Error> {
+        pub fn batch_burn(&mut self, ids: Vec<TokenId>) -> Vec<Result<(), Error>> {
+            if ids.len() > self.max_batch_size as usize {
+                return ids.iter().map(|_| Err(Error::BatchSizeExceeded)).collect();
+            }
             let caller = self.env().caller();
-            self.add_token_to(&caller, id)?;
-            self.env().emit_event(Transfer {
-                from: Some(AccountId::from([0x0; 32])),
-                to: Some(caller),
-                id,
+            let mut burned_ids = Vec::new();
+            let mut failed_ids = Vec::new();
+            let results: Vec<Result<(), Error>> = ids
+                .into_iter()
+                .map(|id| {
+                    let result = match self.owner_of(id) {
+                        Some(owner) => self.burn_from(owner, id),
+                        None => Err(Error::TokenNotFound),
+                    };
+                    match result {
+                        Ok(()) => burned_ids.push(id),
+                        Err(_) => failed_ids.push(id),
+                    }
+                    result
+                })
+                .collect();
+            self.env().emit_event(BatchBurn {
+                burner: caller,
+                count: burned_ids.len() as u32,
+                burned_ids,
+                failed_ids,
             });
-            Ok(())
+            results
         }
 
         /// Deletes an existing token. Only the owner can burn the token.
         #[ink(message)]
         pub fn burn(&mut self, id: TokenId) -> Result<(), Error> {
             let caller = self.env().caller();
-            let Self {
-                token_owner,
-                owned_tokens_count,
-                ..
-            } = self;
-            let occupied = match token_owner.entry(id) {
-                Entry::Vacant(_) => return Err(Error::TokenNotFound),
-                Entry::Occupied(occupied) => occupied,
+            self.burn_from(caller, id)
+        }
+
+        /// Deletes an existing token of the owner. Only the owner or an approved account can burn the token.
+        /// Locked tokens can't be burned until their lock is lifted or expires.
+        #[ink(message)]
+        pub fn burn_from(&mut self, owner: AccountId, id: TokenId) -> Result<(), Error> {
+            self.require_not_paused()?;
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
             };
-            if occupied.get() != &caller {
+            if self.owner_of(id) != Some(owner) {
                 return Err(Error::NotOwner);
             };
-            decrease_counter_of(owned_tokens_count, &caller)?;
-            occupied.remove_entry();
+            if !self.approved_or_owner(Some(caller), id) {
+                return Err(Error::NotApproved);
+            };
+            if self.is_token_locked(id) {
+                return Err(Error::TokenLocked);
+            };
+            self.clear_approval(id)?;
+            self.remove_token_from(&owner, id)?;
+            self.all_tokens.retain(|token| *token != id);
+            self.total_supply -= 1;
+            // Nothing of the burned token may carry over to a token minted again under its id
+            self.token_uris.take(&id);
+            self.locked_tokens.take(&id);
+            self.token_lockers.take(&id);
+            self.metadata_frozen.take(&id);
+            self.token_restrictions.take(&id);
+            self.restriction_expiries.take(&id);
+            self.royalty_recipients.take(&id);
+            self.royalty_bps.take(&id);
             self.env().emit_event(Transfer {
-                from: Some(caller),
+                from: Some(owner),
                 to: Some(AccountId::from([0x0; 32])),
                 id,
+                data: None,
             });
             Ok(())
         }
@@ -201,7 +1000,9 @@ pub mod erc721 {
             from: &AccountId,
             to: &AccountId,
             id: TokenId,
+            data: Option<Vec<u8>>,
         ) -> Result<(), Error> {
+            self.require_not_paused()?;
             let caller = self.env().caller();
             if !self.exists(id) {
                 return Err(Error::TokenNotFound);
@@ -209,6 +1010,10 @@ pub mod erc721 {
             if !self.approved_or_owner(Some(caller), id) {
                 return Err(Error::NotApproved);
             };
+            if self.is_token_locked(id) {
+                return Err(Error::TokenLocked);
+            };
+            self.check_transfer_restriction(caller, id)?;
             self.clear_approval(id)?;
             self.remove_token_from(from, id)?;
             self.add_token_to(to, id)?;
@@ -216,6 +1021,49 @@ pub mod erc721 {
                 from: Some(*from),
                 to: Some(*to),
                 id,
+                data,
+            });
+            Ok(())
+        }
+
+        /// Returns an error if the restriction of token `id` forbids `caller` to transfer it now.
+        fn check_transfer_restriction(&self, caller: AccountId, id: TokenId) -> Result<(), Error> {
+            match self.get_transfer_restriction(id) {
+                TransferRestriction::None => Ok(()),
+                TransferRestriction::SoulboundPermanent => Err(Error::NotAllowed),
+                TransferRestriction::LockedUntil(until) => {
+                    if until > self.env().block_timestamp() {
+                        Err(Error::TokenLocked)
+                    } else {
+                        Ok(())
+                    }
+                }
+                TransferRestriction::OwnerOnly => {
+                    if self.owner_of(id) != Some(caller) {
+                        Err(Error::NotAllowed)
+                    } else {
+                        Ok(())
+                    }
+                }
+            }
+        }
+
+        /// Creates token `id` for the `to` AccountId.
+        fn mint_token_to(&mut self, to: &AccountId, id: TokenId) -> Result<(), Error> {
+            self.require_not_paused()?;
+            if let Some(max_supply) = self.max_supply {
+                if self.total_supply >= max_supply {
+                    return Err(Error::CapExceeded);
+                }
+            }
+            self.add_token_to(to, id)?;
+            self.all_tokens.push(id);
+            self.total_supply += 1;
+            self.env().emit_event(Transfer {
+                from: Some(AccountId::from([0x0; 32])),
+                to: Some(*to),
+                id,
+                data: None,
             });
             Ok(())
         }
@@ -225,6 +1073,7 @@ pub mod erc721 {
             let Self {
                 token_owner,
                 owned_tokens_count,
+                owner_tokens,
                 ..
             } = self;
             let occupied = match token_owner.entry(id) {
@@ -232,6 +1081,7 @@ pub mod erc721 {
                 Entry::Occupied(occupied) => occupied,
             };
             decrease_counter_of(owned_tokens_count, from)?;
+            remove_owned_token(owner_tokens, from, id);
             occupied.remove_entry();
             Ok(())
         }
@@ -241,6 +1091,7 @@ pub mod erc721 {
             let Self {
                 token_owner,
                 owned_tokens_count,
+                owner_tokens,
                 ..
             } = self;
             let vacant_token_owner = match token_owner.entry(id) {
@@ -252,6 +1103,7 @@ pub mod erc721 {
             };
             let entry = owned_tokens_count.entry(*to);
             increase_counter_of(entry);
+            owner_tokens.entry(*to).or_insert_with(Vec::new).push(id);
             vacant_token_owner.insert(*to);
             Ok(())
         }
@@ -267,21 +1119,36 @@ pub mod erc721 {
                 operator: to,
                 approved,
             });
-            if self.approved_for_all(caller, to) {
+            // Expired or revoked approvals are updated in place
+            if self.operator_approvals.contains_key(&(caller, to)) {
                 let status = self
                     .operator_approvals
                     .get_mut(&(caller, to))
                     .ok_or(Error::CannotFetchValue)?;
                 *status = approved;
+                self.update_operator_list(caller, to, approved);
                 Ok(())
             } else {
                 match self.operator_approvals.insert((caller, to), approved) {
                     Some(_) => Err(Error::CannotInsert),
-                    None => Ok(()),
+                    None => {
+                        self.update_operator_list(caller, to, approved);
+                        Ok(())
+                    }
                 }
             }
         }
 
+        /// Adds or removes the operator from the list of operators of the owner.
+        fn update_operator_list(&mut self, owner: AccountId, operator: AccountId, approved: bool) {
+            let operators = self.owner_operators.entry(owner).or_insert_with(Vec::new);
+            if !approved {
+                operators.retain(|approved_operator| approved_operator != &operator);
+            } else if !operators.contains(&operator) {
+                operators.push(operator);
+            }
+        }
+
         /// Approve the passed AccountId to transfer the specified token on behalf of the message's sender.
         fn approve_for(&mut self, to: &AccountId, id: TokenId) -> Result<(), Error> {
             let caller = self.env().caller();
@@ -295,9 +1162,7 @@ pub mod erc721 {
                 return Err(Error::NotAllowed);
             };
 
-            if self.token_approvals.insert(id, *to).is_some() {
-                return Err(Error::CannotInsert);
-            };
+            self.token_approvals.insert(id, *to);
             self.env().emit_event(Approval {
                 from: caller,
                 to: *to,
@@ -324,10 +1189,15 @@ pub mod erc721 {
 
         /// Gets an operator on other Account's behalf.
         fn approved_for_all(&self, owner: AccountId, operator: AccountId) -> bool {
-            *self
-                .operator_approvals
+            let expired = self
+                .operator_expiries
                 .get(&(owner, operator))
-                .unwrap_or(&false)
+                .map_or(false, |expiry| *expiry < self.env().block_timestamp());
+            !expired
+                && *self
+                    .operator_approvals
+                    .get(&(owner, operator))
+                    .unwrap_or(&false)
         }
 
         /// Returns true if the AccountId `from` is the owner of token `id`
@@ -343,6 +1213,14 @@ pub mod erc721 {
                     ))
         }
 
+        /// Returns an error if transfers are halted.
+        fn require_not_paused(&self) -> Result<(), Error> {
+            if self.paused {
+                return Err(Error::ContractPaused);
+            };
+            Ok(())
+        }
+
         /// Returns true if token `id` exists or false if it does not.
         fn exists(&self, id: TokenId) -> bool {
             self.token_owner.get(&id).is_some() && self.token_owner.contains_key(&id)
@@ -362,4 +1240,900 @@ pub mod erc721 {
     fn increase_counter_of(entry: Entry<AccountId, u32>) {
         entry.and_modify(|v| *v += 1).or_insert(1);
     }
+
+    /// Removes token `id` from the tokens owned by the `of` AccountId.
+    fn remove_owned_token(
+        hmap: &mut StorageHashMap<AccountId, Vec<TokenId>>,
+        of: &AccountId,
+        id: TokenId,
+    ) {
+        if let Some(tokens) = hmap.get_mut(of) {
+            if let Some(index) = tokens.iter().position(|token| *token == id) {
+                tokens.swap_remove(index);
+            }
+        }
+    }
+
+    /// Unit tests
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_lang as ink;
+
+        type Event = <Erc721 as ::ink_lang::BaseEvent>::Type;
+
+        fn set_caller(caller: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                caller,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+        }
+
+        #[ink::test]
+        fn total_supply_works() {
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.total_supply(), 0);
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.mint(2), Ok(()));
+            assert_eq!(erc721.total_supply(), 2);
+            // Minting an existing token leaves the supply untouched
+            assert_eq!(erc721.mint(2), Err(Error::TokenExists));
+            assert_eq!(erc721.total_supply(), 2);
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.total_supply(), 1);
+        }
+
+        #[ink::test]
+        fn all_token_ids_paginated_works() {
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.get_tokens_count_for_collection(), 0);
+            assert_eq!(
+                erc721.get_all_token_ids_paginated(0, 10),
+                Vec::<TokenId>::new()
+            );
+            for id in 1..=5 {
+                assert_eq!(erc721.mint(id), Ok(()));
+            }
+            assert_eq!(erc721.batch_mint(vec![6, 7]), Ok(()));
+            assert_eq!(erc721.burn(3), Ok(()));
+            assert_eq!(erc721.get_tokens_count_for_collection(), 6);
+
+            // Pages cover every existing token exactly once
+            let mut ids = Vec::new();
+            let mut start = 0;
+            loop {
+                let page = erc721.get_all_token_ids_paginated(start, 4);
+                if page.is_empty() {
+                    break;
+                }
+                start += page.len() as u32;
+                ids.extend(page);
+            }
+            assert_eq!(ids, vec![1, 2, 4, 5, 6, 7]);
+            assert_eq!(erc721.get_all_token_ids_paginated(5, 4), vec![7]);
+            assert_eq!(
+                erc721.get_all_token_ids_paginated(6, 4),
+                Vec::<TokenId>::new()
+            );
+
+            assert_eq!(erc721.collection_id(), None);
+            assert_eq!(erc721.set_collection_id(Some(7)), Ok(()));
+            assert_eq!(erc721.collection_id(), Some(7));
+        }
+
+        #[ink::test]
+        fn lock_token_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.lock_token(2, 10), Err(Error::TokenNotFound));
+            assert_eq!(erc721.unlock_token(1), Err(Error::TokenNotLocked));
+
+            // Operators of the owner can lock the token
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.lock_token(1, 10), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(erc721.is_token_locked(1), true);
+            assert_eq!(erc721.lock_token(1, 20), Err(Error::TokenLocked));
+            assert_eq!(
+                erc721.transfer(accounts.charlie, 1),
+                Err(Error::TokenLocked)
+            );
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+
+            // Only the locker can unlock the token
+            assert_eq!(erc721.unlock_token(1), Err(Error::NotAllowed));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.unlock_token(1), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(erc721.transfer(accounts.charlie, 1), Ok(()));
+
+            // Locks lapse once their time has passed
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.lock_token(1, 5), Ok(()));
+            assert_eq!(erc721.transfer(accounts.alice, 1), Err(Error::TokenLocked));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(erc721.is_token_locked(1), false);
+            assert_eq!(erc721.unlock_token(1), Err(Error::TokenNotLocked));
+            assert_eq!(erc721.transfer(accounts.alice, 1), Ok(()));
+            ink_env::test::pop_execution_context();
+
+            // Locked tokens can't be burned and burning drops an expired lock
+            let until = ink_env::block_timestamp::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get block timestamp")
+                + 5;
+            assert_eq!(erc721.lock_token(1, until), Ok(()));
+            assert_eq!(erc721.burn(1), Err(Error::TokenLocked));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.locked_tokens.get(&1), None);
+            assert_eq!(erc721.token_lockers.get(&1), None);
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.is_token_locked(1), false);
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+        }
+
+        #[ink::test]
+        fn transfer_restriction_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(
+                erc721.get_transfer_restriction(1),
+                TransferRestriction::None
+            );
+            assert_eq!(
+                erc721.set_transfer_restriction(2, TransferRestriction::OwnerOnly),
+                Err(Error::TokenNotFound)
+            );
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.set_transfer_restriction(1, TransferRestriction::OwnerOnly),
+                Err(Error::NotOwner)
+            );
+            ink_env::test::pop_execution_context();
+
+            // Owner only tokens can't be moved by operators
+            assert_eq!(
+                erc721.set_transfer_restriction(1, TransferRestriction::OwnerOnly),
+                Ok(())
+            );
+            assert_eq!(
+                erc721.get_transfer_restriction(1),
+                TransferRestriction::OwnerOnly
+            );
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.transfer_from(accounts.alice, accounts.bob, 1),
+                Err(Error::NotAllowed)
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(erc721.transfer(accounts.charlie, 1), Ok(()));
+
+            // Time locked tokens move again once their time has passed
+            set_caller(accounts.charlie);
+            assert_eq!(
+                erc721.set_transfer_restriction(1, TransferRestriction::LockedUntil(5)),
+                Ok(())
+            );
+            assert_eq!(
+                erc721.get_transfer_restriction(1),
+                TransferRestriction::LockedUntil(5)
+            );
+            assert_eq!(erc721.transfer(accounts.alice, 1), Err(Error::TokenLocked));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(erc721.transfer(accounts.alice, 1), Ok(()));
+            ink_env::test::pop_execution_context();
+
+            // Soulbound tokens never move and keep their restriction
+            assert_eq!(
+                erc721.set_transfer_restriction(1, TransferRestriction::SoulboundPermanent),
+                Ok(())
+            );
+            assert_eq!(erc721.transfer(accounts.bob, 1), Err(Error::NotAllowed));
+            assert_eq!(
+                erc721.set_transfer_restriction(1, TransferRestriction::None),
+                Err(Error::NotAllowed)
+            );
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+
+            // Lifting a restriction allows transfers again
+            assert_eq!(erc721.mint(2), Ok(()));
+            assert_eq!(
+                erc721.set_transfer_restriction(2, TransferRestriction::OwnerOnly),
+                Ok(())
+            );
+            assert_eq!(
+                erc721.set_transfer_restriction(2, TransferRestriction::None),
+                Ok(())
+            );
+            assert_eq!(
+                erc721.get_transfer_restriction(2),
+                TransferRestriction::None
+            );
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.transfer_from(accounts.alice, accounts.bob, 2),
+                Ok(())
+            );
+            ink_env::test::pop_execution_context();
+        }
+
+        #[ink::test]
+        fn mint_at_cap_fails() {
+            let mut erc721 = Erc721::new_with_cap(2);
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.mint(2), Ok(()));
+            assert_eq!(erc721.mint(3), Err(Error::CapExceeded));
+            assert_eq!(erc721.owner_of(3), None);
+            assert_eq!(erc721.total_supply(), 2);
+            // Burning frees up room under the cap
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.mint(3), Ok(()));
+            assert_eq!(erc721.total_supply(), 2);
+        }
+
+        #[ink::test]
+        fn batch_mint_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.batch_mint(vec![1, 2, 3]), Ok(()));
+            assert_eq!(erc721.balance_of(accounts.alice), 3);
+            assert_eq!(erc721.owner_of(2), Some(accounts.alice));
+            assert_eq!(erc721.total_supply(), 3);
+        }
+
+        #[ink::test]
+        fn batch_mint_is_atomic() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(3), Ok(()));
+            // Token 3 already exists
+            assert_eq!(erc721.batch_mint(vec![1, 2, 3]), Err(Error::TokenExists));
+            // Token 4 is repeated within the batch
+            assert_eq!(erc721.batch_mint(vec![4, 5, 4]), Err(Error::TokenExists));
+            assert_eq!(erc721.owner_of(1), None);
+            assert_eq!(erc721.owner_of(4), None);
+            assert_eq!(erc721.balance_of(accounts.alice), 1);
+            assert_eq!(erc721.total_supply(), 1);
+        }
+
+        #[ink::test]
+        fn batch_mint_size_is_limited() {
+            let mut erc721 = Erc721::new();
+            let ids: Vec<TokenId> = (0..101).collect();
+            assert_eq!(erc721.batch_mint(ids), Err(Error::BatchSizeExceeded));
+            assert_eq!(erc721.total_supply(), 0);
+
+            let mut erc721 = Erc721::new_with_cap(2);
+            assert_eq!(erc721.batch_mint(vec![1, 2, 3]), Err(Error::CapExceeded));
+            assert_eq!(erc721.total_supply(), 0);
+        }
+
+        #[ink::test]
+        fn batch_burn_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.batch_mint(vec![1, 2]), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.batch_mint(vec![3, 4]), Ok(()));
+            assert_eq!(erc721.approve(accounts.alice, 3), Ok(()));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.total_supply(), 4);
+
+            // Owned, missing, approved and foreign tokens in one call
+            assert_eq!(
+                erc721.batch_burn(vec![1, 5, 3, 4, 1]),
+                vec![
+                    Ok(()),
+                    Err(Error::TokenNotFound),
+                    Ok(()),
+                    Err(Error::NotApproved),
+                    Err(Error::TokenNotFound)
+                ]
+            );
+            assert_eq!(erc721.total_supply(), 2);
+            assert_eq!(erc721.owner_of(1), None);
+            assert_eq!(erc721.owner_of(3), None);
+            assert_eq!(erc721.owner_of(2), Some(accounts.alice));
+            assert_eq!(erc721.owner_of(4), Some(accounts.bob));
+            assert_eq!(erc721.balance_of(accounts.bob), 1);
+            match <Event as scale::Decode>::decode(
+                &mut &ink_env::test::recorded_events().last().unwrap().data[..],
+            )
+            .expect("encountered invalid contract event data buffer")
+            {
+                Event::BatchBurn(BatchBurn {
+                    burner,
+                    count,
+                    burned_ids,
+                    failed_ids,
+                }) => {
+                    assert_eq!(burner, accounts.alice);
+                    assert_eq!(count, 2);
+                    assert_eq!(burned_ids, vec![1, 3]);
+                    assert_eq!(failed_ids, vec![5, 4, 1]);
+                }
+                _ => panic!("expected a BatchBurn event"),
+            }
+
+            let ids: Vec<TokenId> = (0..101).collect();
+            assert!(erc721
+                .batch_burn(ids)
+                .iter()
+                .all(|result| *result == Err(Error::BatchSizeExceeded)));
+            assert_eq!(erc721.total_supply(), 2);
+        }
+
+        #[ink::test]
+        fn approve_twice_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.approve(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.get_approved(1), Some(accounts.bob));
+            assert_eq!(erc721.approve(accounts.charlie, 1), Ok(()));
+            assert_eq!(erc721.get_approved(1), Some(accounts.charlie));
+        }
+
+        #[ink::test]
+        fn get_tokens_of_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.get_tokens_of(accounts.alice), vec![]);
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.mint(2), Ok(()));
+            assert_eq!(erc721.mint(3), Ok(()));
+            assert_eq!(erc721.get_tokens_of(accounts.alice), vec![1, 2, 3]);
+
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.get_tokens_of(accounts.alice), vec![3, 2]);
+            assert_eq!(erc721.get_tokens_of(accounts.bob), vec![1]);
+
+            assert_eq!(erc721.burn(3), Ok(()));
+            assert_eq!(erc721.get_tokens_of(accounts.alice), vec![2]);
+            assert_eq!(erc721.token_of_owner_by_index(accounts.alice, 0), Ok(2));
+            assert_eq!(
+                erc721.token_of_owner_by_index(accounts.alice, 1),
+                Err(Error::TokenNotFound)
+            );
+            assert_eq!(erc721.token_of_owner_by_index(accounts.bob, 0), Ok(1));
+            assert_eq!(
+                erc721.token_of_owner_by_index(accounts.charlie, 0),
+                Err(Error::TokenNotFound)
+            );
+        }
+
+        #[ink::test]
+        fn mint_to_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            // Owner is a minter
+            assert_eq!(erc721.is_minter(accounts.alice), true);
+            assert_eq!(erc721.mint_to(accounts.charlie, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.charlie));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.is_minter(accounts.bob), false);
+            assert_eq!(erc721.mint_to(accounts.charlie, 2), Err(Error::NotAllowed));
+            assert_eq!(erc721.add_minter(accounts.bob), Err(Error::NotOwner));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.add_minter(accounts.bob), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.mint_to(accounts.charlie, 2), Ok(()));
+            assert_eq!(erc721.owner_of(2), Some(accounts.charlie));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.remove_minter(accounts.bob), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.mint_to(accounts.charlie, 3), Err(Error::NotAllowed));
+            assert_eq!(erc721.owner_of(3), None);
+        }
+
+        #[ink::test]
+        fn burn_from_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.mint(2), Ok(()));
+            assert_eq!(erc721.approve(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, true), Ok(()));
+
+            // Unapproved account cannot burn
+            set_caller(accounts.django);
+            assert_eq!(erc721.burn_from(accounts.alice, 1), Err(Error::NotApproved));
+            // Token is not owned by the given owner
+            set_caller(accounts.bob);
+            assert_eq!(erc721.burn_from(accounts.django, 1), Err(Error::NotOwner));
+            // Owner-only burn is still reserved to the owner
+            assert_eq!(erc721.burn(1), Err(Error::NotOwner));
+
+            assert_eq!(erc721.burn_from(accounts.alice, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), None);
+            assert_eq!(erc721.get_approved(1), None);
+
+            // Locked tokens can't be burned
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.lock_token(2, u64::MAX), Ok(()));
+            assert_eq!(erc721.burn_from(accounts.alice, 2), Err(Error::TokenLocked));
+            assert_eq!(erc721.unlock_token(2), Ok(()));
+            assert_eq!(erc721.burn_from(accounts.alice, 2), Ok(()));
+            assert_eq!(erc721.balance_of(accounts.alice), 0);
+            assert_eq!(erc721.total_supply(), 0);
+            assert_eq!(
+                erc721.burn_from(accounts.alice, 2),
+                Err(Error::TokenNotFound)
+            );
+        }
+
+        #[ink::test]
+        fn burn_and_mint_again_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.set_token_uri(1, String::from("1.json")), Ok(()));
+            assert_eq!(erc721.freeze_metadata(1), Ok(()));
+            assert_eq!(erc721.set_royalty_info(1, accounts.eve, 500), Ok(()));
+            // Locks stay stored after they expire
+            assert_eq!(erc721.lock_token(1, 5), Ok(()));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(
+                erc721.set_transfer_restriction(1, TransferRestriction::SoulboundPermanent),
+                Ok(())
+            );
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.locked_tokens.get(&1), None);
+
+            // The new token carries nothing over from the burned one
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.token_uri(1), None);
+            assert_eq!(erc721.is_metadata_frozen(1), false);
+            assert_eq!(
+                erc721.royalty_info(1, 10_000),
+                (AccountId::from([0x0; 32]), 0)
+            );
+            assert_eq!(erc721.is_token_locked(1), false);
+            assert_eq!(
+                erc721.get_transfer_restriction(1),
+                TransferRestriction::None
+            );
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+        }
+
+        #[ink::test]
+        fn pause_transfers_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.mint(2), Ok(()));
+            assert_eq!(erc721.approve(accounts.bob, 2), Ok(()));
+            // Only the owner can pause
+            set_caller(accounts.bob);
+            assert_eq!(erc721.pause_transfers(), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.pause_transfers(), Ok(()));
+            assert_eq!(erc721.is_paused(), true);
+
+            assert_eq!(erc721.transfer(accounts.bob, 1), Err(Error::ContractPaused));
+            assert_eq!(erc721.mint(3), Err(Error::ContractPaused));
+            assert_eq!(erc721.batch_mint(vec![3, 4]), Err(Error::ContractPaused));
+            assert_eq!(erc721.burn(1), Err(Error::ContractPaused));
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.transfer_from(accounts.alice, accounts.bob, 2),
+                Err(Error::ContractPaused)
+            );
+            assert_eq!(erc721.unpause_transfers(), Err(Error::NotOwner));
+            assert_eq!(erc721.owner_of(2), Some(accounts.alice));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.unpause_transfers(), Ok(()));
+            assert_eq!(erc721.is_paused(), false);
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.mint(3), Ok(()));
+            assert_eq!(erc721.burn(3), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.transfer_from(accounts.alice, accounts.bob, 2),
+                Ok(())
+            );
+            assert_eq!(erc721.balance_of(accounts.bob), 2);
+            assert_eq!(ink_env::test::recorded_events().count(), 9);
+        }
+
+        #[ink::test]
+        fn snapshot_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.get_latest_snapshot_id(), 0);
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.mint(2), Ok(()));
+            // Only the owner can take snapshots
+            set_caller(accounts.bob);
+            assert_eq!(erc721.take_snapshot(), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.take_snapshot(), Ok(1));
+            assert_eq!(erc721.get_latest_snapshot_id(), 1);
+
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.mint(3), Ok(()));
+            // The snapshot still shows the balances before the transfer
+            assert_eq!(erc721.balance_of_at(accounts.alice, 1), 2);
+            assert_eq!(erc721.balance_of_at(accounts.bob, 1), 0);
+            assert_eq!(erc721.snapshot_total_supply_at(1), 2);
+
+            assert_eq!(erc721.take_snapshot(), Ok(2));
+            assert_eq!(erc721.balance_of_at(accounts.alice, 2), 2);
+            assert_eq!(erc721.balance_of_at(accounts.bob, 2), 1);
+            assert_eq!(erc721.snapshot_total_supply_at(2), 3);
+            // Unknown snapshots are empty
+            assert_eq!(erc721.balance_of_at(accounts.alice, 3), 0);
+            assert_eq!(erc721.snapshot_total_supply_at(3), 0);
+            match <Event as scale::Decode>::decode(
+                &mut &ink_env::test::recorded_events().last().unwrap().data[..],
+            )
+            .expect("encountered invalid contract event data buffer")
+            {
+                Event::Snapshot(Snapshot { id }) => assert_eq!(id, 2),
+                _ => panic!("expected a Snapshot event"),
+            }
+        }
+
+        #[ink::test]
+        fn approval_for_all_with_expiry_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            // Permanent approvals never expire
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, true), Ok(()));
+            assert_eq!(
+                erc721.get_operator_expiry(accounts.alice, accounts.charlie),
+                Some(u64::MAX)
+            );
+
+            // Approve bob for one second
+            assert_eq!(
+                erc721.set_approval_for_all_with_expiry(accounts.bob, true, 1_000),
+                Ok(())
+            );
+            assert_eq!(
+                erc721.is_approved_for_all(accounts.alice, accounts.bob),
+                true
+            );
+
+            // Time moves on by 5 ms per block
+            for _ in 0..200 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                    .expect("Cannot advance block");
+            }
+            assert_eq!(
+                erc721.is_approved_for_all(accounts.alice, accounts.bob),
+                true
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(
+                erc721.is_approved_for_all(accounts.alice, accounts.bob),
+                false
+            );
+            assert_eq!(
+                erc721.is_approved_for_all(accounts.alice, accounts.charlie),
+                true
+            );
+
+            // Expired operators can no longer transfer
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.transfer_from(accounts.alice, accounts.bob, 1),
+                Err(Error::NotApproved)
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+
+            // Expired operators can be approved again
+            assert_eq!(
+                erc721.set_approval_for_all_with_expiry(accounts.bob, true, 2_000),
+                Ok(())
+            );
+            assert_eq!(
+                erc721.is_approved_for_all(accounts.alice, accounts.bob),
+                true
+            );
+        }
+
+        #[ink::test]
+        fn operator_transfer_from_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.mint(2), Ok(()));
+            let decode = |event: &ink_env::test::EmittedEvent| {
+                <Event as scale::Decode>::decode(&mut &event.data[..])
+                    .expect("encountered invalid contract event data buffer")
+            };
+
+            // Owners go through transfer_from
+            assert_eq!(
+                erc721.operator_transfer_from(accounts.alice, accounts.bob, 1),
+                Err(Error::NotAllowed)
+            );
+            assert_eq!(
+                erc721.transfer_from(accounts.alice, accounts.bob, 1),
+                Ok(())
+            );
+            match decode(&ink_env::test::recorded_events().last().unwrap()) {
+                Event::Transfer(Transfer { from, to, id, .. }) => {
+                    assert_eq!(from, Some(accounts.alice));
+                    assert_eq!(to, Some(accounts.bob));
+                    assert_eq!(id, 1);
+                }
+                _ => panic!("expected a Transfer event"),
+            }
+
+            // Operators need approval
+            set_caller(accounts.charlie);
+            assert_eq!(
+                erc721.operator_transfer_from(accounts.alice, accounts.charlie, 2),
+                Err(Error::NotApproved)
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, true), Ok(()));
+            set_caller(accounts.charlie);
+            assert_eq!(
+                erc721.operator_transfer_from(accounts.alice, accounts.django, 2),
+                Ok(())
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(erc721.owner_of(2), Some(accounts.django));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            match decode(&emitted_events[emitted_events.len() - 2]) {
+                Event::Transfer(Transfer { to, id, .. }) => {
+                    assert_eq!(to, Some(accounts.django));
+                    assert_eq!(id, 2);
+                }
+                _ => panic!("expected a Transfer event"),
+            }
+            match decode(&emitted_events[emitted_events.len() - 1]) {
+                Event::OperatorTransfer(OperatorTransfer {
+                    operator,
+                    from,
+                    to,
+                    id,
+                }) => {
+                    assert_eq!(operator, accounts.charlie);
+                    assert_eq!(from, accounts.alice);
+                    assert_eq!(to, accounts.django);
+                    assert_eq!(id, 2);
+                }
+                _ => panic!("expected an OperatorTransfer event"),
+            }
+        }
+
+        #[ink::test]
+        fn transfer_with_data_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(
+                erc721.transfer_with_data(accounts.bob, 1, vec![7, 7]),
+                Ok(())
+            );
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(emitted_events.len(), 3);
+            let decode = |event: &ink_env::test::EmittedEvent| {
+                <Event as scale::Decode>::decode(&mut &event.data[..])
+                    .expect("encountered invalid contract event data buffer")
+            };
+            match decode(&emitted_events[0]) {
+                Event::Transfer(Transfer { data, .. }) => assert_eq!(data, None),
+                _ => panic!("expected a Transfer event"),
+            }
+            match decode(&emitted_events[1]) {
+                Event::Transfer(Transfer { data, .. }) => assert_eq!(data, Some(vec![7, 7])),
+                _ => panic!("expected a Transfer event"),
+            }
+            match decode(&emitted_events[2]) {
+                Event::TransferWithData(TransferWithData { from, to, id, data }) => {
+                    assert_eq!(from, accounts.alice);
+                    assert_eq!(to, accounts.bob);
+                    assert_eq!(id, 1);
+                    assert_eq!(data, vec![7, 7]);
+                }
+                _ => panic!("expected a TransferWithData event"),
+            }
+        }
+
+        #[ink::test]
+        fn get_operator_list_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.get_operator_list(accounts.alice), vec![]);
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, true), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(accounts.django, true), Ok(()));
+            assert_eq!(
+                erc721.get_operator_list(accounts.alice),
+                vec![accounts.bob, accounts.charlie, accounts.django]
+            );
+            // Approving twice does not duplicate the operator
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, false), Ok(()));
+            assert_eq!(
+                erc721.get_operator_list(accounts.alice),
+                vec![accounts.bob, accounts.django]
+            );
+
+            assert_eq!(erc721.revoke_all_operators(), Ok(()));
+            assert_eq!(erc721.get_operator_list(accounts.alice), vec![]);
+            assert_eq!(
+                erc721.is_approved_for_all(accounts.alice, accounts.bob),
+                false
+            );
+            assert_eq!(
+                erc721.is_approved_for_all(accounts.alice, accounts.django),
+                false
+            );
+        }
+
+        #[ink::test]
+        fn royalty_info_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(
+                erc721.set_royalty_info(1, accounts.charlie, 250),
+                Err(Error::TokenNotFound)
+            );
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(
+                erc721.royalty_info(1, 1000),
+                (AccountId::from([0x0; 32]), 0)
+            );
+            assert_eq!(
+                erc721.set_royalty_info(1, accounts.charlie, 10_001),
+                Err(Error::NotAllowed)
+            );
+            assert_eq!(erc721.set_royalty_info(1, accounts.charlie, 250), Ok(()));
+            assert_eq!(erc721.royalty_info(1, 1000), (accounts.charlie, 25));
+
+            // Only the token owner can change the royalty
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.set_royalty_info(1, accounts.bob, 250),
+                Err(Error::NotOwner)
+            );
+            assert_eq!(erc721.delete_royalty_info(1), Err(Error::NotOwner));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.delete_royalty_info(1), Ok(()));
+            assert_eq!(
+                erc721.royalty_info(1, 1000),
+                (AccountId::from([0x0; 32]), 0)
+            );
+        }
+
+        #[ink::test]
+        fn token_uri_works() {
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.token_uri(1), None);
+            assert_eq!(
+                erc721.set_token_uri(2, String::from("2.json")),
+                Err(Error::TokenNotFound)
+            );
+
+            assert_eq!(erc721.set_token_uri(1, String::from("1.json")), Ok(()));
+            assert_eq!(erc721.token_uri(1), Some(String::from("1.json")));
+
+            assert_eq!(erc721.set_base_uri(String::from("ipfs://vera/")), Ok(()));
+            assert_eq!(
+                erc721.token_uri(1),
+                Some(String::from("ipfs://vera/1.json"))
+            );
+
+            // Burning the token drops its URI
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.token_uri(1), None);
+        }
+
+        #[ink::test]
+        fn freeze_metadata_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Cannot get accounts");
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.freeze_metadata(2), Err(Error::TokenNotFound));
+            assert_eq!(erc721.is_metadata_frozen(1), false);
+            assert_eq!(erc721.set_token_uri(1, String::from("1.json")), Ok(()));
+
+            // Only the token owner can freeze
+            set_caller(accounts.bob);
+            assert_eq!(erc721.freeze_metadata(1), Err(Error::NotOwner));
+            ink_env::test::pop_execution_context();
+
+            assert_eq!(erc721.freeze_metadata(1), Ok(()));
+            assert_eq!(erc721.is_metadata_frozen(1), true);
+            assert_eq!(erc721.freeze_metadata(1), Err(Error::MetadataFrozen));
+            assert_eq!(
+                erc721.set_token_uri(1, String::from("other.json")),
+                Err(Error::MetadataFrozen)
+            );
+            assert_eq!(erc721.token_uri(1), Some(String::from("1.json")));
+
+            // Base URI stays mutable
+            assert_eq!(erc721.set_base_uri(String::from("ipfs://vera/")), Ok(()));
+            assert_eq!(
+                erc721.token_uri(1),
+                Some(String::from("ipfs://vera/1.json"))
+            );
+
+            match <Event as scale::Decode>::decode(
+                &mut &ink_env::test::recorded_events().last().unwrap().data[..],
+            )
+            .expect("encountered invalid contract event data buffer")
+            {
+                Event::MetadataFrozen(MetadataFrozen { id }) => assert_eq!(id, 1),
+                _ => panic!("expected a MetadataFrozen event"),
+            }
+
+            // A token minted again under a burned id starts unfrozen
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.mint(1), Ok(()));
+            assert_eq!(erc721.is_metadata_frozen(1), false);
+            assert_eq!(erc721.set_token_uri(1, String::from("new.json")), Ok(()));
+        }
+
+        #[ink::test]
+        fn metadata_works() {
+            let erc721 = Erc721::new();
+            assert_eq!(erc721.name(), String::new());
+            assert_eq!(erc721.symbol(), String::new());
+
+            let erc721 =
+                Erc721::new_with_metadata(String::from("Vera Asset"), String::from("VASSET"));
+            assert_eq!(erc721.name(), String::from("Vera Asset"));
+            assert_eq!(erc721.symbol(), String::from("VASSET"));
+
+            let encoded = (erc721.name(), erc721.symbol()).encode();
+            let decoded = <(String, String)>::decode(&mut &encoded[..]);
+            assert_eq!(
+                decoded,
+                Ok((String::from("Vera Asset"), String::from("VASSET")))
+            );
+        }
+    }
 }
@@ -4,11 +4,12 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod exchangemanager {
+    use addressmanager::{AddressManager, ERC20};
     use erc20::Erc20;
     use erc721::Erc721;
 
     use ink_env::call::FromAccountId;
-    use ink_prelude::vec::Vec;
+    use ink_prelude::{string::String, vec::Vec};
     use ink_storage::{
         collections::HashMap as StorageHashMap,
         traits::{PackedLayout, SpreadLayout, StorageLayout},
@@ -29,6 +30,10 @@ mod exchangemanager {
     pub struct Administration {
         fee: u64,
         enabled: bool,
+        royalties_enabled: bool,
+        whitelist_enabled: bool,
+        auto_fee_sweep_threshold: Balance,
+        fee_recipient: AccountId,
     }
 
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
@@ -39,6 +44,14 @@ mod exchangemanager {
         Cancelled,
     }
 
+    #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum CollectionOfferStatus {
+        Open,
+        Accepted,
+        Cancelled,
+    }
+
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub enum Error {
@@ -46,6 +59,30 @@ mod exchangemanager {
         ERC721TransferFailed,
         ERC20TransferFailed,
         InsufficientBalance,
+        ContractImmutable,
+        Reentrant,
+        NoSuchTrade,
+        TradeUnavailable,
+        NotSeller,
+        NoSuchBundle,
+        InvalidBundle,
+        NFTNotWhitelisted,
+        NoSuchAuction,
+        InvalidAuction,
+        TradeNotExpired,
+        BidTooLow,
+        BidAlreadyPlaced,
+        NoSuchBid,
+        AuctionHasBids,
+        SellerCannotBid,
+        TradePurchased,
+        NoSuchOffer,
+        OfferUnavailable,
+        OfferExpired,
+        OfferTooLow,
+        NotOfferBuyer,
+        InvalidCollection,
+        AddressNotFound,
     }
 
     #[derive(Clone, Default, Copy, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
@@ -63,6 +100,76 @@ mod exchangemanager {
         fee: u64,
     }
 
+    /// Several nfts sold together for a single price
+    #[derive(Clone, Default, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct Bundle {
+        id: u64,
+        nft_addresses: Vec<AccountId>,
+        token_ids: Vec<TokenId>,
+        seller_address: AccountId,
+        beneficiary_address: AccountId,
+        price: Balance,
+        expiration_date: u64,
+        fee: u64,
+        status: u8,
+        buyer_address: Option<AccountId>,
+    }
+
+    /// Trade whose price decays linearly from start_price to end_price
+    #[derive(Clone, Default, Copy, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct DutchAuction {
+        id: u64,
+        nft_address: AccountId,
+        token_id: TokenId,
+        seller: AccountId,
+        start_price: Balance,
+        end_price: Balance,
+        start_time: u64,
+        end_time: u64,
+        fee: u64,
+        status: u8,
+        buyer: Option<AccountId>,
+    }
+
+    /// Bid on a trade created with create_trade_with_minimum_bid
+    /// The bid amount is held by the contract until the auction is settled
+    #[derive(
+        Clone, Default, Copy, PartialEq, Eq, Encode, Decode, Debug, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct SealedBid {
+        bidder: AccountId,
+        amount: Balance,
+        placed_at: u64,
+    }
+
+    /// Standing bid for any nft of a collection
+    /// max_price is held by the contract until the offer is accepted or cancelled
+    #[derive(
+        Clone, Default, Copy, PartialEq, Eq, Encode, Decode, Debug, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct CollectionOffer {
+        id: u64,
+        buyer: AccountId,
+        nft_address: AccountId,
+        max_price: Balance,
+        expiry: u64,
+        status: u8,
+    }
+
+    /// Summary of a collection's trades for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct CollectionStats {
+        listed: u32,
+        sold: u32,
+        floor_price: Option<Balance>,
+        total_volume: Balance,
+    }
+
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
     /// to add new static storage fields to your contract.
@@ -73,6 +180,25 @@ mod exchangemanager {
         administration: Administration,
         total_trades: u32,
         erc20: Lazy<Erc20>,
+        total_volume: Balance,
+        total_completed_trades: u32,
+        nft_volumes: StorageHashMap<AccountId, Balance>,
+        address_manager: Option<AddressManager>,
+        locked: bool,
+        bundles: StorageHashMap<u64, Bundle>,
+        total_bundles: u64,
+        token_trade_history: StorageHashMap<(AccountId, TokenId), Vec<TradeId>>,
+        nft_whitelist: StorageHashMap<AccountId, bool>,
+        collection_floor: StorageHashMap<AccountId, Balance>,
+        dutch_auctions: StorageHashMap<u64, DutchAuction>,
+        total_dutch_auctions: u64,
+        sealed_bids: StorageHashMap<(TradeId, AccountId), SealedBid>,
+        sealed_bidders: StorageHashMap<TradeId, Vec<AccountId>>,
+        seller_trades: StorageHashMap<AccountId, Vec<TradeId>>,
+        nft_address_trades: StorageHashMap<AccountId, Vec<TradeId>>,
+        collection_offers: StorageHashMap<u64, CollectionOffer>,
+        total_collection_offers: u64,
+        escrowed: Balance,
     }
 
     #[ink(event)]
@@ -108,6 +234,136 @@ mod exchangemanager {
         token_id: u32,
     }
 
+    #[ink(event)]
+    pub struct BundleListed {
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        bundle_id: u64,
+        nft_addresses: Vec<AccountId>,
+        token_ids: Vec<TokenId>,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BundlePurchased {
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        bundle_id: u64,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BundleCancelled {
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        bundle_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct DutchAuctionCreated {
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        nft_address: AccountId,
+        #[ink(topic)]
+        auction_id: u64,
+        token_id: TokenId,
+        start_price: Balance,
+        end_price: Balance,
+        end_time: u64,
+    }
+
+    #[ink(event)]
+    pub struct DutchAuctionPurchased {
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        nft_address: AccountId,
+        #[ink(topic)]
+        auction_id: u64,
+        token_id: TokenId,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct DutchAuctionCancelled {
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        nft_address: AccountId,
+        #[ink(topic)]
+        auction_id: u64,
+        token_id: TokenId,
+    }
+
+    #[ink(event)]
+    pub struct BidPlaced {
+        #[ink(topic)]
+        trade_id: TradeId,
+        #[ink(topic)]
+        bidder: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct BidCancelled {
+        #[ink(topic)]
+        trade_id: TradeId,
+        #[ink(topic)]
+        bidder: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct SealedAuctionSettled {
+        #[ink(topic)]
+        trade_id: TradeId,
+        #[ink(topic)]
+        winner: Option<AccountId>,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct AdminCancellation {
+        #[ink(topic)]
+        trade_id: TradeId,
+        #[ink(topic)]
+        seller: AccountId,
+        reason: Vec<u8>,
+    }
+
+    #[ink(event)]
+    pub struct CollectionOfferMade {
+        #[ink(topic)]
+        offer_id: u64,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        nft_address: AccountId,
+        max_price: Balance,
+        expiry: u64,
+    }
+
+    #[ink(event)]
+    pub struct CollectionOfferAccepted {
+        #[ink(topic)]
+        offer_id: u64,
+        #[ink(topic)]
+        trade_id: TradeId,
+        #[ink(topic)]
+        seller: AccountId,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct CollectionOfferCancelled {
+        #[ink(topic)]
+        offer_id: u64,
+        #[ink(topic)]
+        buyer: AccountId,
+    }
+
     #[ink(event)]
     pub struct Enabled {}
 
@@ -122,6 +378,13 @@ mod exchangemanager {
         new_value: u64,
     }
 
+    #[ink(event)]
+    pub struct AutoFeeSweep {
+        amount: Balance,
+        #[ink(topic)]
+        recipient: AccountId,
+    }
+
     #[ink(event)]
     pub struct OwnershipTransferred {
         #[ink(topic)]
@@ -130,6 +393,18 @@ mod exchangemanager {
         to: AccountId,
     }
 
+    #[ink(event)]
+    pub struct OwnershipRenounced {
+        #[ink(topic)]
+        former_owner: AccountId,
+    }
+
+    pub const MAX_BUNDLE_SIZE: usize = 20;
+
+    pub const VERSION: (u32, u32, u32) = (1, 0, 0);
+    // Fails to compile unless the minor and patch versions are below 1000
+    const _: [(); 0 - !(VERSION.1 < 1000 && VERSION.2 < 1000) as usize] = [];
+
     impl ExchangeManager {
         /// Constructors can delegate to other constructors.
         #[ink(constructor)]
@@ -139,14 +414,68 @@ mod exchangemanager {
             let erc20 = Erc20::from_account_id(erc20_address);
             let instance = Self {
                 owner: Ownable { owner },
-                administration: Administration { fee, enabled },
+                administration: Administration {
+                    fee,
+                    enabled,
+                    royalties_enabled: false,
+                    whitelist_enabled: false,
+                    auto_fee_sweep_threshold: 0,
+                    fee_recipient: owner,
+                },
                 trades: Default::default(),
                 total_trades: 0,
                 erc20: Lazy::new(erc20),
+                total_volume: 0,
+                total_completed_trades: 0,
+                nft_volumes: Default::default(),
+                address_manager: None,
+                locked: false,
+                bundles: Default::default(),
+                total_bundles: 0,
+                token_trade_history: Default::default(),
+                nft_whitelist: Default::default(),
+                collection_floor: Default::default(),
+                dutch_auctions: Default::default(),
+                total_dutch_auctions: 0,
+                sealed_bids: Default::default(),
+                sealed_bidders: Default::default(),
+                seller_trades: Default::default(),
+                nft_address_trades: Default::default(),
+                collection_offers: Default::default(),
+                total_collection_offers: 0,
+                escrowed: 0,
             };
             instance
         }
 
+        /// Looks up the erc20 address through the address manager on every call
+        #[ink(constructor)]
+        pub fn new_with_address_manager(
+            address_manager: AccountId,
+            fee: u64,
+            enabled: bool,
+        ) -> Self {
+            let address_manager = AddressManager::from_account_id(address_manager);
+            let erc20_address = address_manager
+                .require_address(String::from(ERC20))
+                .expect("ERC20 address not registered");
+            let mut instance = Self::new(erc20_address, fee, enabled);
+            instance.address_manager = Some(address_manager);
+            instance
+        }
+
+        /// Returns the (major, minor, patch) version of the contract
+        #[ink(message)]
+        pub fn get_contract_version(&self) -> (u32, u32, u32) {
+            VERSION
+        }
+
+        /// Returns the name of the contract
+        #[ink(message)]
+        pub fn get_contract_name(&self) -> String {
+            String::from("ExchangeManager")
+        }
+
         /// Checks if caller is owner of AssetManager contract
         #[ink(message)]
         pub fn is_owner(&self) -> bool {
@@ -173,10 +502,41 @@ mod exchangemanager {
             true
         }
 
+        /// Permanently removes the owner making all parameters immutable
+        /// Can only be called by the current owner
+        #[ink(message)]
+        pub fn renounce_ownership(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_owner(caller));
+            self.owner.owner = AccountId::from([0x0; 32]);
+            self.env().emit_event(OwnershipRenounced {
+                former_owner: caller,
+            });
+            Ok(())
+        }
+
         fn only_owner(&self, caller: AccountId) -> bool {
             caller == self.owner.owner
         }
 
+        fn ensure_mutable(&self) -> Result<(), Error> {
+            if self.owner.owner == AccountId::from([0x0; 32]) {
+                return Err(Error::ContractImmutable);
+            }
+            Ok(())
+        }
+
+        /// Guards against re-entrant calls from the token contracts
+        /// Callers must reset `locked` before returning
+        fn lock(&mut self) -> Result<(), Error> {
+            if self.locked {
+                return Err(Error::Reentrant);
+            }
+            self.locked = true;
+            Ok(())
+        }
+
         /// Allows borrowing on behalf of another account
         /// erc20_owner should have granted approval to assetmanager contract to make transfer on their behalf and have sufficient balance
         /// Caller should have granted approval to erc721 token before executing this function
@@ -189,16 +549,21 @@ mod exchangemanager {
             price: Balance,
             expiration_date: u64,
         ) -> Result<(), Error> {
+            self.lock()?;
+            if let Err(error) = self.ensure_whitelisted(nft_address) {
+                self.locked = false;
+                return Err(error);
+            }
+
             let caller = self.env().caller();
             let contract_address = self.env().account_id();
             // Transfer tokens from caller to contract
             let mut erc721 = Self::get_nft(nft_address);
             let erc721_transfer = erc721.transfer_from(caller, contract_address, token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            if erc721_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC721TransferFailed);
+            }
 
             self.total_trades += 1;
             let trade_id = self.total_trades as u64;
@@ -215,7 +580,8 @@ mod exchangemanager {
                 expiration_date: expiration_date,
                 fee: self.administration.fee,
             };
-            self.trades.insert(trade_id, trade);
+            self.insert_trade(trade);
+            self.lower_collection_floor(nft_address, price);
 
             self.env().emit_event(TradeListed {
                 seller: caller,
@@ -224,249 +590,2785 @@ mod exchangemanager {
                 token_id: token_id,
                 price: price,
             });
+            self.locked = false;
             Ok(())
         }
 
         #[ink(message)]
         pub fn purchase(&mut self, trade_id: u64) -> Result<(), Error> {
+            self.lock()?;
             let current_time = self.get_current_time();
             let caller = self.env().caller();
             let contract_address = self.env().account_id();
 
-            let trade_opt = self.trades.get_mut(&trade_id);
-            assert_eq!(trade_opt.is_some(), true, "Trade not available");
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            let trade_opt = self.trades.get(&trade_id);
+            if trade_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchTrade);
+            }
 
-            let trade = trade_opt.unwrap();
+            let trade = *trade_opt.unwrap();
 
-            assert_eq!(
-                trade.status,
-                TradeStatus::Available as u8,
-                "Only available trades can be purchased"
-            );
+            // Sealed bid auctions only sell through settle_sealed_bid_auction
+            if trade.status != TradeStatus::Available as u8
+                || self.sealed_bidders.contains_key(&trade_id)
+            {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
 
             // Deduct fee
             let fee: u128 = (trade.fee as u128) * trade.price / 100;
-            let erc20_amount = trade.price - fee;
+            let mut erc20_amount = trade.price - fee;
+            let mut erc721 = Self::get_nft(trade.nft_address);
+
+            // Deduct royalty if the nft defines one
+            let (royalty_recipient, royalty_amount) = if self.administration.royalties_enabled {
+                let (recipient, amount) = erc721.royalty_info(trade.token_id, trade.price);
+                (recipient, amount.min(erc20_amount))
+            } else {
+                (AccountId::from([0x0; 32]), 0)
+            };
+            erc20_amount -= royalty_amount;
 
             // Transfer tokens to contract
-            let erc20_transfer =
-                self.erc20
-                    .transfer_from(caller, contract_address, trade.price as u128);
-            assert_eq!(erc20_transfer.is_ok(), true, "ERC20 Token transfer failed");
+            let erc20_transfer = erc20.transfer_from(caller, contract_address, trade.price as u128);
+            if erc20_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
 
+            // The buyer has paid: any failure from here on must revert the whole call
             // Transfer tokens to seller deducting fee
-            let fee_transfer = self
-                .erc20
-                .transfer(trade.beneficiary_address, erc20_amount as u128);
-            assert_eq!(fee_transfer.is_ok(), true, "ERC20 Token transfer failed");
+            let fee_transfer = erc20.transfer(trade.beneficiary_address, erc20_amount as u128);
+            assert!(fee_transfer.is_ok(), "Seller transfer failed");
+
+            // Transfer royalty to recipient
+            if royalty_amount > 0 {
+                let royalty_transfer = erc20.transfer(royalty_recipient, royalty_amount);
+                assert!(royalty_transfer.is_ok(), "Royalty transfer failed");
+            }
 
             // Transfer nft to buyer
-            let mut erc721 = Self::get_nft(trade.nft_address);
             let erc721_transfer = erc721.transfer_from(contract_address, caller, trade.token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            assert!(erc721_transfer.is_ok(), "Nft transfer failed");
 
             // Mark trade as done
-            trade.buyer_address = Some(caller);
-            trade.status = TradeStatus::Purchased as u8;
-
-            let trade_clone = trade.clone();
+            self.mark_trade_purchased(trade_id, caller, trade.price);
             self.env().emit_event(TradePurchased {
                 buyer: caller,
-                nft_address: trade_clone.nft_address,
-                trade_id: trade_clone.id,
-                token_id: trade_clone.token_id,
+                nft_address: trade.nft_address,
+                trade_id: trade.id,
+                token_id: trade.token_id,
             });
+            self.sweep_fees_if_due();
 
+            self.locked = false;
             Ok(())
         }
 
         #[ink(message)]
         pub fn expire_trade(&mut self, trade_id: u64) -> Result<(), Error> {
+            self.lock()?;
             let caller = self.env().caller();
-            let contract_address = self.env().account_id();
 
-            let trade_opt = self.trades.get_mut(&trade_id);
-            assert_eq!(trade_opt.is_some(), true, "Trade not available");
+            let trade_opt = self.trades.get(&trade_id);
+            if trade_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchTrade);
+            }
 
             let trade = trade_opt.unwrap();
-            assert_eq!(trade.seller_address, caller, "Only seller can expire trade");
-
-            assert_eq!(
-                trade.status,
-                TradeStatus::Available as u8,
-                "Only available trades can be expired"
-            );
-
-            //Transfer token back to seller
-            let mut erc721 = Self::get_nft(trade.nft_address);
-            let erc721_transfer = erc721.transfer_from(contract_address, caller, trade.token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
-
-            trade.status = TradeStatus::Cancelled as u8;
+            if trade.seller_address != caller {
+                self.locked = false;
+                return Err(Error::NotSeller);
+            }
 
-            let trade_clone = trade.clone();
-            self.env().emit_event(TradeCancelled {
-                buyer: caller,
-                nft_address: trade_clone.nft_address,
-                trade_id: trade_clone.id,
-                token_id: trade_clone.token_id,
-            });
+            if trade.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
 
-            Ok(())
+            let result = self.return_trade_nft(trade_id);
+            self.locked = false;
+            result
         }
 
+        /// Allows seller to take back the token of a trade past its expiration date
         #[ink(message)]
-        pub fn withdraw_fees(&mut self, erc20_address: AccountId) {
-            assert!(self.only_owner(self.env().caller()));
-            let contract_address = self.env().account_id();
+        pub fn withdraw_unsold_nft(&mut self, trade_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let current_time = self.get_current_time();
+            let caller = self.env().caller();
 
-            let balance = self.erc20.balance_of(contract_address);
-            let fee_transfer = self.erc20.transfer(erc20_address, balance);
-            assert_eq!(fee_transfer.is_ok(), true, "ERC20 Token transfer failed");
+            let trade = match self.trades.get(&trade_id) {
+                Some(trade) => trade,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchTrade);
+                }
+            };
+            if trade.seller_address != caller {
+                self.locked = false;
+                return Err(Error::NotSeller);
+            }
+            if trade.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
+            if current_time <= trade.expiration_date {
+                self.locked = false;
+                return Err(Error::TradeNotExpired);
+            }
+
+            let result = self.return_trade_nft(trade_id);
+            self.locked = false;
+            result
         }
 
+        /// Allows owner to send the token of an available trade back to its seller
         #[ink(message)]
-        pub fn list_trades_paginated(&self, start: u64, end: u64) -> Vec<Trade> {
-            let mut trades: Vec<Trade> = Vec::new();
+        pub fn admin_return_nft(&mut self, trade_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            assert!(self.only_owner(self.env().caller()));
 
-            for i in start..end {
-                let trade_opt = self.trades.get(&i);
-                if trade_opt.is_some() {
-                    trades.push(*trade_opt.unwrap());
+            let trade = match self.trades.get(&trade_id) {
+                Some(trade) => trade,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchTrade);
                 }
+            };
+            if trade.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
             }
-            trades
+
+            let result = self.return_trade_nft(trade_id);
+            self.locked = false;
+            result
         }
 
+        /// Allows owner to delist a trade for compliance reasons, returning the nft to its seller
+        /// Sealed bids on the trade are refunded. Purchased trades can't be cancelled
         #[ink(message)]
-        pub fn list_available_trades(&self) -> Vec<Trade> {
-            let mut trades: Vec<Trade> = Vec::new();
+        pub fn admin_cancel_trade(&mut self, trade_id: u64, reason: Vec<u8>) -> Result<(), Error> {
+            self.lock()?;
+            assert!(self.only_owner(self.env().caller()));
 
-            for (_i, trade) in self.trades.iter() {
-                if trade.status == TradeStatus::Available as u8 {
-                    trades.push(*trade);
-                }
-            }
-            trades
+            let result = self.admin_cancel(trade_id, reason);
+            self.locked = false;
+            result
         }
 
+        /// Allows owner to delist several trades at once
+        /// Trades that can't be cancelled are skipped. Returns number of cancelled trades
         #[ink(message)]
-        pub fn list_trades(&self) -> Vec<Trade> {
-            let mut trades: Vec<Trade> = Vec::new();
+        pub fn admin_cancel_trades_batch(&mut self, trade_ids: Vec<u64>) -> u32 {
+            assert!(self.lock().is_ok(), "Reentrant call");
+            assert!(self.only_owner(self.env().caller()));
 
-            for (_i, trade) in self.trades.iter() {
-                trades.push(*trade);
+            let mut cancelled = 0;
+            for trade_id in trade_ids {
+                if self.admin_cancel(trade_id, Vec::new()).is_ok() {
+                    cancelled += 1;
+                }
             }
-            trades
+            self.locked = false;
+            cancelled
         }
 
+        /// Lists several nfts as a single trade
+        /// Caller should have granted approval to every erc721 token before executing this function
+        /// Nfts already moved are returned to caller if any transfer fails
         #[ink(message)]
-        pub fn list_trade(&self, trade_id: u64) -> Trade {
+        pub fn create_bundle_trade(
+            &mut self,
+            nft_addresses: Vec<AccountId>,
+            token_ids: Vec<TokenId>,
+            beneficiary_address: AccountId,
+            price: Balance,
+            expiration_date: u64,
+        ) -> Result<(), Error> {
+            self.lock()?;
+            if nft_addresses.is_empty()
+                || nft_addresses.len() != token_ids.len()
+                || nft_addresses.len() > MAX_BUNDLE_SIZE
+            {
+                self.locked = false;
+                return Err(Error::InvalidBundle);
+            }
+            for nft_address in nft_addresses.iter() {
+                if let Err(error) = self.ensure_whitelisted(*nft_address) {
+                    self.locked = false;
+                    return Err(error);
+                }
+            }
+
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+            // Transfer tokens from caller to contract
+            // Once the first nft moved a failure must revert the whole call
+            for (index, (nft_address, token_id)) in
+                nft_addresses.iter().zip(token_ids.iter()).enumerate()
+            {
+                let mut erc721 = Self::get_nft(*nft_address);
+                let erc721_transfer = erc721.transfer_from(caller, contract_address, *token_id);
+                if erc721_transfer.is_err() {
+                    assert!(index == 0, "Nft transfer failed");
+                    self.locked = false;
+                    return Err(Error::ERC721TransferFailed);
+                }
+            }
+
+            self.total_bundles += 1;
+            let bundle_id = self.total_bundles;
+            let bundle = Bundle {
+                id: bundle_id,
+                nft_addresses: nft_addresses.clone(),
+                token_ids: token_ids.clone(),
+                seller_address: caller,
+                beneficiary_address,
+                price,
+                expiration_date,
+                fee: self.administration.fee,
+                status: TradeStatus::Available as u8,
+                buyer_address: None,
+            };
+            self.bundles.insert(bundle_id, bundle);
+
+            self.env().emit_event(BundleListed {
+                seller: caller,
+                bundle_id,
+                nft_addresses,
+                token_ids,
+                price,
+            });
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Buys every nft of the bundle
+        /// Royalties are not paid on bundles
+        #[ink(message)]
+        pub fn purchase_bundle(&mut self, bundle_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            let bundle = match self.bundles.get_mut(&bundle_id) {
+                Some(bundle) => bundle,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchBundle);
+                }
+            };
+
+            if bundle.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
+
+            // Deduct fee
+            let fee: u128 = (bundle.fee as u128) * bundle.price / 100;
+            let erc20_amount = bundle.price - fee;
+
+            // Transfer tokens to contract
+            let erc20_transfer = erc20.transfer_from(caller, contract_address, bundle.price);
+            if erc20_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            // The buyer has paid: any failure from here on must revert the whole call
+            // Transfer tokens to seller deducting fee
+            let fee_transfer = erc20.transfer(bundle.beneficiary_address, erc20_amount);
+            assert!(fee_transfer.is_ok(), "Seller transfer failed");
+
+            // Transfer nfts to buyer
+            Self::return_nfts(&bundle.nft_addresses, &bundle.token_ids, caller);
+
+            // Mark bundle as done
+            bundle.buyer_address = Some(caller);
+            bundle.status = TradeStatus::Purchased as u8;
+
+            let price = bundle.price;
+            self.env().emit_event(BundlePurchased {
+                buyer: caller,
+                bundle_id,
+                price,
+            });
+            self.sweep_fees_if_due();
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Returns nfts of an unsold bundle to the seller
+        #[ink(message)]
+        pub fn expire_bundle(&mut self, bundle_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let caller = self.env().caller();
+
+            let bundle = match self.bundles.get_mut(&bundle_id) {
+                Some(bundle) => bundle,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchBundle);
+                }
+            };
+            if bundle.seller_address != caller {
+                self.locked = false;
+                return Err(Error::NotSeller);
+            }
+            if bundle.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
+
+            //Transfer tokens back to seller
+            Self::return_nfts(&bundle.nft_addresses, &bundle.token_ids, caller);
+
+            bundle.status = TradeStatus::Cancelled as u8;
+
+            self.env().emit_event(BundleCancelled {
+                seller: caller,
+                bundle_id,
+            });
+
+            self.locked = false;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn list_bundle(&self, bundle_id: u64) -> Result<Bundle, Error> {
+            self.bundles
+                .get(&bundle_id)
+                .cloned()
+                .ok_or(Error::NoSuchBundle)
+        }
+
+        /// Lists token at start_price decaying to end_price at end_time
+        /// Caller should have granted approval to erc721 token before executing this function
+        #[ink(message)]
+        pub fn create_dutch_auction(
+            &mut self,
+            nft_address: AccountId,
+            token_id: TokenId,
+            start_price: Balance,
+            end_price: Balance,
+            end_time: u64,
+        ) -> Result<(), Error> {
+            self.lock()?;
+            let current_time = self.get_current_time();
+            if start_price < end_price || end_time <= current_time {
+                self.locked = false;
+                return Err(Error::InvalidAuction);
+            }
+            if let Err(error) = self.ensure_whitelisted(nft_address) {
+                self.locked = false;
+                return Err(error);
+            }
+
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+            // Transfer tokens from caller to contract
+            let mut erc721 = Self::get_nft(nft_address);
+            let erc721_transfer = erc721.transfer_from(caller, contract_address, token_id);
+            if erc721_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC721TransferFailed);
+            }
+
+            self.total_dutch_auctions += 1;
+            let auction_id = self.total_dutch_auctions;
+            let auction = DutchAuction {
+                id: auction_id,
+                nft_address,
+                token_id,
+                seller: caller,
+                start_price,
+                end_price,
+                start_time: current_time,
+                end_time,
+                fee: self.administration.fee,
+                status: TradeStatus::Available as u8,
+                buyer: None,
+            };
+            self.dutch_auctions.insert(auction_id, auction);
+
+            self.env().emit_event(DutchAuctionCreated {
+                seller: caller,
+                nft_address,
+                auction_id,
+                token_id,
+                start_price,
+                end_price,
+                end_time,
+            });
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Returns price the auction can be bought at now
+        /// Returns 0 if there is no such auction
+        #[ink(message)]
+        pub fn get_dutch_auction_current_price(&self, auction_id: u64) -> Balance {
+            self.dutch_auctions.get(&auction_id).map_or(0, |auction| {
+                Self::dutch_auction_price(auction, self.get_current_time())
+            })
+        }
+
+        #[ink(message)]
+        pub fn list_dutch_auction(&self, auction_id: u64) -> Result<DutchAuction, Error> {
+            self.dutch_auctions
+                .get(&auction_id)
+                .copied()
+                .ok_or(Error::NoSuchAuction)
+        }
+
+        /// Buys the auctioned token at its current price
+        #[ink(message)]
+        pub fn purchase_dutch_auction(&mut self, auction_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let current_time = self.get_current_time();
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            let auction = match self.dutch_auctions.get_mut(&auction_id) {
+                Some(auction) => auction,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchAuction);
+                }
+            };
+
+            if auction.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
+
+            let price = Self::dutch_auction_price(auction, current_time);
+            // Deduct fee
+            let fee: u128 = (auction.fee as u128) * price / 100;
+            let erc20_amount = price - fee;
+
+            // Transfer tokens to contract
+            let erc20_transfer = erc20.transfer_from(caller, contract_address, price);
+            if erc20_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            // The buyer has paid: any failure from here on must revert the whole call
+            // Transfer tokens to seller deducting fee
+            let fee_transfer = erc20.transfer(auction.seller, erc20_amount);
+            assert!(fee_transfer.is_ok(), "Seller transfer failed");
+
+            // Transfer nft to buyer
+            let mut erc721 = Self::get_nft(auction.nft_address);
+            let erc721_transfer = erc721.transfer_from(contract_address, caller, auction.token_id);
+            assert!(erc721_transfer.is_ok(), "Nft transfer failed");
+
+            // Mark auction as done
+            auction.buyer = Some(caller);
+            auction.status = TradeStatus::Purchased as u8;
+
+            let auction_clone = *auction;
+            self.record_trade_volume(auction_clone.nft_address, price);
+            self.env().emit_event(DutchAuctionPurchased {
+                buyer: caller,
+                nft_address: auction_clone.nft_address,
+                auction_id,
+                token_id: auction_clone.token_id,
+                price,
+            });
+            self.sweep_fees_if_due();
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Returns token of an unsold auction to the seller
+        #[ink(message)]
+        pub fn expire_dutch_auction(&mut self, auction_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+
+            let auction = match self.dutch_auctions.get_mut(&auction_id) {
+                Some(auction) => auction,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchAuction);
+                }
+            };
+            if auction.seller != caller {
+                self.locked = false;
+                return Err(Error::NotSeller);
+            }
+            if auction.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
+
+            //Transfer token back to seller
+            let mut erc721 = Self::get_nft(auction.nft_address);
+            let erc721_transfer = erc721.transfer_from(contract_address, caller, auction.token_id);
+            if erc721_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC721TransferFailed);
+            }
+
+            auction.status = TradeStatus::Cancelled as u8;
+
+            let auction_clone = *auction;
+            self.env().emit_event(DutchAuctionCancelled {
+                seller: caller,
+                nft_address: auction_clone.nft_address,
+                auction_id,
+                token_id: auction_clone.token_id,
+            });
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Lists nft for a sealed bid auction that accepts any bid of at least min_price
+        /// until expiration_date. Proceeds go to the caller
+        #[ink(message)]
+        pub fn create_trade_with_minimum_bid(
+            &mut self,
+            nft_address: AccountId,
+            token_id: TokenId,
+            min_price: Balance,
+            expiration_date: u64,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.create_trade(nft_address, token_id, caller, min_price, expiration_date)?;
+            self.sealed_bidders
+                .insert(self.total_trades as TradeId, Vec::new());
+            Ok(())
+        }
+
+        /// Bids amount on a sealed bid auction, holding amount in the contract
+        /// Caller should have granted approval to erc20 before executing this function
+        #[ink(message)]
+        pub fn place_sealed_bid(&mut self, trade_id: u64, amount: Balance) -> Result<(), Error> {
+            self.lock()?;
+            let current_time = self.get_current_time();
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+
+            if let Err(error) = self.validate_sealed_bid(trade_id, caller, amount, current_time) {
+                self.locked = false;
+                return Err(error);
+            }
+
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            if erc20
+                .transfer_from(caller, contract_address, amount)
+                .is_err()
+            {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            self.insert_sealed_bid(
+                trade_id,
+                SealedBid {
+                    bidder: caller,
+                    amount,
+                    placed_at: current_time,
+                },
+            );
+
+            self.env().emit_event(BidPlaced {
+                trade_id,
+                bidder: caller,
+            });
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows bidder to withdraw their bid before the auction is settled
+        #[ink(message)]
+        pub fn cancel_sealed_bid(&mut self, trade_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let caller = self.env().caller();
+
+            let bid = match self.sealed_bids.get(&(trade_id, caller)) {
+                Some(bid) => *bid,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchBid);
+                }
+            };
+
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            if erc20.transfer(caller, bid.amount).is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+            self.remove_sealed_bid(trade_id, caller);
+
+            self.env().emit_event(BidCancelled {
+                trade_id,
+                bidder: caller,
+            });
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows seller to settle a sealed bid auction once it expired
+        /// The highest bid wins the nft and every other bid is refunded.
+        /// Without bids the nft goes back to the seller
+        #[ink(message)]
+        pub fn settle_sealed_bid_auction(&mut self, trade_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let current_time = self.get_current_time();
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+
+            let trade = match self.trades.get(&trade_id) {
+                Some(trade) if self.sealed_bidders.contains_key(&trade_id) => *trade,
+                _ => {
+                    self.locked = false;
+                    return Err(Error::NoSuchAuction);
+                }
+            };
+            if trade.seller_address != caller {
+                self.locked = false;
+                return Err(Error::NotSeller);
+            }
+            if trade.status != TradeStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::TradeUnavailable);
+            }
+            if current_time <= trade.expiration_date {
+                self.locked = false;
+                return Err(Error::TradeNotExpired);
+            }
+
+            let winning_bid = match self.highest_sealed_bid(trade_id) {
+                Some(bid) => bid,
+                None => {
+                    let result = self.return_trade_nft(trade_id);
+                    if result.is_ok() {
+                        self.env().emit_event(SealedAuctionSettled {
+                            trade_id,
+                            winner: None,
+                            amount: 0,
+                        });
+                    }
+                    self.locked = false;
+                    return result;
+                }
+            };
+
+            // Refund every bid but the winning one
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            // Any failure from here on must revert the refunds already made
+            let bidders = self
+                .sealed_bidders
+                .get(&trade_id)
+                .cloned()
+                .unwrap_or_default();
+            for bidder in bidders {
+                let bid = *self.sealed_bids.get(&(trade_id, bidder)).unwrap();
+                assert!(
+                    bidder == winning_bid.bidder || erc20.transfer(bidder, bid.amount).is_ok(),
+                    "Bid refund failed"
+                );
+                self.remove_sealed_bid(trade_id, bidder);
+            }
+
+            // Pay seller deducting fee
+            let fee: u128 = (trade.fee as u128) * winning_bid.amount / 100;
+            let seller_amount = winning_bid.amount - fee;
+            assert!(
+                erc20
+                    .transfer(trade.beneficiary_address, seller_amount)
+                    .is_ok(),
+                "Seller transfer failed"
+            );
+
+            // Transfer nft to winner
+            let mut erc721 = Self::get_nft(trade.nft_address);
+            let erc721_transfer =
+                erc721.transfer_from(contract_address, winning_bid.bidder, trade.token_id);
+            assert!(erc721_transfer.is_ok(), "Nft transfer failed");
+
+            // Mark trade as done
+            self.mark_trade_purchased(trade_id, winning_bid.bidder, winning_bid.amount);
+            self.env().emit_event(SealedAuctionSettled {
+                trade_id,
+                winner: Some(winning_bid.bidder),
+                amount: winning_bid.amount,
+            });
+            self.sweep_fees_if_due();
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Returns bid of bidder on a sealed bid auction
+        #[ink(message)]
+        pub fn get_sealed_bid(&self, trade_id: u64, bidder: AccountId) -> Option<SealedBid> {
+            self.sealed_bids.get(&(trade_id, bidder)).copied()
+        }
+
+        /// Bids up to max_price on any nft of nft_address until expiry
+        /// max_price is held by the contract until the offer is accepted or cancelled.
+        /// Caller should have granted approval to erc20 before executing this function
+        #[ink(message)]
+        pub fn make_collection_offer(
+            &mut self,
+            nft_address: AccountId,
+            max_price: Balance,
+            expiry: u64,
+        ) -> Result<(), Error> {
+            self.lock()?;
+            if let Err(error) = self.ensure_whitelisted(nft_address) {
+                self.locked = false;
+                return Err(error);
+            }
+            if expiry <= self.get_current_time() {
+                self.locked = false;
+                return Err(Error::OfferExpired);
+            }
+
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            if erc20
+                .transfer_from(caller, contract_address, max_price)
+                .is_err()
+            {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            self.total_collection_offers += 1;
+            let offer_id = self.total_collection_offers;
+            self.insert_collection_offer(CollectionOffer {
+                id: offer_id,
+                buyer: caller,
+                nft_address,
+                max_price,
+                expiry,
+                status: CollectionOfferStatus::Open as u8,
+            });
+
+            self.env().emit_event(CollectionOfferMade {
+                offer_id,
+                buyer: caller,
+                nft_address,
+                max_price,
+                expiry,
+            });
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows seller of an available trade to sell it to a collection offer
+        /// The nft is sold at the trade price and the rest of max_price is refunded to the buyer
+        #[ink(message)]
+        pub fn accept_collection_offer(
+            &mut self,
+            offer_id: u64,
+            trade_id: u64,
+        ) -> Result<(), Error> {
+            self.lock()?;
+            let current_time = self.get_current_time();
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+
+            let (offer, trade) =
+                match self.validate_collection_offer(offer_id, trade_id, caller, current_time) {
+                    Ok(result) => result,
+                    Err(error) => {
+                        self.locked = false;
+                        return Err(error);
+                    }
+                };
+
+            // Deduct fee
+            let fee: u128 = (trade.fee as u128) * trade.price / 100;
+            let mut erc20_amount = trade.price - fee;
+            let mut erc721 = Self::get_nft(trade.nft_address);
+
+            // Deduct royalty if the nft defines one
+            let (royalty_recipient, royalty_amount) = if self.administration.royalties_enabled {
+                let (recipient, amount) = erc721.royalty_info(trade.token_id, trade.price);
+                (recipient, amount.min(erc20_amount))
+            } else {
+                (AccountId::from([0x0; 32]), 0)
+            };
+            erc20_amount -= royalty_amount;
+
+            // Pay seller deducting fee
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            if erc20
+                .transfer(trade.beneficiary_address, erc20_amount)
+                .is_err()
+            {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            // The seller has been paid: any failure from here on must revert the whole call
+            // Transfer royalty to recipient
+            assert!(
+                royalty_amount == 0 || erc20.transfer(royalty_recipient, royalty_amount).is_ok(),
+                "Royalty transfer failed"
+            );
+
+            // Refund the escrow exceeding the trade price
+            let excess = offer.max_price - trade.price;
+            assert!(
+                excess == 0 || erc20.transfer(offer.buyer, excess).is_ok(),
+                "Offer refund failed"
+            );
+
+            // Transfer nft to buyer
+            let erc721_transfer =
+                erc721.transfer_from(contract_address, offer.buyer, trade.token_id);
+            assert!(erc721_transfer.is_ok(), "Nft transfer failed");
+
+            // Mark trade and offer as done
+            self.mark_trade_purchased(trade_id, offer.buyer, trade.price);
+            self.close_collection_offer(offer_id, CollectionOfferStatus::Accepted);
+
+            self.env().emit_event(CollectionOfferAccepted {
+                offer_id,
+                trade_id,
+                seller: caller,
+                price: trade.price,
+            });
+            self.sweep_fees_if_due();
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows buyer to withdraw an open collection offer, refunding max_price
+        /// Expired offers can be cancelled as well so their escrow isn't locked forever
+        #[ink(message)]
+        pub fn cancel_collection_offer(&mut self, offer_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let caller = self.env().caller();
+
+            let offer = match self.collection_offers.get(&offer_id) {
+                Some(offer) => *offer,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchOffer);
+                }
+            };
+            if offer.buyer != caller {
+                self.locked = false;
+                return Err(Error::NotOfferBuyer);
+            }
+            if offer.status != CollectionOfferStatus::Open as u8 {
+                self.locked = false;
+                return Err(Error::OfferUnavailable);
+            }
+
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            if erc20.transfer(caller, offer.max_price).is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+            self.close_collection_offer(offer_id, CollectionOfferStatus::Cancelled);
+
+            self.env().emit_event(CollectionOfferCancelled {
+                offer_id,
+                buyer: caller,
+            });
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Returns collection offer by id
+        #[ink(message)]
+        pub fn get_collection_offer(&self, offer_id: u64) -> Option<CollectionOffer> {
+            self.collection_offers.get(&offer_id).copied()
+        }
+
+        #[ink(message)]
+        pub fn withdraw_fees(&mut self, erc20_address: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            self.lock()?;
+            assert!(self.only_owner(self.env().caller()));
+            let contract_address = self.env().account_id();
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+
+            // Bids and offers held in escrow stay in the contract
+            let balance = self.withdrawable_fees(erc20.balance_of(contract_address));
+            let fee_transfer = erc20.transfer(erc20_address, balance);
+            if fee_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+            self.locked = false;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn list_trades_paginated(&self, start: u64, end: u64) -> Vec<Trade> {
+            let mut trades: Vec<Trade> = Vec::new();
+
+            for i in start..end {
+                let trade_opt = self.trades.get(&i);
+                if trade_opt.is_some() {
+                    trades.push(*trade_opt.unwrap());
+                }
+            }
+            trades
+        }
+
+        #[ink(message)]
+        pub fn list_available_trades(&self) -> Vec<Trade> {
+            let mut trades: Vec<Trade> = Vec::new();
+
+            for (_i, trade) in self.trades.iter() {
+                if trade.status == TradeStatus::Available as u8 {
+                    trades.push(*trade);
+                }
+            }
+            trades
+        }
+
+        /// Returns available trades expiring within within_ms milliseconds from now
+        /// Trades without an expiration date and trades already expired are left out
+        #[ink(message)]
+        pub fn get_trades_expiring_soon(&self, within_ms: u64) -> Vec<TradeId> {
+            let current_time = self.get_current_time();
+            self.available_trade_ids_with(|expiration_date| {
+                expiration_date >= current_time && expiration_date - current_time <= within_ms
+            })
+        }
+
+        /// Returns available trades past their expiration date whose nft was not withdrawn yet
+        #[ink(message)]
+        pub fn get_expired_unclaimed_trades(&self) -> Vec<TradeId> {
+            let current_time = self.get_current_time();
+            self.available_trade_ids_with(|expiration_date| expiration_date < current_time)
+        }
+
+        #[ink(message)]
+        pub fn list_trades(&self) -> Vec<Trade> {
+            let mut trades: Vec<Trade> = Vec::new();
+
+            for (_i, trade) in self.trades.iter() {
+                trades.push(*trade);
+            }
+            trades
+        }
+
+        #[ink(message)]
+        pub fn list_trade(&self, trade_id: u64) -> Result<Trade, Error> {
             let trade_opt = self.trades.get(&trade_id);
-            assert_eq!(trade_opt.is_some(), true, "Trade not available");
+            if trade_opt.is_none() {
+                return Err(Error::NoSuchTrade);
+            }
+
+            Ok(*trade_opt.clone().unwrap())
+        }
+
+        /// Returns every trade ever created for the token, oldest first
+        #[ink(message)]
+        pub fn get_trade_history_for_token(
+            &self,
+            nft_address: AccountId,
+            token_id: TokenId,
+        ) -> Vec<Trade> {
+            self.token_trade_history
+                .get(&(nft_address, token_id))
+                .map_or(Vec::new(), |trade_ids| {
+                    trade_ids
+                        .iter()
+                        .filter_map(|trade_id| self.trades.get(trade_id).copied())
+                        .collect()
+                })
+        }
+
+        /// Returns number of trades ever created for the token
+        #[ink(message)]
+        pub fn get_trade_count_for_token(&self, nft_address: AccountId, token_id: TokenId) -> u32 {
+            self.token_trade_history
+                .get(&(nft_address, token_id))
+                .map_or(0, |trade_ids| trade_ids.len() as u32)
+        }
+
+        /// Returns up to limit trades of seller starting at offset, oldest first
+        #[ink(message)]
+        pub fn list_trades_by_seller(
+            &self,
+            seller: AccountId,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Trade> {
+            self.seller_trades_with(seller, |_| true)
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect()
+        }
+
+        /// Returns up to limit trades of seller in status starting at offset, oldest first
+        #[ink(message)]
+        pub fn list_trades_by_seller_status(
+            &self,
+            seller: AccountId,
+            status: TradeStatus,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Trade> {
+            self.seller_trades_with(seller, |trade| trade.status == status as u8)
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect()
+        }
+
+        /// Returns number of trades ever created by seller
+        #[ink(message)]
+        pub fn get_seller_trade_count(&self, seller: AccountId) -> u32 {
+            self.seller_trades
+                .get(&seller)
+                .map_or(0, |trade_ids| trade_ids.len() as u32)
+        }
+
+        /// Returns number of trades of seller in status
+        #[ink(message)]
+        pub fn get_seller_trade_count_by_status(
+            &self,
+            seller: AccountId,
+            status: TradeStatus,
+        ) -> u32 {
+            self.seller_trades_with(seller, |trade| trade.status == status as u8)
+                .count() as u32
+        }
+
+        /// Returns up to limit trades of nft_address starting at offset, oldest first
+        #[ink(message)]
+        pub fn list_all_trades_by_nft(
+            &self,
+            nft_address: AccountId,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Trade> {
+            self.nft_trades_with(nft_address, |_| true)
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect()
+        }
+
+        /// Returns up to limit available trades of nft_address starting at offset, oldest first
+        #[ink(message)]
+        pub fn list_available_trades_by_nft(
+            &self,
+            nft_address: AccountId,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Trade> {
+            self.nft_trades_with(nft_address, |trade| {
+                trade.status == TradeStatus::Available as u8
+            })
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect()
+        }
+
+        /// Returns number of available and purchased trades, floor price and volume of nft_address
+        /// Bundles and dutch auctions are not taken into account
+        #[ink(message)]
+        pub fn get_nft_collection_stats(&self, nft_address: AccountId) -> CollectionStats {
+            let mut stats = CollectionStats {
+                floor_price: self.floor_price_for_collection(nft_address),
+                total_volume: self.get_volume_for_nft_address(nft_address),
+                ..Default::default()
+            };
+            for trade in self.nft_trades_with(nft_address, |_| true) {
+                if trade.status == TradeStatus::Available as u8 {
+                    stats.listed += 1;
+                } else if trade.status == TradeStatus::Purchased as u8 {
+                    stats.sold += 1;
+                }
+            }
+            stats
+        }
+
+        /// Returns sum of prices of all purchased trades
+        #[ink(message)]
+        pub fn get_total_volume(&self) -> Balance {
+            self.total_volume
+        }
+
+        /// Returns number of purchased trades
+        #[ink(message)]
+        pub fn get_total_completed_trades(&self) -> u32 {
+            self.total_completed_trades
+        }
+
+        /// Returns sum of prices of all purchased trades of nft_address
+        #[ink(message)]
+        pub fn get_volume_for_nft_address(&self, nft_address: AccountId) -> Balance {
+            *self.nft_volumes.get(&nft_address).unwrap_or(&0)
+        }
+
+        /// Returns lowest price of available trades of nft_address
+        /// Bundles are not taken into account
+        #[ink(message)]
+        pub fn floor_price_for_collection(&self, nft_address: AccountId) -> Option<Balance> {
+            self.collection_floor.get(&nft_address).copied()
+        }
+
+        /// Returns sum of prices of all purchased trades of nft_address
+        #[ink(message)]
+        pub fn get_collection_volume(&self, nft_address: AccountId) -> Balance {
+            self.get_volume_for_nft_address(nft_address)
+        }
+
+        /// Allows owner to set transfer rate
+        /// Only affects future borrowing
+        #[ink(message)]
+        pub fn set_fee(&mut self, _fee: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.env().emit_event(FeeChanged {
+                old_value: self.administration.fee,
+                new_value: _fee,
+            });
+            self.administration.fee = _fee;
+            Ok(())
+        }
+
+        /// Returns current transfer rate
+        #[ink(message)]
+        pub fn get_fee(&self) -> u64 {
+            self.administration.fee
+        }
+
+        /// Returns fees collected by the contract and not yet withdrawn
+        /// Bids and offers held in escrow are not counted
+        #[ink(message)]
+        pub fn get_pending_fees(&self) -> Balance {
+            self.get_erc20().map_or(0, |erc20| {
+                self.withdrawable_fees(erc20.balance_of(self.env().account_id()))
+            })
+        }
+
+        /// Allows owner to set the pending fees at which a purchase sweeps them
+        /// to the fee recipient. A threshold of 0 disables the sweep
+        #[ink(message)]
+        pub fn set_auto_fee_sweep_threshold(&mut self, threshold: Balance) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.auto_fee_sweep_threshold = threshold;
+            Ok(())
+        }
+
+        /// Returns the pending fees at which a purchase sweeps them
+        #[ink(message)]
+        pub fn get_auto_fee_sweep_threshold(&self) -> Balance {
+            self.administration.auto_fee_sweep_threshold
+        }
+
+        /// Allows owner to set the address swept fees are sent to
+        #[ink(message)]
+        pub fn set_fee_recipient(&mut self, addr: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.fee_recipient = addr;
+            Ok(())
+        }
+
+        /// Returns the address swept fees are sent to
+        #[ink(message)]
+        pub fn get_fee_recipient(&self) -> AccountId {
+            self.administration.fee_recipient
+        }
+
+        /// Allows owner to enable or disable paying nft royalties on purchase
+        #[ink(message)]
+        pub fn set_royalties_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.royalties_enabled = enabled;
+            Ok(())
+        }
+
+        /// Checks if nft royalties are paid on purchase
+        #[ink(message)]
+        pub fn is_royalties_enabled(&self) -> bool {
+            self.administration.royalties_enabled
+        }
+
+        /// Allows owner to restrict listings to whitelisted nft contracts
+        #[ink(message)]
+        pub fn set_whitelist_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.whitelist_enabled = enabled;
+            Ok(())
+        }
+
+        /// Checks if listings are restricted to whitelisted nft contracts
+        #[ink(message)]
+        pub fn is_whitelist_enabled(&self) -> bool {
+            self.administration.whitelist_enabled
+        }
+
+        /// Allows owner to whitelist nft_address for listing
+        #[ink(message)]
+        pub fn add_nft_to_whitelist(&mut self, nft_address: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.nft_whitelist.insert(nft_address, true);
+            Ok(())
+        }
+
+        /// Allows owner to remove nft_address from the whitelist
+        /// Existing trades of nft_address are not affected
+        #[ink(message)]
+        pub fn remove_nft_from_whitelist(&mut self, nft_address: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.nft_whitelist.take(&nft_address);
+            Ok(())
+        }
+
+        /// Checks if nft_address is whitelisted
+        #[ink(message)]
+        pub fn is_nft_whitelisted(&self, nft_address: AccountId) -> bool {
+            *self.nft_whitelist.get(&nft_address).unwrap_or(&false)
+        }
+
+        /// Allows owner to enable borrowing
+        #[ink(message)]
+        pub fn enable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.enabled = true;
+            self.env().emit_event(Enabled {});
+            Ok(())
+        }
+
+        /// Allows owner to disable borrowing
+        #[ink(message)]
+        pub fn disable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.enabled = false;
+            self.env().emit_event(Disbaled {});
+            Ok(())
+        }
+
+        /// Checks if borrowing is enabled
+        #[ink(message)]
+        pub fn is_enabled(&self) -> bool {
+            self.administration.enabled
+        }
+
+        fn get_current_time(&self) -> u64 {
+            self.env().block_timestamp()
+        }
+
+        /// Stores trade and adds it to the history of its token
+        fn insert_trade(&mut self, trade: Trade) {
+            self.token_trade_history
+                .entry((trade.nft_address, trade.token_id))
+                .or_insert_with(Vec::new)
+                .push(trade.id);
+            self.seller_trades
+                .entry(trade.seller_address)
+                .or_insert_with(Vec::new)
+                .push(trade.id);
+            self.nft_address_trades
+                .entry(trade.nft_address)
+                .or_insert_with(Vec::new)
+                .push(trade.id);
+            self.trades.insert(trade.id, trade);
+        }
+
+        /// Returns available trades with an expiration date matching predicate
+        fn available_trade_ids_with<F: Fn(u64) -> bool>(&self, predicate: F) -> Vec<TradeId> {
+            let mut trades: Vec<TradeId> = Vec::new();
+            for (trade_id, trade) in self.trades.iter() {
+                if trade.status == TradeStatus::Available as u8
+                    && trade.expiration_date > 0
+                    && predicate(trade.expiration_date)
+                {
+                    trades.push(*trade_id);
+                }
+            }
+            trades
+        }
+
+        fn seller_trades_with<'a, F: Fn(&Trade) -> bool + 'a>(
+            &'a self,
+            seller: AccountId,
+            predicate: F,
+        ) -> impl Iterator<Item = Trade> + 'a {
+            self.indexed_trades_with(self.seller_trades.get(&seller), predicate)
+        }
+
+        fn nft_trades_with<'a, F: Fn(&Trade) -> bool + 'a>(
+            &'a self,
+            nft_address: AccountId,
+            predicate: F,
+        ) -> impl Iterator<Item = Trade> + 'a {
+            self.indexed_trades_with(self.nft_address_trades.get(&nft_address), predicate)
+        }
+
+        fn indexed_trades_with<'a, F: Fn(&Trade) -> bool + 'a>(
+            &'a self,
+            trade_ids: Option<&'a Vec<TradeId>>,
+            predicate: F,
+        ) -> impl Iterator<Item = Trade> + 'a {
+            trade_ids
+                .into_iter()
+                .flatten()
+                .filter_map(move |trade_id| self.trades.get(trade_id).copied())
+                .filter(move |trade| predicate(trade))
+        }
+
+        /// Transfers token of an available trade back to its seller and cancels the trade
+        fn return_trade_nft(&mut self, trade_id: TradeId) -> Result<(), Error> {
+            // Bidders have to be refunded through settle_sealed_bid_auction
+            if self
+                .sealed_bidders
+                .get(&trade_id)
+                .map_or(false, |bidders| !bidders.is_empty())
+            {
+                return Err(Error::AuctionHasBids);
+            }
+            let contract_address = self.env().account_id();
+            let trade = self.trades.get_mut(&trade_id).ok_or(Error::NoSuchTrade)?;
+
+            //Transfer token back to seller
+            let mut erc721 = Self::get_nft(trade.nft_address);
+            let erc721_transfer =
+                erc721.transfer_from(contract_address, trade.seller_address, trade.token_id);
+            if erc721_transfer.is_err() {
+                return Err(Error::ERC721TransferFailed);
+            }
+
+            trade.status = TradeStatus::Cancelled as u8;
+
+            let trade_clone = trade.clone();
+            self.refresh_collection_floor(trade_clone.nft_address, trade_clone.price);
+            self.env().emit_event(TradeCancelled {
+                buyer: trade_clone.seller_address,
+                nft_address: trade_clone.nft_address,
+                trade_id: trade_clone.id,
+                token_id: trade_clone.token_id,
+            });
+            Ok(())
+        }
+
+        /// Refunds sealed bids of trade_id and returns its nft to the seller
+        fn admin_cancel(&mut self, trade_id: TradeId, reason: Vec<u8>) -> Result<(), Error> {
+            let trade = *self.trades.get(&trade_id).ok_or(Error::NoSuchTrade)?;
+            if trade.status == TradeStatus::Purchased as u8 {
+                return Err(Error::TradePurchased);
+            }
+            // Cancelled trades have already returned their nft
+            if trade.status != TradeStatus::Available as u8 {
+                return Err(Error::TradeUnavailable);
+            }
+
+            let bidders = self
+                .sealed_bidders
+                .get(&trade_id)
+                .cloned()
+                .unwrap_or_default();
+            if !bidders.is_empty() {
+                let mut erc20 = self.get_erc20()?;
+                for bidder in bidders {
+                    let bid = *self.sealed_bids.get(&(trade_id, bidder)).unwrap();
+                    if erc20.transfer(bidder, bid.amount).is_err() {
+                        return Err(Error::ERC20TransferFailed);
+                    }
+                    self.remove_sealed_bid(trade_id, bidder);
+                    self.env().emit_event(BidCancelled { trade_id, bidder });
+                }
+            }
+
+            self.return_trade_nft(trade_id)?;
+            self.env().emit_event(AdminCancellation {
+                trade_id,
+                seller: trade.seller_address,
+                reason,
+            });
+            Ok(())
+        }
+
+        /// Price decays linearly from start_price at start_time to end_price at end_time
+        fn dutch_auction_price(auction: &DutchAuction, current_time: u64) -> Balance {
+            if current_time >= auction.end_time {
+                return auction.end_price;
+            }
+            let elapsed = current_time.saturating_sub(auction.start_time) as Balance;
+            let duration = (auction.end_time - auction.start_time) as Balance;
+            auction.start_price - (auction.start_price - auction.end_price) * elapsed / duration
+        }
+
+        fn validate_sealed_bid(
+            &self,
+            trade_id: TradeId,
+            bidder: AccountId,
+            amount: Balance,
+            time: u64,
+        ) -> Result<(), Error> {
+            if !self.sealed_bidders.contains_key(&trade_id) {
+                return Err(Error::NoSuchAuction);
+            }
+            let trade = self.trades.get(&trade_id).ok_or(Error::NoSuchTrade)?;
+            if trade.status != TradeStatus::Available as u8 || time > trade.expiration_date {
+                return Err(Error::TradeUnavailable);
+            }
+            if trade.seller_address == bidder {
+                return Err(Error::SellerCannotBid);
+            }
+            if amount < trade.price {
+                return Err(Error::BidTooLow);
+            }
+            if self.sealed_bids.contains_key(&(trade_id, bidder)) {
+                return Err(Error::BidAlreadyPlaced);
+            }
+            Ok(())
+        }
+
+        /// Returns the highest bid on a sealed bid auction, the earliest one on a tie
+        fn highest_sealed_bid(&self, trade_id: TradeId) -> Option<SealedBid> {
+            let bidders = self.sealed_bidders.get(&trade_id)?;
+            let mut highest: Option<SealedBid> = None;
+            for bidder in bidders {
+                let bid = *self.sealed_bids.get(&(trade_id, *bidder)).unwrap();
+                if highest.map_or(true, |highest| bid.amount > highest.amount) {
+                    highest = Some(bid);
+                }
+            }
+            highest
+        }
+
+        /// Stores bid on trade_id, counting its amount as escrowed
+        fn insert_sealed_bid(&mut self, trade_id: TradeId, bid: SealedBid) {
+            self.escrowed += bid.amount;
+            self.sealed_bids.insert((trade_id, bid.bidder), bid);
+            self.sealed_bidders
+                .get_mut(&trade_id)
+                .unwrap()
+                .push(bid.bidder);
+        }
+
+        /// Drops bid of bidder on trade_id and releases its amount from escrow
+        fn remove_sealed_bid(&mut self, trade_id: TradeId, bidder: AccountId) {
+            if let Some(bid) = self.sealed_bids.take(&(trade_id, bidder)) {
+                self.escrowed -= bid.amount;
+            }
+            if let Some(bidders) = self.sealed_bidders.get_mut(&trade_id) {
+                bidders.retain(|account| *account != bidder);
+            }
+        }
+
+        /// Stores an open offer, counting its max_price as escrowed
+        fn insert_collection_offer(&mut self, offer: CollectionOffer) {
+            self.escrowed += offer.max_price;
+            self.collection_offers.insert(offer.id, offer);
+        }
+
+        /// Closes an open offer and releases its max_price from escrow
+        fn close_collection_offer(&mut self, offer_id: u64, status: CollectionOfferStatus) {
+            let offer = self.collection_offers.get_mut(&offer_id).unwrap();
+            offer.status = status as u8;
+            self.escrowed -= offer.max_price;
+        }
+
+        /// Returns the part of balance that is fees rather than bids and offers held in escrow
+        fn withdrawable_fees(&self, balance: Balance) -> Balance {
+            balance.saturating_sub(self.escrowed)
+        }
+
+        fn validate_collection_offer(
+            &self,
+            offer_id: u64,
+            trade_id: TradeId,
+            seller: AccountId,
+            time: u64,
+        ) -> Result<(CollectionOffer, Trade), Error> {
+            let offer = *self
+                .collection_offers
+                .get(&offer_id)
+                .ok_or(Error::NoSuchOffer)?;
+            if offer.status != CollectionOfferStatus::Open as u8 {
+                return Err(Error::OfferUnavailable);
+            }
+            if time > offer.expiry {
+                return Err(Error::OfferExpired);
+            }
+            let trade = *self.trades.get(&trade_id).ok_or(Error::NoSuchTrade)?;
+            if trade.seller_address != seller {
+                return Err(Error::NotSeller);
+            }
+            // Sealed bid auctions only sell through settle_sealed_bid_auction
+            if trade.status != TradeStatus::Available as u8
+                || self.sealed_bidders.contains_key(&trade_id)
+            {
+                return Err(Error::TradeUnavailable);
+            }
+            if trade.nft_address != offer.nft_address {
+                return Err(Error::InvalidCollection);
+            }
+            if trade.price > offer.max_price {
+                return Err(Error::OfferTooLow);
+            }
+            Ok((offer, trade))
+        }
+
+        fn ensure_whitelisted(&self, nft_address: AccountId) -> Result<(), Error> {
+            if self.administration.whitelist_enabled && !self.is_nft_whitelisted(nft_address) {
+                return Err(Error::NFTNotWhitelisted);
+            }
+            Ok(())
+        }
+
+        fn lower_collection_floor(&mut self, nft_address: AccountId, price: Balance) {
+            let floor = self.collection_floor.entry(nft_address).or_insert(price);
+            if price < *floor {
+                *floor = price;
+            }
+        }
+
+        /// Recomputes the floor of nft_address if a trade at the floor price
+        /// is no longer available
+        fn refresh_collection_floor(&mut self, nft_address: AccountId, price: Balance) {
+            if self.collection_floor.get(&nft_address) != Some(&price) {
+                return;
+            }
+            let floor = self
+                .trades
+                .values()
+                .filter(|trade| {
+                    trade.nft_address == nft_address && trade.status == TradeStatus::Available as u8
+                })
+                .map(|trade| trade.price)
+                .min();
+            match floor {
+                Some(floor) => {
+                    self.collection_floor.insert(nft_address, floor);
+                }
+                None => {
+                    self.collection_floor.take(&nft_address);
+                }
+            }
+        }
+
+        /// Marks trade_id as bought by buyer once payment and nft transfers are done
+        /// amount is the price actually paid and is added to the trade volume
+        fn mark_trade_purchased(&mut self, trade_id: TradeId, buyer: AccountId, amount: Balance) {
+            let trade = self.trades.get_mut(&trade_id).unwrap();
+            trade.buyer_address = Some(buyer);
+            trade.status = TradeStatus::Purchased as u8;
+
+            let trade = *trade;
+            self.record_trade_volume(trade.nft_address, amount);
+            self.refresh_collection_floor(trade.nft_address, trade.price);
+        }
+
+        fn record_trade_volume(&mut self, nft_address: AccountId, price: Balance) {
+            self.total_volume += price;
+            self.total_completed_trades += 1;
+            let volume = self.nft_volumes.entry(nft_address).or_insert(0);
+            *volume += price;
+        }
+
+        /// Sends pending fees to the fee recipient once they reach the threshold
+        /// Bids and offers held in escrow are never swept.
+        /// A failed sweep leaves the fees for withdraw_fees and doesn't undo the purchase
+        fn sweep_fees_if_due(&mut self) {
+            if self.administration.auto_fee_sweep_threshold == 0 {
+                return;
+            }
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(_) => return,
+            };
+            let pending_fees = self.withdrawable_fees(erc20.balance_of(self.env().account_id()));
+            if !self.fee_sweep_due(pending_fees) {
+                return;
+            }
+
+            let recipient = self.administration.fee_recipient;
+            if erc20.transfer(recipient, pending_fees).is_ok() {
+                self.env().emit_event(AutoFeeSweep {
+                    amount: pending_fees,
+                    recipient,
+                });
+            }
+        }
+
+        fn fee_sweep_due(&self, pending_fees: Balance) -> bool {
+            let threshold = self.administration.auto_fee_sweep_threshold;
+            threshold > 0 && pending_fees >= threshold
+        }
+
+        /// Fails with AddressNotFound if the address manager has no erc20 registered
+        fn get_erc20(&self) -> Result<Erc20, Error> {
+            match &self.address_manager {
+                Some(address_manager) => address_manager
+                    .require_address(String::from(ERC20))
+                    .map(Erc20::from_account_id)
+                    .map_err(|_| Error::AddressNotFound),
+                None => Ok((*self.erc20).clone()),
+            }
+        }
+
+        fn get_nft(address: AccountId) -> Erc721 {
+            Erc721::from_account_id(address)
+        }
+
+        /// Transfers nfts held by the contract to recipient
+        /// Panics if any of the transfers fails so that none of them is kept
+        fn return_nfts(nft_addresses: &[AccountId], token_ids: &[TokenId], recipient: AccountId) {
+            let contract_address = Self::env().account_id();
+            for (nft_address, token_id) in nft_addresses.iter().zip(token_ids.iter()) {
+                let mut erc721 = Self::get_nft(*nft_address);
+                let erc721_transfer = erc721.transfer_from(contract_address, recipient, *token_id);
+                assert!(erc721_transfer.is_ok(), "Nft transfer failed");
+            }
+        }
+    }
+
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_lang as ink;
+        /// We test if the constructor does its job.
+        fn instantiate_erc20_contract() -> AccountId {
+            let erc20 = Erc20::new(1000000);
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            callee
+        }
+
+        fn set_caller(caller: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                caller,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+        }
+
+        #[ink::test]
+        fn new_works() {
+            let exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            assert_eq!(exchangemanager.is_enabled(), true);
+            assert_eq!(exchangemanager.get_fee(), 10);
+        }
+
+        #[ink::test]
+        fn reentrancy_guard_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let owner = AccountId::from([0x01; 32]);
+            // Simulate a call made while another guarded call is in progress
+            exchangemanager.locked = true;
+            assert_eq!(exchangemanager.purchase(0), Err(Error::Reentrant));
+            assert_eq!(exchangemanager.expire_trade(0), Err(Error::Reentrant));
+            assert_eq!(exchangemanager.withdraw_fees(owner), Err(Error::Reentrant));
+        }
+
+        #[ink::test]
+        fn typed_errors_work() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            assert_eq!(exchangemanager.purchase(1), Err(Error::NoSuchTrade));
+            assert_eq!(exchangemanager.expire_trade(1), Err(Error::NoSuchTrade));
+            assert_eq!(
+                exchangemanager.list_trade(1).err(),
+                Some(Error::NoSuchTrade)
+            );
+            // Failed calls release the re-entrancy guard
+            assert_eq!(exchangemanager.locked, false);
+        }
+
+        #[ink::test]
+        fn bundle_errors_work() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft = AccountId::from([0x02; 32]);
+            let seller = AccountId::from([0x01; 32]);
+
+            // Bundles are validated before any nft is moved
+            assert_eq!(
+                exchangemanager.create_bundle_trade(Vec::new(), Vec::new(), seller, 100, 0),
+                Err(Error::InvalidBundle)
+            );
+            assert_eq!(
+                exchangemanager.create_bundle_trade(vec![nft, nft], vec![1], seller, 100, 0),
+                Err(Error::InvalidBundle)
+            );
+            assert_eq!(
+                exchangemanager.create_bundle_trade(
+                    vec![nft; MAX_BUNDLE_SIZE + 1],
+                    vec![1; MAX_BUNDLE_SIZE + 1],
+                    seller,
+                    100,
+                    0
+                ),
+                Err(Error::InvalidBundle)
+            );
+            assert_eq!(exchangemanager.list_bundle(1).is_err(), true);
+            assert_eq!(exchangemanager.purchase_bundle(1), Err(Error::NoSuchBundle));
+            assert_eq!(exchangemanager.expire_bundle(1), Err(Error::NoSuchBundle));
+
+            exchangemanager.bundles.insert(
+                1,
+                Bundle {
+                    id: 1,
+                    nft_addresses: vec![nft, nft],
+                    token_ids: vec![1, 2],
+                    seller_address: AccountId::from([0x03; 32]),
+                    status: TradeStatus::Purchased as u8,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(
+                exchangemanager.purchase_bundle(1),
+                Err(Error::TradeUnavailable)
+            );
+            assert_eq!(exchangemanager.expire_bundle(1), Err(Error::NotSeller));
+            assert_eq!(
+                exchangemanager.list_bundle(1).unwrap().token_ids,
+                vec![1, 2]
+            );
+            // Failed calls release the re-entrancy guard
+            assert_eq!(exchangemanager.locked, false);
+        }
+
+        #[ink::test]
+        fn bundle_failures_change_nothing() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft = AccountId::from([0x02; 32]);
+            let seller = AccountId::from([0x01; 32]);
+            exchangemanager.bundles.insert(
+                1,
+                Bundle {
+                    id: 1,
+                    nft_addresses: vec![nft, nft],
+                    token_ids: vec![1, 2],
+                    seller_address: seller,
+                    price: 100,
+                    status: TradeStatus::Available as u8,
+                    ..Default::default()
+                },
+            );
+
+            // Token transfers can't succeed off-chain, so both calls panic on their first
+            // transfer. A panic reverts the call, and nothing was written before it
+            for expire in [false, true].iter() {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    if *expire {
+                        exchangemanager.expire_bundle(1)
+                    } else {
+                        exchangemanager.purchase_bundle(1)
+                    }
+                }));
+                assert!(result.is_err());
+                let bundle = exchangemanager.list_bundle(1).unwrap();
+                assert_eq!(bundle.status, TradeStatus::Available as u8);
+                assert_eq!(bundle.buyer_address, None);
+                // The revert would release the guard as well
+                exchangemanager.locked = false;
+            }
+        }
+
+        #[ink::test]
+        fn trade_history_for_token_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft = AccountId::from([0x02; 32]);
+            assert_eq!(exchangemanager.get_trade_count_for_token(nft, 1), 0);
+            assert_eq!(exchangemanager.get_trade_history_for_token(nft, 1).len(), 0);
+
+            // Mirrors create_trade and purchase which cannot be called off-chain
+            let trade = Trade {
+                id: 1,
+                price: 100,
+                nft_address: nft,
+                token_id: 1,
+                status: TradeStatus::Available as u8,
+                ..Default::default()
+            };
+            exchangemanager.insert_trade(trade);
+            exchangemanager.trades.get_mut(&1).unwrap().status = TradeStatus::Purchased as u8;
+            // Buyer lists the token again
+            exchangemanager.insert_trade(Trade {
+                id: 2,
+                price: 150,
+                ..trade
+            });
+            // Other tokens keep their own history
+            exchangemanager.insert_trade(Trade {
+                id: 3,
+                token_id: 2,
+                ..trade
+            });
+
+            assert_eq!(exchangemanager.get_trade_count_for_token(nft, 1), 2);
+            let history = exchangemanager.get_trade_history_for_token(nft, 1);
+            assert_eq!(history.len(), 2);
+            assert_eq!(history[0].id, 1);
+            assert_eq!(history[0].status, TradeStatus::Purchased as u8);
+            assert_eq!(history[1].id, 2);
+            assert_eq!(history[1].price, 150);
+            assert_eq!(history[1].status, TradeStatus::Available as u8);
+            assert_eq!(exchangemanager.get_trade_count_for_token(nft, 2), 1);
+        }
+
+        #[ink::test]
+        fn trades_by_seller_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let seller = AccountId::from([0x01; 32]);
+            let other_seller = AccountId::from([0x02; 32]);
+            assert_eq!(exchangemanager.get_seller_trade_count(seller), 0);
+            assert_eq!(
+                exchangemanager.list_trades_by_seller(seller, 0, 10).len(),
+                0
+            );
+
+            let statuses = [
+                TradeStatus::Available,
+                TradeStatus::Purchased,
+                TradeStatus::Available,
+                TradeStatus::Cancelled,
+                TradeStatus::Available,
+            ];
+            for (id, status) in (1..).zip(statuses.iter()) {
+                exchangemanager.insert_trade(Trade {
+                    id,
+                    seller_address: seller,
+                    status: *status as u8,
+                    ..Default::default()
+                });
+            }
+            exchangemanager.insert_trade(Trade {
+                id: 6,
+                seller_address: other_seller,
+                status: TradeStatus::Available as u8,
+                ..Default::default()
+            });
+
+            assert_eq!(exchangemanager.get_seller_trade_count(seller), 5);
+            assert_eq!(exchangemanager.get_seller_trade_count(other_seller), 1);
+            let ids = |trades: Vec<Trade>| trades.iter().map(|trade| trade.id).collect::<Vec<_>>();
+            assert_eq!(
+                ids(exchangemanager.list_trades_by_seller(seller, 0, 10)),
+                vec![1, 2, 3, 4, 5]
+            );
+            assert_eq!(
+                ids(exchangemanager.list_trades_by_seller(seller, 1, 2)),
+                vec![2, 3]
+            );
+            assert_eq!(exchangemanager.list_trades_by_seller(seller, 5, 2).len(), 0);
+
+            for status in statuses.iter() {
+                let expected = statuses.iter().filter(|s| *s == status).count() as u32;
+                assert_eq!(
+                    exchangemanager.get_seller_trade_count_by_status(seller, *status),
+                    expected
+                );
+            }
+            assert_eq!(
+                ids(exchangemanager.list_trades_by_seller_status(
+                    seller,
+                    TradeStatus::Available,
+                    1,
+                    10
+                )),
+                vec![3, 5]
+            );
+            assert_eq!(
+                ids(exchangemanager.list_trades_by_seller_status(
+                    other_seller,
+                    TradeStatus::Purchased,
+                    0,
+                    10
+                )),
+                Vec::<TradeId>::new()
+            );
+        }
+
+        #[ink::test]
+        fn trades_by_nft_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft = AccountId::from([0x02; 32]);
+            let other_nft = AccountId::from([0x03; 32]);
+            assert_eq!(exchangemanager.list_all_trades_by_nft(nft, 0, 10).len(), 0);
+            assert_eq!(
+                exchangemanager.get_nft_collection_stats(nft),
+                CollectionStats::default()
+            );
 
-            *trade_opt.clone().unwrap()
-        }
+            // Mirrors create_trade and purchase which cannot be called off-chain
+            let trades = [
+                (nft, 100, TradeStatus::Available),
+                (other_nft, 10, TradeStatus::Available),
+                (nft, 80, TradeStatus::Purchased),
+                (nft, 120, TradeStatus::Available),
+                (other_nft, 20, TradeStatus::Cancelled),
+                (nft, 90, TradeStatus::Available),
+            ];
+            for (id, (nft_address, price, status)) in (1..).zip(trades.iter()) {
+                exchangemanager.insert_trade(Trade {
+                    id,
+                    price: *price,
+                    nft_address: *nft_address,
+                    token_id: id as TokenId,
+                    status: *status as u8,
+                    ..Default::default()
+                });
+                match status {
+                    TradeStatus::Available => {
+                        exchangemanager.lower_collection_floor(*nft_address, *price)
+                    }
+                    TradeStatus::Purchased => {
+                        exchangemanager.record_trade_volume(*nft_address, *price)
+                    }
+                    TradeStatus::Cancelled => {}
+                }
+            }
 
-        /// Allows owner to set transfer rate
-        /// Only affects future borrowing
-        #[ink(message)]
-        pub fn set_fee(&mut self, _fee: u64) {
-            assert!(self.only_owner(self.env().caller()));
-            self.env().emit_event(FeeChanged {
-                old_value: self.administration.fee,
-                new_value: _fee,
-            });
-            self.administration.fee = _fee;
+            let ids = |trades: Vec<Trade>| trades.iter().map(|trade| trade.id).collect::<Vec<_>>();
+            assert_eq!(
+                ids(exchangemanager.list_all_trades_by_nft(nft, 0, 10)),
+                vec![1, 3, 4, 6]
+            );
+            assert_eq!(
+                ids(exchangemanager.list_all_trades_by_nft(nft, 1, 2)),
+                vec![3, 4]
+            );
+            assert_eq!(
+                ids(exchangemanager.list_all_trades_by_nft(other_nft, 0, 10)),
+                vec![2, 5]
+            );
+            assert_eq!(
+                ids(exchangemanager.list_available_trades_by_nft(nft, 0, 10)),
+                vec![1, 4, 6]
+            );
+            assert_eq!(
+                ids(exchangemanager.list_available_trades_by_nft(nft, 2, 10)),
+                vec![6]
+            );
+            assert_eq!(
+                ids(exchangemanager.list_available_trades_by_nft(other_nft, 0, 10)),
+                vec![2]
+            );
+
+            assert_eq!(
+                exchangemanager.get_nft_collection_stats(nft),
+                CollectionStats {
+                    listed: 3,
+                    sold: 1,
+                    floor_price: Some(90),
+                    total_volume: 80,
+                }
+            );
+            assert_eq!(
+                exchangemanager.get_nft_collection_stats(other_nft),
+                CollectionStats {
+                    listed: 1,
+                    sold: 0,
+                    floor_price: Some(10),
+                    total_volume: 0,
+                }
+            );
         }
 
-        /// Returns current transfer rate
-        #[ink(message)]
-        pub fn get_fee(&self) -> u64 {
-            self.administration.fee
+        #[ink::test]
+        fn nft_whitelist_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft = AccountId::from([0x02; 32]);
+            let seller = AccountId::from([0x01; 32]);
+            assert_eq!(exchangemanager.is_whitelist_enabled(), false);
+            assert_eq!(exchangemanager.is_nft_whitelisted(nft), false);
+            // Any nft can be listed while the whitelist is disabled
+            assert_eq!(exchangemanager.ensure_whitelisted(nft), Ok(()));
+
+            assert_eq!(exchangemanager.set_whitelist_enabled(true), Ok(()));
+            assert_eq!(
+                exchangemanager.create_trade(nft, 1, seller, 100, 0),
+                Err(Error::NFTNotWhitelisted)
+            );
+            assert_eq!(
+                exchangemanager.create_bundle_trade(vec![nft], vec![1], seller, 100, 0),
+                Err(Error::NFTNotWhitelisted)
+            );
+            assert_eq!(exchangemanager.locked, false);
+
+            assert_eq!(exchangemanager.add_nft_to_whitelist(nft), Ok(()));
+            assert_eq!(exchangemanager.is_nft_whitelisted(nft), true);
+            assert_eq!(exchangemanager.ensure_whitelisted(nft), Ok(()));
+
+            assert_eq!(exchangemanager.remove_nft_from_whitelist(nft), Ok(()));
+            assert_eq!(exchangemanager.is_nft_whitelisted(nft), false);
+            assert_eq!(
+                exchangemanager.create_trade(nft, 1, seller, 100, 0),
+                Err(Error::NFTNotWhitelisted)
+            );
+
+            assert_eq!(exchangemanager.set_whitelist_enabled(false), Ok(()));
+            assert_eq!(exchangemanager.ensure_whitelisted(nft), Ok(()));
         }
 
-        /// Allows owner to enable borrowing
-        #[ink(message)]
-        pub fn enable(&mut self) {
-            assert!(self.only_owner(self.env().caller()));
-            self.administration.enabled = true;
-            self.env().emit_event(Enabled {});
+        #[ink::test]
+        fn collection_floor_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft = AccountId::from([0x02; 32]);
+            let other_nft = AccountId::from([0x03; 32]);
+            assert_eq!(exchangemanager.floor_price_for_collection(nft), None);
+
+            // Mirrors create_trade, purchase and expire_trade which cannot be called off-chain
+            let list = |exchangemanager: &mut ExchangeManager, id, nft_address, price| {
+                exchangemanager.insert_trade(Trade {
+                    id,
+                    price,
+                    nft_address,
+                    token_id: id as TokenId,
+                    status: TradeStatus::Available as u8,
+                    ..Default::default()
+                });
+                exchangemanager.lower_collection_floor(nft_address, price);
+            };
+            let close = |exchangemanager: &mut ExchangeManager, id, status: TradeStatus| {
+                let trade = exchangemanager.trades.get_mut(&id).unwrap();
+                trade.status = status as u8;
+                let trade = *trade;
+                if status == TradeStatus::Purchased {
+                    exchangemanager.record_trade_volume(trade.nft_address, trade.price);
+                }
+                exchangemanager.refresh_collection_floor(trade.nft_address, trade.price);
+            };
+
+            list(&mut exchangemanager, 1, nft, 100);
+            assert_eq!(exchangemanager.floor_price_for_collection(nft), Some(100));
+            list(&mut exchangemanager, 2, nft, 50);
+            list(&mut exchangemanager, 3, nft, 80);
+            list(&mut exchangemanager, 4, other_nft, 10);
+            assert_eq!(exchangemanager.floor_price_for_collection(nft), Some(50));
+            assert_eq!(
+                exchangemanager.floor_price_for_collection(other_nft),
+                Some(10)
+            );
+
+            // Buying a trade above the floor keeps it
+            close(&mut exchangemanager, 1, TradeStatus::Purchased);
+            assert_eq!(exchangemanager.floor_price_for_collection(nft), Some(50));
+            assert_eq!(exchangemanager.get_collection_volume(nft), 100);
+
+            // Buying the floor moves it to the next cheapest trade
+            close(&mut exchangemanager, 2, TradeStatus::Purchased);
+            assert_eq!(exchangemanager.floor_price_for_collection(nft), Some(80));
+            assert_eq!(exchangemanager.get_collection_volume(nft), 150);
+
+            // Collection has no floor once nothing is available
+            close(&mut exchangemanager, 3, TradeStatus::Cancelled);
+            assert_eq!(exchangemanager.floor_price_for_collection(nft), None);
+            assert_eq!(
+                exchangemanager.floor_price_for_collection(other_nft),
+                Some(10)
+            );
         }
 
-        /// Allows owner to disable borrowing
-        #[ink(message)]
-        pub fn disable(&mut self) {
-            assert!(self.only_owner(self.env().caller()));
-            self.administration.enabled = false;
-            self.env().emit_event(Disbaled {});
+        #[ink::test]
+        fn dutch_auction_price_works() {
+            let auction = DutchAuction {
+                id: 1,
+                start_price: 1_000,
+                end_price: 200,
+                start_time: 100,
+                end_time: 300,
+                status: TradeStatus::Available as u8,
+                ..Default::default()
+            };
+            // 0% of duration
+            assert_eq!(ExchangeManager::dutch_auction_price(&auction, 100), 1_000);
+            // 50% of duration
+            assert_eq!(ExchangeManager::dutch_auction_price(&auction, 200), 600);
+            // 100% of duration and after
+            assert_eq!(ExchangeManager::dutch_auction_price(&auction, 300), 200);
+            assert_eq!(ExchangeManager::dutch_auction_price(&auction, 1_000), 200);
+            // Flat auctions keep their price
+            let flat = DutchAuction {
+                end_price: 1_000,
+                ..auction
+            };
+            assert_eq!(ExchangeManager::dutch_auction_price(&flat, 200), 1_000);
         }
 
-        /// Checks if borrowing is enabled
-        #[ink(message)]
-        pub fn is_enabled(&self) -> bool {
-            self.administration.enabled
+        #[ink::test]
+        fn dutch_auction_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft = AccountId::from([0x02; 32]);
+            assert_eq!(
+                exchangemanager.create_dutch_auction(nft, 1, 100, 200, 1_000),
+                Err(Error::InvalidAuction)
+            );
+            assert_eq!(
+                exchangemanager.create_dutch_auction(nft, 1, 200, 100, 0),
+                Err(Error::InvalidAuction)
+            );
+            assert_eq!(exchangemanager.get_dutch_auction_current_price(1), 0);
+            assert_eq!(
+                exchangemanager.purchase_dutch_auction(1),
+                Err(Error::NoSuchAuction)
+            );
+            assert_eq!(
+                exchangemanager.expire_dutch_auction(1),
+                Err(Error::NoSuchAuction)
+            );
+
+            exchangemanager.dutch_auctions.insert(
+                1,
+                DutchAuction {
+                    id: 1,
+                    nft_address: nft,
+                    seller: AccountId::from([0x03; 32]),
+                    start_price: 1_000,
+                    end_price: 0,
+                    start_time: 0,
+                    end_time: 20,
+                    status: TradeStatus::Available as u8,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(exchangemanager.get_dutch_auction_current_price(1), 1_000);
+            // Time moves on by 5 ms per block
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(exchangemanager.get_dutch_auction_current_price(1), 500);
+            assert_eq!(
+                exchangemanager.expire_dutch_auction(1),
+                Err(Error::NotSeller)
+            );
+
+            exchangemanager.dutch_auctions.get_mut(&1).unwrap().status =
+                TradeStatus::Purchased as u8;
+            assert_eq!(
+                exchangemanager.purchase_dutch_auction(1),
+                Err(Error::TradeUnavailable)
+            );
+            assert_eq!(exchangemanager.list_dutch_auction(1).unwrap().id, 1);
+            assert_eq!(exchangemanager.locked, false);
         }
 
-        fn get_current_time(&self) -> u64 {
-            self.env().block_timestamp()
+        #[ink::test]
+        fn withdraw_unsold_nft_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let seller = AccountId::from([0x01; 32]);
+            assert_eq!(
+                exchangemanager.withdraw_unsold_nft(1),
+                Err(Error::NoSuchTrade)
+            );
+            assert_eq!(exchangemanager.admin_return_nft(1), Err(Error::NoSuchTrade));
+
+            exchangemanager.insert_trade(Trade {
+                id: 1,
+                seller_address: seller,
+                expiration_date: 5,
+                status: TradeStatus::Available as u8,
+                ..Default::default()
+            });
+            // Trade expires once current time is past expiration_date
+            assert_eq!(
+                exchangemanager.withdraw_unsold_nft(1),
+                Err(Error::TradeNotExpired)
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(
+                exchangemanager.withdraw_unsold_nft(1),
+                Err(Error::TradeNotExpired)
+            );
+
+            exchangemanager.trades.get_mut(&1).unwrap().seller_address =
+                AccountId::from([0x02; 32]);
+            assert_eq!(
+                exchangemanager.withdraw_unsold_nft(1),
+                Err(Error::NotSeller)
+            );
+
+            exchangemanager.trades.get_mut(&1).unwrap().status = TradeStatus::Purchased as u8;
+            assert_eq!(
+                exchangemanager.admin_return_nft(1),
+                Err(Error::TradeUnavailable)
+            );
+            assert_eq!(exchangemanager.locked, false);
         }
 
-        fn get_nft(address: AccountId) -> Erc721 {
-            Erc721::from_account_id(address)
+        #[ink::test]
+        fn admin_cancel_trade_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let seller = AccountId::from([0x02; 32]);
+            assert_eq!(
+                exchangemanager.admin_cancel_trade(1, b"sanctioned".to_vec()),
+                Err(Error::NoSuchTrade)
+            );
+
+            for (id, status) in [(1, TradeStatus::Purchased), (2, TradeStatus::Cancelled)].iter() {
+                exchangemanager.insert_trade(Trade {
+                    id: *id,
+                    seller_address: seller,
+                    status: *status as u8,
+                    ..Default::default()
+                });
+            }
+            // Purchased nfts belong to the buyer
+            assert_eq!(
+                exchangemanager.admin_cancel_trade(1, b"sanctioned".to_vec()),
+                Err(Error::TradePurchased)
+            );
+            assert_eq!(
+                exchangemanager.admin_cancel_trade(2, b"sanctioned".to_vec()),
+                Err(Error::TradeUnavailable)
+            );
+            assert_eq!(exchangemanager.admin_cancel_trades_batch(vec![1, 2, 3]), 0);
+            assert_eq!(
+                exchangemanager.list_trade(1).unwrap().status,
+                TradeStatus::Purchased as u8
+            );
+            assert_eq!(exchangemanager.locked, false);
         }
-    }
 
-    mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
-        use ink_lang as ink;
-        /// We test if the constructor does its job.
-        fn instantiate_erc20_contract() -> AccountId {
-            let erc20 = Erc20::new(1000000);
-            let callee =
-                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
-            callee
+        #[ink::test]
+        #[should_panic]
+        fn non_owner_admin_cancel_trade_fails() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = exchangemanager.admin_cancel_trade(1, Vec::new());
         }
 
         #[ink::test]
-        fn new_works() {
-            let exchangemanager = ExchangeManager::new(
-                instantiate_erc20_contract(),
-                10,
-                true,
+        fn contract_version_works() {
+            let exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            assert_eq!(exchangemanager.get_contract_version(), VERSION);
+            assert_eq!(exchangemanager.get_contract_version(), (1, 0, 0));
+            assert_eq!(
+                exchangemanager.get_contract_name(),
+                String::from("ExchangeManager")
             );
-            assert_eq!(exchangemanager.is_enabled(), true);
-            assert_eq!(exchangemanager.get_fee(), 10);
         }
 
         #[ink::test]
         fn enable_works() {
-            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(),10, false);
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, false);
             assert_eq!(exchangemanager.is_enabled(), false);
 
-            exchangemanager.enable();
+            assert_eq!(exchangemanager.enable(), Ok(()));
             assert_eq!(exchangemanager.is_enabled(), true);
         }
 
         #[ink::test]
         fn set_fee_works() {
-            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(),20, true);
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 20, true);
             assert_eq!(exchangemanager.get_fee(), 20);
 
-            exchangemanager.set_fee(10);
+            assert_eq!(exchangemanager.set_fee(10), Ok(()));
             assert_eq!(exchangemanager.get_fee(), 10);
         }
 
-        
+        #[ink::test]
+        fn auto_fee_sweep_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 20, true);
+            let owner = AccountId::from([0x01; 32]);
+            let recipient = AccountId::from([0x02; 32]);
+            assert_eq!(exchangemanager.get_auto_fee_sweep_threshold(), 0);
+            assert_eq!(exchangemanager.get_fee_recipient(), owner);
+            // Disabled until a threshold is set
+            assert_eq!(exchangemanager.fee_sweep_due(1_000), false);
+
+            assert_eq!(exchangemanager.set_auto_fee_sweep_threshold(100), Ok(()));
+            assert_eq!(exchangemanager.set_fee_recipient(recipient), Ok(()));
+            assert_eq!(exchangemanager.get_auto_fee_sweep_threshold(), 100);
+            assert_eq!(exchangemanager.get_fee_recipient(), recipient);
+
+            assert_eq!(exchangemanager.fee_sweep_due(0), false);
+            assert_eq!(exchangemanager.fee_sweep_due(99), false);
+            assert_eq!(exchangemanager.fee_sweep_due(100), true);
+            assert_eq!(exchangemanager.fee_sweep_due(101), true);
+        }
+
+        #[ink::test]
+        fn escrow_is_not_swept_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 20, true);
+            let bidder = AccountId::from([0x02; 32]);
+            assert_eq!(exchangemanager.set_auto_fee_sweep_threshold(100), Ok(()));
+            assert_eq!(exchangemanager.withdrawable_fees(150), 150);
+
+            exchangemanager.sealed_bidders.insert(1, Vec::new());
+            exchangemanager.insert_sealed_bid(
+                1,
+                SealedBid {
+                    bidder,
+                    amount: 80,
+                    placed_at: 0,
+                },
+            );
+            exchangemanager.insert_collection_offer(CollectionOffer {
+                id: 1,
+                buyer: bidder,
+                nft_address: AccountId::from([0x03; 32]),
+                max_price: 30,
+                expiry: 10,
+                status: CollectionOfferStatus::Open as u8,
+            });
+            // Only the 40 of fees count towards the threshold
+            assert_eq!(exchangemanager.withdrawable_fees(150), 40);
+            assert_eq!(exchangemanager.fee_sweep_due(40), false);
+
+            exchangemanager.remove_sealed_bid(1, bidder);
+            assert_eq!(exchangemanager.withdrawable_fees(150), 120);
+            exchangemanager.close_collection_offer(1, CollectionOfferStatus::Cancelled);
+            assert_eq!(exchangemanager.withdrawable_fees(150), 150);
+            assert_eq!(exchangemanager.escrowed, 0);
+        }
+
+        #[ink::test]
+        fn escrow_survives_fee_withdrawal_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 20, true);
+            let bidder = AccountId::from([0x02; 32]);
+            exchangemanager.sealed_bidders.insert(1, Vec::new());
+            exchangemanager.insert_sealed_bid(
+                1,
+                SealedBid {
+                    bidder,
+                    amount: 80,
+                    placed_at: 0,
+                },
+            );
+            exchangemanager.insert_collection_offer(CollectionOffer {
+                id: 1,
+                buyer: bidder,
+                nft_address: AccountId::from([0x03; 32]),
+                max_price: 30,
+                expiry: 10,
+                status: CollectionOfferStatus::Open as u8,
+            });
+
+            // A withdrawal of every fee leaves exactly the escrow behind
+            let balance = 150;
+            let withdrawn = exchangemanager.withdrawable_fees(balance);
+            assert_eq!(withdrawn, 40);
+            let remaining = balance - withdrawn;
+            assert_eq!(exchangemanager.withdrawable_fees(remaining), 0);
+
+            // The bid and offer can still be refunded in full from what remains
+            assert_eq!(remaining, 80 + 30);
+            assert_eq!(
+                exchangemanager.get_sealed_bid(1, bidder).unwrap().amount,
+                80
+            );
+            assert_eq!(
+                exchangemanager.get_collection_offer(1).unwrap().status,
+                CollectionOfferStatus::Open as u8
+            );
+        }
+
+        #[ink::test]
+        fn sealed_bid_auction_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let seller = AccountId::from([0x01; 32]);
+            let bidders = [AccountId::from([0x02; 32]), AccountId::from([0x03; 32])];
+            exchangemanager.insert_trade(Trade {
+                id: 1,
+                price: 100,
+                seller_address: seller,
+                status: TradeStatus::Available as u8,
+                expiration_date: 10,
+                ..Default::default()
+            });
+            assert_eq!(
+                exchangemanager.validate_sealed_bid(1, bidders[0], 100, 0),
+                Err(Error::NoSuchAuction)
+            );
+            exchangemanager.sealed_bidders.insert(1, Vec::new());
+
+            assert_eq!(
+                exchangemanager.validate_sealed_bid(1, seller, 100, 0),
+                Err(Error::SellerCannotBid)
+            );
+            assert_eq!(
+                exchangemanager.validate_sealed_bid(1, bidders[0], 99, 0),
+                Err(Error::BidTooLow)
+            );
+            assert_eq!(
+                exchangemanager.validate_sealed_bid(1, bidders[0], 100, 11),
+                Err(Error::TradeUnavailable)
+            );
+            assert_eq!(
+                exchangemanager.validate_sealed_bid(1, bidders[0], 100, 10),
+                Ok(())
+            );
+            assert_eq!(exchangemanager.highest_sealed_bid(1), None);
+
+            // Equal bids are won by the earliest bidder
+            for (bidder, placed_at) in bidders.iter().zip(0..) {
+                exchangemanager.insert_sealed_bid(
+                    1,
+                    SealedBid {
+                        bidder: *bidder,
+                        amount: 150,
+                        placed_at,
+                    },
+                );
+            }
+            assert_eq!(
+                exchangemanager.validate_sealed_bid(1, bidders[0], 200, 0),
+                Err(Error::BidAlreadyPlaced)
+            );
+            assert_eq!(
+                exchangemanager.highest_sealed_bid(1).unwrap().bidder,
+                bidders[0]
+            );
+            exchangemanager
+                .sealed_bids
+                .get_mut(&(1, bidders[1]))
+                .unwrap()
+                .amount = 151;
+            assert_eq!(
+                exchangemanager.highest_sealed_bid(1).unwrap().bidder,
+                bidders[1]
+            );
+
+            // Auctions with bids can't be bought outright or withdrawn by the seller
+            assert_eq!(exchangemanager.purchase(1), Err(Error::TradeUnavailable));
+            assert_eq!(exchangemanager.expire_trade(1), Err(Error::AuctionHasBids));
+            assert_eq!(
+                exchangemanager.settle_sealed_bid_auction(1),
+                Err(Error::TradeNotExpired)
+            );
+            assert_eq!(
+                exchangemanager.settle_sealed_bid_auction(2),
+                Err(Error::NoSuchAuction)
+            );
+            assert_eq!(exchangemanager.cancel_sealed_bid(1), Err(Error::NoSuchBid));
+            set_caller(bidders[0]);
+            assert_eq!(
+                exchangemanager.settle_sealed_bid_auction(1),
+                Err(Error::NotSeller)
+            );
+            ink_env::test::pop_execution_context();
+
+            exchangemanager.remove_sealed_bid(1, bidders[0]);
+            assert_eq!(exchangemanager.get_sealed_bid(1, bidders[0]), None);
+            assert_eq!(exchangemanager.highest_sealed_bid(1).unwrap().amount, 151);
+            assert_eq!(exchangemanager.locked, false);
+        }
+
+        #[ink::test]
+        fn collection_offer_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let seller = AccountId::from([0x01; 32]);
+            let buyer = AccountId::from([0x02; 32]);
+            let nft_a = AccountId::from([0x03; 32]);
+            let nft_b = AccountId::from([0x04; 32]);
+            assert_eq!(
+                exchangemanager.make_collection_offer(nft_a, 100, 0),
+                Err(Error::OfferExpired)
+            );
+            assert_eq!(
+                exchangemanager
+                    .validate_collection_offer(1, 1, seller, 0)
+                    .err(),
+                Some(Error::NoSuchOffer)
+            );
+
+            exchangemanager.collection_offers.insert(
+                1,
+                CollectionOffer {
+                    id: 1,
+                    buyer,
+                    nft_address: nft_a,
+                    max_price: 100,
+                    expiry: 10,
+                    status: CollectionOfferStatus::Open as u8,
+                },
+            );
+            let trades = [(1, nft_a, 100), (2, nft_a, 101), (3, nft_b, 50)];
+            for (id, nft_address, price) in trades.iter() {
+                exchangemanager.insert_trade(Trade {
+                    id: *id,
+                    price: *price,
+                    nft_address: *nft_address,
+                    seller_address: seller,
+                    status: TradeStatus::Available as u8,
+                    ..Default::default()
+                });
+            }
+            assert_eq!(
+                exchangemanager
+                    .validate_collection_offer(1, 4, seller, 0)
+                    .err(),
+                Some(Error::NoSuchTrade)
+            );
+            assert_eq!(
+                exchangemanager
+                    .validate_collection_offer(1, 1, buyer, 0)
+                    .err(),
+                Some(Error::NotSeller)
+            );
+            assert_eq!(
+                exchangemanager
+                    .validate_collection_offer(1, 2, seller, 0)
+                    .err(),
+                Some(Error::OfferTooLow)
+            );
+            assert_eq!(
+                exchangemanager
+                    .validate_collection_offer(1, 3, seller, 0)
+                    .err(),
+                Some(Error::InvalidCollection)
+            );
+            assert_eq!(
+                exchangemanager
+                    .validate_collection_offer(1, 1, seller, 11)
+                    .err(),
+                Some(Error::OfferExpired)
+            );
+            let (offer, trade) = exchangemanager
+                .validate_collection_offer(1, 1, seller, 10)
+                .unwrap();
+            assert_eq!(offer, exchangemanager.get_collection_offer(1).unwrap());
+            assert_eq!(trade.id, 1);
+
+            // Only the buyer can withdraw an open offer
+            assert_eq!(
+                exchangemanager.cancel_collection_offer(2),
+                Err(Error::NoSuchOffer)
+            );
+            assert_eq!(
+                exchangemanager.cancel_collection_offer(1),
+                Err(Error::NotOfferBuyer)
+            );
+            exchangemanager
+                .collection_offers
+                .get_mut(&1)
+                .unwrap()
+                .status = CollectionOfferStatus::Accepted as u8;
+            assert_eq!(
+                exchangemanager
+                    .validate_collection_offer(1, 1, seller, 0)
+                    .err(),
+                Some(Error::OfferUnavailable)
+            );
+            set_caller(buyer);
+            assert_eq!(
+                exchangemanager.cancel_collection_offer(1),
+                Err(Error::OfferUnavailable)
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(exchangemanager.get_collection_offer(2), None);
+            assert_eq!(exchangemanager.locked, false);
+        }
+
+        #[ink::test]
+        fn trades_expiring_soon_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let expirations = [0, 3, 10, 1000, 3];
+            for (id, expiration_date) in expirations.iter().enumerate() {
+                exchangemanager.insert_trade(Trade {
+                    id: id as TradeId,
+                    status: TradeStatus::Available as u8,
+                    expiration_date: *expiration_date,
+                    ..Default::default()
+                });
+            }
+            // Only available trades are counted
+            exchangemanager.trades.get_mut(&4).unwrap().status = TradeStatus::Purchased as u8;
+
+            let sorted = |mut trades: Vec<TradeId>| {
+                trades.sort();
+                trades
+            };
+            assert_eq!(
+                exchangemanager.get_expired_unclaimed_trades(),
+                Vec::<TradeId>::new()
+            );
+            assert_eq!(
+                exchangemanager.get_trades_expiring_soon(2),
+                Vec::<TradeId>::new()
+            );
+            assert_eq!(exchangemanager.get_trades_expiring_soon(3), vec![1]);
+            assert_eq!(
+                sorted(exchangemanager.get_trades_expiring_soon(10)),
+                vec![1, 2]
+            );
+            assert_eq!(
+                sorted(exchangemanager.get_trades_expiring_soon(u64::MAX)),
+                vec![1, 2, 3]
+            );
+
+            // Time moves on by 5 ms per block
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(exchangemanager.get_expired_unclaimed_trades(), vec![1]);
+            assert_eq!(exchangemanager.get_trades_expiring_soon(5), vec![2]);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(exchangemanager.get_trades_expiring_soon(5), vec![2]);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(
+                sorted(exchangemanager.get_expired_unclaimed_trades()),
+                vec![1, 2]
+            );
+            assert_eq!(exchangemanager.get_trades_expiring_soon(985), vec![3]);
+            assert_eq!(
+                exchangemanager.get_trades_expiring_soon(984),
+                Vec::<TradeId>::new()
+            );
+        }
+
+        #[ink::test]
+        fn set_royalties_enabled_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 20, true);
+            assert_eq!(exchangemanager.is_royalties_enabled(), false);
+
+            assert_eq!(exchangemanager.set_royalties_enabled(true), Ok(()));
+            assert_eq!(exchangemanager.is_royalties_enabled(), true);
+        }
+
+        #[ink::test]
+        fn renounce_ownership_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 20, true);
+            assert_eq!(exchangemanager.renounce_ownership(), Ok(()));
+            assert_eq!(exchangemanager.is_owner(), false);
+
+            assert_eq!(exchangemanager.enable(), Err(Error::ContractImmutable));
+            assert_eq!(exchangemanager.disable(), Err(Error::ContractImmutable));
+            assert_eq!(exchangemanager.set_fee(10), Err(Error::ContractImmutable));
+            assert_eq!(
+                exchangemanager.set_royalties_enabled(true),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(exchangemanager.is_enabled(), true);
+            assert_eq!(exchangemanager.get_fee(), 20);
+        }
+
+        #[ink::test]
+        fn trade_volume_works() {
+            let mut exchangemanager = ExchangeManager::new(instantiate_erc20_contract(), 10, true);
+            let nft_a = AccountId::from([0x01; 32]);
+            let nft_b = AccountId::from([0x02; 32]);
+            assert_eq!(exchangemanager.get_total_volume(), 0);
+            assert_eq!(exchangemanager.get_total_completed_trades(), 0);
+            assert_eq!(exchangemanager.get_volume_for_nft_address(nft_a), 0);
+
+            let trades = [(1, nft_a, 100), (2, nft_a, 50), (3, nft_b, 25)];
+            for (id, nft_address, price) in trades.iter() {
+                exchangemanager.insert_trade(Trade {
+                    id: *id,
+                    nft_address: *nft_address,
+                    price: *price,
+                    seller_address: AccountId::from([0x01; 32]),
+                    status: TradeStatus::Available as u8,
+                    ..Default::default()
+                });
+            }
+
+            // Token transfers of purchase need a chain, so settle the trade the way purchase does
+            let buyer = AccountId::from([0x02; 32]);
+            exchangemanager.mark_trade_purchased(1, buyer, 100);
+            assert_eq!(exchangemanager.get_total_volume(), 100);
+            assert_eq!(exchangemanager.get_total_completed_trades(), 1);
+            assert_eq!(
+                exchangemanager.list_trade(1).unwrap().buyer_address,
+                Some(buyer)
+            );
+            assert_eq!(exchangemanager.purchase(1), Err(Error::TradeUnavailable));
 
+            exchangemanager.mark_trade_purchased(2, buyer, 50);
+            exchangemanager.mark_trade_purchased(3, buyer, 25);
+            assert_eq!(exchangemanager.get_total_volume(), 175);
+            assert_eq!(exchangemanager.get_total_completed_trades(), 3);
+            assert_eq!(exchangemanager.get_volume_for_nft_address(nft_a), 150);
+            assert_eq!(exchangemanager.get_volume_for_nft_address(nft_b), 25);
+
+            // Expiring a purchased trade leaves the volume untouched
+            assert_eq!(
+                exchangemanager.expire_trade(2),
+                Err(Error::TradeUnavailable)
+            );
+            assert_eq!(exchangemanager.get_total_volume(), 175);
+            assert_eq!(exchangemanager.get_total_completed_trades(), 3);
+        }
     }
 }
@@ -4,11 +4,12 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod leasingmanager {
+    use addressmanager::{AddressManager, ERC20};
     use erc20::Erc20;
     use erc721::Erc721;
 
     use ink_env::call::FromAccountId;
-    use ink_prelude::vec::Vec;
+    use ink_prelude::{string::String, vec::Vec};
     use ink_storage::{
         collections::HashMap as StorageHashMap,
         traits::{PackedLayout, SpreadLayout, StorageLayout},
@@ -27,6 +28,8 @@ mod leasingmanager {
     #[cfg_attr(feature = "std", derive(StorageLayout))]
     pub struct Administration {
         enabled: bool,
+        minimum_daily_rent: u64,
+        maximum_lease_duration: u64,
     }
 
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
@@ -52,6 +55,30 @@ mod leasingmanager {
         ERC721TransferFailed,
         ERC20TransferFailed,
         InsufficientBalance,
+        ContractImmutable,
+        Reentrant,
+        InvalidDuration,
+        RentTooLow,
+        DurationTooLong,
+        LeaseExpired,
+        NotLeaseParty,
+        NoSuchDispute,
+        DisputeAlreadyOpen,
+        DisputeAlreadyResolved,
+        LeaseDisputed,
+        InvalidRuling,
+        NotRenter,
+        LeaseNotTerminated,
+        NoDefaultTermination,
+        TerminatedDueToDefault,
+        NoSecurityDeposit,
+        InvalidScore,
+        AlreadyRated,
+        AddressNotFound,
+        SecurityDepositTooHigh,
+        SubLeaseAlreadyActive,
+        SubLeaseTooDeep,
+        RentOverdue,
     }
 
     #[derive(Clone, Default, Copy, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
@@ -71,6 +98,72 @@ mod leasingmanager {
         lease_paid_until: Option<u64>,
         terminated_at: Option<u64>,
         status: u8,
+        fixed_term: bool,
+        security_deposit: Balance,
+        deposit_held: bool,
+        terminated_due_to_default: bool,
+        parent_lease_id: Option<LeaseId>,
+    }
+
+    /// Number of leases in each status and rent collected over the contract lifetime
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq, SpreadLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct LeaseStatistics {
+        total_leases: u32,
+        available_count: u32,
+        rented_count: u32,
+        terminated_count: u32,
+        removed_count: u32,
+        total_rent_paid: Balance,
+    }
+
+    /// Leasing parameters for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LeasingConfig {
+        enabled: bool,
+        grace_period_days: u64,
+        min_daily_rent: u64,
+        max_duration: u64,
+    }
+
+    /// Single rent payment made by a renter
+    #[derive(
+        Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct RentPayment {
+        lease_id: LeaseId,
+        renter: AccountId,
+        amount: Balance,
+        paid_at: u64,
+        days_covered: u64,
+    }
+
+    /// Disagreement between investor and renter raised for the owner to arbitrate
+    #[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct Dispute {
+        id: u64,
+        lease_id: LeaseId,
+        requester: AccountId,
+        respondent: AccountId,
+        reason: Vec<u8>,
+        created_at: u64,
+        resolved_at: Option<u64>,
+        ruling: Option<u8>,
+    }
+
+    /// Review of a renter left by the investor once their lease ended
+    #[derive(Encode, Decode, Debug, Default, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct RenterRating {
+        lease_id: LeaseId,
+        rater: AccountId,
+        ratee: AccountId,
+        score: u8,
+        review: Vec<u8>,
+        rated_at: u64,
     }
 
     /// Defines the storage of your contract.
@@ -85,6 +178,19 @@ mod leasingmanager {
         administration: Administration,
         total_leases: u32,
         erc20: Lazy<Erc20>,
+        address_manager: Option<AddressManager>,
+        locked: bool,
+        lease_statistics: LeaseStatistics,
+        renter_active_count: StorageHashMap<AccountId, u32>,
+        investor_active_count: StorageHashMap<AccountId, u32>,
+        rent_history: StorageHashMap<AccountId, Vec<RentPayment>>,
+        rent_received: StorageHashMap<AccountId, Vec<RentPayment>>,
+        disputes: StorageHashMap<u64, Dispute>,
+        total_disputes: u64,
+        open_disputes: StorageHashMap<LeaseId, u64>,
+        renter_ratings: StorageHashMap<AccountId, Vec<RenterRating>>,
+        rated_leases: StorageHashMap<LeaseId, bool>,
+        sub_leases: StorageHashMap<LeaseId, Vec<LeaseId>>,
     }
 
     #[ink(event)]
@@ -146,6 +252,100 @@ mod leasingmanager {
         token_id: u32,
     }
 
+    #[ink(event)]
+    pub struct SubLeaseListed {
+        #[ink(topic)]
+        parent_lease_id: LeaseId,
+        #[ink(topic)]
+        lease_id: LeaseId,
+        #[ink(topic)]
+        renter: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct LeaseOwnershipTransferred {
+        #[ink(topic)]
+        lease_id: LeaseId,
+        #[ink(topic)]
+        from_investor: AccountId,
+        #[ink(topic)]
+        to_investor: AccountId,
+        beneficiary_address: AccountId,
+        token_id: u32,
+    }
+
+    #[ink(event)]
+    pub struct LeaseDurationExtended {
+        #[ink(topic)]
+        lease_id: LeaseId,
+        old_duration: u64,
+        new_duration: u64,
+    }
+
+    #[ink(event)]
+    pub struct LeaseDurationShortened {
+        #[ink(topic)]
+        lease_id: LeaseId,
+        old_duration: u64,
+        new_duration: u64,
+    }
+
+    #[ink(event)]
+    pub struct MinimumDailyRentChanged {
+        old: u64,
+        new: u64,
+    }
+
+    #[ink(event)]
+    pub struct MaximumLeaseDurationChanged {
+        old: u64,
+        new: u64,
+    }
+
+    #[ink(event)]
+    pub struct DisputeRaised {
+        #[ink(topic)]
+        dispute_id: u64,
+        #[ink(topic)]
+        lease_id: LeaseId,
+        #[ink(topic)]
+        requester: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct DisputeResolved {
+        #[ink(topic)]
+        dispute_id: u64,
+        ruling: u8,
+    }
+
+    #[ink(event)]
+    pub struct DepositCollected {
+        #[ink(topic)]
+        lease_id: LeaseId,
+        #[ink(topic)]
+        investor: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct DepositRefunded {
+        #[ink(topic)]
+        lease_id: LeaseId,
+        #[ink(topic)]
+        renter: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct RenterRated {
+        #[ink(topic)]
+        lease_id: LeaseId,
+        #[ink(topic)]
+        renter: AccountId,
+        score: u8,
+    }
+
     #[ink(event)]
     pub struct Enabled {}
 
@@ -160,7 +360,28 @@ mod leasingmanager {
         to: AccountId,
     }
 
+    #[ink(event)]
+    pub struct OwnershipRenounced {
+        #[ink(topic)]
+        former_owner: AccountId,
+    }
+
     pub const SECONDS_IN_DAYS: u64 = 86_400;
+    /// Days rent can be overdue before the renter is a defaulter
+    pub const GRACE_PERIOD_DAYS: u64 = 3;
+    pub const DEFAULT_MINIMUM_DAILY_RENT: u64 = 1;
+    pub const DEFAULT_MAXIMUM_LEASE_DURATION: u64 = 365 * SECONDS_IN_DAYS * 1_000;
+    /// Arbitration rulings
+    pub const RULING_RETURN_NFT: u8 = 0;
+    pub const RULING_CONTINUE_LEASE: u8 = 1;
+    /// Renter ratings range from 1 to MAX_RATING_SCORE
+    pub const MAX_RATING_SCORE: u8 = 5;
+    /// Sub-leases of sub-leases can be nested this deep below the original lease
+    pub const MAX_SUB_LEASE_DEPTH: u32 = 3;
+
+    pub const VERSION: (u32, u32, u32) = (1, 0, 0);
+    // Fails to compile unless the minor and patch versions are below 1000
+    const _: [(); 0 - !(VERSION.1 < 1000 && VERSION.2 < 1000) as usize] = [];
 
     impl LeasingManager {
         /// Constructors can delegate to other constructors.
@@ -172,16 +393,57 @@ mod leasingmanager {
 
             let instance = Self {
                 owner: Ownable { owner },
-                administration: Administration { enabled },
+                administration: Administration {
+                    enabled,
+                    minimum_daily_rent: DEFAULT_MINIMUM_DAILY_RENT,
+                    maximum_lease_duration: DEFAULT_MAXIMUM_LEASE_DURATION,
+                },
                 leases: Default::default(),
                 investors: Default::default(),
                 renters: Default::default(),
                 total_leases: 0,
                 erc20: Lazy::new(erc20),
+                address_manager: None,
+                locked: false,
+                lease_statistics: Default::default(),
+                renter_active_count: Default::default(),
+                investor_active_count: Default::default(),
+                rent_history: Default::default(),
+                rent_received: Default::default(),
+                disputes: Default::default(),
+                total_disputes: 0,
+                open_disputes: Default::default(),
+                renter_ratings: Default::default(),
+                rated_leases: Default::default(),
+                sub_leases: Default::default(),
             };
             instance
         }
 
+        /// Looks up the erc20 address through the address manager on every call
+        #[ink(constructor)]
+        pub fn new_with_address_manager(address_manager: AccountId, enabled: bool) -> Self {
+            let address_manager = AddressManager::from_account_id(address_manager);
+            let erc20_address = address_manager
+                .require_address(String::from(ERC20))
+                .expect("ERC20 address not registered");
+            let mut instance = Self::new(erc20_address, enabled);
+            instance.address_manager = Some(address_manager);
+            instance
+        }
+
+        /// Returns the (major, minor, patch) version of the contract
+        #[ink(message)]
+        pub fn get_contract_version(&self) -> (u32, u32, u32) {
+            VERSION
+        }
+
+        /// Returns the name of the contract
+        #[ink(message)]
+        pub fn get_contract_name(&self) -> String {
+            String::from("LeasingManager")
+        }
+
         /// Checks if caller is owner of AssetManager contract
         #[ink(message)]
         pub fn is_owner(&self) -> bool {
@@ -208,10 +470,41 @@ mod leasingmanager {
             true
         }
 
+        /// Permanently removes the owner making all parameters immutable
+        /// Can only be called by the current owner
+        #[ink(message)]
+        pub fn renounce_ownership(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_owner(caller));
+            self.owner.owner = AccountId::from([0x0; 32]);
+            self.env().emit_event(OwnershipRenounced {
+                former_owner: caller,
+            });
+            Ok(())
+        }
+
         fn only_owner(&self, caller: AccountId) -> bool {
             caller == self.owner.owner
         }
 
+        fn ensure_mutable(&self) -> Result<(), Error> {
+            if self.owner.owner == AccountId::from([0x0; 32]) {
+                return Err(Error::ContractImmutable);
+            }
+            Ok(())
+        }
+
+        /// Guards against re-entrant calls from the token contracts
+        /// Callers must reset `locked` before returning
+        fn lock(&mut self) -> Result<(), Error> {
+            if self.locked {
+                return Err(Error::Reentrant);
+            }
+            self.locked = true;
+            Ok(())
+        }
+
         /// List token for leasing
         #[ink(message)]
         pub fn list_token(
@@ -222,19 +515,119 @@ mod leasingmanager {
             daily_rent: u64,
             lease_duration: u64,
         ) -> Result<(), Error> {
-            assert_eq!(self.is_enabled(), true, "Listing is not enabled");
+            self.handle_list_token(
+                nft_address,
+                token_id,
+                beneficiary_address,
+                daily_rent,
+                lease_duration,
+                false,
+                0,
+            )
+        }
+
+        /// List token for leasing, requiring the renter to put down required_deposit on rent
+        /// Caller should have granted approval to erc721 token before executing this function
+        #[ink(message)]
+        pub fn list_token_with_deposit_requirement(
+            &mut self,
+            nft_address: AccountId,
+            token_id: TokenId,
+            beneficiary_address: AccountId,
+            daily_rent: u64,
+            lease_duration: u64,
+            required_deposit: Balance,
+        ) -> Result<(), Error> {
+            self.handle_list_token(
+                nft_address,
+                token_id,
+                beneficiary_address,
+                daily_rent,
+                lease_duration,
+                false,
+                required_deposit,
+            )
+        }
+
+        /// List token for leasing with a fixed term
+        /// Rent can't be paid once lease_duration is over and the investor
+        /// can terminate the lease even if the renter is not a defaulter
+        #[ink(message)]
+        pub fn list_token_for_fixed_term(
+            &mut self,
+            nft_address: AccountId,
+            token_id: TokenId,
+            beneficiary_address: AccountId,
+            daily_rent: u64,
+            lease_duration: u64,
+        ) -> Result<(), Error> {
+            self.handle_list_token(
+                nft_address,
+                token_id,
+                beneficiary_address,
+                daily_rent,
+                lease_duration,
+                true,
+                0,
+            )
+        }
+
+        fn handle_list_token(
+            &mut self,
+            nft_address: AccountId,
+            token_id: TokenId,
+            beneficiary_address: AccountId,
+            daily_rent: u64,
+            lease_duration: u64,
+            fixed_term: bool,
+            security_deposit: Balance,
+        ) -> Result<(), Error> {
+            self.lock()?;
+            if !self.is_enabled() {
+                self.locked = false;
+                return Err(Error::LeasingNotEnabled);
+            }
+
+            if let Err(error) = self.validate_lease_terms(daily_rent, lease_duration) {
+                self.locked = false;
+                return Err(error);
+            }
 
             let caller = self.env().caller();
             let contract_address = self.env().account_id();
             // Transfer tokens from caller to contract
             let mut erc721 = Self::get_nft(nft_address);
             let erc721_transfer = erc721.transfer_from(caller, contract_address, token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
+            if erc721_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC721TransferFailed);
+            }
+
+            self.record_listing(
+                nft_address,
+                token_id,
+                beneficiary_address,
+                daily_rent,
+                lease_duration,
+                fixed_term,
+                security_deposit,
             );
 
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Records a lease listed by the caller whose nft the contract now holds
+        fn record_listing(
+            &mut self,
+            nft_address: AccountId,
+            token_id: TokenId,
+            beneficiary_address: AccountId,
+            daily_rent: u64,
+            lease_duration: u64,
+            fixed_term: bool,
+            security_deposit: Balance,
+        ) {
             let lease_id = self.total_leases as LeaseId;
             // Add trade into current active list
             let lease = Lease {
@@ -242,7 +635,7 @@ mod leasingmanager {
                 daily_rent: daily_rent,
                 nft_address: nft_address,
                 token_id: token_id,
-                investor_address: caller,
+                investor_address: self.env().caller(),
                 beneficiary_address: beneficiary_address,
                 renter_address: None,
                 status: LeaseStatus::Available as u8,
@@ -252,57 +645,150 @@ mod leasingmanager {
                 last_paid_at: None,
                 lease_paid_until: None,
                 terminated_at: None,
+                fixed_term,
+                security_deposit,
+                deposit_held: false,
+                terminated_due_to_default: false,
+                parent_lease_id: None,
             };
-            self.leases.insert(lease_id, lease);
-            self.total_leases += 1;
+            self.insert_lease(lease);
+        }
 
-            let mut invested: Vec<LeaseId> = Vec::new();
-            let investor_opt = self.investors.get_mut(&caller);
-            if investor_opt.is_some() {
-                invested = investor_opt.unwrap().to_vec();
+        /// Allows renter of a rented lease to list its usage rights for daily_rent
+        /// The nft stays in the contract and rent of the sub-lease goes to new_beneficiary.
+        /// A lease has at most one listed or rented sub-lease, which ends with the term of the parent
+        /// and can only be listed while the parent's rent is paid.
+        /// Terminating the parent lease terminates its sub-leases as well
+        #[ink(message)]
+        pub fn sub_lease(
+            &mut self,
+            original_lease_id: u64,
+            new_beneficiary: AccountId,
+            daily_rent: u64,
+        ) -> Result<(), Error> {
+            if !self.is_enabled() {
+                return Err(Error::LeasingNotEnabled);
             }
-            invested.push(lease_id);
-
-            self.investors.insert(caller, invested);
+            let caller = self.env().caller();
+            let parent = *self
+                .leases
+                .get(&original_lease_id)
+                .ok_or(Error::NoSuchLease)?;
+            if parent.status != LeaseStatus::Rented as u8 {
+                return Err(Error::LeaseNotRented);
+            }
+            if parent.renter_address != Some(caller) {
+                return Err(Error::NotRenter);
+            }
+            if Self::term_expired(&parent) {
+                return Err(Error::LeaseExpired);
+            }
+            if Self::is_defaulter(&parent) {
+                return Err(Error::RentOverdue);
+            }
+            if self.active_sub_lease(original_lease_id).is_some() {
+                return Err(Error::SubLeaseAlreadyActive);
+            }
+            if self.sub_lease_depth(&parent) >= MAX_SUB_LEASE_DEPTH {
+                return Err(Error::SubLeaseTooDeep);
+            }
+            // The sub-lease can't outlast the term of the parent
+            let lease_duration = (parent.leased_at.unwrap_or(0) + parent.lease_duration)
+                .saturating_sub(Self::get_current_time());
+            self.validate_lease_terms(daily_rent, lease_duration)?;
 
-            self.env().emit_event(LeaseListed {
-                investor: caller,
-                nft_address: nft_address,
-                lease_id: lease_id,
-                token_id: token_id,
-                beneficiary_address: beneficiary_address,
-                daily_rent: daily_rent as u128,
-                lease_duration: lease_duration,
+            let lease_id = self.total_leases as LeaseId;
+            self.insert_lease(Lease {
+                id: lease_id,
+                daily_rent,
+                nft_address: parent.nft_address,
+                token_id: parent.token_id,
+                investor_address: caller,
+                beneficiary_address: new_beneficiary,
+                renter_address: None,
+                status: LeaseStatus::Available as u8,
+                lease_duration,
+                created_at: Self::get_current_time(),
+                fixed_term: parent.fixed_term,
+                parent_lease_id: Some(original_lease_id),
+                ..Default::default()
+            });
+            self.sub_leases
+                .entry(original_lease_id)
+                .or_insert_with(Vec::new)
+                .push(lease_id);
+
+            self.env().emit_event(SubLeaseListed {
+                parent_lease_id: original_lease_id,
+                lease_id,
+                renter: caller,
             });
-
             Ok(())
         }
 
         /// Rent a token
+        /// Fails if the security deposit of the lease exceeds max_deposit
         #[ink(message)]
-        pub fn rent(&mut self, lease_id: u64) -> Result<(), Error> {
-            assert_eq!(self.is_enabled(), true, "Leasing is not enabled");
+        pub fn rent(&mut self, lease_id: u64, max_deposit: Balance) -> Result<(), Error> {
+            self.lock()?;
+            if !self.is_enabled() {
+                self.locked = false;
+                return Err(Error::LeasingNotEnabled);
+            }
             let current_time = Self::get_current_time();
             let caller = self.env().caller();
+            let contract_address = self.env().account_id();
 
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
             let lease_opt = self.leases.get_mut(&lease_id);
-            assert_eq!(lease_opt.is_some(), true, "No such lease found");
+            if lease_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLease);
+            }
 
             let lease = lease_opt.unwrap();
-            assert_eq!(
-                lease.status,
-                LeaseStatus::Available as u8,
-                "Lease is not available"
-            );
+            if lease.status != LeaseStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::LeaseUnavailable);
+            }
+            // The investor may have raised the deposit since the renter looked at the lease
+            if lease.security_deposit > max_deposit {
+                self.locked = false;
+                return Err(Error::SecurityDepositTooHigh);
+            }
 
-            // Transfer first day rent to beneficiary
-            let erc20_transfer = self.erc20.transfer_from(
+            if let Err(error) = Self::check_allowance_sufficient(
+                &erc20,
                 caller,
-                lease.beneficiary_address,
-                lease.daily_rent as u128,
-            );
+                contract_address,
+                lease.daily_rent as u128 + lease.security_deposit,
+            ) {
+                self.locked = false;
+                return Err(error);
+            }
 
-            assert_eq!(erc20_transfer.is_ok(), true, "ERC20 Token transfer failed");
+            // Hold security deposit in the contract until the lease ends
+            if lease.security_deposit > 0 {
+                let deposit_transfer =
+                    erc20.transfer_from(caller, contract_address, lease.security_deposit);
+                if deposit_transfer.is_err() {
+                    self.locked = false;
+                    return Err(Error::ERC20TransferFailed);
+                }
+                lease.deposit_held = true;
+            }
+
+            // The deposit is held: a failed rent payment must revert the whole call
+            // Transfer first day rent to beneficiary
+            let erc20_transfer =
+                erc20.transfer_from(caller, lease.beneficiary_address, lease.daily_rent as u128);
+            assert!(erc20_transfer.is_ok(), "Rent transfer failed");
 
             // Mark lease as rented
             lease.renter_address = Some(caller);
@@ -327,33 +813,70 @@ mod leasingmanager {
                 lease_id: lease_clone.id,
                 token_id: lease_clone.token_id,
             });
+            self.record_status_change(Some(LeaseStatus::Available), LeaseStatus::Rented);
+            Self::increment_active_count(&mut self.renter_active_count, caller);
+            self.lease_statistics.total_rent_paid += lease_clone.daily_rent as Balance;
+            self.record_rent_payment(
+                lease_clone.beneficiary_address,
+                RentPayment {
+                    lease_id,
+                    renter: caller,
+                    amount: lease_clone.daily_rent as Balance,
+                    paid_at: current_time,
+                    days_covered: 1,
+                },
+            );
 
+            self.locked = false;
             Ok(())
         }
 
         #[ink(message)]
         pub fn pay_rent(&mut self, lease_id: u64) -> Result<(), Error> {
+            self.lock()?;
             let current_time = Self::get_current_time();
             let caller = self.env().caller();
+            let contract_address = self.env().account_id();
 
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
             let lease_opt = self.leases.get_mut(&lease_id);
-            assert_eq!(lease_opt.is_some(), true, "No such lease found");
+            if lease_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLease);
+            }
 
             let lease = lease_opt.unwrap();
-            assert_eq!(
-                lease.status,
-                LeaseStatus::Rented as u8,
-                "Lease is not rented"
-            );
+            if lease.status != LeaseStatus::Rented as u8 {
+                self.locked = false;
+                return Err(Error::LeaseNotRented);
+            }
+
+            if Self::term_expired(lease) {
+                self.locked = false;
+                return Err(Error::LeaseExpired);
+            }
+
+            let (lease_duration, rent_amount) = Self::next_rent_payment(lease, current_time);
+            if let Err(error) =
+                Self::check_allowance_sufficient(&erc20, caller, contract_address, rent_amount)
+            {
+                self.locked = false;
+                return Err(error);
+            }
 
-            let lease_duration =
-                Self::duration_in_days(lease.lease_paid_until.unwrap(), current_time);
-            let rent_amount = (lease_duration * lease.daily_rent) as u128;
             // Transfer daily rent to beneficiary
             let erc20_transfer =
-                self.erc20
-                    .transfer_from(caller, lease.beneficiary_address, rent_amount);
-            assert_eq!(erc20_transfer.is_ok(), true, "ERC20 Token transfer failed");
+                erc20.transfer_from(caller, lease.beneficiary_address, rent_amount);
+            if erc20_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
 
             lease.last_paid_at = Some(current_time);
             lease.lease_paid_until =
@@ -368,86 +891,272 @@ mod leasingmanager {
                 token_id: lease_.token_id,
                 rent_amount: rent_amount,
             });
+            self.lease_statistics.total_rent_paid += rent_amount;
+            self.record_rent_payment(
+                lease_.beneficiary_address,
+                RentPayment {
+                    lease_id,
+                    renter: caller,
+                    amount: rent_amount,
+                    paid_at: current_time,
+                    days_covered: lease_duration,
+                },
+            );
 
+            self.locked = false;
             Ok(())
         }
 
         #[ink(message)]
         pub fn terminate(&mut self, lease_id: u64) -> Result<(), Error> {
+            self.lock()?;
             let caller = self.env().caller();
 
             let lease_opt = self.leases.get_mut(&lease_id);
-            assert_eq!(lease_opt.is_some(), true, "No lease found");
+            if lease_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLease);
+            }
 
             let lease = lease_opt.unwrap();
-            assert_eq!(
-                lease.investor_address, caller,
-                "Only investor can terminate lease"
-            );
+            if lease.investor_address != caller {
+                self.locked = false;
+                return Err(Error::NotInvestor);
+            }
 
-            assert_eq!(
-                lease.status,
-                LeaseStatus::Rented as u8,
-                "Only rented leases can be terminated"
-            );
+            if lease.status != LeaseStatus::Rented as u8 {
+                self.locked = false;
+                return Err(Error::LeaseNotRented);
+            }
 
-            if !Self::is_defaulter(lease) {
-                return Err(Error::LeaseNotDefault);
+            if self.open_disputes.contains_key(&lease_id) {
+                self.locked = false;
+                return Err(Error::LeaseDisputed);
             }
 
-            if !Self::lease_duration_over(lease) {
-                return Err(Error::LeaseNotOver);
+            if let Err(error) = Self::validate_termination(lease) {
+                self.locked = false;
+                return Err(error);
             }
 
             // Transfer nft to investor
-            let mut erc721 = Self::get_nft(lease.nft_address);
-            let erc721_transfer = erc721.transfer(caller, lease.token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            if let Err(error) = Self::return_lease_nft(lease, caller) {
+                self.locked = false;
+                return Err(error);
+            }
 
-            // Mark lease as terminated
-            lease.status = LeaseStatus::Terminated as u8;
+            // Fixed term leases may end on their term without the renter defaulting
+            lease.terminated_due_to_default = Self::is_defaulter(lease);
+            self.mark_terminated(lease_id);
 
-            let lease_clone = lease.clone();
-            self.env().emit_event(LeaseTermintated {
-                investor: caller,
-                nft_address: lease_clone.nft_address,
-                lease_id: lease_clone.id,
-                token_id: lease_clone.token_id,
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows investor to require a security deposit from the renter of a listed lease
+        #[ink(message)]
+        pub fn set_security_deposit(
+            &mut self,
+            lease_id: u64,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let lease = self.leases.get_mut(&lease_id).ok_or(Error::NoSuchLease)?;
+            if lease.investor_address != caller {
+                return Err(Error::NotInvestor);
+            }
+            if lease.status != LeaseStatus::Available as u8 {
+                return Err(Error::LeaseUnavailable);
+            }
+            lease.security_deposit = amount;
+            Ok(())
+        }
+
+        /// Allows investor to collect the security deposit of a lease terminated on default
+        #[ink(message)]
+        pub fn collect_security_deposit(&mut self, lease_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let caller = self.env().caller();
+
+            let lease = match self.leases.get(&lease_id) {
+                Some(lease) => *lease,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchLease);
+                }
+            };
+            if lease.investor_address != caller {
+                self.locked = false;
+                return Err(Error::NotInvestor);
+            }
+            if let Err(error) = Self::validate_deposit_release(&lease, true) {
+                self.locked = false;
+                return Err(error);
+            }
+
+            if let Err(error) = self.release_security_deposit(lease_id, caller) {
+                self.locked = false;
+                return Err(error);
+            }
+            self.env().emit_event(DepositCollected {
+                lease_id,
+                investor: caller,
+                amount: lease.security_deposit,
+            });
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows renter to take back the security deposit of a lease that ended without default
+        #[ink(message)]
+        pub fn refund_security_deposit(&mut self, lease_id: u64) -> Result<(), Error> {
+            self.lock()?;
+            let caller = self.env().caller();
+
+            let lease = match self.leases.get(&lease_id) {
+                Some(lease) => *lease,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchLease);
+                }
+            };
+            if lease.renter_address != Some(caller) {
+                self.locked = false;
+                return Err(Error::NotRenter);
+            }
+            if let Err(error) = Self::validate_deposit_release(&lease, false) {
+                self.locked = false;
+                return Err(error);
+            }
+
+            if let Err(error) = self.release_security_deposit(lease_id, caller) {
+                self.locked = false;
+                return Err(error);
+            }
+            self.env().emit_event(DepositRefunded {
+                lease_id,
+                renter: caller,
+                amount: lease.security_deposit,
+            });
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows investor or renter of a rented lease to raise a dispute
+        /// The lease can't be terminated until the owner resolves it
+        #[ink(message)]
+        pub fn arbitration_request(&mut self, lease_id: u64, reason: Vec<u8>) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let lease = self.leases.get(&lease_id).ok_or(Error::NoSuchLease)?;
+            if lease.status != LeaseStatus::Rented as u8 {
+                return Err(Error::LeaseNotRented);
+            }
+
+            let renter = lease.renter_address.unwrap_or_default();
+            let respondent = if caller == lease.investor_address {
+                renter
+            } else if caller == renter {
+                lease.investor_address
+            } else {
+                return Err(Error::NotLeaseParty);
+            };
+
+            if self.open_disputes.contains_key(&lease_id) {
+                return Err(Error::DisputeAlreadyOpen);
+            }
+
+            let dispute_id = self.total_disputes;
+            self.disputes.insert(
+                dispute_id,
+                Dispute {
+                    id: dispute_id,
+                    lease_id,
+                    requester: caller,
+                    respondent,
+                    reason,
+                    created_at: Self::get_current_time(),
+                    resolved_at: None,
+                    ruling: None,
+                },
+            );
+            self.open_disputes.insert(lease_id, dispute_id);
+            self.total_disputes += 1;
+
+            self.env().emit_event(DisputeRaised {
+                dispute_id,
+                lease_id,
+                requester: caller,
             });
+            Ok(())
+        }
+
+        /// Allows owner to settle a dispute as arbitrator
+        /// RULING_RETURN_NFT terminates the lease and returns nft to investor
+        /// RULING_CONTINUE_LEASE leaves the lease running
+        #[ink(message)]
+        pub fn resolve_dispute(&mut self, dispute_id: u64, ruling: u8) -> Result<(), Error> {
+            if !self.only_owner(self.env().caller()) {
+                return Err(Error::NotOwner);
+            }
+            if ruling != RULING_RETURN_NFT && ruling != RULING_CONTINUE_LEASE {
+                return Err(Error::InvalidRuling);
+            }
+            let dispute = self.disputes.get(&dispute_id).ok_or(Error::NoSuchDispute)?;
+            if dispute.resolved_at.is_some() {
+                return Err(Error::DisputeAlreadyResolved);
+            }
+            let lease_id = dispute.lease_id;
+
+            self.lock()?;
+            if ruling == RULING_RETURN_NFT {
+                let lease = self.leases.get(&lease_id).unwrap();
+                if lease.status != LeaseStatus::Rented as u8 {
+                    self.locked = false;
+                    return Err(Error::LeaseNotRented);
+                }
+
+                // Transfer nft to investor
+                if let Err(error) = Self::return_lease_nft(lease, lease.investor_address) {
+                    self.locked = false;
+                    return Err(error);
+                }
 
+                self.mark_terminated(lease_id);
+            }
+
+            self.close_dispute(dispute_id, ruling);
+            self.locked = false;
             Ok(())
         }
 
         #[ink(message)]
         pub fn remove_token(&mut self, lease_id: u64) -> Result<(), Error> {
+            self.lock()?;
             let caller = self.env().caller();
 
             let lease_opt = self.leases.get_mut(&lease_id);
-            assert_eq!(lease_opt.is_some(), true, "No lease found");
+            if lease_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLease);
+            }
             let lease = lease_opt.unwrap();
-            assert_eq!(
-                lease.investor_address, caller,
-                "Only investor can remove lease"
-            );
+            if lease.investor_address != caller {
+                self.locked = false;
+                return Err(Error::NotInvestor);
+            }
 
-            assert_eq!(
-                lease.status,
-                LeaseStatus::Available as u8,
-                "Only available leases can be removed"
-            );
+            if lease.status != LeaseStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::LeaseUnavailable);
+            }
 
             // Transfer nft to investor
-            let mut erc721 = Self::get_nft(lease.nft_address);
-            let erc721_transfer = erc721.transfer(caller, lease.token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            if let Err(error) = Self::return_lease_nft(lease, caller) {
+                self.locked = false;
+                return Err(error);
+            }
 
             // Mark lease as removed
             lease.status = LeaseStatus::Removed as u8;
@@ -459,7 +1168,115 @@ mod leasingmanager {
                 lease_id: lease_clone.id,
                 token_id: lease_clone.token_id,
             });
+            self.record_status_change(Some(LeaseStatus::Available), LeaseStatus::Removed);
+            Self::decrement_active_count(&mut self.investor_active_count, caller);
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows investor to hand a rented lease over to new_investor
+        /// Rent goes to new_beneficiary from then on if one is given
+        #[ink(message)]
+        pub fn transfer_lease_ownership(
+            &mut self,
+            lease_id: u64,
+            new_investor: AccountId,
+            new_beneficiary: Option<AccountId>,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let lease = self.leases.get_mut(&lease_id).ok_or(Error::NoSuchLease)?;
+            if lease.investor_address != caller {
+                return Err(Error::NotInvestor);
+            }
+            if lease.status != LeaseStatus::Rented as u8 {
+                return Err(Error::LeaseNotRented);
+            }
+
+            lease.investor_address = new_investor;
+            if let Some(beneficiary_address) = new_beneficiary {
+                lease.beneficiary_address = beneficiary_address;
+            }
+            let lease_clone = lease.clone();
+
+            if let Some(invested) = self.investors.get_mut(&caller) {
+                invested.retain(|id| *id != lease_id);
+            }
+            let mut invested = self.get_leased_assets(new_investor);
+            invested.push(lease_id);
+            self.investors.insert(new_investor, invested);
+            Self::decrement_active_count(&mut self.investor_active_count, caller);
+            Self::increment_active_count(&mut self.investor_active_count, new_investor);
+
+            self.env().emit_event(LeaseOwnershipTransferred {
+                lease_id,
+                from_investor: caller,
+                to_investor: new_investor,
+                beneficiary_address: lease_clone.beneficiary_address,
+                token_id: lease_clone.token_id,
+            });
+            Ok(())
+        }
+
+        /// Allows investor to extend the duration of a rented lease
+        /// Rent already paid by the renter is not affected
+        /// A sub-lease can't be extended past the term of its parent
+        #[ink(message)]
+        pub fn extend_lease_duration(
+            &mut self,
+            lease_id: u64,
+            additional_ms: u64,
+        ) -> Result<(), Error> {
+            let parent_term_end = self
+                .leases
+                .get(&lease_id)
+                .and_then(|lease| lease.parent_lease_id)
+                .and_then(|parent_id| self.leases.get(&parent_id))
+                .map(|parent| parent.leased_at.unwrap_or(0) + parent.lease_duration);
+            let lease = self.get_rented_lease_of(lease_id, self.env().caller())?;
+            let old_duration = lease.lease_duration;
+            let new_duration = old_duration
+                .checked_add(additional_ms)
+                .ok_or(Error::InvalidDuration)?;
+            if let Some(parent_term_end) = parent_term_end {
+                if lease.leased_at.unwrap_or(0).saturating_add(new_duration) > parent_term_end {
+                    return Err(Error::InvalidDuration);
+                }
+            }
+            lease.lease_duration = new_duration;
+
+            self.env().emit_event(LeaseDurationExtended {
+                lease_id,
+                old_duration,
+                new_duration,
+            });
+            Ok(())
+        }
+
+        /// Allows investor to shorten the duration of a rented lease
+        /// The lease can't be shortened to a duration that is already over
+        #[ink(message)]
+        pub fn shorten_lease_duration(
+            &mut self,
+            lease_id: u64,
+            reduce_by_ms: u64,
+        ) -> Result<(), Error> {
+            let current_time = Self::get_current_time();
+            let lease = self.get_rented_lease_of(lease_id, self.env().caller())?;
+            let old_duration = lease.lease_duration;
+            let new_duration = old_duration
+                .checked_sub(reduce_by_ms)
+                .ok_or(Error::InvalidDuration)?;
+            if lease.leased_at.unwrap_or(0) + new_duration <= current_time {
+                return Err(Error::InvalidDuration);
+            }
+            lease.lease_duration = new_duration;
 
+            self.env().emit_event(LeaseDurationShortened {
+                lease_id,
+                old_duration,
+                new_duration,
+            });
             Ok(())
         }
 
@@ -497,6 +1314,83 @@ mod leasingmanager {
             Ok(*lease_opt.unwrap())
         }
 
+        /// Allows investor to rate the renter of a terminated lease once
+        #[ink(message)]
+        pub fn rate_renter(
+            &mut self,
+            lease_id: u64,
+            score: u8,
+            review: Vec<u8>,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let lease = self.leases.get(&lease_id).ok_or(Error::NoSuchLease)?;
+            if lease.investor_address != caller {
+                return Err(Error::NotInvestor);
+            }
+            if lease.status != LeaseStatus::Terminated as u8 {
+                return Err(Error::LeaseNotTerminated);
+            }
+            if score == 0 || score > MAX_RATING_SCORE {
+                return Err(Error::InvalidScore);
+            }
+            if self.rated_leases.contains_key(&lease_id) {
+                return Err(Error::AlreadyRated);
+            }
+
+            let renter = lease.renter_address.unwrap_or_default();
+            self.renter_ratings
+                .entry(renter)
+                .or_insert_with(Vec::new)
+                .push(RenterRating {
+                    lease_id,
+                    rater: caller,
+                    ratee: renter,
+                    score,
+                    review,
+                    rated_at: Self::get_current_time(),
+                });
+            self.rated_leases.insert(lease_id, true);
+
+            self.env().emit_event(RenterRated {
+                lease_id,
+                renter,
+                score,
+            });
+            Ok(())
+        }
+
+        /// Returns every rating renter received, oldest first
+        #[ink(message)]
+        pub fn get_renter_ratings(&self, renter: AccountId) -> Vec<RenterRating> {
+            self.renter_ratings
+                .get(&renter)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// Returns average score of renter rounded down, 0 if renter has no ratings
+        #[ink(message)]
+        pub fn get_renter_average_score(&self, renter: AccountId) -> u8 {
+            let ratings = match self.renter_ratings.get(&renter) {
+                Some(ratings) if !ratings.is_empty() => ratings,
+                _ => return 0,
+            };
+            let total: u64 = ratings.iter().map(|rating| rating.score as u64).sum();
+            (total / ratings.len() as u64) as u8
+        }
+
+        /// Returns the sub-leases listed by renters of lease_id
+        #[ink(message)]
+        pub fn get_sub_leases(&self, lease_id: LeaseId) -> Vec<LeaseId> {
+            self.sub_leases.get(&lease_id).cloned().unwrap_or_default()
+        }
+
+        /// Returns the dispute with the given id
+        #[ink(message)]
+        pub fn get_dispute(&self, dispute_id: u64) -> Option<Dispute> {
+            self.disputes.get(&dispute_id).cloned()
+        }
+
         #[ink(message)]
         pub fn is_rent_due(&self, lease_id: u64) -> Result<bool, Error> {
             let lease_opt = self.leases.get(&lease_id);
@@ -511,6 +1405,21 @@ mod leasingmanager {
             Ok(rent_due)
         }
 
+        /// Returns what pay_rent would charge now and the date rent is paid until
+        /// Overdue renters owe every day since that date, current renters a single day ahead
+        #[ink(message)]
+        pub fn get_next_payment_amount_and_date(
+            &self,
+            lease_id: u64,
+        ) -> Result<(Balance, u64), Error> {
+            let lease = self.leases.get(&lease_id).ok_or(Error::NoSuchLease)?;
+            if lease.status != LeaseStatus::Rented as u8 {
+                return Err(Error::LeaseNotRented);
+            }
+            let (_, rent_amount) = Self::next_rent_payment(lease, Self::get_current_time());
+            Ok((rent_amount, lease.lease_paid_until.unwrap_or(0)))
+        }
+
         #[ink(message)]
         pub fn get_lease_duration(&self, lease_id: LeaseId) -> Result<u64, Error> {
             let lease_opt = self.leases.get(&lease_id);
@@ -549,20 +1458,148 @@ mod leasingmanager {
             leases
         }
 
+        /// Returns rent payments made by renter
+        #[ink(message)]
+        pub fn get_renter_rent_history(&self, renter: AccountId) -> Vec<RentPayment> {
+            self.rent_history.get(&renter).cloned().unwrap_or_default()
+        }
+
+        /// Returns rent payments received by investor as beneficiary
+        #[ink(message)]
+        pub fn get_investor_rent_received(&self, investor: AccountId) -> Vec<RentPayment> {
+            self.rent_received
+                .get(&investor)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// Returns total rent paid by renter
+        #[ink(message)]
+        pub fn get_total_rent_paid_by(&self, renter: AccountId) -> Balance {
+            self.rent_history.get(&renter).map_or(0, |payments| {
+                payments.iter().map(|payment| payment.amount).sum()
+            })
+        }
+
+        /// Returns number of leases in each status and total rent paid
+        #[ink(message)]
+        pub fn get_lease_statistics(&self) -> LeaseStatistics {
+            LeaseStatistics {
+                total_leases: self.total_leases,
+                ..self.lease_statistics
+            }
+        }
+
+        /// Returns number of leases renter is currently renting
+        #[ink(message)]
+        pub fn renters_total_active_leases(&self, renter: AccountId) -> u32 {
+            self.renter_active_count.get(&renter).copied().unwrap_or(0)
+        }
+
+        /// Returns number of leases of investor that are listed or rented out
+        #[ink(message)]
+        pub fn investors_total_active_leases(&self, investor: AccountId) -> u32 {
+            self.investor_active_count
+                .get(&investor)
+                .copied()
+                .unwrap_or(0)
+        }
+
+        /// Returns leases renter is currently renting
+        #[ink(message)]
+        pub fn get_active_rented_assets(&self, renter: AccountId) -> Vec<LeaseId> {
+            self.get_rented_assets(renter)
+                .into_iter()
+                .filter(|lease_id| {
+                    self.leases.get(lease_id).map_or(false, |lease| {
+                        lease.status == LeaseStatus::Rented as u8
+                            && lease.renter_address == Some(renter)
+                    })
+                })
+                .collect()
+        }
+
+        /// Returns leases of investor that are listed or rented out
+        #[ink(message)]
+        pub fn get_active_leased_assets(&self, investor: AccountId) -> Vec<LeaseId> {
+            self.get_leased_assets(investor)
+                .into_iter()
+                .filter(|lease_id| {
+                    self.leases.get(lease_id).map_or(false, |lease| {
+                        lease.status == LeaseStatus::Available as u8
+                            || lease.status == LeaseStatus::Rented as u8
+                    })
+                })
+                .collect()
+        }
+
+        /// Allows owner to set the minimum daily rent of new leases
+        #[ink(message)]
+        pub fn set_minimum_daily_rent(&mut self, v: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.env().emit_event(MinimumDailyRentChanged {
+                old: self.administration.minimum_daily_rent,
+                new: v,
+            });
+            self.administration.minimum_daily_rent = v;
+            Ok(())
+        }
+
+        /// Returns minimum daily rent of new leases
+        #[ink(message)]
+        pub fn get_minimum_daily_rent(&self) -> u64 {
+            self.administration.minimum_daily_rent
+        }
+
+        /// Allows owner to set the maximum duration of new leases in milliseconds
+        #[ink(message)]
+        pub fn set_maximum_lease_duration(&mut self, v: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.env().emit_event(MaximumLeaseDurationChanged {
+                old: self.administration.maximum_lease_duration,
+                new: v,
+            });
+            self.administration.maximum_lease_duration = v;
+            Ok(())
+        }
+
+        /// Returns maximum duration of new leases in milliseconds
+        #[ink(message)]
+        pub fn get_maximum_lease_duration(&self) -> u64 {
+            self.administration.maximum_lease_duration
+        }
+
+        /// Returns whether leasing is enabled and the bounds of new leases
+        #[ink(message)]
+        pub fn get_leasing_config(&self) -> LeasingConfig {
+            LeasingConfig {
+                enabled: self.administration.enabled,
+                grace_period_days: GRACE_PERIOD_DAYS,
+                min_daily_rent: self.administration.minimum_daily_rent,
+                max_duration: self.administration.maximum_lease_duration,
+            }
+        }
+
         /// Allows owner to enable leasing
         #[ink(message)]
-        pub fn enable(&mut self) {
+        pub fn enable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
             self.administration.enabled = true;
             self.env().emit_event(Enabled {});
+            Ok(())
         }
 
         /// Allows owner to disable leasingleasingleasing
         #[ink(message)]
-        pub fn disable(&mut self) {
+        pub fn disable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
             self.administration.enabled = false;
             self.env().emit_event(Disbaled {});
+            Ok(())
         }
 
         /// Checks if leasing is enabled
@@ -571,70 +1608,1390 @@ mod leasingmanager {
             self.administration.enabled
         }
 
-        fn get_current_time() -> u64 {
-            Self::env().block_timestamp()
+        /// Adds payment to the history of both renter and beneficiary
+        fn record_rent_payment(&mut self, beneficiary: AccountId, payment: RentPayment) {
+            self.rent_history
+                .entry(payment.renter)
+                .or_insert_with(Vec::new)
+                .push(payment);
+            self.rent_received
+                .entry(beneficiary)
+                .or_insert_with(Vec::new)
+                .push(payment);
         }
 
-        fn get_nft(address: AccountId) -> Erc721 {
-            Erc721::from_account_id(address)
+        /// Lists lease as available and records it for its investor
+        fn insert_lease(&mut self, lease: Lease) {
+            let (lease_id, investor) = (lease.id, lease.investor_address);
+            self.leases.insert(lease_id, lease);
+            self.total_leases += 1;
+
+            let mut invested: Vec<LeaseId> = Vec::new();
+            let investor_opt = self.investors.get_mut(&investor);
+            if investor_opt.is_some() {
+                invested = investor_opt.unwrap().to_vec();
+            }
+            invested.push(lease_id);
+
+            self.investors.insert(investor, invested);
+            self.record_status_change(None, LeaseStatus::Available);
+            Self::increment_active_count(&mut self.investor_active_count, investor);
+
+            self.env().emit_event(LeaseListed {
+                investor,
+                nft_address: lease.nft_address,
+                lease_id,
+                token_id: lease.token_id,
+                beneficiary_address: lease.beneficiary_address,
+                daily_rent: lease.daily_rent as u128,
+                lease_duration: lease.lease_duration,
+            });
         }
 
-        fn is_defaulter(lease: &Lease) -> bool {
-            lease.lease_paid_until.unwrap()
-                < (Self::get_current_time() - SECONDS_IN_DAYS * 3 * 1000)
+        /// Marks a rented lease as terminated once its nft is back with the investor
+        /// Sub-leases of the lease that are still listed or rented are terminated with it
+        fn mark_terminated(&mut self, lease_id: LeaseId) {
+            let mut next = Some(lease_id);
+            // Each lease has at most one active sub-lease and chains are at most
+            // MAX_SUB_LEASE_DEPTH deep below the lease
+            for _ in 0..=MAX_SUB_LEASE_DEPTH {
+                let lease_id = match next {
+                    Some(lease_id) => lease_id,
+                    None => return,
+                };
+                self.terminate_lease(lease_id);
+                next = self.active_sub_lease(lease_id);
+            }
         }
 
-        fn lease_duration_over(lease: &Lease) -> bool {
-            (lease.leased_at.unwrap() + lease.lease_duration) < Self::get_current_time()
+        fn terminate_lease(&mut self, lease_id: LeaseId) {
+            let lease = self.leases.get_mut(&lease_id).unwrap();
+            let from = if lease.status == LeaseStatus::Available as u8 {
+                LeaseStatus::Available
+            } else {
+                LeaseStatus::Rented
+            };
+            lease.status = LeaseStatus::Terminated as u8;
+
+            let lease_clone = lease.clone();
+            self.env().emit_event(LeaseTermintated {
+                investor: lease_clone.investor_address,
+                nft_address: lease_clone.nft_address,
+                lease_id: lease_clone.id,
+                token_id: lease_clone.token_id,
+            });
+            self.record_status_change(Some(from), LeaseStatus::Terminated);
+            Self::decrement_active_count(
+                &mut self.investor_active_count,
+                lease_clone.investor_address,
+            );
+            if let Some(renter) = lease_clone.renter_address {
+                Self::decrement_active_count(&mut self.renter_active_count, renter);
+            }
         }
 
-        fn duration_in_days(current_time: u64, leased_at: u64) -> u64 {
-            let seconds_since_leased = (current_time - leased_at) / 1000;
-            let mut days = Self::divide(seconds_since_leased, SECONDS_IN_DAYS, 3);
-            days = days / 1000;
-            if seconds_since_leased > 0 && days == 0 {
-                days += 1;
-            } else if seconds_since_leased > (days * SECONDS_IN_DAYS) {
-                days += 1;
+        /// Returns the listed or rented sub-lease of lease_id
+        /// Only the latest sub-lease can be active as a new one needs the previous one closed
+        fn active_sub_lease(&self, lease_id: LeaseId) -> Option<LeaseId> {
+            let sub_lease_id = *self.sub_leases.get(&lease_id)?.last()?;
+            let status = self.leases.get(&sub_lease_id).unwrap().status;
+            if status == LeaseStatus::Available as u8 || status == LeaseStatus::Rented as u8 {
+                Some(sub_lease_id)
+            } else {
+                None
             }
+        }
 
-            days
+        /// Returns the number of parent leases above lease, up to MAX_SUB_LEASE_DEPTH
+        fn sub_lease_depth(&self, lease: &Lease) -> u32 {
+            let mut depth = 0;
+            let mut parent_lease_id = lease.parent_lease_id;
+            while let Some(lease_id) = parent_lease_id {
+                depth += 1;
+                if depth >= MAX_SUB_LEASE_DEPTH {
+                    break;
+                }
+                parent_lease_id = self.leases.get(&lease_id).unwrap().parent_lease_id;
+            }
+            depth
         }
 
-        fn divide(numerator: u64, denominator: u64, precision: u32) -> u64 {
-            let power: u64 = (10 as u64).pow(precision + 1);
-            let _numerator: u64 = numerator * power;
-            // with rounding of last digit
-            let _quotient: u64 = ((_numerator / denominator) + 5) / 10;
-            return _quotient;
+        /// Records the ruling on a dispute and frees its lease
+        fn close_dispute(&mut self, dispute_id: u64, ruling: u8) {
+            let current_time = Self::get_current_time();
+            let dispute = self.disputes.get_mut(&dispute_id).unwrap();
+            dispute.resolved_at = Some(current_time);
+            dispute.ruling = Some(ruling);
+            let lease_id = dispute.lease_id;
+            self.open_disputes.take(&lease_id);
+
+            self.env()
+                .emit_event(DisputeResolved { dispute_id, ruling });
         }
-    }
 
-    /// Testcases
-    #[cfg(test)]
-    mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
-        use ink_lang as ink;
-        /// We test if the constructor does its job.
-        fn instantiate_erc20_contract() -> AccountId {
-            let erc20 = Erc20::new(1000000);
-            let callee =
-                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
-            callee
+        /// Moves a lease between the status counters
+        fn record_status_change(&mut self, from: Option<LeaseStatus>, to: LeaseStatus) {
+            if let Some(from) = from {
+                *self.status_count_mut(from) -= 1;
+            }
+            *self.status_count_mut(to) += 1;
         }
-        #[ink::test]
-        fn new_works() {
-            let leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
-            assert_eq!(leasingmanager.is_enabled(), true);
+
+        fn increment_active_count(counts: &mut StorageHashMap<AccountId, u32>, account: AccountId) {
+            *counts.entry(account).or_insert(0) += 1;
         }
 
-        #[ink::test]
-        fn enable_works() {
-            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), false);
+        fn decrement_active_count(counts: &mut StorageHashMap<AccountId, u32>, account: AccountId) {
+            if let Some(count) = counts.get_mut(&account) {
+                *count = count.saturating_sub(1);
+            }
+        }
+
+        fn status_count_mut(&mut self, status: LeaseStatus) -> &mut u32 {
+            let statistics = &mut self.lease_statistics;
+            match status {
+                LeaseStatus::Available => &mut statistics.available_count,
+                LeaseStatus::Rented => &mut statistics.rented_count,
+                LeaseStatus::Terminated => &mut statistics.terminated_count,
+                LeaseStatus::Removed => &mut statistics.removed_count,
+            }
+        }
+
+        fn get_current_time() -> u64 {
+            Self::env().block_timestamp()
+        }
+
+        /// Checks that spender may transfer amount on behalf of owner
+        /// so that transfers fail with a clear error before anything else happens
+        fn check_allowance_sufficient(
+            erc20: &Erc20,
+            owner: AccountId,
+            spender: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            if erc20.allowance(owner, spender) < amount {
+                return Err(Error::InsufficientBalance);
+            }
+            Ok(())
+        }
+
+        /// Fails with AddressNotFound if the address manager has no erc20 registered
+        fn get_erc20(&self) -> Result<Erc20, Error> {
+            match &self.address_manager {
+                Some(address_manager) => address_manager
+                    .require_address(String::from(ERC20))
+                    .map(Erc20::from_account_id)
+                    .map_err(|_| Error::AddressNotFound),
+                None => Ok((*self.erc20).clone()),
+            }
+        }
+
+        /// Sends the nft of lease to account, sub-leases hold no nft of their own
+        fn return_lease_nft(lease: &Lease, account: AccountId) -> Result<(), Error> {
+            if lease.parent_lease_id.is_some() {
+                return Ok(());
+            }
+            let mut erc721 = Self::get_nft(lease.nft_address);
+            erc721
+                .transfer(account, lease.token_id)
+                .map_err(|_| Error::ERC721TransferFailed)
+        }
+
+        fn get_nft(address: AccountId) -> Erc721 {
+            Erc721::from_account_id(address)
+        }
+
+        fn is_defaulter(lease: &Lease) -> bool {
+            // Leases that were never rented have no rent to default on
+            if lease.lease_paid_until.is_none() {
+                return false;
+            }
+            lease.lease_paid_until.unwrap()
+                < Self::get_current_time()
+                    .saturating_sub(SECONDS_IN_DAYS * GRACE_PERIOD_DAYS * 1000)
+        }
+
+        fn term_expired(lease: &Lease) -> bool {
+            lease.fixed_term && Self::lease_duration_over(lease)
+        }
+
+        fn validate_termination(lease: &Lease) -> Result<(), Error> {
+            // Fixed term leases end with their term whether rent is paid or not
+            if Self::term_expired(lease) {
+                return Ok(());
+            }
+            if !Self::is_defaulter(lease) {
+                return Err(Error::LeaseNotDefault);
+            }
+            if !Self::lease_duration_over(lease) {
+                return Err(Error::LeaseNotOver);
+            }
+            Ok(())
+        }
+
+        /// Checks that the deposit of lease is held and may go to the investor
+        /// when to_investor is set, or back to the renter otherwise
+        fn validate_deposit_release(lease: &Lease, to_investor: bool) -> Result<(), Error> {
+            if lease.status != LeaseStatus::Terminated as u8 {
+                return Err(Error::LeaseNotTerminated);
+            }
+            if to_investor && !lease.terminated_due_to_default {
+                return Err(Error::NoDefaultTermination);
+            }
+            if !to_investor && lease.terminated_due_to_default {
+                return Err(Error::TerminatedDueToDefault);
+            }
+            if !lease.deposit_held {
+                return Err(Error::NoSecurityDeposit);
+            }
+            Ok(())
+        }
+
+        /// Sends the held deposit of lease to recipient
+        fn release_security_deposit(
+            &mut self,
+            lease_id: LeaseId,
+            recipient: AccountId,
+        ) -> Result<(), Error> {
+            let mut erc20 = self.get_erc20()?;
+            let lease = self.leases.get_mut(&lease_id).unwrap();
+            if erc20.transfer(recipient, lease.security_deposit).is_err() {
+                return Err(Error::ERC20TransferFailed);
+            }
+            lease.deposit_held = false;
+            Ok(())
+        }
+
+        fn validate_lease_terms(&self, daily_rent: u64, lease_duration: u64) -> Result<(), Error> {
+            if daily_rent < self.administration.minimum_daily_rent {
+                return Err(Error::RentTooLow);
+            }
+            if lease_duration > self.administration.maximum_lease_duration {
+                return Err(Error::DurationTooLong);
+            }
+            Ok(())
+        }
+
+        fn get_rented_lease_of(
+            &mut self,
+            lease_id: u64,
+            investor: AccountId,
+        ) -> Result<&mut Lease, Error> {
+            let lease = self.leases.get_mut(&lease_id).ok_or(Error::NoSuchLease)?;
+            if lease.investor_address != investor {
+                return Err(Error::NotInvestor);
+            }
+            if lease.status != LeaseStatus::Rented as u8 {
+                return Err(Error::LeaseNotRented);
+            }
+            Ok(lease)
+        }
+
+        fn lease_duration_over(lease: &Lease) -> bool {
+            (lease.leased_at.unwrap() + lease.lease_duration) < Self::get_current_time()
+        }
+
+        /// Whole days since leased_at, counting a started day as a full day
+        /// Returns days pay_rent covers and rent it charges at current_time
+        fn next_rent_payment(lease: &Lease, current_time: u64) -> (u64, Balance) {
+            let overdue_days =
+                Self::duration_in_days(current_time, lease.lease_paid_until.unwrap_or(0));
+            let days = overdue_days.max(1);
+            (days, (days * lease.daily_rent) as Balance)
+        }
+
+        fn duration_in_days(current_time: u64, leased_at: u64) -> u64 {
+            if current_time <= leased_at {
+                return 0;
+            }
+            let seconds_since_leased = (current_time - leased_at) / 1000;
+            // Integer ceiling division so no partial day is rounded away
+            (seconds_since_leased + SECONDS_IN_DAYS - 1) / SECONDS_IN_DAYS
+        }
+    }
+
+    /// Testcases
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_lang as ink;
+
+        type Event = <LeasingManager as ::ink_lang::BaseEvent>::Type;
+
+        /// We test if the constructor does its job.
+        fn instantiate_erc20_contract() -> AccountId {
+            let erc20 = Erc20::new(1000000);
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            callee
+        }
+        #[ink::test]
+        fn new_works() {
+            let leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            assert_eq!(leasingmanager.is_enabled(), true);
+            assert_eq!(
+                leasingmanager.get_leasing_config(),
+                LeasingConfig {
+                    enabled: true,
+                    grace_period_days: 3,
+                    min_daily_rent: 1,
+                    max_duration: 365 * SECONDS_IN_DAYS * 1_000,
+                }
+            );
+        }
+
+        #[ink::test]
+        fn reentrancy_guard_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            // Simulate a call made while another guarded call is in progress
+            leasingmanager.locked = true;
+            assert_eq!(leasingmanager.rent(0, 0), Err(Error::Reentrant));
+            assert_eq!(leasingmanager.pay_rent(0), Err(Error::Reentrant));
+            assert_eq!(leasingmanager.terminate(0), Err(Error::Reentrant));
+            assert_eq!(leasingmanager.remove_token(0), Err(Error::Reentrant));
+        }
+
+        #[ink::test]
+        fn typed_errors_work() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), false);
+            assert_eq!(leasingmanager.rent(0, 0), Err(Error::LeasingNotEnabled));
+
+            assert_eq!(leasingmanager.enable(), Ok(()));
+            assert_eq!(leasingmanager.rent(0, 0), Err(Error::NoSuchLease));
+            assert_eq!(leasingmanager.pay_rent(0), Err(Error::NoSuchLease));
+            assert_eq!(leasingmanager.terminate(0), Err(Error::NoSuchLease));
+            assert_eq!(leasingmanager.remove_token(0), Err(Error::NoSuchLease));
+            assert_eq!(leasingmanager.list_lease(0).err(), Some(Error::NoSuchLease));
+            // Failed calls release the re-entrancy guard
+            assert_eq!(leasingmanager.locked, false);
+        }
+
+        #[ink::test]
+        fn lease_statistics_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            assert_eq!(
+                leasingmanager.get_lease_statistics(),
+                LeaseStatistics::default()
+            );
+
+            // Mirrors the storage updates of list_token, rent, pay_rent,
+            // terminate and remove_token which cannot be called off-chain
+            for lease_id in 0..2 {
+                leasingmanager.leases.insert(
+                    lease_id,
+                    Lease {
+                        id: lease_id,
+                        daily_rent: 100,
+                        status: LeaseStatus::Available as u8,
+                        ..Default::default()
+                    },
+                );
+                leasingmanager.total_leases += 1;
+                leasingmanager.record_status_change(None, LeaseStatus::Available);
+            }
+            assert_eq!(
+                leasingmanager.get_lease_statistics(),
+                LeaseStatistics {
+                    total_leases: 2,
+                    available_count: 2,
+                    ..Default::default()
+                }
+            );
+
+            // rent
+            leasingmanager.record_status_change(Some(LeaseStatus::Available), LeaseStatus::Rented);
+            leasingmanager.lease_statistics.total_rent_paid += 100;
+            assert_eq!(
+                leasingmanager.get_lease_statistics(),
+                LeaseStatistics {
+                    total_leases: 2,
+                    available_count: 1,
+                    rented_count: 1,
+                    total_rent_paid: 100,
+                    ..Default::default()
+                }
+            );
+
+            // pay_rent
+            leasingmanager.lease_statistics.total_rent_paid += 300;
+            assert_eq!(leasingmanager.get_lease_statistics().total_rent_paid, 400);
+            assert_eq!(leasingmanager.get_lease_statistics().rented_count, 1);
+
+            // terminate
+            leasingmanager.record_status_change(Some(LeaseStatus::Rented), LeaseStatus::Terminated);
+            // remove_token
+            leasingmanager.record_status_change(Some(LeaseStatus::Available), LeaseStatus::Removed);
+            assert_eq!(
+                leasingmanager.get_lease_statistics(),
+                LeaseStatistics {
+                    total_leases: 2,
+                    available_count: 0,
+                    rented_count: 0,
+                    terminated_count: 1,
+                    removed_count: 1,
+                    total_rent_paid: 400,
+                }
+            );
+        }
+
+        #[ink::test]
+        fn transfer_lease_ownership_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            // The default caller is the investor
+            let investor = AccountId::from([0x01; 32]);
+            let new_investor = AccountId::from([0x02; 32]);
+            let new_beneficiary = AccountId::from([0x03; 32]);
+            leasingmanager.leases.insert(
+                0,
+                Lease {
+                    id: 0,
+                    investor_address: investor,
+                    beneficiary_address: investor,
+                    status: LeaseStatus::Available as u8,
+                    ..Default::default()
+                },
+            );
+            leasingmanager.investors.insert(investor, vec![0]);
+
+            assert_eq!(
+                leasingmanager.transfer_lease_ownership(1, new_investor, None),
+                Err(Error::NoSuchLease)
+            );
+            assert_eq!(
+                leasingmanager.transfer_lease_ownership(0, new_investor, None),
+                Err(Error::LeaseNotRented)
+            );
+
+            leasingmanager.leases.get_mut(&0).unwrap().status = LeaseStatus::Rented as u8;
+            assert_eq!(
+                leasingmanager.transfer_lease_ownership(0, new_investor, Some(new_beneficiary)),
+                Ok(())
+            );
+            let lease = leasingmanager.list_lease(0).unwrap();
+            assert_eq!(lease.investor_address, new_investor);
+            // Rent is paid to the beneficiary address
+            assert_eq!(lease.beneficiary_address, new_beneficiary);
+            assert_eq!(
+                leasingmanager.get_leased_assets(investor),
+                Vec::<LeaseId>::new()
+            );
+            assert_eq!(leasingmanager.get_leased_assets(new_investor), vec![0]);
+
+            // The previous investor no longer controls the lease
+            assert_eq!(
+                leasingmanager.transfer_lease_ownership(0, investor, None),
+                Err(Error::NotInvestor)
+            );
+        }
+
+        fn set_caller(caller: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                caller,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+        }
+
+        fn insert_rented_lease(leasingmanager: &mut LeasingManager, renter: AccountId) {
+            leasingmanager.leases.insert(
+                0,
+                Lease {
+                    id: 0,
+                    investor_address: AccountId::from([0x01; 32]),
+                    renter_address: Some(renter),
+                    status: LeaseStatus::Rented as u8,
+                    ..Default::default()
+                },
+            );
+            leasingmanager.lease_statistics.rented_count = 1;
+        }
+
+        #[ink::test]
+        fn sub_lease_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let investor = AccountId::from([0x01; 32]);
+            let renter = AccountId::from([0x02; 32]);
+            let sub_renter = AccountId::from([0x03; 32]);
+            insert_rented_lease(&mut leasingmanager, renter);
+            leasingmanager.total_leases = 1;
+            assert_eq!(
+                leasingmanager.sub_lease(1, renter, 10),
+                Err(Error::NoSuchLease)
+            );
+            assert_eq!(
+                leasingmanager.sub_lease(0, renter, 10),
+                Err(Error::NotRenter)
+            );
+
+            let parent = leasingmanager.leases.get_mut(&0).unwrap();
+            parent.leased_at = Some(0);
+            parent.lease_duration = 10_000;
+
+            set_caller(renter);
+            assert_eq!(
+                leasingmanager.sub_lease(0, renter, 0),
+                Err(Error::RentTooLow)
+            );
+            assert_eq!(leasingmanager.sub_lease(0, renter, 10), Ok(()));
+            // Only one sub-lease can be listed or rented at a time
+            assert_eq!(
+                leasingmanager.sub_lease(0, renter, 20),
+                Err(Error::SubLeaseAlreadyActive)
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(leasingmanager.get_sub_leases(0), vec![1]);
+            let sub_lease = leasingmanager.list_lease(1).unwrap();
+            assert_eq!(sub_lease.parent_lease_id, Some(0));
+            assert_eq!(sub_lease.investor_address, renter);
+            assert_eq!(sub_lease.beneficiary_address, renter);
+            assert_eq!(sub_lease.status, LeaseStatus::Available as u8);
+            assert_eq!(sub_lease.lease_duration, 10_000);
+            assert_eq!(leasingmanager.get_leased_assets(renter), vec![1]);
+
+            // Withdrawing a sub-lease leaves the nft in the contract
+            set_caller(renter);
+            assert_eq!(leasingmanager.remove_token(1), Ok(()));
+            assert_eq!(
+                leasingmanager.list_lease(1).unwrap().status,
+                LeaseStatus::Removed as u8
+            );
+            // and frees the parent for another sub-lease, ending with the parent's term
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(leasingmanager.sub_lease(0, renter, 20), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(leasingmanager.get_sub_leases(0), vec![1, 2]);
+            assert_eq!(leasingmanager.list_lease(2).unwrap().lease_duration, 9_995);
+
+            // Sub-leases end with their parent lease
+            let sub_lease = leasingmanager.leases.get_mut(&2).unwrap();
+            sub_lease.renter_address = Some(sub_renter);
+            sub_lease.status = LeaseStatus::Rented as u8;
+            leasingmanager.lease_statistics.available_count -= 1;
+            leasingmanager.lease_statistics.rented_count += 1;
+            leasingmanager.mark_terminated(0);
+            for lease_id in [0, 2].iter() {
+                assert_eq!(
+                    leasingmanager.list_lease(*lease_id).unwrap().status,
+                    LeaseStatus::Terminated as u8
+                );
+            }
+            assert_eq!(
+                leasingmanager.list_lease(1).unwrap().status,
+                LeaseStatus::Removed as u8
+            );
+            assert_eq!(leasingmanager.get_lease_statistics().terminated_count, 2);
+            assert_eq!(leasingmanager.get_lease_statistics().rented_count, 0);
+            assert_eq!(leasingmanager.investors_total_active_leases(renter), 0);
+            assert_eq!(leasingmanager.investors_total_active_leases(investor), 0);
+            assert_eq!(leasingmanager.renters_total_active_leases(sub_renter), 0);
+        }
+
+        #[ink::test]
+        fn sub_lease_chain_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let renters = [
+                AccountId::from([0x02; 32]),
+                AccountId::from([0x03; 32]),
+                AccountId::from([0x04; 32]),
+                AccountId::from([0x05; 32]),
+            ];
+            insert_rented_lease(&mut leasingmanager, renters[0]);
+            leasingmanager.total_leases = 1;
+
+            // Every renter sub-leases the lease they rented to the next renter
+            for (lease_id, renter) in renters.iter().enumerate() {
+                set_caller(*renter);
+                let result = leasingmanager.sub_lease(lease_id as LeaseId, *renter, 10);
+                ink_env::test::pop_execution_context();
+                if lease_id as u32 == MAX_SUB_LEASE_DEPTH {
+                    assert_eq!(result, Err(Error::SubLeaseTooDeep));
+                    break;
+                }
+                assert_eq!(result, Ok(()));
+                let sub_lease = leasingmanager
+                    .leases
+                    .get_mut(&(lease_id as LeaseId + 1))
+                    .unwrap();
+                sub_lease.renter_address = Some(renters[lease_id + 1]);
+                sub_lease.status = LeaseStatus::Rented as u8;
+                leasingmanager.lease_statistics.available_count -= 1;
+                leasingmanager.lease_statistics.rented_count += 1;
+            }
+            assert_eq!(
+                leasingmanager.sub_lease_depth(&leasingmanager.list_lease(3).unwrap()),
+                MAX_SUB_LEASE_DEPTH
+            );
+
+            // Terminating the original lease ends the whole chain
+            leasingmanager.mark_terminated(0);
+            for lease_id in 0..=MAX_SUB_LEASE_DEPTH {
+                assert_eq!(
+                    leasingmanager
+                        .list_lease(lease_id as LeaseId)
+                        .unwrap()
+                        .status,
+                    LeaseStatus::Terminated as u8
+                );
+            }
+        }
+
+        #[ink::test]
+        fn dispute_continue_lease_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let renter = AccountId::from([0x02; 32]);
+            let outsider = AccountId::from([0x03; 32]);
+            assert_eq!(
+                leasingmanager.arbitration_request(0, vec![]),
+                Err(Error::NoSuchLease)
+            );
+            insert_rented_lease(&mut leasingmanager, renter);
+
+            set_caller(outsider);
+            assert_eq!(
+                leasingmanager.arbitration_request(0, vec![]),
+                Err(Error::NotLeaseParty)
+            );
+            ink_env::test::pop_execution_context();
+
+            set_caller(renter);
+            assert_eq!(
+                leasingmanager.arbitration_request(0, b"late".to_vec()),
+                Ok(())
+            );
+            assert_eq!(
+                leasingmanager.resolve_dispute(0, RULING_CONTINUE_LEASE),
+                Err(Error::NotOwner)
+            );
+            ink_env::test::pop_execution_context();
+
+            let dispute = leasingmanager.get_dispute(0).unwrap();
+            assert_eq!(dispute.requester, renter);
+            assert_eq!(dispute.respondent, AccountId::from([0x01; 32]));
+            assert_eq!(dispute.reason, b"late".to_vec());
+            assert_eq!(
+                leasingmanager.arbitration_request(0, vec![]),
+                Err(Error::DisputeAlreadyOpen)
+            );
+            assert_eq!(leasingmanager.terminate(0), Err(Error::LeaseDisputed));
+
+            assert_eq!(
+                leasingmanager.resolve_dispute(0, 2),
+                Err(Error::InvalidRuling)
+            );
+            assert_eq!(
+                leasingmanager.resolve_dispute(1, RULING_CONTINUE_LEASE),
+                Err(Error::NoSuchDispute)
+            );
+            assert_eq!(
+                leasingmanager.resolve_dispute(0, RULING_CONTINUE_LEASE),
+                Ok(())
+            );
+            let dispute = leasingmanager.get_dispute(0).unwrap();
+            assert_eq!(dispute.resolved_at, Some(0));
+            assert_eq!(dispute.ruling, Some(RULING_CONTINUE_LEASE));
+            assert_eq!(
+                leasingmanager.list_lease(0).unwrap().status,
+                LeaseStatus::Rented as u8
+            );
+            assert_eq!(
+                leasingmanager.resolve_dispute(0, RULING_CONTINUE_LEASE),
+                Err(Error::DisputeAlreadyResolved)
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded = <Event as scale::Decode>::decode(&mut &emitted_events[0].data[..])
+                .expect("encountered invalid contract event data buffer");
+            assert!(matches!(decoded, Event::DisputeRaised(_)));
+            let decoded = <Event as scale::Decode>::decode(&mut &emitted_events[1].data[..])
+                .expect("encountered invalid contract event data buffer");
+            assert!(matches!(decoded, Event::DisputeResolved(_)));
+
+            // The investor may raise a new dispute once the previous one is resolved
+            assert_eq!(leasingmanager.arbitration_request(0, vec![]), Ok(()));
+            assert_eq!(leasingmanager.get_dispute(1).unwrap().respondent, renter);
+        }
+
+        #[ink::test]
+        fn dispute_return_nft_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let renter = AccountId::from([0x02; 32]);
+            insert_rented_lease(&mut leasingmanager, renter);
+            assert_eq!(leasingmanager.arbitration_request(0, vec![]), Ok(()));
+
+            // The nft transfer can't run off-chain, so apply the ruling's bookkeeping directly
+            leasingmanager.mark_terminated(0);
+            leasingmanager.close_dispute(0, RULING_RETURN_NFT);
+            assert_eq!(
+                leasingmanager.list_lease(0).unwrap().status,
+                LeaseStatus::Terminated as u8
+            );
+            assert_eq!(leasingmanager.get_lease_statistics().terminated_count, 1);
+            assert_eq!(
+                leasingmanager.get_dispute(0).unwrap().ruling,
+                Some(RULING_RETURN_NFT)
+            );
+
+            // A terminated lease can't be disputed or returned again
+            assert_eq!(
+                leasingmanager.arbitration_request(0, vec![]),
+                Err(Error::LeaseNotRented)
+            );
+            leasingmanager.leases.get_mut(&0).unwrap().status = LeaseStatus::Rented as u8;
+            assert_eq!(leasingmanager.arbitration_request(0, vec![]), Ok(()));
+            leasingmanager.leases.get_mut(&0).unwrap().status = LeaseStatus::Terminated as u8;
+            assert_eq!(
+                leasingmanager.resolve_dispute(1, RULING_RETURN_NFT),
+                Err(Error::LeaseNotRented)
+            );
+        }
+
+        #[ink::test]
+        fn extend_lease_duration_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let investor = AccountId::from([0x01; 32]);
+            leasingmanager.leases.insert(
+                0,
+                Lease {
+                    id: 0,
+                    investor_address: investor,
+                    lease_duration: 1_000,
+                    leased_at: Some(0),
+                    lease_paid_until: Some(500),
+                    status: LeaseStatus::Available as u8,
+                    ..Default::default()
+                },
+            );
+
+            assert_eq!(
+                leasingmanager.extend_lease_duration(1, 500),
+                Err(Error::NoSuchLease)
+            );
+            assert_eq!(
+                leasingmanager.extend_lease_duration(0, 500),
+                Err(Error::LeaseNotRented)
+            );
+
+            leasingmanager.leases.get_mut(&0).unwrap().status = LeaseStatus::Rented as u8;
+            assert_eq!(leasingmanager.extend_lease_duration(0, 500), Ok(()));
+            let lease = leasingmanager.list_lease(0).unwrap();
+            assert_eq!(lease.lease_duration, 1_500);
+            assert_eq!(lease.lease_paid_until, Some(500));
+            assert_eq!(
+                leasingmanager.extend_lease_duration(0, u64::MAX),
+                Err(Error::InvalidDuration)
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event =
+                <Event as scale::Decode>::decode(&mut &emitted_events.last().unwrap().data[..])
+                    .expect("encountered invalid contract event data buffer");
+            if let Event::LeaseDurationExtended(LeaseDurationExtended {
+                lease_id,
+                old_duration,
+                new_duration,
+            }) = decoded_event
+            {
+                assert_eq!(lease_id, 0);
+                assert_eq!(old_duration, 1_000);
+                assert_eq!(new_duration, 1_500);
+            } else {
+                panic!("encountered unexpected event kind: expected a LeaseDurationExtended event")
+            }
+
+            leasingmanager.leases.get_mut(&0).unwrap().investor_address =
+                AccountId::from([0x02; 32]);
+            assert_eq!(
+                leasingmanager.extend_lease_duration(0, 500),
+                Err(Error::NotInvestor)
+            );
+        }
+
+        #[ink::test]
+        fn extend_sub_lease_past_parent_fails() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let investor = AccountId::from([0x01; 32]);
+            leasingmanager.leases.insert(
+                0,
+                Lease {
+                    id: 0,
+                    investor_address: AccountId::from([0x02; 32]),
+                    renter_address: Some(investor),
+                    lease_duration: 1_000,
+                    leased_at: Some(0),
+                    status: LeaseStatus::Rented as u8,
+                    ..Default::default()
+                },
+            );
+            leasingmanager.leases.insert(
+                1,
+                Lease {
+                    id: 1,
+                    investor_address: investor,
+                    lease_duration: 500,
+                    leased_at: Some(200),
+                    status: LeaseStatus::Rented as u8,
+                    parent_lease_id: Some(0),
+                    ..Default::default()
+                },
+            );
+
+            assert_eq!(
+                leasingmanager.extend_lease_duration(1, 301),
+                Err(Error::InvalidDuration)
+            );
+            assert_eq!(leasingmanager.list_lease(1).unwrap().lease_duration, 500);
+            assert_eq!(leasingmanager.extend_lease_duration(1, 300), Ok(()));
+            assert_eq!(leasingmanager.list_lease(1).unwrap().lease_duration, 800);
+
+            // Extending the parent makes room for the sub-lease
+            set_caller(AccountId::from([0x02; 32]));
+            assert_eq!(leasingmanager.extend_lease_duration(0, 1_000), Ok(()));
+            ink_env::test::pop_execution_context();
+            assert_eq!(leasingmanager.extend_lease_duration(1, 1_000), Ok(()));
+            assert_eq!(leasingmanager.list_lease(1).unwrap().lease_duration, 1_800);
+        }
+
+        #[ink::test]
+        fn shorten_lease_duration_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let investor = AccountId::from([0x01; 32]);
+            leasingmanager.leases.insert(
+                0,
+                Lease {
+                    id: 0,
+                    investor_address: investor,
+                    lease_duration: 1_000,
+                    leased_at: Some(0),
+                    status: LeaseStatus::Rented as u8,
+                    ..Default::default()
+                },
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            let current_time = LeasingManager::get_current_time();
+
+            assert_eq!(leasingmanager.shorten_lease_duration(0, 400), Ok(()));
+            assert_eq!(leasingmanager.list_lease(0).unwrap().lease_duration, 600);
+            assert_eq!(
+                leasingmanager.shorten_lease_duration(0, 700),
+                Err(Error::InvalidDuration)
+            );
+            // Duration can't end at or before the current time
+            assert_eq!(
+                leasingmanager.shorten_lease_duration(0, 600 - current_time),
+                Err(Error::InvalidDuration)
+            );
+            assert_eq!(
+                leasingmanager.shorten_lease_duration(0, 600 - current_time - 1),
+                Ok(())
+            );
+            assert_eq!(
+                leasingmanager.list_lease(0).unwrap().lease_duration,
+                current_time + 1
+            );
+        }
+
+        #[ink::test]
+        fn lease_term_bounds_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let nft_address = AccountId::from([0x02; 32]);
+            let max_duration = 365 * SECONDS_IN_DAYS * 1_000;
+            assert_eq!(leasingmanager.get_minimum_daily_rent(), 1);
+            assert_eq!(leasingmanager.get_maximum_lease_duration(), max_duration);
+
+            assert_eq!(
+                leasingmanager.list_token(nft_address, 1, nft_address, 0, max_duration),
+                Err(Error::RentTooLow)
+            );
+            assert_eq!(
+                leasingmanager.list_token(nft_address, 1, nft_address, 1, max_duration + 1),
+                Err(Error::DurationTooLong)
+            );
+            assert_eq!(leasingmanager.validate_lease_terms(1, max_duration), Ok(()));
+
+            assert_eq!(leasingmanager.set_minimum_daily_rent(100), Ok(()));
+            assert_eq!(leasingmanager.set_maximum_lease_duration(1_000), Ok(()));
+            assert_eq!(leasingmanager.get_minimum_daily_rent(), 100);
+            assert_eq!(leasingmanager.get_maximum_lease_duration(), 1_000);
+            assert_eq!(
+                leasingmanager.validate_lease_terms(99, 1_000),
+                Err(Error::RentTooLow)
+            );
+            assert_eq!(
+                leasingmanager.validate_lease_terms(100, 1_001),
+                Err(Error::DurationTooLong)
+            );
+            assert_eq!(leasingmanager.validate_lease_terms(100, 1_000), Ok(()));
+            // Lock is released after a rejected listing
+            assert_eq!(
+                leasingmanager.list_token(nft_address, 1, nft_address, 99, 1_000),
+                Err(Error::RentTooLow)
+            );
+            assert_eq!(leasingmanager.locked, false);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(emitted_events.len(), 2);
+            let decoded_event = <Event as scale::Decode>::decode(&mut &emitted_events[1].data[..])
+                .expect("encountered invalid contract event data buffer");
+            if let Event::MaximumLeaseDurationChanged(MaximumLeaseDurationChanged { old, new }) =
+                decoded_event
+            {
+                assert_eq!(old, max_duration);
+                assert_eq!(new, 1_000);
+            } else {
+                panic!("encountered unexpected event kind: expected a MaximumLeaseDurationChanged event")
+            }
+        }
+
+        #[ink::test]
+        fn rent_history_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let renter = AccountId::from([0x02; 32]);
+            let beneficiary = AccountId::from([0x03; 32]);
+            assert_eq!(
+                leasingmanager.get_renter_rent_history(renter),
+                Vec::<RentPayment>::new()
+            );
+            assert_eq!(leasingmanager.get_total_rent_paid_by(renter), 0);
+
+            // Initial rent covers a single day
+            let initial = RentPayment {
+                lease_id: 0,
+                renter,
+                amount: 10,
+                paid_at: 0,
+                days_covered: 1,
+            };
+            // Subsequent payment covers the days since the lease was last paid until
+            let subsequent = RentPayment {
+                lease_id: 0,
+                renter,
+                amount: 30,
+                paid_at: 2 * SECONDS_IN_DAYS * 1000,
+                days_covered: 3,
+            };
+            let other_lease = RentPayment {
+                lease_id: 1,
+                renter,
+                amount: 5,
+                paid_at: 0,
+                days_covered: 1,
+            };
+            leasingmanager.record_rent_payment(beneficiary, initial);
+            leasingmanager.record_rent_payment(beneficiary, subsequent);
+            leasingmanager.record_rent_payment(renter, other_lease);
+
+            assert_eq!(
+                leasingmanager.get_renter_rent_history(renter),
+                vec![initial, subsequent, other_lease]
+            );
+            assert_eq!(leasingmanager.get_total_rent_paid_by(renter), 45);
+            assert_eq!(
+                leasingmanager.get_investor_rent_received(beneficiary),
+                vec![initial, subsequent]
+            );
+            assert_eq!(
+                leasingmanager.get_investor_rent_received(renter),
+                vec![other_lease]
+            );
+            assert_eq!(leasingmanager.get_total_rent_paid_by(beneficiary), 0);
+        }
+
+        #[ink::test]
+        fn renter_rating_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            // The default caller is the investor
+            let investor = AccountId::from([0x01; 32]);
+            let renter = AccountId::from([0x02; 32]);
+            assert_eq!(
+                leasingmanager.rate_renter(0, 5, vec![]),
+                Err(Error::NoSuchLease)
+            );
+            for lease_id in 0..3 {
+                leasingmanager.leases.insert(
+                    lease_id,
+                    Lease {
+                        id: lease_id,
+                        investor_address: investor,
+                        renter_address: Some(renter),
+                        status: LeaseStatus::Terminated as u8,
+                        ..Default::default()
+                    },
+                );
+            }
+            leasingmanager.leases.get_mut(&2).unwrap().status = LeaseStatus::Rented as u8;
+            assert_eq!(leasingmanager.get_renter_average_score(renter), 0);
+
+            assert_eq!(
+                leasingmanager.rate_renter(2, 5, vec![]),
+                Err(Error::LeaseNotTerminated)
+            );
+            assert_eq!(
+                leasingmanager.rate_renter(0, 0, vec![]),
+                Err(Error::InvalidScore)
+            );
+            assert_eq!(
+                leasingmanager.rate_renter(0, MAX_RATING_SCORE + 1, vec![]),
+                Err(Error::InvalidScore)
+            );
+            assert_eq!(
+                leasingmanager.rate_renter(0, 5, b"on time".to_vec()),
+                Ok(())
+            );
+            // Only one rating per lease
+            assert_eq!(
+                leasingmanager.rate_renter(0, 1, vec![]),
+                Err(Error::AlreadyRated)
+            );
+            assert_eq!(leasingmanager.get_renter_average_score(renter), 5);
+
+            // Average is rounded down
+            assert_eq!(leasingmanager.rate_renter(1, 2, vec![]), Ok(()));
+            assert_eq!(leasingmanager.get_renter_average_score(renter), 3);
+            let ratings = leasingmanager.get_renter_ratings(renter);
+            assert_eq!(ratings.len(), 2);
+            assert_eq!(ratings[0].review, b"on time".to_vec());
+            assert_eq!(ratings[1].score, 2);
+            assert_eq!(ratings[1].rater, investor);
+        }
+
+        #[ink::test]
+        fn security_deposit_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            // The default caller is the investor
+            let investor = AccountId::from([0x01; 32]);
+            let renter = AccountId::from([0x02; 32]);
+            assert_eq!(leasingmanager.disable(), Ok(()));
+            assert_eq!(
+                leasingmanager.list_token_with_deposit_requirement(
+                    AccountId::from([0x05; 32]),
+                    1,
+                    investor,
+                    10,
+                    30,
+                    100
+                ),
+                Err(Error::LeasingNotEnabled)
+            );
+            assert_eq!(leasingmanager.total_leases, 0);
+            assert_eq!(leasingmanager.enable(), Ok(()));
+            assert_eq!(
+                leasingmanager.set_security_deposit(0, 100),
+                Err(Error::NoSuchLease)
+            );
+            leasingmanager.leases.insert(
+                0,
+                Lease {
+                    id: 0,
+                    investor_address: investor,
+                    status: LeaseStatus::Available as u8,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(leasingmanager.set_security_deposit(0, 100), Ok(()));
+            assert_eq!(leasingmanager.list_lease(0).unwrap().security_deposit, 100);
+            // Renters cap the deposit they agree to
+            set_caller(renter);
+            assert_eq!(
+                leasingmanager.rent(0, 99),
+                Err(Error::SecurityDepositTooHigh)
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(leasingmanager.list_lease(0).unwrap().renter_address, None);
+
+            // Deposit is taken on rent
+            let lease = leasingmanager.leases.get_mut(&0).unwrap();
+            lease.status = LeaseStatus::Rented as u8;
+            lease.renter_address = Some(renter);
+            lease.deposit_held = true;
+            assert_eq!(
+                leasingmanager.set_security_deposit(0, 200),
+                Err(Error::LeaseUnavailable)
+            );
+            assert_eq!(
+                leasingmanager.collect_security_deposit(0),
+                Err(Error::LeaseNotTerminated)
+            );
+
+            leasingmanager.leases.get_mut(&0).unwrap().status = LeaseStatus::Terminated as u8;
+            assert_eq!(
+                leasingmanager.collect_security_deposit(0),
+                Err(Error::NoDefaultTermination)
+            );
+            assert_eq!(
+                leasingmanager.refund_security_deposit(0),
+                Err(Error::NotRenter)
+            );
+
+            set_caller(renter);
+            assert_eq!(
+                leasingmanager.collect_security_deposit(0),
+                Err(Error::NotInvestor)
+            );
+            leasingmanager
+                .leases
+                .get_mut(&0)
+                .unwrap()
+                .terminated_due_to_default = true;
+            assert_eq!(
+                leasingmanager.refund_security_deposit(0),
+                Err(Error::TerminatedDueToDefault)
+            );
+            ink_env::test::pop_execution_context();
+            assert_eq!(leasingmanager.locked, false);
+
+            let lease = leasingmanager.list_lease(0).unwrap();
+            assert_eq!(
+                LeasingManager::validate_deposit_release(&lease, true),
+                Ok(())
+            );
+            let lease = Lease {
+                deposit_held: false,
+                ..lease
+            };
+            assert_eq!(
+                LeasingManager::validate_deposit_release(&lease, true),
+                Err(Error::NoSecurityDeposit)
+            );
+        }
+
+        #[ink::test]
+        fn deposit_requirement_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            // The default caller is the investor
+            let investor = AccountId::from([0x01; 32]);
+            let renter = AccountId::from([0x02; 32]);
+            let nft_address = AccountId::from([0x05; 32]);
+            for (token_id, required_deposit) in [(1, 0), (2, 100), (3, 100)].iter() {
+                leasingmanager.record_listing(
+                    nft_address,
+                    *token_id,
+                    investor,
+                    10,
+                    30,
+                    false,
+                    *required_deposit,
+                );
+            }
+            assert_eq!(leasingmanager.total_leases, 3);
+            let lease = leasingmanager.list_lease(1).unwrap();
+            assert_eq!(lease.security_deposit, 100);
+            assert_eq!(lease.deposit_held, false);
+            assert_eq!(lease.investor_address, investor);
+
+            // rent only holds a deposit that the listing requires
+            for (lease_id, deposit_held) in [(0, false), (1, true), (2, true)].iter() {
+                let lease = leasingmanager.leases.get_mut(lease_id).unwrap();
+                lease.status = LeaseStatus::Rented as u8;
+                lease.renter_address = Some(renter);
+                lease.deposit_held = *deposit_held;
+            }
+            leasingmanager.lease_statistics.rented_count = 3;
+
+            // Zero deposit: nothing to release whichever way the lease ends
+            leasingmanager.mark_terminated(0);
+            set_caller(renter);
+            assert_eq!(
+                leasingmanager.refund_security_deposit(0),
+                Err(Error::NoSecurityDeposit)
+            );
+            ink_env::test::pop_execution_context();
+            leasingmanager
+                .leases
+                .get_mut(&0)
+                .unwrap()
+                .terminated_due_to_default = true;
+            assert_eq!(
+                leasingmanager.collect_security_deposit(0),
+                Err(Error::NoSecurityDeposit)
+            );
+
+            // Lease ending without default: the deposit goes back to the renter
+            leasingmanager.mark_terminated(1);
+            assert_eq!(
+                leasingmanager.collect_security_deposit(1),
+                Err(Error::NoDefaultTermination)
+            );
+            let lease = leasingmanager.list_lease(1).unwrap();
+            assert_eq!(
+                LeasingManager::validate_deposit_release(&lease, false),
+                Ok(())
+            );
+
+            // Lease terminated on default: the deposit goes to the investor
+            leasingmanager.mark_terminated(2);
+            leasingmanager
+                .leases
+                .get_mut(&2)
+                .unwrap()
+                .terminated_due_to_default = true;
+            set_caller(renter);
+            assert_eq!(
+                leasingmanager.refund_security_deposit(2),
+                Err(Error::TerminatedDueToDefault)
+            );
+            ink_env::test::pop_execution_context();
+            let lease = leasingmanager.list_lease(2).unwrap();
+            assert_eq!(
+                LeasingManager::validate_deposit_release(&lease, true),
+                Ok(())
+            );
+            assert_eq!(leasingmanager.locked, false);
+        }
+
+        #[ink::test]
+        fn fixed_term_lease_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let investor = AccountId::from([0x01; 32]);
+            let renter = AccountId::from([0x02; 32]);
+            for (lease_id, fixed_term) in [(0, true), (1, false)].iter() {
+                leasingmanager.leases.insert(
+                    *lease_id,
+                    Lease {
+                        id: *lease_id,
+                        investor_address: investor,
+                        renter_address: Some(renter),
+                        lease_duration: 5,
+                        leased_at: Some(0),
+                        lease_paid_until: Some(SECONDS_IN_DAYS * 1000),
+                        status: LeaseStatus::Rented as u8,
+                        fixed_term: *fixed_term,
+                        ..Default::default()
+                    },
+                );
+            }
+
+            // Term is over once leased_at + lease_duration < current time
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                    .expect("Cannot advance block");
+            }
+            let fixed = *leasingmanager.leases.get(&0).unwrap();
+            let standard = *leasingmanager.leases.get(&1).unwrap();
+            assert!(LeasingManager::term_expired(&fixed));
+            assert!(!LeasingManager::term_expired(&standard));
+
+            // Rent can't be paid after the fixed term
+            assert_eq!(leasingmanager.pay_rent(0), Err(Error::LeaseExpired));
+            assert_eq!(leasingmanager.locked, false);
+
+            // Investor can take back the token once the fixed term is over,
+            // even though the renter is up to date with rent
+            assert_eq!(LeasingManager::validate_termination(&fixed), Ok(()));
+        }
+
+        #[ink::test]
+        fn active_lease_counts_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let investor = AccountId::from([0x01; 32]);
+            let renter = AccountId::from([0x02; 32]);
+            assert_eq!(leasingmanager.investors_total_active_leases(investor), 0);
+            assert_eq!(leasingmanager.renters_total_active_leases(renter), 0);
+
+            // Mirrors the storage updates of list_token, rent, terminate
+            // and remove_token which cannot be called off-chain
+            for lease_id in 0..3 {
+                leasingmanager.leases.insert(
+                    lease_id,
+                    Lease {
+                        id: lease_id,
+                        investor_address: investor,
+                        status: LeaseStatus::Available as u8,
+                        ..Default::default()
+                    },
+                );
+                LeasingManager::increment_active_count(
+                    &mut leasingmanager.investor_active_count,
+                    investor,
+                );
+            }
+            leasingmanager.investors.insert(investor, vec![0, 1, 2]);
+            assert_eq!(leasingmanager.investors_total_active_leases(investor), 3);
+            assert_eq!(
+                leasingmanager.get_active_leased_assets(investor),
+                vec![0, 1, 2]
+            );
+
+            // rent leases 0 and 1
+            for lease_id in 0..2 {
+                let lease = leasingmanager.leases.get_mut(&lease_id).unwrap();
+                lease.status = LeaseStatus::Rented as u8;
+                lease.renter_address = Some(renter);
+                LeasingManager::increment_active_count(
+                    &mut leasingmanager.renter_active_count,
+                    renter,
+                );
+            }
+            leasingmanager.renters.insert(renter, vec![0, 1]);
+            assert_eq!(leasingmanager.renters_total_active_leases(renter), 2);
+            assert_eq!(leasingmanager.get_active_rented_assets(renter), vec![0, 1]);
+            assert_eq!(leasingmanager.investors_total_active_leases(investor), 3);
+
+            // terminate lease 0
+            leasingmanager.leases.get_mut(&0).unwrap().status = LeaseStatus::Terminated as u8;
+            LeasingManager::decrement_active_count(
+                &mut leasingmanager.investor_active_count,
+                investor,
+            );
+            LeasingManager::decrement_active_count(&mut leasingmanager.renter_active_count, renter);
+            assert_eq!(leasingmanager.renters_total_active_leases(renter), 1);
+            assert_eq!(leasingmanager.get_active_rented_assets(renter), vec![1]);
+            assert_eq!(leasingmanager.investors_total_active_leases(investor), 2);
+            assert_eq!(
+                leasingmanager.get_active_leased_assets(investor),
+                vec![1, 2]
+            );
+
+            // remove lease 2
+            leasingmanager.leases.get_mut(&2).unwrap().status = LeaseStatus::Removed as u8;
+            LeasingManager::decrement_active_count(
+                &mut leasingmanager.investor_active_count,
+                investor,
+            );
+            assert_eq!(leasingmanager.investors_total_active_leases(investor), 1);
+            assert_eq!(leasingmanager.get_active_leased_assets(investor), vec![1]);
+            // History is kept
+            assert_eq!(leasingmanager.get_leased_assets(investor), vec![0, 1, 2]);
+            assert_eq!(leasingmanager.get_rented_assets(renter), vec![0, 1]);
+
+            // Handing the rented lease over moves its count to the new investor
+            let new_investor = AccountId::from([0x03; 32]);
+            assert_eq!(
+                leasingmanager.transfer_lease_ownership(1, new_investor, None),
+                Ok(())
+            );
+            assert_eq!(leasingmanager.investors_total_active_leases(investor), 0);
+            assert_eq!(
+                leasingmanager.investors_total_active_leases(new_investor),
+                1
+            );
+        }
+
+        #[ink::test]
+        fn contract_version_works() {
+            let leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            assert_eq!(leasingmanager.get_contract_version(), VERSION);
+            assert_eq!(leasingmanager.get_contract_version(), (1, 0, 0));
+            assert_eq!(
+                leasingmanager.get_contract_name(),
+                String::from("LeasingManager")
+            );
+        }
+
+        #[ink::test]
+        fn enable_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), false);
             assert_eq!(leasingmanager.is_enabled(), false);
 
-            leasingmanager.enable();
+            assert_eq!(leasingmanager.enable(), Ok(()));
             assert_eq!(leasingmanager.is_enabled(), true);
         }
 
@@ -643,10 +3000,21 @@ mod leasingmanager {
             let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
             assert_eq!(leasingmanager.is_enabled(), true);
 
-            leasingmanager.disable();
+            assert_eq!(leasingmanager.disable(), Ok(()));
             assert_eq!(leasingmanager.is_enabled(), false);
         }
 
+        #[ink::test]
+        fn renounce_ownership_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            assert_eq!(leasingmanager.renounce_ownership(), Ok(()));
+            assert_eq!(leasingmanager.is_owner(), false);
+
+            assert_eq!(leasingmanager.enable(), Err(Error::ContractImmutable));
+            assert_eq!(leasingmanager.disable(), Err(Error::ContractImmutable));
+            assert_eq!(leasingmanager.is_enabled(), true);
+        }
+
         #[ink::test]
         fn lease_duration_works() {
             assert_eq!(
@@ -694,5 +3062,99 @@ mod leasingmanager {
                 2
             );
         }
+
+        #[ink::test]
+        fn lease_duration_edge_cases_work() {
+            // A timestamp before leased_at doesn't underflow
+            assert_eq!(
+                LeasingManager::duration_in_days(0, SECONDS_IN_DAYS * 1000),
+                0
+            );
+            assert_eq!(
+                LeasingManager::duration_in_days(SECONDS_IN_DAYS * 1000, SECONDS_IN_DAYS * 1000),
+                0
+            );
+
+            // Partial days are charged as full days
+            assert_eq!(LeasingManager::duration_in_days(1000, 0), 1);
+            assert_eq!(
+                LeasingManager::duration_in_days(SECONDS_IN_DAYS / 2 * 1000, 0),
+                1
+            );
+            assert_eq!(
+                LeasingManager::duration_in_days((SECONDS_IN_DAYS * 2 + 1) * 1000, 0),
+                3
+            );
+        }
+
+        #[ink::test]
+        fn next_payment_works() {
+            let mut leasingmanager = LeasingManager::new(instantiate_erc20_contract(), true);
+            let day = SECONDS_IN_DAYS * 1000;
+            assert_eq!(
+                leasingmanager.get_next_payment_amount_and_date(0),
+                Err(Error::NoSuchLease)
+            );
+            leasingmanager.leases.insert(
+                0,
+                Lease {
+                    id: 0,
+                    daily_rent: 10,
+                    status: LeaseStatus::Available as u8,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(
+                leasingmanager.get_next_payment_amount_and_date(0),
+                Err(Error::LeaseNotRented)
+            );
+
+            // Current renters owe a single day ahead
+            let lease = leasingmanager.leases.get_mut(&0).unwrap();
+            lease.status = LeaseStatus::Rented as u8;
+            lease.lease_paid_until = Some(3 * day);
+            assert_eq!(
+                leasingmanager.get_next_payment_amount_and_date(0),
+                Ok((10, 3 * day))
+            );
+            let lease = *leasingmanager.leases.get(&0).unwrap();
+            assert_eq!(LeasingManager::next_rent_payment(&lease, 3 * day), (1, 10));
+
+            // Overdue renters owe every started day since lease_paid_until
+            assert_eq!(
+                LeasingManager::next_rent_payment(&lease, 3 * day + 1),
+                (1, 10)
+            );
+            assert_eq!(LeasingManager::next_rent_payment(&lease, 5 * day), (2, 20));
+            assert_eq!(
+                LeasingManager::next_rent_payment(&lease, 5 * day + 1000),
+                (3, 30)
+            );
+            leasingmanager.leases.get_mut(&0).unwrap().lease_paid_until = Some(0);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(
+                leasingmanager.get_next_payment_amount_and_date(0),
+                Ok((10, 0))
+            );
+        }
+
+        #[ink::test]
+        fn is_defaulter_works() {
+            // Available leases have never been paid for
+            let lease = Lease {
+                status: LeaseStatus::Available as u8,
+                ..Default::default()
+            };
+            assert_eq!(LeasingManager::is_defaulter(&lease), false);
+
+            // Within the grace period at the start of the chain
+            let lease = Lease {
+                status: LeaseStatus::Rented as u8,
+                lease_paid_until: Some(0),
+                ..Default::default()
+            };
+            assert_eq!(LeasingManager::is_defaulter(&lease), false);
+        }
     }
 }
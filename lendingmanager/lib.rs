@@ -4,11 +4,12 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod lendingmanager {
+    use addressmanager::{AddressManager, ERC20, ERC721};
     use erc20::Erc20;
     use erc721::Erc721;
 
     use ink_env::call::FromAccountId;
-    use ink_prelude::vec::Vec;
+    use ink_prelude::{string::String, vec::Vec};
     use ink_storage::{
         collections::HashMap as StorageHashMap,
         traits::{PackedLayout, SpreadLayout, StorageLayout},
@@ -18,6 +19,7 @@ mod lendingmanager {
 
     type TokenId = u32;
     type LoanId = u64;
+    type TemplateId = u64;
     #[derive(Encode, Decode, Debug, Default, Copy, Clone, SpreadLayout)]
     #[cfg_attr(feature = "std", derive(StorageLayout))]
     struct Ownable {
@@ -28,6 +30,8 @@ mod lendingmanager {
     pub struct Administration {
         interest_rate: u64,
         enabled: bool,
+        paused: bool,
+        whitelist_enabled: bool,
     }
 
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
@@ -47,6 +51,25 @@ mod lendingmanager {
         ERC721TransferFailed,
         ERC20TransferFailed,
         InsufficientBalance,
+        ContractImmutable,
+        Reentrant,
+        NotEnabled,
+        NoSuchLoan,
+        LoanUnavailable,
+        LoanNotBorrowed,
+        NotBorrower,
+        NotInvestor,
+        LoanAmountTooSmall,
+        InterestRateTooSmall,
+        ContractPaused,
+        NoSuchListing,
+        LoanAlreadyListed,
+        NotSeller,
+        NoSuchRestructure,
+        NFTNotWhitelisted,
+        NoSuchTemplate,
+        AddressNotFound,
+        RestructureMismatch,
     }
 
     #[derive(Clone, Default, Copy, Encode, Decode, Debug, SpreadLayout, PackedLayout)]
@@ -67,6 +90,83 @@ mod lendingmanager {
         interest_rate: u64,
     }
 
+    /// Summary of an account's borrowing for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LendingBorrowerStats {
+        active_loans: u32,
+        total_loans: u32,
+        total_borrowed: u128,
+        total_repaid: u128,
+        total_outstanding: u128,
+    }
+
+    /// Summary of an account's lending for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LendingInvestorStats {
+        funded_loans: u32,
+        outstanding_capital: u128,
+        accrued_interest: u128,
+    }
+
+    /// Lending parameters for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LendingConfig {
+        interest_rate: u64,
+        enabled: bool,
+    }
+
+    /// Protocol wide loan counters for rendering in a single query
+    #[derive(Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LoanOriginationStats {
+        total_loans_created: u64,
+        total_loans_funded: u64,
+        total_loans_repaid: u64,
+        total_loans_liquidated: u64,
+        total_loans_cancelled: u64,
+        total_volume_funded: u128,
+    }
+
+    /// Investor position in a funded loan offered for sale
+    #[derive(
+        Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct SecondaryListing {
+        loan_id: LoanId,
+        seller: AccountId,
+        ask_price: Balance,
+        created_at: u64,
+    }
+
+    /// Standard terms loans can be listed with
+    #[derive(
+        Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct LoanTemplate {
+        id: TemplateId,
+        interest_rate: u64,
+        duration: u64,
+        nft_address: AccountId,
+        creator: AccountId,
+    }
+
+    /// New terms the borrower asked the investor of a funded loan to accept
+    #[derive(
+        Encode, Decode, Debug, Default, Copy, Clone, PartialEq, Eq, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct PendingRestructure {
+        loan_id: LoanId,
+        new_rate: u64,
+        new_duration: u64,
+        proposed_by_borrower_at: u64,
+    }
+
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
     /// to add new static storage fields to your contract.
@@ -80,6 +180,21 @@ mod lendingmanager {
         total_loans: u32,
         erc20: Lazy<Erc20>,
         erc721: Lazy<Erc721>,
+        address_manager: Option<AddressManager>,
+        locked: bool,
+        secondary_listings: StorageHashMap<u64, SecondaryListing>,
+        total_secondary_listings: u64,
+        nft_loans: StorageHashMap<AccountId, Vec<LoanId>>,
+        pending_restructures: StorageHashMap<LoanId, PendingRestructure>,
+        approved_nfts: StorageHashMap<AccountId, bool>,
+        total_loans_created: u64,
+        total_loans_funded: u64,
+        total_loans_repaid: u64,
+        total_loans_liquidated: u64,
+        total_loans_cancelled: u64,
+        total_volume_funded: u128,
+        templates: StorageHashMap<TemplateId, LoanTemplate>,
+        total_templates: u64,
     }
 
     #[ink(event)]
@@ -95,6 +210,22 @@ mod lendingmanager {
         loan_duration: u64,
     }
 
+    #[ink(event)]
+    pub struct LoanAmountUpdated {
+        #[ink(topic)]
+        loan_id: LoanId,
+        old_amount: u64,
+        new_amount: u64,
+    }
+
+    #[ink(event)]
+    pub struct LoanInterestRateUpdated {
+        #[ink(topic)]
+        loan_id: LoanId,
+        old_rate: u64,
+        new_rate: u64,
+    }
+
     #[ink(event)]
     pub struct LoanBorrowed {
         #[ink(topic)]
@@ -139,12 +270,91 @@ mod lendingmanager {
         token_id: u32,
     }
 
+    #[ink(event)]
+    pub struct SecondaryListingCreated {
+        #[ink(topic)]
+        listing_id: u64,
+        #[ink(topic)]
+        loan_id: LoanId,
+        #[ink(topic)]
+        seller: AccountId,
+        ask_price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SecondaryPurchased {
+        #[ink(topic)]
+        listing_id: u64,
+        #[ink(topic)]
+        loan_id: LoanId,
+        #[ink(topic)]
+        buyer: AccountId,
+        seller: AccountId,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SecondaryCancelled {
+        #[ink(topic)]
+        listing_id: u64,
+        #[ink(topic)]
+        loan_id: LoanId,
+    }
+
+    #[ink(event)]
+    pub struct RestructureProposed {
+        #[ink(topic)]
+        loan_id: LoanId,
+        #[ink(topic)]
+        borrower: AccountId,
+        new_rate: u64,
+        new_duration: u64,
+    }
+
+    #[ink(event)]
+    pub struct RestructureApproved {
+        #[ink(topic)]
+        loan_id: LoanId,
+        #[ink(topic)]
+        investor: AccountId,
+        new_rate: u64,
+        new_duration: u64,
+    }
+
+    #[ink(event)]
+    pub struct RestructureRejected {
+        #[ink(topic)]
+        loan_id: LoanId,
+        #[ink(topic)]
+        investor: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct TemplateListed {
+        #[ink(topic)]
+        template_id: TemplateId,
+    }
+
+    #[ink(event)]
+    pub struct LoanListedFromTemplate {
+        #[ink(topic)]
+        template_id: TemplateId,
+        #[ink(topic)]
+        loan_id: LoanId,
+    }
+
     #[ink(event)]
     pub struct Enabled {}
 
     #[ink(event)]
     pub struct Disbaled {}
 
+    #[ink(event)]
+    pub struct Paused {}
+
+    #[ink(event)]
+    pub struct Unpaused {}
+
     #[ink(event)]
     pub struct InterestRateChanged {
         #[ink(topic)]
@@ -161,6 +371,18 @@ mod lendingmanager {
         to: AccountId,
     }
 
+    #[ink(event)]
+    pub struct OwnershipRenounced {
+        #[ink(topic)]
+        former_owner: AccountId,
+    }
+
+    pub const MAX_BATCH_LEND: usize = 20;
+
+    pub const VERSION: (u32, u32, u32) = (1, 0, 0);
+    // Fails to compile unless the minor and patch versions are below 1000
+    const _: [(); 0 - !(VERSION.1 < 1000 && VERSION.2 < 1000) as usize] = [];
+
     impl LendingManager {
         /// Constructors can delegate to other constructors.
         #[ink(constructor)]
@@ -180,6 +402,8 @@ mod lendingmanager {
                 administration: Administration {
                     interest_rate,
                     enabled,
+                    paused: false,
+                    whitelist_enabled: false,
                 },
                 loans: Default::default(),
                 investors: Default::default(),
@@ -187,10 +411,56 @@ mod lendingmanager {
                 total_loans: 0,
                 erc20: Lazy::new(erc20),
                 erc721: Lazy::new(erc721),
+                address_manager: None,
+                locked: false,
+                secondary_listings: Default::default(),
+                total_secondary_listings: 0,
+                nft_loans: Default::default(),
+                pending_restructures: Default::default(),
+                approved_nfts: Default::default(),
+                total_loans_created: 0,
+                total_loans_funded: 0,
+                total_loans_repaid: 0,
+                total_loans_liquidated: 0,
+                total_loans_cancelled: 0,
+                total_volume_funded: 0,
+                templates: Default::default(),
+                total_templates: 0,
             };
             instance
         }
 
+        /// Looks up the erc20 and erc721 addresses through the address manager on every call
+        #[ink(constructor)]
+        pub fn new_with_address_manager(
+            address_manager: AccountId,
+            interest_rate: u64,
+            enabled: bool,
+        ) -> Self {
+            let address_manager = AddressManager::from_account_id(address_manager);
+            let erc20_address = address_manager
+                .require_address(String::from(ERC20))
+                .expect("ERC20 address not registered");
+            let erc721_address = address_manager
+                .require_address(String::from(ERC721))
+                .expect("ERC721 address not registered");
+            let mut instance = Self::new(erc20_address, erc721_address, interest_rate, enabled);
+            instance.address_manager = Some(address_manager);
+            instance
+        }
+
+        /// Returns the (major, minor, patch) version of the contract
+        #[ink(message)]
+        pub fn get_contract_version(&self) -> (u32, u32, u32) {
+            VERSION
+        }
+
+        /// Returns the name of the contract
+        #[ink(message)]
+        pub fn get_contract_name(&self) -> String {
+            String::from("LendingManager")
+        }
+
         /// Checks if caller is owner of AssetManager contract
         #[ink(message)]
         pub fn is_owner(&self) -> bool {
@@ -217,10 +487,41 @@ mod lendingmanager {
             true
         }
 
+        /// Permanently removes the owner making all parameters immutable
+        /// Can only be called by the current owner
+        #[ink(message)]
+        pub fn renounce_ownership(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            let caller = self.env().caller();
+            assert!(self.only_owner(caller));
+            self.owner.owner = AccountId::from([0x0; 32]);
+            self.env().emit_event(OwnershipRenounced {
+                former_owner: caller,
+            });
+            Ok(())
+        }
+
         fn only_owner(&self, caller: AccountId) -> bool {
             caller == self.owner.owner
         }
-        
+
+        fn ensure_mutable(&self) -> Result<(), Error> {
+            if self.owner.owner == AccountId::from([0x0; 32]) {
+                return Err(Error::ContractImmutable);
+            }
+            Ok(())
+        }
+
+        /// Guards against re-entrant calls from the token contracts
+        /// Callers must reset `locked` before returning
+        fn lock(&mut self) -> Result<(), Error> {
+            if self.locked {
+                return Err(Error::Reentrant);
+            }
+            self.locked = true;
+            Ok(())
+        }
+
         /// To list your token for lending
         #[ink(message)]
         pub fn list_token(
@@ -231,200 +532,594 @@ mod lendingmanager {
             loan_amount: u64,
             loan_duration: u64,
         ) -> Result<(), Error> {
-            assert_eq!(self.is_enabled(), true, "Listing is not enabled");
-            let caller = self.env().caller();
-            let contract_address = self.env().account_id();
-            
-            // Transfer tokens from caller to contract
-
-            let erc721_transfer = self
-                .erc721
-                .transfer_from(caller, contract_address, token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
-
-            let loan_id = self.total_loans as LoanId;
-            // Add trade into current active list
-            let loan = Loan {
-                id: loan_id,
-                amount: loan_amount,
-                nft_address: erc721_address,
-                token_id: token_id,
-                borrower_address: caller,
-                beneficiary_address: beneficiary_address,
-                investor_address: None,
-                status: LoanStatus::Available as u8,
-                duration: loan_duration,
-                created_at: self.get_current_time(),
-                fulfilled_at: None,
-                repaid_at: None,
-                interest_rate: self.administration.interest_rate,
-            };
-
-            self.loans.insert(loan_id, loan);
-            self.total_loans += 1;
+            let loan = self.new_loan(
+                erc721_address,
+                token_id,
+                beneficiary_address,
+                loan_amount,
+                loan_duration,
+                self.administration.interest_rate,
+            );
+            self.handle_list_token(loan)
+        }
 
-            let mut borrowed: Vec<LoanId> = Vec::new();
-            let borrower_opt = self.borrowers.get_mut(&caller);
-            if borrower_opt.is_some() {
-                borrowed = borrower_opt.unwrap().to_vec();
+        /// Stores standard terms anyone can list loans with
+        #[ink(message)]
+        pub fn create_loan_template(
+            &mut self,
+            interest_rate: u64,
+            duration: u64,
+            nft_address: AccountId,
+        ) -> Result<TemplateId, Error> {
+            if interest_rate == 0 {
+                return Err(Error::InterestRateTooSmall);
             }
-            borrowed.push(loan_id);
+            let template_id = self.total_templates;
+            self.templates.insert(
+                template_id,
+                LoanTemplate {
+                    id: template_id,
+                    interest_rate,
+                    duration,
+                    nft_address,
+                    creator: self.env().caller(),
+                },
+            );
+            self.total_templates += 1;
+
+            self.env().emit_event(TemplateListed { template_id });
+            Ok(template_id)
+        }
 
-            self.borrowers.insert(caller, borrowed);
+        /// To list your token for lending with the interest rate, duration and nft address
+        /// of a template
+        #[ink(message)]
+        pub fn list_token_from_template(
+            &mut self,
+            template_id: u64,
+            token_id: TokenId,
+            beneficiary_address: AccountId,
+            loan_amount: u64,
+        ) -> Result<(), Error> {
+            let loan =
+                self.loan_from_template(template_id, token_id, beneficiary_address, loan_amount)?;
+            let loan_id = loan.id;
+            self.handle_list_token(loan)?;
+
+            self.env().emit_event(LoanListedFromTemplate {
+                template_id,
+                loan_id,
+            });
             Ok(())
         }
-        
+
+        /// Returns the template with the given id
+        #[ink(message)]
+        pub fn get_template(&self, id: TemplateId) -> Option<LoanTemplate> {
+            self.templates.get(&id).copied()
+        }
+
+        #[ink(message)]
+        pub fn list_templates(&self) -> Vec<LoanTemplate> {
+            let mut templates: Vec<LoanTemplate> = Vec::new();
+
+            for (_i, template) in self.templates.iter() {
+                templates.push(*template);
+            }
+            templates
+        }
+
         /// Lend vt against NFT as collateral
         #[ink(message)]
         pub fn lend(&mut self, loan_id: u64) -> Result<(), Error> {
-            assert_eq!(self.is_enabled(), true, "Lending is not enabled");
+            self.require_not_paused()?;
+            self.lock()?;
+            if !self.is_enabled() {
+                self.locked = false;
+                return Err(Error::NotEnabled);
+            }
             let current_time = self.get_current_time();
             let caller = self.env().caller();
+            let contract_address = self.env().account_id();
 
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
             let loan_opt = self.loans.get_mut(&loan_id);
-            assert_eq!(loan_opt.is_some(), true, "Loan not available");
+            if loan_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLoan);
+            }
 
             let loan = loan_opt.unwrap();
+            // Funded, repaid and cancelled loans can't be lent against again
+            if loan.status != LoanStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::LoanUnavailable);
+            }
+
+            if let Err(error) = Self::check_allowance_sufficient(
+                &erc20,
+                caller,
+                contract_address,
+                loan.amount as u128,
+            ) {
+                self.locked = false;
+                return Err(error);
+            }
 
             // Transfer tokens to contract
             let erc20_transfer =
-                self.erc20
-                    .transfer_from(caller, loan.beneficiary_address, loan.amount as u128);
-            assert_eq!(erc20_transfer.is_ok(), true, "ERC20 Token transfer failed");
+                erc20.transfer_from(caller, loan.beneficiary_address, loan.amount as u128);
+            if erc20_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
 
-            // Mark loan as done
-            loan.investor_address = Some(caller);
-            loan.fulfilled_at = Some(current_time);
-            loan.status = LoanStatus::Borrowed as u8;
+            self.handle_lend(loan_id, caller, current_time);
 
-            let mut lent: Vec<LoanId> = Vec::new();
-            let investor_opt = self.investors.get_mut(&caller);
-            if investor_opt.is_some() {
-                lent = investor_opt.unwrap().to_vec();
-            }
-            lent.push(loan_id);
+            self.locked = false;
+            Ok(())
+        }
 
-            self.investors.insert(caller, lent);
+        /// Allows borrower to change the amount asked for a loan that is not funded yet
+        #[ink(message)]
+        pub fn update_loan_amount(&mut self, loan_id: u64, new_amount: u64) -> Result<(), Error> {
+            self.require_not_paused()?;
+            if new_amount == 0 {
+                return Err(Error::LoanAmountTooSmall);
+            }
+            let loan = self.get_updatable_loan(loan_id)?;
+            let old_amount = loan.amount;
+            loan.amount = new_amount;
+            self.env().emit_event(LoanAmountUpdated {
+                loan_id,
+                old_amount,
+                new_amount,
+            });
+            Ok(())
+        }
 
+        /// Allows borrower to change the interest rate offered on a loan that is not funded yet
+        #[ink(message)]
+        pub fn update_loan_interest_rate(
+            &mut self,
+            loan_id: u64,
+            new_rate: u64,
+        ) -> Result<(), Error> {
+            self.require_not_paused()?;
+            if new_rate == 0 {
+                return Err(Error::InterestRateTooSmall);
+            }
+            let loan = self.get_updatable_loan(loan_id)?;
+            let old_rate = loan.interest_rate;
+            loan.interest_rate = new_rate;
+            self.env().emit_event(LoanInterestRateUpdated {
+                loan_id,
+                old_rate,
+                new_rate,
+            });
             Ok(())
         }
 
+        /// Funds several loans at once
+        /// Each loan succeeds or fails independently and the outcomes are returned in order
+        /// Amounts going to the same beneficiary are paid in a single transfer
+        #[ink(message)]
+        pub fn batch_lend(&mut self, loan_ids: Vec<LoanId>) -> Vec<Result<(), Error>> {
+            assert!(loan_ids.len() <= MAX_BATCH_LEND, "Too many loans in batch");
+            if let Err(error) = self.require_not_paused().and_then(|_| self.lock()) {
+                return loan_ids.iter().map(|_| Err(error)).collect();
+            }
+            let current_time = self.get_current_time();
+            let caller = self.env().caller();
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return loan_ids.iter().map(|_| Err(error)).collect();
+                }
+            };
+            let mut results = self.validate_batch_lend(&loan_ids);
+
+            let mut transfers: Vec<(AccountId, Balance)> = Vec::new();
+            for (loan_id, result) in loan_ids.iter().zip(results.iter()) {
+                if result.is_err() {
+                    continue;
+                }
+                let loan = self.loans.get(loan_id).unwrap();
+                match transfers
+                    .iter_mut()
+                    .find(|(beneficiary, _)| *beneficiary == loan.beneficiary_address)
+                {
+                    Some((_, amount)) => *amount += loan.amount as Balance,
+                    None => transfers.push((loan.beneficiary_address, loan.amount as Balance)),
+                }
+            }
+
+            for (beneficiary, amount) in transfers {
+                if erc20.transfer_from(caller, beneficiary, amount).is_ok() {
+                    continue;
+                }
+                for (loan_id, result) in loan_ids.iter().zip(results.iter_mut()) {
+                    if result.is_ok()
+                        && self.loans.get(loan_id).unwrap().beneficiary_address == beneficiary
+                    {
+                        *result = Err(Error::ERC20TransferFailed);
+                    }
+                }
+            }
+
+            for (loan_id, result) in loan_ids.iter().zip(results.iter()) {
+                if result.is_ok() {
+                    self.handle_lend(*loan_id, caller, current_time);
+                }
+            }
+
+            self.locked = false;
+            results
+        }
+
         #[ink(message)]
         pub fn expire_loan(&mut self, loan_id: u64) -> Result<(), Error> {
+            self.require_not_paused()?;
+            self.lock()?;
             let caller = self.env().caller();
             let contract_address = self.env().account_id();
 
             let loan_opt = self.loans.get_mut(&loan_id);
-            assert_eq!(loan_opt.is_some(), true, "Loan not available");
+            if loan_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLoan);
+            }
 
             let loan = loan_opt.unwrap();
-            assert_eq!(loan.borrower_address, caller, "Only owner can expire loan");
-            assert_eq!(
-                loan.status,
-                LoanStatus::Available as u8,
-                "Only non-fulfilled loans can be expired"
-            );
+            if loan.borrower_address != caller {
+                self.locked = false;
+                return Err(Error::NotBorrower);
+            }
+            if loan.status != LoanStatus::Available as u8 {
+                self.locked = false;
+                return Err(Error::LoanUnavailable);
+            }
 
             //Transfer token back to seller
-            let erc721_transfer =
-                self.erc721
-                    .transfer_from(contract_address, caller, loan.token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            let mut erc721 = Self::get_nft(loan.nft_address);
+            let erc721_transfer = erc721.transfer_from(contract_address, caller, loan.token_id);
+            if erc721_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC721TransferFailed);
+            }
 
-            loan.status = LoanStatus::Cancelled as u8;
+            self.close_loan(loan_id, LoanStatus::Cancelled, self.get_current_time());
 
+            self.locked = false;
             Ok(())
         }
 
         #[ink(message)]
         pub fn withdraw(&mut self, loan_id: u64) -> Result<(), Error> {
+            self.require_not_paused()?;
+            self.lock()?;
             let caller = self.env().caller();
             let current_time = self.get_current_time();
 
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
             let loan_opt = self.loans.get_mut(&loan_id);
-            assert_eq!(loan_opt.is_some(), true, "Loan not available");
+            if loan_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLoan);
+            }
 
             let loan = loan_opt.unwrap();
-            assert_eq!(
-                loan.borrower_address, caller,
-                "Only owner can withdraw loan"
-            );
-            assert_eq!(
-                loan.status,
-                LoanStatus::Borrowed as u8,
-                "Only borrowed loans can be withdrawn"
-            );
+            if loan.borrower_address != caller {
+                self.locked = false;
+                return Err(Error::NotBorrower);
+            }
+            if loan.status != LoanStatus::Borrowed as u8 {
+                self.locked = false;
+                return Err(Error::LoanNotBorrowed);
+            }
 
             // Calculate interest
-            let final_amount = Self::calculate_interest(
-                loan.amount as u128,
-                10,
-                current_time,
-                loan.fulfilled_at.unwrap(),
-            ) + loan.amount as u128;
+            let final_amount = Self::repayment_amount(loan, current_time);
 
             // Transfer tokens to contract
             let erc20_transfer =
-                self.erc20
-                    .transfer_from(caller, loan.investor_address.unwrap(), final_amount);
-            assert_eq!(erc20_transfer.is_ok(), true, "ERC20 Token transfer failed");
+                erc20.transfer_from(caller, loan.investor_address.unwrap(), final_amount);
+            if erc20_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
 
+            // The investor has been repaid: a failed nft transfer must revert the whole call
             // Transfer nft to borrower
-            let erc721_transfer = self.erc721.transfer(caller, loan.token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            let mut erc721 = Self::get_nft(loan.nft_address);
+            let erc721_transfer = erc721.transfer(caller, loan.token_id);
+            assert!(erc721_transfer.is_ok(), "Nft transfer failed");
 
             // Mark loan as done
-            loan.status = LoanStatus::Repaid as u8;
-            loan.repaid_at = Some(current_time);
+            self.close_loan(loan_id, LoanStatus::Repaid, current_time);
 
+            self.locked = false;
             Ok(())
         }
 
         #[ink(message)]
         pub fn liquidate(&mut self, loan_id: u64) -> Result<(), Error> {
+            self.require_not_paused()?;
+            self.lock()?;
             let caller = self.env().caller();
 
             let loan_opt = self.loans.get_mut(&loan_id);
-            assert_eq!(loan_opt.is_some(), true, "Loan not available");
+            if loan_opt.is_none() {
+                self.locked = false;
+                return Err(Error::NoSuchLoan);
+            }
 
             let loan = loan_opt.unwrap();
-            assert_eq!(
-                loan.investor_address.unwrap(),
-                caller,
-                "Only lender can liquidate loan"
-            );
-            assert_eq!(
-                loan.status,
-                LoanStatus::Borrowed as u8,
-                "Only borrowed loans can be liquidated"
-            );
+            if loan.investor_address != Some(caller) {
+                self.locked = false;
+                return Err(Error::NotInvestor);
+            }
+            if loan.status != LoanStatus::Borrowed as u8 {
+                self.locked = false;
+                return Err(Error::LoanNotBorrowed);
+            }
 
             // Transfer nft to borrower
-            let erc721_transfer = self.erc721.transfer(caller, loan.token_id);
-            assert_eq!(
-                erc721_transfer.is_ok(),
-                true,
-                "ERC721 Token transfer failed"
-            );
+            let mut erc721 = Self::get_nft(loan.nft_address);
+            let erc721_transfer = erc721.transfer(caller, loan.token_id);
+            if erc721_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC721TransferFailed);
+            }
 
             // Mark loan as done
-            loan.status = LoanStatus::Liquidated as u8;
+            self.close_loan(loan_id, LoanStatus::Liquidated, self.get_current_time());
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows investor of a funded loan to offer their position for ask_price
+        /// The borrower's obligations are not affected by the sale
+        #[ink(message)]
+        pub fn secondary_market_list(
+            &mut self,
+            loan_id: u64,
+            ask_price: Balance,
+        ) -> Result<(), Error> {
+            self.require_not_paused()?;
+            let caller = self.env().caller();
+            let current_time = self.get_current_time();
+
+            let loan = self.loans.get(&loan_id).ok_or(Error::NoSuchLoan)?;
+            if loan.status != LoanStatus::Borrowed as u8 {
+                return Err(Error::LoanNotBorrowed);
+            }
+            if loan.investor_address != Some(caller) {
+                return Err(Error::NotInvestor);
+            }
+            if self.get_secondary_listing_of(loan_id).is_some() {
+                return Err(Error::LoanAlreadyListed);
+            }
+
+            let listing_id = self.total_secondary_listings;
+            self.secondary_listings.insert(
+                listing_id,
+                SecondaryListing {
+                    loan_id,
+                    seller: caller,
+                    ask_price,
+                    created_at: current_time,
+                },
+            );
+            self.total_secondary_listings += 1;
+
+            self.env().emit_event(SecondaryListingCreated {
+                listing_id,
+                loan_id,
+                seller: caller,
+                ask_price,
+            });
+            Ok(())
+        }
+
+        /// Buys a listed loan position, paying ask_price to the seller
+        /// Caller should have granted approval to erc20 before executing this function
+        #[ink(message)]
+        pub fn secondary_market_purchase(&mut self, listing_id: u64) -> Result<(), Error> {
+            self.require_not_paused()?;
+            self.lock()?;
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+
+            let listing = match self.secondary_listings.get(&listing_id) {
+                Some(listing) => *listing,
+                None => {
+                    self.locked = false;
+                    return Err(Error::NoSuchListing);
+                }
+            };
+
+            // The loan may have been repaid or liquidated since it was listed
+            let loan = self.loans.get(&listing.loan_id).unwrap();
+            if loan.status != LoanStatus::Borrowed as u8
+                || loan.investor_address != Some(listing.seller)
+            {
+                self.locked = false;
+                return Err(Error::LoanNotBorrowed);
+            }
+
+            let mut erc20 = match self.get_erc20() {
+                Ok(erc20) => erc20,
+                Err(error) => {
+                    self.locked = false;
+                    return Err(error);
+                }
+            };
+            if let Err(error) = Self::check_allowance_sufficient(
+                &erc20,
+                caller,
+                contract_address,
+                listing.ask_price,
+            ) {
+                self.locked = false;
+                return Err(error);
+            }
+
+            let erc20_transfer = erc20.transfer_from(caller, listing.seller, listing.ask_price);
+            if erc20_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC20TransferFailed);
+            }
+
+            self.transfer_loan_position(listing.loan_id, listing.seller, caller);
+            self.secondary_listings.take(&listing_id);
+
+            self.env().emit_event(SecondaryPurchased {
+                listing_id,
+                loan_id: listing.loan_id,
+                buyer: caller,
+                seller: listing.seller,
+                price: listing.ask_price,
+            });
+
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Allows the seller to withdraw a loan position from sale
+        #[ink(message)]
+        pub fn secondary_market_cancel(&mut self, listing_id: u64) -> Result<(), Error> {
+            let listing = self
+                .secondary_listings
+                .get(&listing_id)
+                .ok_or(Error::NoSuchListing)?;
+            if listing.seller != self.env().caller() {
+                return Err(Error::NotSeller);
+            }
+            let loan_id = listing.loan_id;
+            self.secondary_listings.take(&listing_id);
+
+            self.env().emit_event(SecondaryCancelled {
+                listing_id,
+                loan_id,
+            });
+            Ok(())
+        }
+
+        /// Returns the secondary market listing with the given id
+        #[ink(message)]
+        pub fn get_secondary_listing(&self, listing_id: u64) -> Option<SecondaryListing> {
+            self.secondary_listings.get(&listing_id).copied()
+        }
+
+        /// Allows borrower of a funded loan to ask the investor for new terms
+        /// Replaces any earlier proposal for the same loan
+        #[ink(message)]
+        pub fn propose_restructure(
+            &mut self,
+            loan_id: u64,
+            new_rate: u64,
+            new_duration: u64,
+        ) -> Result<(), Error> {
+            self.require_not_paused()?;
+            let caller = self.env().caller();
+            if new_rate == 0 {
+                return Err(Error::InterestRateTooSmall);
+            }
+            let loan = self.loans.get(&loan_id).ok_or(Error::NoSuchLoan)?;
+            if loan.borrower_address != caller {
+                return Err(Error::NotBorrower);
+            }
+            if loan.status != LoanStatus::Borrowed as u8 {
+                return Err(Error::LoanNotBorrowed);
+            }
+
+            self.pending_restructures.insert(
+                loan_id,
+                PendingRestructure {
+                    loan_id,
+                    new_rate,
+                    new_duration,
+                    proposed_by_borrower_at: self.get_current_time(),
+                },
+            );
+            self.env().emit_event(RestructureProposed {
+                loan_id,
+                borrower: caller,
+                new_rate,
+                new_duration,
+            });
+            Ok(())
+        }
+
+        /// Allows investor to accept the proposed terms
+        /// new_rate and new_duration must match the proposal, so that the borrower can't replace
+        /// it while the approval is pending.
+        /// Interest restarts from the current time under the new rate and duration
+        #[ink(message)]
+        pub fn approve_restructure(
+            &mut self,
+            loan_id: u64,
+            new_rate: u64,
+            new_duration: u64,
+        ) -> Result<(), Error> {
+            self.require_not_paused()?;
+            let caller = self.env().caller();
+            let current_time = self.get_current_time();
+            let restructure = self.validate_restructure_response(loan_id, caller)?;
+            if restructure.new_rate != new_rate || restructure.new_duration != new_duration {
+                return Err(Error::RestructureMismatch);
+            }
+
+            let loan = self.loans.get_mut(&loan_id).unwrap();
+            loan.interest_rate = restructure.new_rate;
+            loan.duration = restructure.new_duration;
+            loan.fulfilled_at = Some(current_time);
+            self.pending_restructures.take(&loan_id);
+
+            self.env().emit_event(RestructureApproved {
+                loan_id,
+                investor: caller,
+                new_rate: restructure.new_rate,
+                new_duration: restructure.new_duration,
+            });
+            Ok(())
+        }
+
+        /// Allows investor to decline the proposed terms, leaving the loan unchanged
+        #[ink(message)]
+        pub fn reject_restructure(&mut self, loan_id: u64) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.validate_restructure_response(loan_id, caller)?;
+            self.pending_restructures.take(&loan_id);
 
+            self.env().emit_event(RestructureRejected {
+                loan_id,
+                investor: caller,
+            });
             Ok(())
         }
 
+        /// Returns the restructure proposal awaiting the investor of loan_id
+        #[ink(message)]
+        pub fn get_pending_restructure(&self, loan_id: u64) -> Option<PendingRestructure> {
+            self.pending_restructures.get(&loan_id).copied()
+        }
+
         #[ink(message)]
         pub fn list_loans_paginated(&self, start: u64, end: u64) -> Vec<Loan> {
             let mut loans: Vec<Loan> = Vec::new();
@@ -448,12 +1143,16 @@ mod lendingmanager {
             loans
         }
 
+        /// Returns the loan with the given id or NoSuchLoan
         #[ink(message)]
-        pub fn list_loan(&self, loan_id: u64) -> Loan {
-            let loan_opt = self.loans.get(&loan_id);
-            assert_eq!(loan_opt.is_some(), true, "Loan not available");
+        pub fn get_loan_details(&self, loan_id: u64) -> Result<Loan, Error> {
+            self.loans.get(&loan_id).copied().ok_or(Error::NoSuchLoan)
+        }
 
-            *loan_opt.unwrap()
+        /// Deprecated: use get_loan_details instead
+        #[ink(message)]
+        pub fn list_loan(&self, loan_id: u64) -> Result<Loan, Error> {
+            self.get_loan_details(loan_id)
         }
 
         #[ink(message)]
@@ -467,6 +1166,64 @@ mod lendingmanager {
             loans
         }
 
+        /// Returns ids of all loans listed against tokens of the nft_address collection
+        #[ink(message)]
+        pub fn get_loans_by_nft_address(&self, nft_address: AccountId) -> Vec<LoanId> {
+            self.nft_loans
+                .get(&nft_address)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// Returns loans of the nft_address collection that are still waiting for an investor
+        #[ink(message)]
+        pub fn get_available_loans_by_nft_address(&self, nft_address: AccountId) -> Vec<Loan> {
+            self.nft_loans
+                .get(&nft_address)
+                .map(|loan_ids| {
+                    loan_ids
+                        .iter()
+                        .filter_map(|loan_id| self.loans.get(loan_id))
+                        .filter(|loan| loan.status == LoanStatus::Available as u8)
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+
+        /// Returns number of loans listed against tokens of the nft_address collection
+        #[ink(message)]
+        pub fn get_loan_count_by_nft_address(&self, nft_address: AccountId) -> u32 {
+            self.nft_loans
+                .get(&nft_address)
+                .map_or(0, |loan_ids| loan_ids.len() as u32)
+        }
+
+        /// Returns the amount withdraw would charge for loan_id in the current block
+        #[ink(message)]
+        pub fn get_loan_repayment_amount(&self, loan_id: u64) -> Result<u128, Error> {
+            let loan = self.loans.get(&loan_id).ok_or(Error::NoSuchLoan)?;
+            if loan.status != LoanStatus::Borrowed as u8 {
+                return Err(Error::LoanNotBorrowed);
+            }
+            Ok(Self::repayment_amount(loan, self.get_current_time()))
+        }
+
+        /// Returns borrowed loans whose deadline falls within ms milliseconds from now
+        /// Loans already past their deadline are included
+        #[ink(message)]
+        pub fn get_loans_expiring_within(&self, ms: u64) -> Vec<LoanId> {
+            let current_time = self.get_current_time();
+            self.filter_borrowed_loans(|deadline| deadline.saturating_sub(current_time) < ms)
+        }
+
+        /// Returns borrowed loans that are past their deadline
+        #[ink(message)]
+        pub fn get_expired_loans(&self) -> Vec<LoanId> {
+            let current_time = self.get_current_time();
+            self.filter_borrowed_loans(|deadline| deadline < current_time)
+        }
+
         #[ink(message)]
         pub fn get_investor_loans(&self, investor: AccountId) -> Vec<LoanId> {
             let investor_opt = self.investors.get(&investor);
@@ -478,20 +1235,151 @@ mod lendingmanager {
             loans
         }
 
+        /// Returns borrowed loans funded by investor that are past their deadline
+        #[ink(message)]
+        pub fn get_overdue_loans_for_investor(&self, investor: AccountId) -> Vec<LoanId> {
+            self.overdue_investor_loans(investor)
+                .iter()
+                .map(|loan| loan.id)
+                .collect()
+        }
+
+        /// Returns the total principal of overdue loans funded by investor
+        #[ink(message)]
+        pub fn get_total_overdue_amount_for_investor(&self, investor: AccountId) -> Balance {
+            self.overdue_investor_loans(investor)
+                .iter()
+                .map(|loan| loan.amount as Balance)
+                .sum()
+        }
+
+        /// Returns loan counts and amounts borrowed, repaid and owed by borrower
+        #[ink(message)]
+        pub fn get_borrower_stats(&self, borrower: AccountId) -> LendingBorrowerStats {
+            let current_time = self.get_current_time();
+            let mut stats = LendingBorrowerStats::default();
+            for loan_id in self.get_borrowed_loans(borrower) {
+                let loan = match self.loans.get(&loan_id) {
+                    Some(loan) => loan,
+                    None => continue,
+                };
+                stats.total_loans += 1;
+                if loan.fulfilled_at.is_some() {
+                    stats.total_borrowed += loan.amount as u128;
+                }
+                if loan.status == LoanStatus::Borrowed as u8 {
+                    stats.active_loans += 1;
+                    stats.total_outstanding += Self::repayment_amount(loan, current_time);
+                }
+                if loan.status == LoanStatus::Repaid as u8 {
+                    stats.total_repaid += Self::repayment_amount(loan, loan.repaid_at.unwrap());
+                }
+            }
+            stats
+        }
+
+        /// Returns number of loans funded by investor and the capital and interest still owed
+        #[ink(message)]
+        pub fn get_investor_stats(&self, investor: AccountId) -> LendingInvestorStats {
+            let current_time = self.get_current_time();
+            let mut stats = LendingInvestorStats::default();
+            for loan_id in self.get_investor_loans(investor) {
+                let loan = match self.loans.get(&loan_id) {
+                    Some(loan) => loan,
+                    None => continue,
+                };
+                stats.funded_loans += 1;
+                if loan.status == LoanStatus::Borrowed as u8 {
+                    let amount = loan.amount as u128;
+                    stats.outstanding_capital += amount;
+                    stats.accrued_interest += Self::repayment_amount(loan, current_time) - amount;
+                }
+            }
+            stats
+        }
+
+        /// Returns principal and principal plus interest of loans investor has funded
+        /// that are not repaid yet
+        #[ink(message)]
+        pub fn get_investor_portfolio_value(&self, investor: AccountId) -> (Balance, Balance) {
+            let current_time = self.get_current_time();
+            let mut total_principal: Balance = 0;
+            let mut total_with_interest: Balance = 0;
+            for loan_id in self.get_investor_loans(investor) {
+                let loan = match self.loans.get(&loan_id) {
+                    Some(loan) => loan,
+                    None => continue,
+                };
+                if loan.status == LoanStatus::Borrowed as u8 {
+                    total_principal += loan.amount as Balance;
+                    total_with_interest += Self::repayment_amount(loan, current_time);
+                }
+            }
+            (total_principal, total_with_interest)
+        }
+
+        /// Returns principal of repaid loans investor has funded
+        #[ink(message)]
+        pub fn get_investor_completed_loans_value(&self, investor: AccountId) -> Balance {
+            self.get_investor_loans(investor)
+                .iter()
+                .filter_map(|loan_id| self.loans.get(loan_id))
+                .filter(|loan| loan.status == LoanStatus::Repaid as u8)
+                .map(|loan| loan.amount as Balance)
+                .sum()
+        }
+
         /// Allows owner to enable borrowing
         #[ink(message)]
-        pub fn enable(&mut self) {
+        pub fn enable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
             self.administration.enabled = true;
             self.env().emit_event(Enabled {});
+            Ok(())
         }
 
         /// Allows owner to disable borrowing
         #[ink(message)]
-        pub fn disable(&mut self) {
+        pub fn disable(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
             self.administration.enabled = false;
             self.env().emit_event(Disbaled {});
+            Ok(())
+        }
+
+        /// Allows owner to halt every state changing operation in an emergency
+        #[ink(message)]
+        pub fn pause(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.paused = true;
+            self.env().emit_event(Paused {});
+            Ok(())
+        }
+
+        /// Allows owner to resume operations after a pause
+        #[ink(message)]
+        pub fn unpause(&mut self) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.paused = false;
+            self.env().emit_event(Unpaused {});
+            Ok(())
+        }
+
+        /// Checks if the contract is paused
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.administration.paused
+        }
+
+        fn require_not_paused(&self) -> Result<(), Error> {
+            if self.administration.paused {
+                return Err(Error::ContractPaused);
+            }
+            Ok(())
         }
 
         /// Checks if borrowing is enabled
@@ -503,13 +1391,15 @@ mod lendingmanager {
         /// Allows owner to set interest rate
         /// Only affects future borrowing
         #[ink(message)]
-        pub fn set_interest_rate(&mut self, _interest_rate: u64) {
+        pub fn set_interest_rate(&mut self, _interest_rate: u64) -> Result<(), Error> {
+            self.ensure_mutable()?;
             assert!(self.only_owner(self.env().caller()));
             self.env().emit_event(InterestRateChanged {
                 old_value: self.administration.interest_rate,
                 new_value: _interest_rate,
             });
             self.administration.interest_rate = _interest_rate;
+            Ok(())
         }
 
         /// Returns current yearly interest rate
@@ -518,54 +1408,432 @@ mod lendingmanager {
             self.administration.interest_rate
         }
 
+        /// Allows owner to restrict collateral to approved nft contracts
+        #[ink(message)]
+        pub fn set_whitelist_enabled(&mut self, enabled: bool) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.administration.whitelist_enabled = enabled;
+            Ok(())
+        }
+
+        /// Checks if collateral is restricted to approved nft contracts
+        #[ink(message)]
+        pub fn is_whitelist_enabled(&self) -> bool {
+            self.administration.whitelist_enabled
+        }
+
+        /// Allows owner to approve nft_address as collateral
+        #[ink(message)]
+        pub fn whitelist_nft_address(&mut self, nft_address: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.approved_nfts.insert(nft_address, true);
+            Ok(())
+        }
+
+        /// Allows owner to withdraw approval of nft_address as collateral
+        /// Existing loans against nft_address are not affected
+        #[ink(message)]
+        pub fn delist_nft_address(&mut self, nft_address: AccountId) -> Result<(), Error> {
+            self.ensure_mutable()?;
+            assert!(self.only_owner(self.env().caller()));
+            self.approved_nfts.take(&nft_address);
+            Ok(())
+        }
+
+        /// Checks if nft_address is approved as collateral
+        #[ink(message)]
+        pub fn is_nft_approved(&self, nft_address: AccountId) -> bool {
+            *self.approved_nfts.get(&nft_address).unwrap_or(&false)
+        }
+
+        /// Returns number of nft contracts approved as collateral
+        #[ink(message)]
+        pub fn get_approved_nft_count(&self) -> u32 {
+            self.approved_nfts.len()
+        }
+
+        /// Returns how many loans were listed, funded and closed and the total amount funded
+        #[ink(message)]
+        pub fn get_loan_origination_stats(&self) -> LoanOriginationStats {
+            LoanOriginationStats {
+                total_loans_created: self.total_loans_created,
+                total_loans_funded: self.total_loans_funded,
+                total_loans_repaid: self.total_loans_repaid,
+                total_loans_liquidated: self.total_loans_liquidated,
+                total_loans_cancelled: self.total_loans_cancelled,
+                total_volume_funded: self.total_volume_funded,
+            }
+        }
+
+        /// Returns the share of listed loans that got funded in basis points
+        #[ink(message)]
+        pub fn get_funding_ratio(&self) -> u64 {
+            if self.total_loans_created == 0 {
+                return 0;
+            }
+            self.total_loans_funded * 10_000 / self.total_loans_created
+        }
+
+        /// Returns interest rate and whether lending is enabled
+        #[ink(message)]
+        pub fn get_lending_config(&self) -> LendingConfig {
+            LendingConfig {
+                interest_rate: self.administration.interest_rate,
+                enabled: self.administration.enabled,
+            }
+        }
+
+        fn ensure_nft_approved(&self, nft_address: AccountId) -> Result<(), Error> {
+            if self.administration.whitelist_enabled && !self.is_nft_approved(nft_address) {
+                return Err(Error::NFTNotWhitelisted);
+            }
+            Ok(())
+        }
+
         fn get_current_time(&self) -> u64 {
             self.env().block_timestamp()
         }
 
-        fn calculate_interest(
-            amount: u128,
-            interest_rate: u64,
-            current_timestamp: u64,
-            date_borrowed: u64,
-        ) -> Balance {
-            let difference_in_secs: u128 =
-                ((current_timestamp - date_borrowed) as u128 / 1000_u128).into(); // Total time elapsed in seconds
-            let secs_in_day: u128 = 24 * 60 * 60;
-            let difference_in_days: u128 = difference_in_secs / secs_in_day;
-            let mut days_since_borrowed = difference_in_days;
-            if difference_in_secs - (difference_in_days * days_since_borrowed) > 0 {
-                days_since_borrowed = days_since_borrowed + 1;
+        /// Checks that spender may transfer amount on behalf of owner
+        /// so that transfers fail with a clear error before anything else happens
+        fn check_allowance_sufficient(
+            erc20: &Erc20,
+            owner: AccountId,
+            spender: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            if erc20.allowance(owner, spender) < amount {
+                return Err(Error::InsufficientBalance);
+            }
+            Ok(())
+        }
+
+        /// Fails with AddressNotFound if the address manager has no erc20 registered
+        fn get_erc20(&self) -> Result<Erc20, Error> {
+            match &self.address_manager {
+                Some(address_manager) => address_manager
+                    .require_address(String::from(ERC20))
+                    .map(Erc20::from_account_id)
+                    .map_err(|_| Error::AddressNotFound),
+                None => Ok((*self.erc20).clone()),
             }
+        }
 
-            let mut s = 0;
-            let mut n = 1;
-            let mut b = 1;
-            let q: u128 = 365 * 100 / interest_rate as u128;
+        /// Loans keep the collection they were listed with, so their nft is always
+        /// moved through that contract
+        fn get_nft(address: AccountId) -> Erc721 {
+            Erc721::from_account_id(address)
+        }
 
-            // let mut p = 8_u32;
-            // if p < days_since_borrowed as u32 {
-            //     p = days_since_borrowed as u32;
-            // }
-            for x in 0..8 {
-                s = s + amount * n / b / (q.pow(x));
-                if days_since_borrowed < x.into() {
-                    break;
+        /// Returns ids of borrowed loans whose deadline matches predicate
+        fn filter_borrowed_loans<F: Fn(u64) -> bool>(&self, predicate: F) -> Vec<LoanId> {
+            let mut loans: Vec<LoanId> = Vec::new();
+            for (loan_id, loan) in self.loans.iter() {
+                if loan.status != LoanStatus::Borrowed as u8 {
+                    continue;
+                }
+                let deadline = loan.fulfilled_at.unwrap_or(0).saturating_add(loan.duration);
+                if predicate(deadline) {
+                    loans.push(*loan_id);
                 }
-                n = n * (days_since_borrowed - x as u128);
-                b = b * (x as u128 + 1);
             }
-            s - amount
+            loans
         }
-    }
 
-    /// Testcases
-    #[cfg(test)]
-    mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
-        use ink_lang as ink;
-        /// We test if the constructor does its job.
-        fn instantiate_erc20_contract() -> AccountId {
+        /// Returns borrowed loans held by investor whose deadline has passed
+        fn overdue_investor_loans(&self, investor: AccountId) -> Vec<Loan> {
+            let current_time = self.get_current_time();
+            let mut loans: Vec<Loan> = Vec::new();
+            for loan_id in self.get_investor_loans(investor).iter() {
+                if let Some(loan) = self.loans.get(loan_id) {
+                    let deadline = loan.fulfilled_at.unwrap_or(0).saturating_add(loan.duration);
+                    if loan.status == LoanStatus::Borrowed as u8
+                        && loan.investor_address == Some(investor)
+                        && deadline < current_time
+                    {
+                        loans.push(loan.clone());
+                    }
+                }
+            }
+            loans
+        }
+
+        /// Returns the loan if it is still available and the caller listed it
+        fn get_updatable_loan(&mut self, loan_id: LoanId) -> Result<&mut Loan, Error> {
+            let caller = self.env().caller();
+            let loan = self.loans.get_mut(&loan_id).ok_or(Error::NoSuchLoan)?;
+            if loan.borrower_address != caller {
+                return Err(Error::NotBorrower);
+            }
+            if loan.status != LoanStatus::Available as u8 {
+                return Err(Error::LoanUnavailable);
+            }
+            Ok(loan)
+        }
+
+        fn validate_batch_lend(&self, loan_ids: &[LoanId]) -> Vec<Result<(), Error>> {
+            let enabled = self.is_enabled();
+            loan_ids
+                .iter()
+                .enumerate()
+                .map(|(index, loan_id)| {
+                    if !enabled {
+                        return Err(Error::NotEnabled);
+                    }
+                    let loan = self.loans.get(loan_id).ok_or(Error::NoSuchLoan)?;
+                    // Loans listed twice in the batch are only funded once
+                    if loan.status != LoanStatus::Available as u8
+                        || loan_ids[..index].contains(loan_id)
+                    {
+                        return Err(Error::LoanUnavailable);
+                    }
+                    Ok(())
+                })
+                .collect()
+        }
+
+        /// Stores a newly listed loan and indexes it by borrower and collection
+        /// Takes the nft of the caller into custody and lists loan
+        fn handle_list_token(&mut self, loan: Loan) -> Result<(), Error> {
+            self.require_not_paused()?;
+            self.lock()?;
+            if !self.is_enabled() {
+                self.locked = false;
+                return Err(Error::NotEnabled);
+            }
+            if let Err(error) = self.ensure_nft_approved(loan.nft_address) {
+                self.locked = false;
+                return Err(error);
+            }
+            let caller = self.env().caller();
+            let contract_address = self.env().account_id();
+            let mut erc721 = Self::get_nft(loan.nft_address);
+
+            // Transfer tokens from caller to contract
+
+            let erc721_transfer = erc721.transfer_from(caller, contract_address, loan.token_id);
+            if erc721_transfer.is_err() {
+                self.locked = false;
+                return Err(Error::ERC721TransferFailed);
+            }
+
+            self.insert_loan(loan);
+            self.locked = false;
+            Ok(())
+        }
+
+        /// Returns an available loan of the caller that would be listed next
+        fn new_loan(
+            &self,
+            nft_address: AccountId,
+            token_id: TokenId,
+            beneficiary_address: AccountId,
+            amount: u64,
+            duration: u64,
+            interest_rate: u64,
+        ) -> Loan {
+            Loan {
+                id: self.total_loans as LoanId,
+                amount,
+                nft_address,
+                token_id,
+                borrower_address: self.env().caller(),
+                beneficiary_address,
+                investor_address: None,
+                status: LoanStatus::Available as u8,
+                duration,
+                created_at: self.get_current_time(),
+                fulfilled_at: None,
+                repaid_at: None,
+                interest_rate,
+            }
+        }
+
+        fn loan_from_template(
+            &self,
+            template_id: TemplateId,
+            token_id: TokenId,
+            beneficiary_address: AccountId,
+            amount: u64,
+        ) -> Result<Loan, Error> {
+            let template = self
+                .get_template(template_id)
+                .ok_or(Error::NoSuchTemplate)?;
+            Ok(self.new_loan(
+                template.nft_address,
+                token_id,
+                beneficiary_address,
+                amount,
+                template.duration,
+                template.interest_rate,
+            ))
+        }
+
+        fn insert_loan(&mut self, loan: Loan) {
+            let (loan_id, borrower, nft_address) =
+                (loan.id, loan.borrower_address, loan.nft_address);
+            self.loans.insert(loan_id, loan);
+            self.total_loans += 1;
+            self.total_loans_created += 1;
+
+            let mut borrowed: Vec<LoanId> = Vec::new();
+            let borrower_opt = self.borrowers.get_mut(&borrower);
+            if borrower_opt.is_some() {
+                borrowed = borrower_opt.unwrap().to_vec();
+            }
+            borrowed.push(loan_id);
+
+            self.borrowers.insert(borrower, borrowed);
+            self.nft_loans
+                .entry(nft_address)
+                .or_insert_with(Vec::new)
+                .push(loan_id);
+        }
+
+        /// Marks loan as funded by investor
+        fn handle_lend(&mut self, loan_id: LoanId, investor: AccountId, time: u64) {
+            let loan = self.loans.get_mut(&loan_id).unwrap();
+            loan.investor_address = Some(investor);
+            loan.fulfilled_at = Some(time);
+            loan.status = LoanStatus::Borrowed as u8;
+            let (nft_address, token_id) = (loan.nft_address, loan.token_id);
+            self.total_loans_funded += 1;
+            self.total_volume_funded += loan.amount as u128;
+
+            let mut lent: Vec<LoanId> = Vec::new();
+            let investor_opt = self.investors.get_mut(&investor);
+            if investor_opt.is_some() {
+                lent = investor_opt.unwrap().to_vec();
+            }
+            lent.push(loan_id);
+
+            self.investors.insert(investor, lent);
+
+            self.env().emit_event(LoanBorrowed {
+                investor,
+                loan_id,
+                nft_address,
+                token_id,
+            });
+        }
+
+        /// Marks loan as repaid, liquidated or cancelled
+        fn close_loan(&mut self, loan_id: LoanId, status: LoanStatus, time: u64) {
+            let loan = self.loans.get_mut(&loan_id).unwrap();
+            loan.status = status as u8;
+            match status {
+                LoanStatus::Repaid => {
+                    loan.repaid_at = Some(time);
+                    self.total_loans_repaid += 1;
+                }
+                LoanStatus::Liquidated => self.total_loans_liquidated += 1,
+                LoanStatus::Cancelled => self.total_loans_cancelled += 1,
+                _ => {}
+            }
+        }
+
+        /// Returns the pending proposal for loan_id if investor may respond to it
+        fn validate_restructure_response(
+            &self,
+            loan_id: LoanId,
+            investor: AccountId,
+        ) -> Result<PendingRestructure, Error> {
+            let loan = self.loans.get(&loan_id).ok_or(Error::NoSuchLoan)?;
+            if loan.investor_address != Some(investor) {
+                return Err(Error::NotInvestor);
+            }
+            let restructure = self
+                .pending_restructures
+                .get(&loan_id)
+                .ok_or(Error::NoSuchRestructure)?;
+            // The loan may have been repaid or liquidated since the proposal
+            if loan.status != LoanStatus::Borrowed as u8 {
+                return Err(Error::LoanNotBorrowed);
+            }
+            Ok(*restructure)
+        }
+
+        /// Returns id of the open secondary market listing for loan_id
+        fn get_secondary_listing_of(&self, loan_id: LoanId) -> Option<u64> {
+            self.secondary_listings
+                .iter()
+                .find(|(_, listing)| listing.loan_id == loan_id)
+                .map(|(listing_id, _)| *listing_id)
+        }
+
+        /// Hands the investor position of a funded loan from seller to buyer
+        fn transfer_loan_position(&mut self, loan_id: LoanId, seller: AccountId, buyer: AccountId) {
+            let loan = self.loans.get_mut(&loan_id).unwrap();
+            loan.investor_address = Some(buyer);
+
+            if let Some(lent) = self.investors.get_mut(&seller) {
+                lent.retain(|id| *id != loan_id);
+            }
+            let mut lent = self.get_investor_loans(buyer);
+            lent.push(loan_id);
+            self.investors.insert(buyer, lent);
+        }
+
+        /// Returns principal and interest owed on a fulfilled loan at the given time
+        /// Interest accrues at the rate of the loan from the time it was last fulfilled
+        fn repayment_amount(loan: &Loan, time: u64) -> Balance {
+            Self::calculate_interest(
+                loan.amount as u128,
+                loan.interest_rate,
+                time,
+                loan.fulfilled_at.unwrap(),
+            ) + loan.amount as u128
+        }
+
+        fn calculate_interest(
+            amount: u128,
+            interest_rate: u64,
+            current_timestamp: u64,
+            date_borrowed: u64,
+        ) -> Balance {
+            if interest_rate == 0 {
+                return 0;
+            }
+            let difference_in_secs: u128 =
+                ((current_timestamp - date_borrowed) as u128 / 1000_u128).into(); // Total time elapsed in seconds
+            let secs_in_day: u128 = 24 * 60 * 60;
+            let difference_in_days: u128 = difference_in_secs / secs_in_day;
+            let mut days_since_borrowed = difference_in_days;
+            if difference_in_secs - (difference_in_days * days_since_borrowed) > 0 {
+                days_since_borrowed = days_since_borrowed + 1;
+            }
+
+            let mut s = 0;
+            let mut n = 1;
+            let mut b = 1;
+            let q: u128 = 365 * 100 / interest_rate as u128;
+
+            // let mut p = 8_u32;
+            // if p < days_since_borrowed as u32 {
+            //     p = days_since_borrowed as u32;
+            // }
+            for x in 0..8 {
+                s = s + amount * n / b / (q.pow(x));
+                if days_since_borrowed < x.into() {
+                    break;
+                }
+                n = n * (days_since_borrowed - x as u128);
+                b = b * (x as u128 + 1);
+            }
+            s - amount
+        }
+    }
+
+    /// Testcases
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+        use ink_lang as ink;
+        /// We test if the constructor does its job.
+        fn instantiate_erc20_contract() -> AccountId {
             let erc20 = Erc20::new(1000000);
             let callee =
                 ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
@@ -577,6 +1845,201 @@ mod lendingmanager {
                 ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
             callee
         }
+
+        fn set_caller(caller: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                caller,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])),
+            );
+        }
+
+        #[ink::test]
+        fn restructured_interest_rate_is_charged() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let erc20_decimals = 1000_000_000_000;
+            let investor = AccountId::from([0x02; 32]);
+            lendingmanager.loans.insert(
+                0,
+                Loan {
+                    id: 0,
+                    amount: erc20_decimals,
+                    borrower_address: AccountId::from([0x01; 32]),
+                    investor_address: Some(investor),
+                    status: LoanStatus::Borrowed as u8,
+                    duration: 86400 * 30 * 1000,
+                    fulfilled_at: Some(0),
+                    interest_rate: 10,
+                    ..Default::default()
+                },
+            );
+            let advance_one_second = || {
+                for _ in 0..200 {
+                    ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                        .expect("Cannot advance block");
+                }
+            };
+            // Any part of a day is charged as a whole day
+            advance_one_second();
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Ok(erc20_decimals as u128 + 273_972_602)
+            );
+
+            assert_eq!(
+                lendingmanager.propose_restructure(0, 20, 86400 * 30 * 1000),
+                Ok(())
+            );
+            set_caller(investor);
+            assert_eq!(
+                lendingmanager.approve_restructure(0, 20, 86400 * 30 * 1000),
+                Ok(())
+            );
+            ink_env::test::pop_execution_context();
+            // Interest restarts from the new terms
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Ok(erc20_decimals as u128)
+            );
+            advance_one_second();
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Ok(erc20_decimals as u128 + 547_945_205)
+            );
+        }
+
+        #[ink::test]
+        fn loan_restructure_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            let investor = AccountId::from([0x02; 32]);
+            assert_eq!(
+                lendingmanager.propose_restructure(0, 5, 1000),
+                Err(Error::NoSuchLoan)
+            );
+            for loan_id in 0..2 {
+                lendingmanager.loans.insert(
+                    loan_id,
+                    Loan {
+                        id: loan_id,
+                        amount: 1000,
+                        borrower_address: borrower,
+                        investor_address: Some(investor),
+                        status: LoanStatus::Borrowed as u8,
+                        duration: 500,
+                        fulfilled_at: Some(0),
+                        interest_rate: 10,
+                        ..Default::default()
+                    },
+                );
+            }
+            assert_eq!(
+                lendingmanager.propose_restructure(0, 0, 1000),
+                Err(Error::InterestRateTooSmall)
+            );
+            assert_eq!(lendingmanager.propose_restructure(0, 5, 1000), Ok(()));
+            assert_eq!(lendingmanager.propose_restructure(1, 8, 2000), Ok(()));
+            assert_eq!(
+                lendingmanager.get_pending_restructure(0),
+                Some(PendingRestructure {
+                    loan_id: 0,
+                    new_rate: 5,
+                    new_duration: 1000,
+                    proposed_by_borrower_at: 0,
+                })
+            );
+            // Only the investor can respond
+            assert_eq!(
+                lendingmanager.approve_restructure(0, 5, 1000),
+                Err(Error::NotInvestor)
+            );
+
+            set_caller(investor);
+            assert_eq!(
+                lendingmanager.propose_restructure(0, 5, 1000),
+                Err(Error::NotBorrower)
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            // Terms other than the proposed ones are refused
+            assert_eq!(
+                lendingmanager.approve_restructure(0, 4, 1000),
+                Err(Error::RestructureMismatch)
+            );
+            assert_eq!(
+                lendingmanager.approve_restructure(0, 5, 999),
+                Err(Error::RestructureMismatch)
+            );
+            assert_eq!(
+                lendingmanager.get_loan_details(0).unwrap().interest_rate,
+                10
+            );
+            assert_eq!(lendingmanager.approve_restructure(0, 5, 1000), Ok(()));
+            let loan = lendingmanager.get_loan_details(0).unwrap();
+            assert_eq!(loan.interest_rate, 5);
+            assert_eq!(loan.duration, 1000);
+            assert_eq!(loan.fulfilled_at, Some(5));
+            assert_eq!(lendingmanager.get_pending_restructure(0), None);
+            assert_eq!(
+                lendingmanager.approve_restructure(0, 5, 1000),
+                Err(Error::NoSuchRestructure)
+            );
+
+            // Rejected proposals leave the loan untouched
+            assert_eq!(lendingmanager.reject_restructure(1), Ok(()));
+            let loan = lendingmanager.get_loan_details(1).unwrap();
+            assert_eq!(loan.interest_rate, 10);
+            assert_eq!(loan.duration, 500);
+            assert_eq!(loan.fulfilled_at, Some(0));
+            assert_eq!(lendingmanager.get_pending_restructure(1), None);
+            assert_eq!(
+                lendingmanager.reject_restructure(1),
+                Err(Error::NoSuchRestructure)
+            );
+            ink_env::test::pop_execution_context();
+
+            // Proposals lapse once the loan is no longer borrowed
+            assert_eq!(lendingmanager.propose_restructure(1, 8, 2000), Ok(()));
+            lendingmanager.loans.get_mut(&1).unwrap().status = LoanStatus::Repaid as u8;
+            assert_eq!(
+                lendingmanager.propose_restructure(1, 8, 2000),
+                Err(Error::LoanNotBorrowed)
+            );
+            set_caller(investor);
+            assert_eq!(
+                lendingmanager.approve_restructure(1, 8, 2000),
+                Err(Error::LoanNotBorrowed)
+            );
+            ink_env::test::pop_execution_context();
+
+            // A proposal replaced before the approval lands isn't applied
+            lendingmanager.loans.get_mut(&1).unwrap().status = LoanStatus::Borrowed as u8;
+            assert_eq!(lendingmanager.propose_restructure(1, 8, 2000), Ok(()));
+            assert_eq!(lendingmanager.propose_restructure(1, 80, 2000), Ok(()));
+            set_caller(investor);
+            assert_eq!(
+                lendingmanager.approve_restructure(1, 8, 2000),
+                Err(Error::RestructureMismatch)
+            );
+            assert_eq!(
+                lendingmanager.get_loan_details(1).unwrap().interest_rate,
+                10
+            );
+        }
         #[ink::test]
         fn new_works() {
             let lendingmanager = LendingManager::new(
@@ -587,21 +2050,1034 @@ mod lendingmanager {
             );
             assert_eq!(lendingmanager.is_enabled(), true);
             assert_eq!(lendingmanager.get_interest_rate(), 10);
+            assert_eq!(
+                lendingmanager.get_lending_config(),
+                LendingConfig {
+                    interest_rate: 10,
+                    enabled: true,
+                }
+            );
         }
 
         #[ink::test]
-        fn enable_works() {
+        fn typed_errors_work() {
             let mut lendingmanager = LendingManager::new(
                 instantiate_erc20_contract(),
                 instantiate_erc721_contract(),
-                7,
+                10,
                 false,
             );
-            assert_eq!(lendingmanager.is_enabled(), false);
-            assert_eq!(lendingmanager.get_interest_rate(), 7);
+            assert_eq!(lendingmanager.lend(0), Err(Error::NotEnabled));
+
+            assert_eq!(lendingmanager.enable(), Ok(()));
+            assert_eq!(lendingmanager.lend(0), Err(Error::NoSuchLoan));
+            assert_eq!(lendingmanager.expire_loan(0), Err(Error::NoSuchLoan));
+            assert_eq!(lendingmanager.withdraw(0), Err(Error::NoSuchLoan));
+            assert_eq!(lendingmanager.liquidate(0), Err(Error::NoSuchLoan));
+            assert_eq!(lendingmanager.list_loan(0).err(), Some(Error::NoSuchLoan));
+            assert_eq!(
+                lendingmanager.get_loan_details(0).err(),
+                Some(Error::NoSuchLoan)
+            );
+            // Failed calls release the re-entrancy guard
+            assert_eq!(lendingmanager.locked, false);
+        }
 
-            lendingmanager.enable();
-            assert_eq!(lendingmanager.is_enabled(), true);
+        #[ink::test]
+        fn nft_whitelist_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let nft = AccountId::from([0x05; 32]);
+            let other_nft = AccountId::from([0x06; 32]);
+            assert_eq!(lendingmanager.is_whitelist_enabled(), false);
+            assert_eq!(lendingmanager.is_nft_approved(nft), false);
+            // Any nft can be used as collateral while the whitelist is disabled
+            assert_eq!(lendingmanager.ensure_nft_approved(nft), Ok(()));
+
+            assert_eq!(lendingmanager.set_whitelist_enabled(true), Ok(()));
+            assert_eq!(lendingmanager.is_whitelist_enabled(), true);
+            assert_eq!(
+                lendingmanager.list_token(nft, 1, AccountId::from([0x01; 32]), 1000, 30),
+                Err(Error::NFTNotWhitelisted)
+            );
+            assert_eq!(lendingmanager.locked, false);
+
+            assert_eq!(lendingmanager.whitelist_nft_address(nft), Ok(()));
+            assert_eq!(lendingmanager.whitelist_nft_address(other_nft), Ok(()));
+            assert_eq!(lendingmanager.get_approved_nft_count(), 2);
+            assert_eq!(lendingmanager.is_nft_approved(nft), true);
+            assert_eq!(lendingmanager.ensure_nft_approved(nft), Ok(()));
+
+            assert_eq!(lendingmanager.delist_nft_address(nft), Ok(()));
+            assert_eq!(lendingmanager.get_approved_nft_count(), 1);
+            assert_eq!(
+                lendingmanager.ensure_nft_approved(nft),
+                Err(Error::NFTNotWhitelisted)
+            );
+            assert_eq!(lendingmanager.ensure_nft_approved(other_nft), Ok(()));
+
+            assert_eq!(lendingmanager.set_whitelist_enabled(false), Ok(()));
+            assert_eq!(lendingmanager.ensure_nft_approved(nft), Ok(()));
+        }
+
+        #[ink::test]
+        #[should_panic]
+        fn non_owner_whitelist_nft_address_fails() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            set_caller(AccountId::from([0x02; 32]));
+            let _ = lendingmanager.whitelist_nft_address(AccountId::from([0x05; 32]));
+        }
+
+        #[ink::test]
+        fn borrower_and_investor_stats_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            let investor = AccountId::from([0x02; 32]);
+            let erc20_decimals = 1000_000_000_000;
+            assert_eq!(
+                lendingmanager.get_borrower_stats(borrower),
+                LendingBorrowerStats::default()
+            );
+            assert_eq!(
+                lendingmanager.get_investor_stats(investor),
+                LendingInvestorStats::default()
+            );
+
+            // Mirrors the storage updates of list_token, lend and withdraw
+            // which cannot be called off-chain
+            let list = |lendingmanager: &mut LendingManager, loan_id: LoanId| {
+                let loan = Loan {
+                    id: loan_id,
+                    amount: erc20_decimals as u64,
+                    borrower_address: borrower,
+                    status: LoanStatus::Available as u8,
+                    interest_rate: 10,
+                    ..Default::default()
+                };
+                lendingmanager.loans.insert(loan_id, loan);
+                let mut borrowed = lendingmanager.get_borrowed_loans(borrower);
+                borrowed.push(loan_id);
+                lendingmanager.borrowers.insert(borrower, borrowed);
+            };
+            let lend = |lendingmanager: &mut LendingManager, loan_id: LoanId, at: u64| {
+                let loan = lendingmanager.loans.get_mut(&loan_id).unwrap();
+                loan.investor_address = Some(investor);
+                loan.fulfilled_at = Some(at);
+                loan.status = LoanStatus::Borrowed as u8;
+                let mut lent = lendingmanager.get_investor_loans(investor);
+                lent.push(loan_id);
+                lendingmanager.investors.insert(investor, lent);
+            };
+
+            list(&mut lendingmanager, 0);
+            list(&mut lendingmanager, 1);
+            let stats = lendingmanager.get_borrower_stats(borrower);
+            assert_eq!(stats.total_loans, 2);
+            assert_eq!(stats.active_loans, 0);
+            assert_eq!(stats.total_borrowed, 0);
+
+            lend(&mut lendingmanager, 0, 0);
+            lend(&mut lendingmanager, 1, 0);
+            assert_eq!(
+                lendingmanager.get_investor_stats(investor),
+                LendingInvestorStats {
+                    funded_loans: 2,
+                    outstanding_capital: 2 * erc20_decimals,
+                    accrued_interest: 0,
+                }
+            );
+
+            // Loan 0 is repaid after 29 days
+            let loan = lendingmanager.loans.get_mut(&0).unwrap();
+            loan.status = LoanStatus::Repaid as u8;
+            loan.repaid_at = Some(86400 * 29 * 1000);
+
+            assert_eq!(
+                lendingmanager.get_borrower_stats(borrower),
+                LendingBorrowerStats {
+                    active_loans: 1,
+                    total_loans: 2,
+                    total_borrowed: 2 * erc20_decimals,
+                    total_repaid: erc20_decimals + 8_251_913_257,
+                    total_outstanding: erc20_decimals,
+                }
+            );
+            assert_eq!(
+                lendingmanager.get_investor_stats(investor),
+                LendingInvestorStats {
+                    funded_loans: 2,
+                    outstanding_capital: erc20_decimals,
+                    accrued_interest: 0,
+                }
+            );
+
+            // Loan 1 is liquidated
+            lendingmanager.loans.get_mut(&1).unwrap().status = LoanStatus::Liquidated as u8;
+            let stats = lendingmanager.get_borrower_stats(borrower);
+            assert_eq!(stats.active_loans, 0);
+            assert_eq!(stats.total_outstanding, 0);
+            assert_eq!(stats.total_repaid, erc20_decimals + 8_251_913_257);
+            assert_eq!(
+                lendingmanager
+                    .get_investor_stats(investor)
+                    .outstanding_capital,
+                0
+            );
+        }
+
+        #[ink::test]
+        fn loan_origination_stats_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let investor = AccountId::from([0x02; 32]);
+            assert_eq!(
+                lendingmanager.get_loan_origination_stats(),
+                LoanOriginationStats::default()
+            );
+            assert_eq!(lendingmanager.get_funding_ratio(), 0);
+
+            for loan_id in 0..5 {
+                lendingmanager.insert_loan(Loan {
+                    id: loan_id,
+                    amount: 1000 * (loan_id + 1),
+                    status: LoanStatus::Available as u8,
+                    ..Default::default()
+                });
+            }
+            for loan_id in 0..4 {
+                lendingmanager.handle_lend(loan_id, investor, 0);
+            }
+            lendingmanager.close_loan(0, LoanStatus::Repaid, 7);
+            lendingmanager.close_loan(1, LoanStatus::Repaid, 8);
+            lendingmanager.close_loan(2, LoanStatus::Liquidated, 9);
+            lendingmanager.close_loan(4, LoanStatus::Cancelled, 9);
+
+            assert_eq!(
+                lendingmanager.get_loan_origination_stats(),
+                LoanOriginationStats {
+                    total_loans_created: 5,
+                    total_loans_funded: 4,
+                    total_loans_repaid: 2,
+                    total_loans_liquidated: 1,
+                    total_loans_cancelled: 1,
+                    total_volume_funded: 10_000,
+                }
+            );
+            assert_eq!(lendingmanager.get_funding_ratio(), 8_000);
+            let repaid = lendingmanager.list_loan(0).unwrap();
+            assert_eq!(repaid.status, LoanStatus::Repaid as u8);
+            assert_eq!(repaid.repaid_at, Some(7));
+            assert_eq!(
+                lendingmanager.list_loan(4).unwrap().status,
+                LoanStatus::Cancelled as u8
+            );
+        }
+
+        #[ink::test]
+        fn loan_template_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let creator = AccountId::from([0x01; 32]);
+            let nft_address = AccountId::from([0x03; 32]);
+            let beneficiary = AccountId::from([0x04; 32]);
+            assert_eq!(
+                lendingmanager.create_loan_template(0, 1000, nft_address),
+                Err(Error::InterestRateTooSmall)
+            );
+            assert_eq!(
+                lendingmanager.list_token_from_template(0, 1, beneficiary, 500),
+                Err(Error::NoSuchTemplate)
+            );
+
+            assert_eq!(
+                lendingmanager.create_loan_template(15, 1000, nft_address),
+                Ok(0)
+            );
+            assert_eq!(
+                lendingmanager.create_loan_template(20, 2000, nft_address),
+                Ok(1)
+            );
+            let template = LoanTemplate {
+                id: 0,
+                interest_rate: 15,
+                duration: 1000,
+                nft_address,
+                creator,
+            };
+            assert_eq!(lendingmanager.get_template(0), Some(template));
+            assert_eq!(lendingmanager.get_template(2), None);
+            assert_eq!(lendingmanager.list_templates().len(), 2);
+
+            // Loans take their terms from the template rather than the contract
+            let loan = lendingmanager
+                .loan_from_template(0, 1, beneficiary, 500)
+                .unwrap();
+            assert_eq!(loan.interest_rate, 15);
+            assert_eq!(loan.duration, 1000);
+            assert_eq!(loan.nft_address, nft_address);
+            assert_eq!(loan.token_id, 1);
+            assert_eq!(loan.amount, 500);
+            assert_eq!(loan.beneficiary_address, beneficiary);
+            assert_eq!(loan.borrower_address, creator);
+            assert_eq!(loan.status, LoanStatus::Available as u8);
+            assert_eq!(lendingmanager.get_interest_rate(), 10);
+
+            // Repayments are charged at the rate of the template
+            let erc20_decimals = 1000_000_000_000;
+            let mut loan = lendingmanager
+                .loan_from_template(1, 2, beneficiary, erc20_decimals)
+                .unwrap();
+            // Mirrors the storage updates of list_token and lend which cannot be called off-chain
+            loan.status = LoanStatus::Borrowed as u8;
+            loan.fulfilled_at = Some(0);
+            lendingmanager.insert_loan(loan);
+            // Any part of a day is charged as a whole day
+            for _ in 0..200 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                    .expect("Cannot advance block");
+            }
+            // One day at 20% rather than the 10% of the contract
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(loan.id),
+                Ok(erc20_decimals as u128 + 547_945_205)
+            );
+
+            // Listing is subject to the same checks as list_token
+            assert_eq!(lendingmanager.disable(), Ok(()));
+            assert_eq!(
+                lendingmanager.list_token_from_template(1, 1, beneficiary, 500),
+                Err(Error::NotEnabled)
+            );
+            assert_eq!(lendingmanager.locked, false);
+        }
+
+        #[ink::test]
+        fn loans_by_nft_address_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            let punks = AccountId::from([0x05; 32]);
+            let kitties = AccountId::from([0x06; 32]);
+            assert_eq!(lendingmanager.get_loans_by_nft_address(punks), vec![]);
+            assert_eq!(lendingmanager.get_loan_count_by_nft_address(punks), 0);
+
+            // Mirrors the storage updates of list_token which cannot be called off-chain
+            for (loan_id, nft_address) in [punks, kitties, punks].iter().enumerate() {
+                lendingmanager.insert_loan(Loan {
+                    id: loan_id as LoanId,
+                    token_id: loan_id as TokenId,
+                    nft_address: *nft_address,
+                    borrower_address: borrower,
+                    status: LoanStatus::Available as u8,
+                    ..Default::default()
+                });
+            }
+            assert_eq!(lendingmanager.get_borrowed_loans(borrower), vec![0, 1, 2]);
+            assert_eq!(lendingmanager.get_loans_by_nft_address(punks), vec![0, 2]);
+            assert_eq!(lendingmanager.get_loans_by_nft_address(kitties), vec![1]);
+            assert_eq!(lendingmanager.get_loan_count_by_nft_address(punks), 2);
+            assert_eq!(lendingmanager.get_loan_count_by_nft_address(kitties), 1);
+
+            // Funded loans stay indexed but are no longer available
+            lendingmanager.handle_lend(2, AccountId::from([0x02; 32]), 0);
+            let available: Vec<LoanId> = lendingmanager
+                .get_available_loans_by_nft_address(punks)
+                .iter()
+                .map(|loan| loan.id)
+                .collect();
+            assert_eq!(available, vec![0]);
+            assert_eq!(lendingmanager.get_loan_count_by_nft_address(punks), 2);
+            assert_eq!(
+                lendingmanager
+                    .get_available_loans_by_nft_address(kitties)
+                    .len(),
+                1
+            );
+        }
+
+        #[ink::test]
+        fn lend_unavailable_loan_fails() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let investor = AccountId::from([0x02; 32]);
+            assert_eq!(lendingmanager.lend(0), Err(Error::NoSuchLoan));
+
+            let statuses = [
+                LoanStatus::Borrowed,
+                LoanStatus::Repaid,
+                LoanStatus::Cancelled,
+                LoanStatus::Liquidated,
+            ];
+            for (loan_id, status) in statuses.iter().enumerate() {
+                lendingmanager.loans.insert(
+                    loan_id as LoanId,
+                    Loan {
+                        id: loan_id as LoanId,
+                        amount: 1000,
+                        investor_address: Some(investor),
+                        status: *status as u8,
+                        ..Default::default()
+                    },
+                );
+                assert_eq!(
+                    lendingmanager.lend(loan_id as LoanId),
+                    Err(Error::LoanUnavailable)
+                );
+                let loan = lendingmanager.list_loan(loan_id as LoanId).unwrap();
+                assert_eq!(loan.investor_address, Some(investor));
+                assert_eq!(loan.status, *status as u8);
+            }
+            assert_eq!(lendingmanager.locked, false);
+        }
+
+        #[ink::test]
+        fn batch_lend_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let borrower = AccountId::from([0x01; 32]);
+            let investor = AccountId::from([0x02; 32]);
+            for loan_id in 0..3 {
+                lendingmanager.loans.insert(
+                    loan_id,
+                    Loan {
+                        id: loan_id,
+                        amount: 1000,
+                        borrower_address: borrower,
+                        status: LoanStatus::Available as u8,
+                        ..Default::default()
+                    },
+                );
+            }
+            lendingmanager.loans.get_mut(&2).unwrap().status = LoanStatus::Cancelled as u8;
+
+            assert_eq!(
+                lendingmanager.validate_batch_lend(&[0, 5, 1, 2, 0]),
+                vec![
+                    Ok(()),
+                    Err(Error::NoSuchLoan),
+                    Ok(()),
+                    Err(Error::LoanUnavailable),
+                    Err(Error::LoanUnavailable)
+                ]
+            );
+
+            // A batch without fundable loans makes no transfers
+            assert_eq!(
+                lendingmanager.batch_lend(vec![5, 2]),
+                vec![Err(Error::NoSuchLoan), Err(Error::LoanUnavailable)]
+            );
+            assert_eq!(lendingmanager.locked, false);
+
+            lendingmanager.handle_lend(0, investor, 5);
+            let loan = lendingmanager.list_loan(0).unwrap();
+            assert_eq!(loan.status, LoanStatus::Borrowed as u8);
+            assert_eq!(loan.investor_address, Some(investor));
+            assert_eq!(loan.fulfilled_at, Some(5));
+            assert_eq!(lendingmanager.get_investor_loans(investor), vec![0]);
+            assert_eq!(ink_env::test::recorded_events().count(), 1);
+            assert_eq!(
+                lendingmanager.validate_batch_lend(&[0, 1]),
+                vec![Err(Error::LoanUnavailable), Ok(())]
+            );
+
+            assert_eq!(lendingmanager.disable(), Ok(()));
+            assert_eq!(
+                lendingmanager.batch_lend(vec![1]),
+                vec![Err(Error::NotEnabled)]
+            );
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Too many loans in batch")]
+        fn batch_lend_limit_fails() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            lendingmanager.batch_lend(vec![0; MAX_BATCH_LEND + 1]);
+        }
+
+        #[ink::test]
+        fn update_loan_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            // The default caller lists both loans
+            let borrower = AccountId::from([0x01; 32]);
+            for loan_id in 0..2 {
+                lendingmanager.loans.insert(
+                    loan_id,
+                    Loan {
+                        id: loan_id,
+                        amount: 1000,
+                        borrower_address: borrower,
+                        status: LoanStatus::Available as u8,
+                        interest_rate: 10,
+                        ..Default::default()
+                    },
+                );
+            }
+            lendingmanager.loans.get_mut(&1).unwrap().status = LoanStatus::Borrowed as u8;
+
+            assert_eq!(lendingmanager.update_loan_amount(0, 1500), Ok(()));
+            assert_eq!(lendingmanager.update_loan_interest_rate(0, 12), Ok(()));
+            let loan = lendingmanager.list_loan(0).unwrap();
+            assert_eq!(loan.amount, 1500);
+            assert_eq!(loan.interest_rate, 12);
+            assert_eq!(ink_env::test::recorded_events().count(), 2);
+
+            assert_eq!(
+                lendingmanager.update_loan_amount(0, 0),
+                Err(Error::LoanAmountTooSmall)
+            );
+            assert_eq!(
+                lendingmanager.update_loan_interest_rate(0, 0),
+                Err(Error::InterestRateTooSmall)
+            );
+            assert_eq!(
+                lendingmanager.update_loan_amount(1, 1500),
+                Err(Error::LoanUnavailable)
+            );
+            assert_eq!(
+                lendingmanager.update_loan_interest_rate(1, 12),
+                Err(Error::LoanUnavailable)
+            );
+            assert_eq!(
+                lendingmanager.update_loan_amount(2, 1500),
+                Err(Error::NoSuchLoan)
+            );
+            let loan = lendingmanager.list_loan(1).unwrap();
+            assert_eq!(loan.amount, 1000);
+            assert_eq!(loan.interest_rate, 10);
+
+            lendingmanager.loans.get_mut(&0).unwrap().borrower_address =
+                AccountId::from([0x02; 32]);
+            assert_eq!(
+                lendingmanager.update_loan_amount(0, 2000),
+                Err(Error::NotBorrower)
+            );
+        }
+
+        #[ink::test]
+        fn updated_interest_rate_is_charged() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let erc20_decimals = 1000_000_000_000;
+            lendingmanager.loans.insert(
+                0,
+                Loan {
+                    id: 0,
+                    amount: erc20_decimals,
+                    borrower_address: AccountId::from([0x01; 32]),
+                    status: LoanStatus::Available as u8,
+                    interest_rate: 10,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(lendingmanager.update_loan_interest_rate(0, 20), Ok(()));
+
+            // Mirrors the storage updates of lend which cannot be called off-chain
+            let loan = lendingmanager.loans.get_mut(&0).unwrap();
+            loan.status = LoanStatus::Borrowed as u8;
+            loan.fulfilled_at = Some(0);
+            // Any part of a day is charged as a whole day
+            for _ in 0..200 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                    .expect("Cannot advance block");
+            }
+            // One day at 20% instead of 10%
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Ok(erc20_decimals as u128 + 547_945_205)
+            );
+        }
+
+        #[ink::test]
+        fn expiring_loans_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let durations = [2, 10, 1000, 2];
+            for (loan_id, duration) in durations.iter().enumerate() {
+                let loan_id = loan_id as LoanId;
+                lendingmanager.loans.insert(
+                    loan_id,
+                    Loan {
+                        id: loan_id,
+                        amount: 1000,
+                        duration: *duration,
+                        fulfilled_at: Some(0),
+                        status: LoanStatus::Borrowed as u8,
+                        ..Default::default()
+                    },
+                );
+            }
+            // Only borrowed loans can expire
+            lendingmanager.loans.get_mut(&3).unwrap().status = LoanStatus::Repaid as u8;
+
+            let sorted = |mut loans: Vec<LoanId>| {
+                loans.sort();
+                loans
+            };
+            assert_eq!(lendingmanager.get_expired_loans(), Vec::<LoanId>::new());
+            assert_eq!(sorted(lendingmanager.get_loans_expiring_within(5)), vec![0]);
+            assert_eq!(
+                sorted(lendingmanager.get_loans_expiring_within(1000)),
+                vec![0, 1]
+            );
+            assert_eq!(
+                sorted(lendingmanager.get_loans_expiring_within(1001)),
+                vec![0, 1, 2]
+            );
+
+            // Time moves on by 5 ms per block
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(lendingmanager.get_expired_loans(), vec![0]);
+            assert_eq!(
+                sorted(lendingmanager.get_loans_expiring_within(6)),
+                vec![0, 1]
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(lendingmanager.get_expired_loans(), vec![0]);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(sorted(lendingmanager.get_expired_loans()), vec![0, 1]);
+        }
+
+        #[ink::test]
+        fn overdue_loans_for_investor_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let bob = AccountId::from([0x02; 32]);
+            let charlie = AccountId::from([0x03; 32]);
+            let loans = [
+                (bob, 2, 1000),
+                (bob, 1000, 500),
+                (bob, 2, 300),
+                (charlie, 2, 700),
+            ];
+            for (loan_id, (investor, duration, amount)) in loans.iter().enumerate() {
+                let loan_id = loan_id as LoanId;
+                lendingmanager.loans.insert(
+                    loan_id,
+                    Loan {
+                        id: loan_id,
+                        amount: *amount,
+                        duration: *duration,
+                        fulfilled_at: Some(0),
+                        investor_address: Some(*investor),
+                        status: LoanStatus::Borrowed as u8,
+                        ..Default::default()
+                    },
+                );
+                lendingmanager
+                    .investors
+                    .entry(*investor)
+                    .or_insert_with(Vec::new)
+                    .push(loan_id);
+            }
+            // Repaid loans are never overdue
+            lendingmanager.loans.get_mut(&2).unwrap().status = LoanStatus::Repaid as u8;
+
+            assert_eq!(
+                lendingmanager.get_overdue_loans_for_investor(bob),
+                Vec::<LoanId>::new()
+            );
+            assert_eq!(lendingmanager.get_total_overdue_amount_for_investor(bob), 0);
+
+            // Time moves on by 5 ms per block
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            assert_eq!(lendingmanager.get_overdue_loans_for_investor(bob), vec![0]);
+            assert_eq!(
+                lendingmanager.get_total_overdue_amount_for_investor(bob),
+                1000
+            );
+            assert_eq!(
+                lendingmanager.get_overdue_loans_for_investor(charlie),
+                vec![3]
+            );
+            assert_eq!(
+                lendingmanager.get_total_overdue_amount_for_investor(charlie),
+                700
+            );
+            assert_eq!(
+                lendingmanager.get_overdue_loans_for_investor(AccountId::from([0x04; 32])),
+                Vec::<LoanId>::new()
+            );
+        }
+
+        #[ink::test]
+        fn loan_repayment_amount_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let erc20_decimals = 1000_000_000_000;
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Err(Error::NoSuchLoan)
+            );
+
+            lendingmanager.loans.insert(
+                0,
+                Loan {
+                    id: 0,
+                    amount: erc20_decimals,
+                    status: LoanStatus::Available as u8,
+                    interest_rate: 10,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Err(Error::LoanNotBorrowed)
+            );
+
+            let loan = lendingmanager.loans.get_mut(&0).unwrap();
+            loan.status = LoanStatus::Borrowed as u8;
+            loan.fulfilled_at = Some(0);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>()
+                .expect("Cannot advance block");
+            // withdraw charges repayment_amount at the current block time
+            let loan = *lendingmanager.loans.get(&0).unwrap();
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Ok(LendingManager::repayment_amount(
+                    &loan,
+                    lendingmanager.get_current_time()
+                ))
+            );
+            assert_eq!(
+                lendingmanager.get_loan_repayment_amount(0),
+                Ok(erc20_decimals as u128)
+            );
+            // Matches calculate_interest for a loan fulfilled 29 days earlier
+            assert_eq!(
+                LendingManager::repayment_amount(&loan, 86400 * 29 * 1000),
+                erc20_decimals as u128 + 8_251_913_257
+            );
+        }
+
+        #[ink::test]
+        fn pause_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let owner = AccountId::from([0x01; 32]);
+            assert_eq!(lendingmanager.is_paused(), false);
+
+            assert_eq!(lendingmanager.pause(), Ok(()));
+            assert_eq!(lendingmanager.is_paused(), true);
+            assert_eq!(
+                lendingmanager.list_token(owner, 1, owner, 1000, 10),
+                Err(Error::ContractPaused)
+            );
+            assert_eq!(lendingmanager.lend(0), Err(Error::ContractPaused));
+            assert_eq!(
+                lendingmanager.batch_lend(vec![0, 1]),
+                vec![Err(Error::ContractPaused), Err(Error::ContractPaused)]
+            );
+            assert_eq!(lendingmanager.expire_loan(0), Err(Error::ContractPaused));
+            assert_eq!(lendingmanager.withdraw(0), Err(Error::ContractPaused));
+            assert_eq!(lendingmanager.liquidate(0), Err(Error::ContractPaused));
+            assert_eq!(
+                lendingmanager.update_loan_amount(0, 10),
+                Err(Error::ContractPaused)
+            );
+            assert_eq!(
+                lendingmanager.update_loan_interest_rate(0, 10),
+                Err(Error::ContractPaused)
+            );
+            assert_eq!(lendingmanager.locked, false);
+
+            // Unpausing restores operations, which now fail for lack of loans
+            assert_eq!(lendingmanager.unpause(), Ok(()));
+            assert_eq!(lendingmanager.is_paused(), false);
+            assert_eq!(lendingmanager.lend(0), Err(Error::NoSuchLoan));
+            assert_eq!(lendingmanager.expire_loan(0), Err(Error::NoSuchLoan));
+            assert_eq!(lendingmanager.withdraw(0), Err(Error::NoSuchLoan));
+            assert_eq!(lendingmanager.liquidate(0), Err(Error::NoSuchLoan));
+            assert_eq!(
+                lendingmanager.update_loan_amount(0, 10),
+                Err(Error::NoSuchLoan)
+            );
+        }
+
+        #[ink::test]
+        fn investor_portfolio_value_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let investor = AccountId::from([0x02; 32]);
+            let erc20_decimals: u64 = 1000_000_000_000;
+            assert_eq!(
+                lendingmanager.get_investor_portfolio_value(investor),
+                (0, 0)
+            );
+            assert_eq!(
+                lendingmanager.get_investor_completed_loans_value(investor),
+                0
+            );
+
+            for loan_id in 0..2 {
+                lendingmanager.loans.insert(
+                    loan_id,
+                    Loan {
+                        id: loan_id,
+                        amount: erc20_decimals * (loan_id + 1),
+                        status: LoanStatus::Available as u8,
+                        interest_rate: 10,
+                        ..Default::default()
+                    },
+                );
+                lendingmanager.handle_lend(loan_id, investor, 0);
+            }
+            let principal = 3 * erc20_decimals as Balance;
+            assert_eq!(
+                lendingmanager.get_investor_portfolio_value(investor),
+                (principal, principal)
+            );
+
+            // Interest accrues on loans in progress
+            let loan = *lendingmanager.loans.get(&1).unwrap();
+            assert_eq!(
+                LendingManager::repayment_amount(&loan, 86400 * 29 * 1000),
+                2 * (erc20_decimals as Balance + 8_251_913_257)
+            );
+
+            // Loan 0 is repaid
+            let loan = lendingmanager.loans.get_mut(&0).unwrap();
+            loan.status = LoanStatus::Repaid as u8;
+            loan.repaid_at = Some(0);
+            assert_eq!(
+                lendingmanager.get_investor_portfolio_value(investor),
+                (2 * erc20_decimals as Balance, 2 * erc20_decimals as Balance)
+            );
+            assert_eq!(
+                lendingmanager.get_investor_completed_loans_value(investor),
+                erc20_decimals as Balance
+            );
+
+            // Loan 1 is repaid, completing the cycle
+            lendingmanager.loans.get_mut(&1).unwrap().status = LoanStatus::Repaid as u8;
+            assert_eq!(
+                lendingmanager.get_investor_portfolio_value(investor),
+                (0, 0)
+            );
+            assert_eq!(
+                lendingmanager.get_investor_completed_loans_value(investor),
+                principal
+            );
+        }
+
+        #[ink::test]
+        fn secondary_market_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            // The default caller is the investor
+            let investor = AccountId::from([0x01; 32]);
+            let buyer = AccountId::from([0x02; 32]);
+            assert_eq!(
+                lendingmanager.secondary_market_list(0, 500),
+                Err(Error::NoSuchLoan)
+            );
+
+            lendingmanager.loans.insert(
+                0,
+                Loan {
+                    id: 0,
+                    amount: 1000,
+                    status: LoanStatus::Available as u8,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(
+                lendingmanager.secondary_market_list(0, 500),
+                Err(Error::LoanNotBorrowed)
+            );
+            lendingmanager.handle_lend(0, buyer, 0);
+            assert_eq!(
+                lendingmanager.secondary_market_list(0, 500),
+                Err(Error::NotInvestor)
+            );
+            lendingmanager.transfer_loan_position(0, buyer, investor);
+            assert_eq!(
+                lendingmanager.get_investor_loans(buyer),
+                Vec::<LoanId>::new()
+            );
+            assert_eq!(lendingmanager.get_investor_loans(investor), vec![0]);
+
+            assert_eq!(lendingmanager.secondary_market_list(0, 500), Ok(()));
+            assert_eq!(
+                lendingmanager.get_secondary_listing(0),
+                Some(SecondaryListing {
+                    loan_id: 0,
+                    seller: investor,
+                    ask_price: 500,
+                    created_at: 0,
+                })
+            );
+            assert_eq!(
+                lendingmanager.secondary_market_list(0, 600),
+                Err(Error::LoanAlreadyListed)
+            );
+            assert_eq!(
+                lendingmanager.secondary_market_purchase(1),
+                Err(Error::NoSuchListing)
+            );
+
+            // Listings of repaid loans can no longer be bought
+            lendingmanager.loans.get_mut(&0).unwrap().status = LoanStatus::Repaid as u8;
+            assert_eq!(
+                lendingmanager.secondary_market_purchase(0),
+                Err(Error::LoanNotBorrowed)
+            );
+            assert_eq!(lendingmanager.locked, false);
+
+            assert_eq!(
+                lendingmanager.secondary_market_cancel(1),
+                Err(Error::NoSuchListing)
+            );
+            assert_eq!(lendingmanager.secondary_market_cancel(0), Ok(()));
+            assert_eq!(lendingmanager.get_secondary_listing(0), None);
+        }
+
+        #[ink::test]
+        fn reentrancy_guard_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            let owner = AccountId::from([0x01; 32]);
+            // Simulate a call made while another guarded call is in progress
+            lendingmanager.locked = true;
+            assert_eq!(
+                lendingmanager.list_token(owner, 1, owner, 1000, 10),
+                Err(Error::Reentrant)
+            );
+            assert_eq!(lendingmanager.lend(0), Err(Error::Reentrant));
+            assert_eq!(lendingmanager.expire_loan(0), Err(Error::Reentrant));
+            assert_eq!(lendingmanager.withdraw(0), Err(Error::Reentrant));
+            assert_eq!(lendingmanager.liquidate(0), Err(Error::Reentrant));
+        }
+
+        #[ink::test]
+        fn contract_version_works() {
+            let lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                10,
+                true,
+            );
+            assert_eq!(lendingmanager.get_contract_version(), VERSION);
+            assert_eq!(lendingmanager.get_contract_version(), (1, 0, 0));
+            assert_eq!(
+                lendingmanager.get_contract_name(),
+                String::from("LendingManager")
+            );
+        }
+
+        #[ink::test]
+        fn enable_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                false,
+            );
+            assert_eq!(lendingmanager.is_enabled(), false);
+            assert_eq!(lendingmanager.get_interest_rate(), 7);
+
+            assert_eq!(lendingmanager.enable(), Ok(()));
+            assert_eq!(lendingmanager.is_enabled(), true);
+        }
+
+        #[ink::test]
+        fn renounce_ownership_works() {
+            let mut lendingmanager = LendingManager::new(
+                instantiate_erc20_contract(),
+                instantiate_erc721_contract(),
+                7,
+                true,
+            );
+            assert_eq!(lendingmanager.renounce_ownership(), Ok(()));
+            assert_eq!(lendingmanager.is_owner(), false);
+
+            assert_eq!(lendingmanager.enable(), Err(Error::ContractImmutable));
+            assert_eq!(lendingmanager.disable(), Err(Error::ContractImmutable));
+            assert_eq!(
+                lendingmanager.set_interest_rate(8),
+                Err(Error::ContractImmutable)
+            );
+            assert_eq!(lendingmanager.is_enabled(), true);
+            assert_eq!(lendingmanager.get_interest_rate(), 7);
         }
 
         #[ink::test]
@@ -615,7 +3091,7 @@ mod lendingmanager {
             assert_eq!(lendingmanager.is_enabled(), true);
             assert_eq!(lendingmanager.get_interest_rate(), 7);
 
-            lendingmanager.disable();
+            assert_eq!(lendingmanager.disable(), Ok(()));
             assert_eq!(lendingmanager.is_enabled(), false);
         }
 
@@ -631,7 +3107,7 @@ mod lendingmanager {
             assert_eq!(lendingmanager.is_enabled(), true);
             assert_eq!(lendingmanager.get_interest_rate(), 7);
 
-            lendingmanager.set_interest_rate(8);
+            assert_eq!(lendingmanager.set_interest_rate(8), Ok(()));
             assert_eq!(lendingmanager.get_interest_rate(), 8);
         }
 
@@ -651,7 +3127,7 @@ mod lendingmanager {
                 "Should not allow deposit in disabled state"
             );
 
-            lendingmanager.enable();
+            assert_eq!(lendingmanager.enable(), Ok(()));
             assert_eq!(lendingmanager.is_enabled(), true);
             assert!(
                 lendingmanager